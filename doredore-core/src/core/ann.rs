@@ -0,0 +1,703 @@
+//! 近似最近傍探索（ANN）のためのHNSWインデックス
+//!
+//! セマンティック検索の全件スキャン（O(n × d)）の代わりに、階層的なグラフ
+//! （Hierarchical Navigable Small World）をたどって類似度の高いベクトルを探す。
+//! 真の上位K件を取りこぼすことがある（近似）が、返すスコアは正確なコサイン類似度
+//!
+//! - ベクトルは正規化して保持するため、内積がそのままコサイン類似度になる
+//! - 削除は墓標方式。削除済みのノードも探索の経路としては使い、結果には含めない。
+//!   削除済みのノードが半数を超えた場合は、残っているノードだけで作り直す
+//! - [`HnswIndex::to_bytes`] / [`HnswIndex::from_bytes`] でベクトルごと保存・復元できる
+
+use crate::core::search::{dot, normalized};
+use crate::error::{Error, Result};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+
+/// 第1層以上でノードが持つ隣接ノードの最大数（第0層はこの2倍）
+const M: usize = 16;
+
+/// 挿入時の探索幅（大きいほどグラフの質が上がり、挿入が遅くなる）
+const EF_CONSTRUCTION: usize = 100;
+
+/// 検索時の探索幅のデフォルト（大きいほど取りこぼしが減り、検索が遅くなる）
+pub const DEFAULT_EF_SEARCH: usize = 64;
+
+/// ノードの最上位の層の上限
+const MAX_LEVEL: usize = 16;
+
+/// 保存形式の識別子とバージョン
+const MAGIC: &[u8; 4] = b"HNSW";
+const FORMAT_VERSION: u32 = 1;
+
+/// 探索中の候補（類似度、同じ類似度ならノード番号の小さい方を大きいとみなす）
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate {
+    similarity: f32,
+    node: usize,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.similarity
+            .total_cmp(&other.similarity)
+            .then(other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+struct Node {
+    document_id: i64,
+    /// 正規化済みのベクトル
+    vector: Vec<f32>,
+    /// 層ごとの隣接ノード（`links.len() - 1` がこのノードの最上位の層）
+    links: Vec<Vec<u32>>,
+    deleted: bool,
+}
+
+/// ドキュメントIDをキーとするHNSWインデックス（1つのコレクション分）
+pub struct HnswIndex {
+    dimension: usize,
+    nodes: Vec<Node>,
+    /// ドキュメントID → 削除されていないノード
+    by_document: HashMap<i64, usize>,
+    /// 探索を始めるノード（最上位の層を持つノード）
+    entry_point: Option<usize>,
+}
+
+impl HnswIndex {
+    /// `dimension` 次元のベクトルを格納する空のインデックス
+    pub fn new(dimension: usize) -> Self {
+        Self {
+            dimension,
+            nodes: Vec::new(),
+            by_document: HashMap::new(),
+            entry_point: None,
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    /// 格納しているドキュメント数（削除済みは含まない）
+    pub fn len(&self) -> usize {
+        self.by_document.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_document.is_empty()
+    }
+
+    pub fn contains(&self, document_id: i64) -> bool {
+        self.by_document.contains_key(&document_id)
+    }
+
+    /// 格納しているドキュメントのID（順不同）
+    pub fn document_ids(&self) -> impl Iterator<Item = i64> + '_ {
+        self.by_document.keys().copied()
+    }
+
+    /// ドキュメントを追加（同じIDが既にあれば置き換える）
+    ///
+    /// # エラー
+    /// `vector` の次元数がインデックスの次元数と一致しない場合は `Error::InvalidInput`
+    pub fn insert(&mut self, document_id: i64, vector: &[f32]) -> Result<()> {
+        self.check_dimension(vector)?;
+        self.remove(document_id);
+        self.insert_normalized(document_id, normalized(vector));
+        Ok(())
+    }
+
+    /// ドキュメントを削除（格納していた場合はtrue）
+    pub fn remove(&mut self, document_id: i64) -> bool {
+        let Some(node) = self.by_document.remove(&document_id) else {
+            return false;
+        };
+        self.nodes[node].deleted = true;
+
+        let deleted = self.nodes.len() - self.by_document.len();
+        if deleted * 2 > self.nodes.len() {
+            self.compact();
+        }
+        true
+    }
+
+    /// `query` とのコサイン類似度が高い順に最大 `k` 件の `(ドキュメントID, 類似度)` を返す
+    ///
+    /// `ef` は探索幅（`k` 未満の場合は `k`）。[`DEFAULT_EF_SEARCH`] を目安に、
+    /// 取りこぼしを減らしたい場合は大きくする
+    ///
+    /// # エラー
+    /// `query` の次元数がインデックスの次元数と一致しない場合は `Error::InvalidInput`
+    pub fn search(&self, query: &[f32], k: usize, ef: usize) -> Result<Vec<(i64, f32)>> {
+        self.check_dimension(query)?;
+        let Some(entry) = self.entry_point else {
+            return Ok(Vec::new());
+        };
+        if k == 0 {
+            return Ok(Vec::new());
+        }
+
+        let query = normalized(query);
+        let mut nearest = vec![self.candidate(&query, entry)];
+        for layer in (1..self.nodes[entry].links.len()).rev() {
+            nearest = self.search_layer(&query, &nearest, 1, layer);
+        }
+
+        // 削除済みのノードは結果から除くため、その分だけ多めに探す
+        let deleted = self.nodes.len() - self.by_document.len();
+        let ef = ef.max(k) + deleted.min(ef.max(k));
+
+        Ok(self
+            .search_layer(&query, &nearest, ef, 0)
+            .into_iter()
+            .filter(|c| !self.nodes[c.node].deleted)
+            .take(k)
+            .map(|c| (self.nodes[c.node].document_id, c.similarity))
+            .collect())
+    }
+
+    /// 保存用のバイト列（ベクトルとグラフを含む。リトルエンディアン）
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&(self.dimension as u32).to_le_bytes());
+        out.extend_from_slice(&(self.nodes.len() as u32).to_le_bytes());
+        out.extend_from_slice(
+            &self
+                .entry_point
+                .map_or(u32::MAX, |n| n as u32)
+                .to_le_bytes(),
+        );
+
+        for node in &self.nodes {
+            out.extend_from_slice(&node.document_id.to_le_bytes());
+            out.push(u8::from(node.deleted));
+            out.extend_from_slice(&(node.links.len() as u32).to_le_bytes());
+            for value in &node.vector {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            for links in &node.links {
+                out.extend_from_slice(&(links.len() as u32).to_le_bytes());
+                for neighbor in links {
+                    out.extend_from_slice(&neighbor.to_le_bytes());
+                }
+            }
+        }
+
+        out
+    }
+
+    /// [`HnswIndex::to_bytes`] で保存したバイト列から復元
+    ///
+    /// # エラー
+    /// 形式が異なる・途中で切れている・グラフが矛盾している場合は `Error::InvalidInput`
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader { data, pos: 0 };
+        if reader.take(4)? != MAGIC {
+            return Err(corrupted("unknown format"));
+        }
+        let version = reader.u32()?;
+        if version != FORMAT_VERSION {
+            return Err(corrupted(&format!("unsupported version {}", version)));
+        }
+        let dimension = reader.u32()? as usize;
+        let count = reader.u32()? as usize;
+        let entry_point = match reader.u32()? {
+            u32::MAX => None,
+            n => Some(n as usize),
+        };
+
+        let mut index = Self::new(dimension);
+        for node in 0..count {
+            let document_id = reader.i64()?;
+            let deleted = reader.u8()? != 0;
+            let levels = reader.u32()? as usize;
+            if levels == 0 || levels > MAX_LEVEL + 1 {
+                return Err(corrupted("invalid node level"));
+            }
+            let vector = (0..dimension)
+                .map(|_| reader.f32())
+                .collect::<Result<Vec<_>>>()?;
+            let links = (0..levels)
+                .map(|_| {
+                    let len = reader.u32()? as usize;
+                    (0..len).map(|_| reader.u32()).collect::<Result<Vec<_>>>()
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            if !deleted && index.by_document.insert(document_id, node).is_some() {
+                return Err(corrupted("duplicate document ID"));
+            }
+            index.nodes.push(Node {
+                document_id,
+                vector,
+                links,
+                deleted,
+            });
+        }
+        if reader.pos != data.len() {
+            return Err(corrupted("trailing data"));
+        }
+
+        // 各層の隣接ノードはその層を持つノードでなければならない（探索中の範囲外アクセスを防ぐ）
+        for node in &index.nodes {
+            for (layer, links) in node.links.iter().enumerate() {
+                let valid = links.iter().all(|&neighbor| {
+                    index
+                        .nodes
+                        .get(neighbor as usize)
+                        .is_some_and(|n| n.links.len() > layer)
+                });
+                if !valid {
+                    return Err(corrupted("invalid link"));
+                }
+            }
+        }
+        match entry_point {
+            Some(entry) if entry < count => index.entry_point = Some(entry),
+            None if count == 0 => {}
+            _ => return Err(corrupted("invalid entry point")),
+        }
+
+        Ok(index)
+    }
+
+    fn check_dimension(&self, vector: &[f32]) -> Result<()> {
+        if vector.len() != self.dimension {
+            return Err(Error::InvalidInput(format!(
+                "Vector dimension mismatch: expected {}, got {}",
+                self.dimension,
+                vector.len()
+            )));
+        }
+        Ok(())
+    }
+
+    fn candidate(&self, query: &[f32], node: usize) -> Candidate {
+        Candidate {
+            similarity: dot(query, &self.nodes[node].vector),
+            node,
+        }
+    }
+
+    fn insert_normalized(&mut self, document_id: i64, vector: Vec<f32>) {
+        let level = random_level(document_id);
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            document_id,
+            vector,
+            links: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        self.by_document.insert(document_id, node);
+
+        let Some(entry) = self.entry_point else {
+            self.entry_point = Some(node);
+            return;
+        };
+
+        // 上の層から貪欲にたどって、新しいノードの層の入口を決める
+        let query = self.nodes[node].vector.clone();
+        let top = self.nodes[entry].links.len() - 1;
+        let mut nearest = vec![self.candidate(&query, entry)];
+        for layer in (level + 1..=top).rev() {
+            nearest = self.search_layer(&query, &nearest, 1, layer);
+        }
+
+        for layer in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(&query, &nearest, EF_CONSTRUCTION, layer);
+            let neighbors = self.select_neighbors(&candidates, max_links(layer));
+            for &neighbor in &neighbors {
+                self.link(neighbor as usize, node, layer);
+            }
+            self.nodes[node].links[layer] = neighbors;
+            nearest = candidates;
+        }
+
+        if level > top {
+            self.entry_point = Some(node);
+        }
+    }
+
+    /// 1つの層で `query` に近いノードを最大 `ef` 件探す（類似度の降順）
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entry_points: &[Candidate],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Candidate> {
+        let mut visited = vec![false; self.nodes.len()];
+        for entry in entry_points {
+            visited[entry.node] = true;
+        }
+        let mut candidates: BinaryHeap<Candidate> = entry_points.iter().copied().collect();
+        // 見つけた上位 `ef` 件（先頭が最も類似度の低いもの）
+        let mut found: BinaryHeap<Reverse<Candidate>> =
+            entry_points.iter().copied().map(Reverse).collect();
+
+        while let Some(current) = candidates.pop() {
+            let worst = found.peek().map_or(f32::NEG_INFINITY, |c| c.0.similarity);
+            if found.len() >= ef && current.similarity < worst {
+                break;
+            }
+
+            for &neighbor in &self.nodes[current.node].links[layer] {
+                let neighbor = neighbor as usize;
+                if std::mem::replace(&mut visited[neighbor], true) {
+                    continue;
+                }
+                let candidate = self.candidate(query, neighbor);
+                let worst = found.peek().map_or(f32::NEG_INFINITY, |c| c.0.similarity);
+                if found.len() < ef || candidate.similarity > worst {
+                    candidates.push(candidate);
+                    found.push(Reverse(candidate));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+
+        let mut found: Vec<Candidate> = found.into_iter().map(|c| c.0).collect();
+        found.sort_by(|a, b| b.cmp(a));
+        found
+    }
+
+    /// 類似度の降順に並んだ候補から隣接ノードを最大 `max` 件選ぶ
+    ///
+    /// 既に選んだどのノードよりも基準のノードに近い候補を優先し（HNSWの論文のヒューリスティック）、
+    /// 隣接ノードが一方向に固まらないようにする。足りない分は残りの候補から類似度の高い順に補う
+    fn select_neighbors(&self, candidates: &[Candidate], max: usize) -> Vec<u32> {
+        let mut selected: Vec<Candidate> = Vec::with_capacity(max);
+        let mut skipped = Vec::new();
+        for &candidate in candidates {
+            if selected.len() >= max {
+                break;
+            }
+            let vector = &self.nodes[candidate.node].vector;
+            let diverse = selected
+                .iter()
+                .all(|s| dot(vector, &self.nodes[s.node].vector) < candidate.similarity);
+            if diverse {
+                selected.push(candidate);
+            } else {
+                skipped.push(candidate);
+            }
+        }
+        selected.extend(skipped.into_iter().take(max - selected.len()));
+
+        selected.into_iter().map(|c| c.node as u32).collect()
+    }
+
+    /// `from` の隣接ノードに `to` を追加し、上限を超えた場合は類似度の低いものから外す
+    ///
+    /// 挿入のたびに多数のノードで起きるため、`select_neighbors` のヒューリスティック
+    /// （候補同士の類似度の計算が必要）は使わない
+    fn link(&mut self, from: usize, to: usize, layer: usize) {
+        self.nodes[from].links[layer].push(to as u32);
+        let max = max_links(layer);
+        if self.nodes[from].links[layer].len() <= max {
+            return;
+        }
+
+        let base = &self.nodes[from].vector;
+        let mut candidates: Vec<Candidate> = self.nodes[from].links[layer]
+            .iter()
+            .map(|&n| Candidate {
+                similarity: dot(base, &self.nodes[n as usize].vector),
+                node: n as usize,
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.cmp(a));
+        self.nodes[from].links[layer] = self.select_neighbors(&candidates, max);
+    }
+
+    /// 削除済みのノードを除いて作り直す
+    fn compact(&mut self) {
+        let nodes = std::mem::take(&mut self.nodes);
+        self.by_document.clear();
+        self.entry_point = None;
+        for node in nodes.into_iter().filter(|n| !n.deleted) {
+            self.insert_normalized(node.document_id, node.vector);
+        }
+    }
+}
+
+/// 層ごとの隣接ノードの最大数
+fn max_links(layer: usize) -> usize {
+    if layer == 0 {
+        M * 2
+    } else {
+        M
+    }
+}
+
+/// ノードの最上位の層（`P(level ≥ l) = M^-l`）
+///
+/// ドキュメントIDから決定的に求めるため、同じドキュメントを同じ順序で追加すれば
+/// 同じグラフになる
+fn random_level(document_id: i64) -> usize {
+    // splitmix64
+    let mut x = (document_id as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 31;
+
+    // (0, 1) の一様乱数
+    let uniform = ((x >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+    ((-uniform.ln() / (M as f64).ln()) as usize).min(MAX_LEVEL)
+}
+
+fn corrupted(reason: &str) -> Error {
+    Error::InvalidInput(format!("Corrupted ANN index: {}", reason))
+}
+
+/// 保存形式の読み取り（範囲外の読み取りは破損としてエラーにする）
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| corrupted("unexpected end of data"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::time::Instant;
+
+    /// 決定的な疑似乱数（xorshift）で [-1, 1) の成分を持つベクトルを生成（正規化済み）
+    fn random_vectors(count: usize, dimension: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut state = seed.max(1);
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 40) as f32 / (1u64 << 23) as f32 * 2.0 - 1.0
+        };
+        (0..count)
+            .map(|_| normalized(&(0..dimension).map(|_| next()).collect::<Vec<_>>()))
+            .collect()
+    }
+
+    /// 全件スキャンによる正確な上位K件
+    fn exact_top_k(vectors: &[Vec<f32>], query: &[f32], k: usize) -> Vec<i64> {
+        let mut scored: Vec<(i64, f32)> = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i as i64, dot(query, v)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.into_iter().take(k).map(|(id, _)| id).collect()
+    }
+
+    fn build(vectors: &[Vec<f32>]) -> HnswIndex {
+        let mut index = HnswIndex::new(vectors[0].len());
+        for (i, vector) in vectors.iter().enumerate() {
+            index.insert(i as i64, vector).unwrap();
+        }
+        index
+    }
+
+    /// 近似検索の結果に含まれる正確な上位K件の割合
+    fn recall(index: &HnswIndex, vectors: &[Vec<f32>], queries: &[Vec<f32>], k: usize) -> f64 {
+        let mut hits = 0;
+        for query in queries {
+            let exact: HashSet<i64> = exact_top_k(vectors, query, k).into_iter().collect();
+            hits += index
+                .search(query, k, DEFAULT_EF_SEARCH)
+                .unwrap()
+                .iter()
+                .filter(|(id, _)| exact.contains(id))
+                .count();
+        }
+        hits as f64 / (queries.len() * k) as f64
+    }
+
+    #[test]
+    fn test_search_recall() {
+        let vectors = random_vectors(1000, 16, 1);
+        let index = build(&vectors);
+        assert_eq!(index.len(), 1000);
+
+        let queries = random_vectors(30, 16, 2);
+        assert!(recall(&index, &vectors, &queries, 10) > 0.95);
+
+        // 格納済みのベクトル自身で検索すると、そのドキュメントが類似度1で先頭に来る
+        let results = index.search(&vectors[42], 3, DEFAULT_EF_SEARCH).unwrap();
+        assert_eq!(results[0].0, 42);
+        assert!((results[0].1 - 1.0).abs() < 1e-5);
+        assert!(results.windows(2).all(|w| w[0].1 >= w[1].1));
+
+        assert!(index.search(&[1.0; 8], 3, DEFAULT_EF_SEARCH).is_err());
+        assert!(HnswIndex::new(16)
+            .search(&vectors[0], 3, DEFAULT_EF_SEARCH)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_remove_and_replace() {
+        let vectors = random_vectors(500, 16, 3);
+        let mut index = build(&vectors);
+
+        assert!(index.remove(42));
+        assert!(!index.remove(42));
+        assert!(!index.contains(42));
+        let results = index.search(&vectors[42], 10, DEFAULT_EF_SEARCH).unwrap();
+        assert_eq!(results.len(), 10);
+        assert!(results.iter().all(|(id, _)| *id != 42));
+
+        // 同じIDで追加し直すと新しいベクトルで検索される
+        index.insert(7, &vectors[42]).unwrap();
+        assert_eq!(index.len(), 499);
+        assert_eq!(
+            index.search(&vectors[42], 1, DEFAULT_EF_SEARCH).unwrap()[0].0,
+            7
+        );
+
+        // 半数以上を削除すると作り直されるが、残りは引き続き検索できる
+        for id in 100..400 {
+            index.remove(id);
+        }
+        assert_eq!(index.len(), 199);
+        assert!(index.nodes.len() < 300);
+        let queries = random_vectors(20, 16, 4);
+        for query in &queries {
+            let results = index.search(query, 5, DEFAULT_EF_SEARCH).unwrap();
+            assert_eq!(results.len(), 5);
+            assert!(results.iter().all(|(id, _)| index.contains(*id)));
+        }
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let vectors = random_vectors(300, 8, 5);
+        let mut index = build(&vectors);
+        index.remove(10);
+
+        let restored = HnswIndex::from_bytes(&index.to_bytes()).unwrap();
+        assert_eq!(restored.len(), 299);
+        assert_eq!(restored.dimension(), 8);
+        assert!(!restored.contains(10));
+        for query in random_vectors(10, 8, 6) {
+            assert_eq!(
+                restored.search(&query, 5, DEFAULT_EF_SEARCH).unwrap(),
+                index.search(&query, 5, DEFAULT_EF_SEARCH).unwrap()
+            );
+        }
+
+        let empty = HnswIndex::from_bytes(&HnswIndex::new(4).to_bytes()).unwrap();
+        assert!(empty.is_empty());
+
+        // 破損したデータはエラー
+        let bytes = index.to_bytes();
+        assert!(HnswIndex::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(HnswIndex::from_bytes(b"not an index").is_err());
+    }
+
+    /// ランダムな中心の周りに散らばるベクトルを生成する
+    ///
+    /// 実際の埋め込みは話題ごとにまとまりを持つため、ベンチマークではこちらを使う。
+    /// 一様乱数の高次元ベクトルは近傍の区別がほとんどつかず、ANN 全般の最悪ケースになる
+    fn clustered_vectors(
+        count: usize,
+        dimension: usize,
+        clusters: usize,
+        seed: u64,
+    ) -> Vec<Vec<f32>> {
+        let centers = random_vectors(clusters, dimension, seed);
+        random_vectors(count, dimension, seed + 1)
+            .iter()
+            .enumerate()
+            .map(|(i, noise)| {
+                let center = &centers[i % clusters];
+                normalized(
+                    &center
+                        .iter()
+                        .zip(noise)
+                        .map(|(c, n)| c + 0.7 * n)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect()
+    }
+
+    /// 10,000件・384次元のランダムなベクトルで全件スキャンと速度・再現率を比較
+    ///
+    /// 時間がかかるため通常は実行しない:
+    /// `cargo test -p doredore-core --release ann_benchmark -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn ann_benchmark() {
+        let mut vectors = clustered_vectors(10_100, 384, 100, 7);
+        let queries = vectors.split_off(10_000);
+        let k = 10;
+
+        let start = Instant::now();
+        let index = build(&vectors);
+        let build_time = start.elapsed();
+
+        let start = Instant::now();
+        for query in &queries {
+            exact_top_k(&vectors, query, k);
+        }
+        let exact_time = start.elapsed();
+
+        let start = Instant::now();
+        for query in &queries {
+            index.search(query, k, DEFAULT_EF_SEARCH).unwrap();
+        }
+        let ann_time = start.elapsed();
+
+        let recall = recall(&index, &vectors, &queries, k);
+        let speedup = exact_time.as_secs_f64() / ann_time.as_secs_f64();
+        println!(
+            "build: {:?}, exact: {:?}/query, hnsw: {:?}/query, speedup: {:.1}x, recall@{}: {:.3}",
+            build_time,
+            exact_time / queries.len() as u32,
+            ann_time / queries.len() as u32,
+            speedup,
+            k,
+            recall
+        );
+        assert!(speedup > 5.0);
+        assert!(recall > 0.95);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::error::{Error, Result};
+
+/// チャンク分割された本文の断片
+/// 元テキスト上の位置（文字単位のオフセット）を保持する
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// チャンクの通し番号（0始まり）
+    pub index: usize,
+
+    /// 元テキスト上の開始位置（文字単位、含む）
+    pub char_start: usize,
+
+    /// 元テキスト上の終了位置（文字単位、含まない）
+    pub char_end: usize,
+
+    /// チャンクの本文
+    pub content: String,
+}
+
+impl Chunk {
+    /// チャンクのメタデータ（元テキストへの対応付け）を生成
+    ///
+    /// ```json
+    /// { "parent_id": 1, "chunk_index": 0, "char_start": 0, "char_end": 500 }
+    /// ```
+    pub fn metadata(&self, parent_id: i64) -> serde_json::Value {
+        serde_json::json!({
+            "parent_id": parent_id,
+            "chunk_index": self.index,
+            "char_start": self.char_start,
+            "char_end": self.char_end,
+        })
+    }
+}
+
+/// テキストをオーバーラップ付きの固定長チャンクに分割
+///
+/// 日本語などのマルチバイト文字を壊さないよう、バイトではなく文字単位で分割する
+///
+/// # 引数
+/// * `text` - 分割対象のテキスト
+/// * `max_chars` - 1チャンクの最大文字数
+/// * `overlap` - 隣接チャンク間で重複させる文字数（`max_chars` 未満）
+///
+/// # 戻り値
+/// 先頭から順に並んだチャンクのリスト（空文字列の場合は空）
+pub fn split_into_chunks(text: &str, max_chars: usize, overlap: usize) -> Result<Vec<Chunk>> {
+    if max_chars == 0 {
+        return Err(Error::InvalidInput(
+            "Chunk size must be greater than 0".to_string(),
+        ));
+    }
+    if overlap >= max_chars {
+        return Err(Error::InvalidInput(format!(
+            "Chunk overlap ({}) must be smaller than chunk size ({})",
+            overlap, max_chars
+        )));
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    let step = max_chars - overlap;
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let end = (start + max_chars).min(chars.len());
+        chunks.push(Chunk {
+            index: chunks.len(),
+            char_start: start,
+            char_end: end,
+            content: chars[start..end].iter().collect(),
+        });
+
+        if end == chars.len() {
+            break;
+        }
+        start += step;
+    }
+
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_offsets_reconstruct_original() {
+        let text = "永代供養とは、お墓の管理を寺院に委託する供養形態です。".repeat(20);
+        let chunks = split_into_chunks(&text, 50, 10).unwrap();
+        assert!(chunks.len() > 1);
+
+        let chars: Vec<char> = text.chars().collect();
+        let mut rebuilt = String::new();
+        let mut covered = 0;
+        for chunk in &chunks {
+            // オフセットが元テキストの該当範囲を正しく指していること
+            let span: String = chars[chunk.char_start..chunk.char_end].iter().collect();
+            assert_eq!(span, chunk.content);
+
+            // 重複部分を除いて連結すると元テキストに戻ること
+            rebuilt.extend(&chars[covered.max(chunk.char_start)..chunk.char_end]);
+            covered = chunk.char_end;
+        }
+        assert_eq!(rebuilt, text);
+    }
+
+    #[test]
+    fn test_invalid_overlap() {
+        assert!(split_into_chunks("hello", 10, 10).is_err());
+        assert!(split_into_chunks("hello", 0, 0).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::core::preprocess::ContentType;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -8,6 +9,9 @@ pub struct Collection {
     pub document_count: i64,
     pub created_at: String,
     pub updated_at: String,
+    /// このコレクションのEmbeddingモデル名。Noneの場合はインスタンスのモデルを使う
+    #[serde(default)]
+    pub embedding_model: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,6 +23,103 @@ pub struct Document {
     pub metadata: Option<serde_json::Value>,
     pub created_at: String,
     pub updated_at: String,
+    /// 呼び出し側が管理するID（UUIDやURLなど）。未設定の場合はNone
+    #[serde(default)]
+    pub external_id: Option<String>,
+}
+
+/// 耐障害モードでの一括追加結果
+/// Embedding生成に失敗したドキュメントはスキップされ、入力のインデックスとエラー内容が記録される
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AddDocumentsResult {
+    /// 追加に成功したドキュメントのID（入力順）
+    pub ids: Vec<i64>,
+
+    /// 失敗したドキュメント `(入力のインデックス, エラーメッセージ)`
+    pub failures: Vec<(usize, String)>,
+}
+
+/// 一括追加のオプション（[`crate::Doredore::add_documents_with_options`] を参照）
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct AddOptions {
+    /// 挿入中はFTSインデックスの更新を保留し、最後に一度だけ再構築する（デフォルト: false）
+    /// - 大量投入時に高速。再構築は全ドキュメントが対象になるため、少量の追加ではfalseの方が速い
+    #[serde(default)]
+    pub bulk: bool,
+
+    /// 同じコレクションに内容が同一のドキュメントが既にあれば、追加せずにそのIDを返す（デフォルト: false）
+    /// - 同じバッチ内の先行ドキュメントも対象（[`crate::Doredore::add_document_unique`] を参照）
+    #[serde(default)]
+    pub skip_duplicates: bool,
+}
+
+/// CSVインポートのオプション
+///
+/// [`crate::Doredore::import_csv_detailed`] と [`crate::Doredore::import_csv_with_progress`] で共通
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CsvImportOptions {
+    /// 本文のカラム名（デフォルト: 空文字列 = セルの平均文字数が最も長いカラムを自動検出）
+    #[serde(default)]
+    pub content_column: String,
+
+    /// メタデータとして取り込むカラム名（デフォルト: None = 取り込み元の記録のみ）
+    #[serde(default)]
+    pub metadata_columns: Option<Vec<String>>,
+
+    /// 本文カラムの形式（デフォルト: Plain）。HTML/Markdownはプレーンテキストに変換して取り込む
+    #[serde(default)]
+    pub content_type: ContentType,
+
+    /// メタデータの値を数値・真偽値として解釈するかどうか（デフォルト: true）
+    #[serde(default = "default_infer_types")]
+    pub infer_types: bool,
+
+    /// ドキュメントの追加のオプション（`bulk` / `skip_duplicates`）
+    #[serde(default)]
+    pub add_options: AddOptions,
+}
+
+impl Default for CsvImportOptions {
+    fn default() -> Self {
+        Self {
+            content_column: String::new(),
+            metadata_columns: None,
+            content_type: ContentType::default(),
+            infer_types: default_infer_types(),
+            add_options: AddOptions::default(),
+        }
+    }
+}
+
+fn default_infer_types() -> bool {
+    true
+}
+
+/// 既存データベースの診断情報（[`crate::Doredore::inspect_database`] の結果）
+///
+/// 保存済みのEmbeddingの長さから次元数を推定し、再オープンに使うモデルを選ぶ手がかりにする
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseInfo {
+    /// コレクション数
+    pub collection_count: usize,
+
+    /// ドキュメント数
+    pub document_count: usize,
+
+    /// 最も多いEmbeddingの次元数（ドキュメントがない場合はNone）
+    pub dimension: Option<usize>,
+
+    /// 次元数ごとのドキュメント数 `(次元数, 件数)`（件数の降順）
+    pub dimension_counts: Vec<(usize, usize)>,
+
+    /// 次元数が混在しているかどうか
+    /// （異なるモデルで生成したEmbeddingが混ざっている可能性が高く、
+    /// 次元数の異なるドキュメントはセマンティック検索で正しくスコア付けされない）
+    pub mixed_dimensions: bool,
+
+    /// `dimension` と次元数が一致するサポート済みモデル名
+    /// （次元数が同じでもモデルが異なればベクトルに互換性はないため、候補として扱う）
+    pub compatible_models: Vec<String>,
 }
 
 impl Collection {
@@ -37,6 +138,7 @@ impl Collection {
             document_count,
             created_at,
             updated_at,
+            embedding_model: None,
         }
     }
 }
@@ -59,6 +161,7 @@ impl Document {
             metadata,
             created_at,
             updated_at,
+            external_id: None,
         }
     }
 }
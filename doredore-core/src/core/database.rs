@@ -1,20 +1,189 @@
 use crate::core::collection::{Collection, Document};
-use crate::error::Result;
-use rusqlite::{params, Connection};
-use std::path::Path;
+use crate::core::filter::MetadataFilter;
+use crate::core::fuzzy::fuzzy_similarity;
+use crate::core::language::like_lang_sql;
+use crate::core::search::{normalized, vector_norm, KeywordBackend, KeywordMatch};
+use crate::error::{Error, Result};
+use rusqlite::{params, Connection, OpenFlags};
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use std::time::Duration;
+
+/// Embedding付きのドキュメント行 `(document_id, content, embedding, collection_name, metadata)`
+pub type EmbeddedDocumentRow = (i64, String, Vec<f32>, String, Option<serde_json::Value>);
+
+/// スコア付きのドキュメント行 `(document_id, content, score, collection_name, metadata)`
+pub type ScoredDocumentRow = (i64, String, f32, String, Option<serde_json::Value>);
+
+/// Embeddingの保存形式
+///
+/// 形式はドキュメントごとに `embedding_format` 列に記録されるため、途中で形式を変えても
+/// 既存のドキュメントはそのまま読める（読み込み時にf32のベクトルに戻す）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmbeddingFormat {
+    /// f32のリトルエンディアン列（デフォルト、精度の劣化なし）
+    #[default]
+    F32,
+
+    /// 最小値・最大値（f32）と、各次元をその範囲で256段階に量子化した1バイトずつの列
+    /// - 保存サイズは約1/4（384次元で1536バイト → 392バイト）
+    /// - 復元誤差は次元ごとに最大で範囲の1/510。コサイン類似度の順位はほとんど変わらない
+    Int8,
+}
+
+impl EmbeddingFormat {
+    /// `embedding_format` 列に記録する名前
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmbeddingFormat::F32 => "f32",
+            EmbeddingFormat::Int8 => "int8",
+        }
+    }
+
+    /// 名前から保存形式を取得（未知の名前はNone）
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "f32" => Some(EmbeddingFormat::F32),
+            "int8" => Some(EmbeddingFormat::Int8),
+            _ => None,
+        }
+    }
+}
 
 pub struct Database {
     conn: Connection,
+    path: PathBuf,
+    /// バルクロード中はFTSインデックスへの挿入を保留する
+    bulk_loading: Cell<bool>,
+    /// 読み取り専用で開いた場合はtrue（変更操作は `Error::ReadOnly` になる）
+    read_only: bool,
+    /// SQLiteがFTS5に対応しているかどうか（falseの場合、キーワード検索はLIKEのみ）
+    fts_available: bool,
+    /// 追加・更新するEmbeddingの保存形式
+    embedding_format: Cell<EmbeddingFormat>,
+    /// 最後のキーワード検索で使った照合方法（[`Database::last_keyword_backend`]）
+    last_keyword_backend: Cell<Option<KeywordBackend>>,
 }
 
+/// FTS5が使えない場合の警告を1プロセスで1回だけ出すためのガード
+static FTS_UNAVAILABLE_WARNING: Once = Once::new();
+
+/// 他の接続がデータベースをロックしている場合に待つ時間
+const BUSY_TIMEOUT: Duration = Duration::from_millis(5000);
+
+/// 孤立したドキュメントの削除（[`Database::remove_orphaned_documents`]）を実行済みかどうかの設定キー
+const ORPHANS_REMOVED_SETTING: &str = "orphaned_documents_removed";
+
+/// メタデータの `lang` がLIKE検索向けの言語のドキュメントがあるかどうかの設定キー
+/// （"1" = ある、"0" = ない。[`Database::routes_like_langs`] を参照）
+const LIKE_LANG_DOCUMENTS_SETTING: &str = "like_lang_documents";
+
 impl Database {
+    /// データベースを開く（ファイルがなければ作成し、スキーマを用意する）
+    ///
+    /// - WALモードにする（設定はファイルに保存される）。書き込み中も他の接続から読み取れる
+    /// - 他の接続のロックは最大5秒待ってから `SQLITE_BUSY` にする
+    /// - 外部キー制約を有効にする（コレクションを削除すると、そのドキュメントも削除される）
     pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
-        let conn = Connection::open(db_path)?;
-        let db = Self { conn };
+        let path = db_path.as_ref().to_path_buf();
+        let conn = Connection::open(&path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // インメモリのデータベースはWALにできず "memory" のままになる
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        let mut db = Self {
+            conn,
+            path,
+            bulk_loading: Cell::new(false),
+            read_only: false,
+            fts_available: false,
+            embedding_format: Cell::new(EmbeddingFormat::default()),
+            last_keyword_backend: Cell::new(None),
+        };
         db.init_schema()?;
+        db.init_fts()?;
+        db.remove_orphaned_documents()?;
+        db.init_like_lang_documents()?;
         Ok(db)
     }
 
+    /// 読み取り専用でデータベースを開く
+    ///
+    /// `SQLITE_OPEN_READONLY` で開くため、読み取り専用のボリューム上のファイルも開ける
+    /// （[`Database::new`] で作成したファイルはWALモードのため、読み取り専用のボリュームに置く場合は
+    /// 事前に `PRAGMA journal_mode=DELETE` に戻しておく）。
+    /// スキーマの作成・マイグレーションは行わない（ファイルが存在しない場合はエラー）。
+    /// 変更操作は実行前に `Error::ReadOnly` を返す
+    pub fn open_read_only<P: AsRef<Path>>(db_path: P) -> Result<Self> {
+        let path = db_path.as_ref().to_path_buf();
+        let conn = Connection::open_with_flags(
+            &path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+
+        let fts_available = conn
+            .prepare("SELECT document_id FROM documents_fts LIMIT 0")
+            .is_ok();
+        if !fts_available {
+            warn_fts_unavailable();
+        }
+
+        Ok(Self {
+            conn,
+            path,
+            bulk_loading: Cell::new(false),
+            read_only: true,
+            fts_available,
+            embedding_format: Cell::new(EmbeddingFormat::default()),
+            last_keyword_backend: Cell::new(None),
+        })
+    }
+
+    /// データベースファイルのパス
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 読み取り専用で開いているかどうか
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// FTS5による全文検索インデックスを使えるかどうか
+    ///
+    /// SQLiteがFTS5なしでビルドされている場合はfalseになり、
+    /// キーワード検索は常にLIKE検索で行われる
+    pub fn fts_available(&self) -> bool {
+        self.fts_available
+    }
+
+    /// 追加・更新するEmbeddingの保存形式
+    pub fn embedding_format(&self) -> EmbeddingFormat {
+        self.embedding_format.get()
+    }
+
+    /// 以降に追加・更新するEmbeddingの保存形式を設定
+    ///
+    /// 保存済みのドキュメントは変換しない（形式の異なるドキュメントが混在しても検索できる）
+    pub fn set_embedding_format(&self, format: EmbeddingFormat) {
+        self.embedding_format.set(format);
+    }
+
+    /// 読み取り専用の場合は `Error::ReadOnly`
+    fn ensure_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Ok(())
+    }
+
     fn init_schema(&self) -> Result<()> {
         // コレクションテーブル
         self.conn.execute(
@@ -22,6 +191,9 @@ impl Database {
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 name TEXT UNIQUE NOT NULL,
                 description TEXT,
+                indexed_metadata_fields TEXT,
+                embedding_model TEXT,
+                ann_generation INTEGER NOT NULL DEFAULT 0,
                 created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
                 updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
             )",
@@ -36,6 +208,12 @@ impl Database {
                 content TEXT NOT NULL,
                 embedding BLOB NOT NULL,
                 metadata TEXT,
+                content_hash TEXT,
+                embedding_norm REAL,
+                embedding_format TEXT,
+                external_id TEXT,
+                parent_id INTEGER REFERENCES documents(id) ON DELETE SET NULL,
+                ann_generation INTEGER NOT NULL DEFAULT 0,
                 created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
                 updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
                 FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE
@@ -43,6 +221,104 @@ impl Database {
             [],
         )?;
 
+        // 既存のデータベースに content_hash 列を追加（重複チェック用）
+        let has_content_hash = self
+            .conn
+            .prepare("SELECT 1 FROM pragma_table_info('documents') WHERE name = 'content_hash'")?
+            .exists([])?;
+        if !has_content_hash {
+            self.conn
+                .execute("ALTER TABLE documents ADD COLUMN content_hash TEXT", [])?;
+            self.backfill_content_hashes()?;
+        }
+
+        // 既存のデータベースに embedding_format 列を追加（NULLはf32）
+        // embedding_norm の補完で参照するため、先に追加する
+        let has_embedding_format = self
+            .conn
+            .prepare(
+                "SELECT 1 FROM pragma_table_info('documents') WHERE name = 'embedding_format'",
+            )?
+            .exists([])?;
+        if !has_embedding_format {
+            self.conn
+                .execute("ALTER TABLE documents ADD COLUMN embedding_format TEXT", [])?;
+        }
+
+        // 既存のデータベースに embedding_norm 列を追加（検索の枝刈り用）
+        let has_embedding_norm = self
+            .conn
+            .prepare("SELECT 1 FROM pragma_table_info('documents') WHERE name = 'embedding_norm'")?
+            .exists([])?;
+        if !has_embedding_norm {
+            self.conn
+                .execute("ALTER TABLE documents ADD COLUMN embedding_norm REAL", [])?;
+            self.backfill_embedding_norms()?;
+        }
+
+        // 既存のデータベースに external_id 列を追加（呼び出し側が管理するID用）
+        let has_external_id = self
+            .conn
+            .prepare("SELECT 1 FROM pragma_table_info('documents') WHERE name = 'external_id'")?
+            .exists([])?;
+        if !has_external_id {
+            self.conn
+                .execute("ALTER TABLE documents ADD COLUMN external_id TEXT", [])?;
+        }
+
+        // 既存のデータベースに parent_id 列を追加（チャンクの親ドキュメント）
+        let has_parent_id = self
+            .conn
+            .prepare("SELECT 1 FROM pragma_table_info('documents') WHERE name = 'parent_id'")?
+            .exists([])?;
+        if !has_parent_id {
+            self.conn.execute(
+                "ALTER TABLE documents ADD COLUMN parent_id INTEGER
+                 REFERENCES documents(id) ON DELETE SET NULL",
+                [],
+            )?;
+            self.backfill_chunk_parents()?;
+        }
+
+        // 既存のデータベースに indexed_metadata_fields 列を追加（キーワード検索の対象にするメタデータ）
+        let has_indexed_metadata_fields = self
+            .conn
+            .prepare(
+                "SELECT 1 FROM pragma_table_info('collections')
+                 WHERE name = 'indexed_metadata_fields'",
+            )?
+            .exists([])?;
+        if !has_indexed_metadata_fields {
+            self.conn.execute(
+                "ALTER TABLE collections ADD COLUMN indexed_metadata_fields TEXT",
+                [],
+            )?;
+        }
+
+        // 既存のデータベースに embedding_model 列を追加（コレクションごとのEmbeddingモデル）
+        let has_embedding_model = self
+            .conn
+            .prepare("SELECT 1 FROM pragma_table_info('collections') WHERE name = 'embedding_model'")?
+            .exists([])?;
+        if !has_embedding_model {
+            self.conn
+                .execute("ALTER TABLE collections ADD COLUMN embedding_model TEXT", [])?;
+        }
+
+        // 既存のデータベースに ann_generation 列を追加（ANNインデックスの変更の検出用）
+        if !self.has_column("collections", "ann_generation")? {
+            self.conn.execute(
+                "ALTER TABLE collections ADD COLUMN ann_generation INTEGER NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
+        if !self.has_column("documents", "ann_generation")? {
+            self.conn.execute(
+                "ALTER TABLE documents ADD COLUMN ann_generation INTEGER NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
+
         // 設定テーブル
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS settings (
@@ -52,6 +328,37 @@ impl Database {
             [],
         )?;
 
+        // 近似最近傍探索のインデックス（コレクションごと、HnswIndex::to_bytes の形式）
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS ann_indexes (
+                collection_id INTEGER PRIMARY KEY,
+                data BLOB NOT NULL,
+                generation INTEGER,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // 既存のデータベースに generation 列を追加（NULLは保存時の変更世代が不明）
+        if !self.has_column("ann_indexes", "generation")? {
+            self.conn
+                .execute("ALTER TABLE ann_indexes ADD COLUMN generation INTEGER", [])?;
+        }
+
+        // Embeddingキャッシュ（モデルへの入力のハッシュ → 正規化前のf32ベクトル）
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS embedding_cache (
+                input_hash TEXT NOT NULL,
+                model TEXT NOT NULL,
+                dimension INTEGER NOT NULL,
+                embedding BLOB NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (input_hash, model, dimension)
+            )",
+            [],
+        )?;
+
         // インデックス
         self.conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_documents_collection ON documents(collection_id)",
@@ -63,9 +370,101 @@ impl Database {
             [],
         )?;
 
-        // FTS5仮想テーブル（Full-Text Search）
-        // キーワード検索用の転置インデックスを提供
         self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_documents_content_hash
+             ON documents(collection_id, content_hash)",
+            [],
+        )?;
+
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_documents_parent ON documents(parent_id)",
+            [],
+        )?;
+
+        // ALTER TABLE ではUNIQUE制約を追加できないため、一意インデックスで重複を防ぐ
+        // （external_id がNULLのドキュメントは何件あってもよい）
+        self.conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_documents_external_id
+             ON documents(external_id)",
+            [],
+        )?;
+
+        // ドキュメントの追加・Embeddingの更新・削除のたびにコレクションの変更世代を進め、
+        // 追加・更新したドキュメントにはその世代を記録する
+        // （他の接続が読み込んだANNインデックスに、どの変更を反映すればよいか分かるように）
+        self.conn.execute_batch(
+            "CREATE TRIGGER IF NOT EXISTS documents_ann_generation_insert
+             AFTER INSERT ON documents
+             BEGIN
+                 UPDATE collections SET ann_generation = ann_generation + 1
+                 WHERE id = NEW.collection_id;
+                 UPDATE documents SET ann_generation =
+                     (SELECT ann_generation FROM collections WHERE id = NEW.collection_id)
+                 WHERE id = NEW.id;
+             END;
+
+             CREATE TRIGGER IF NOT EXISTS documents_ann_generation_update
+             AFTER UPDATE OF embedding, collection_id ON documents
+             BEGIN
+                 UPDATE collections SET ann_generation = ann_generation + 1
+                 WHERE id IN (OLD.collection_id, NEW.collection_id);
+                 UPDATE documents SET ann_generation =
+                     (SELECT ann_generation FROM collections WHERE id = NEW.collection_id)
+                 WHERE id = NEW.id;
+             END;
+
+             CREATE TRIGGER IF NOT EXISTS documents_ann_generation_delete
+             AFTER DELETE ON documents
+             BEGIN
+                 UPDATE collections SET ann_generation = ann_generation + 1
+                 WHERE id = OLD.collection_id;
+             END;",
+        )?;
+
+        // LIKE検索向けの言語のドキュメントが追加されたら記録する
+        // （どの経路でメタデータを書き込んでも記録されるよう、トリガーで行う）
+        let mark_like_lang = format!(
+            "INSERT INTO settings (key, value) VALUES ('{}', '1')
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            LIKE_LANG_DOCUMENTS_SETTING
+        );
+        self.conn.execute_batch(&format!(
+            "CREATE TRIGGER IF NOT EXISTS documents_like_lang_insert
+             AFTER INSERT ON documents WHEN {cond}
+             BEGIN {mark}; END;
+
+             CREATE TRIGGER IF NOT EXISTS documents_like_lang_update
+             AFTER UPDATE OF metadata ON documents WHEN {cond}
+             BEGIN {mark}; END;",
+            cond = like_lang_sql("NEW.metadata"),
+            mark = mark_like_lang,
+        ))?;
+
+        Ok(())
+    }
+
+    /// テーブルに列があるかどうか
+    fn has_column(&self, table: &str, column: &str) -> Result<bool> {
+        Ok(self
+            .conn
+            .prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+            .exists(params![table, column])?)
+    }
+
+    /// FTS5仮想テーブル（Full-Text Search）を作成
+    ///
+    /// キーワード検索用の転置インデックスを提供する。SQLiteがFTS5に対応していない
+    /// 場合（システムのSQLiteや `fts5` オプションなしの静的ビルド）はエラーにせず、
+    /// 警告を出して `fts_available` をfalseのままにする
+    /// （セマンティック検索とLIKEによるキーワード検索は使える）
+    fn init_fts(&mut self) -> Result<()> {
+        let existed: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'documents_fts')",
+            [],
+            |row| row.get(0),
+        )?;
+
+        let created = self.conn.execute(
             "CREATE VIRTUAL TABLE IF NOT EXISTS documents_fts USING fts5(
                 document_id UNINDEXED,  -- ドキュメントIDは検索対象外（JOINキーとして使用）
                 content,                -- 検索対象のテキストカラム
@@ -76,6 +475,63 @@ impl Database {
             // - remove_diacritics 2: アクセント記号を除去してマッチング精度を向上
             // 注意: CJK言語（日本語・中国語・韓国語）の分割は不完全
             [],
+        );
+        match created {
+            Ok(_) => {}
+            Err(e) if e.to_string().contains("no such module") => {
+                warn_fts_unavailable();
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        }
+        self.fts_available = true;
+
+        // FTS5なしの環境で作成したデータベースには、既存ドキュメントのインデックスがない
+        if !existed {
+            let has_documents: bool =
+                self.conn
+                    .query_row("SELECT EXISTS(SELECT 1 FROM documents)", [], |row| row.get(0))?;
+            if has_documents {
+                self.rebuild_fts_index()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// content_hash 列がない頃に追加されたドキュメントのハッシュを計算して保存
+    fn backfill_content_hashes(&self) -> Result<()> {
+        self.transaction(|| {
+            let mut select = self
+                .conn
+                .prepare("SELECT id, content FROM documents WHERE content_hash IS NULL")?;
+            let mut update = self
+                .conn
+                .prepare("UPDATE documents SET content_hash = ?1 WHERE id = ?2")?;
+
+            let rows = select
+                .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            for (id, content) in rows {
+                update.execute(params![content_hash(&content), id])?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// `parent_id` 列を追加する前に作成したチャンクの親ドキュメントIDを補完
+    ///
+    /// 以前はチャンクのメタデータの `parent_id` / `chunk_index` で対応付けていたため、
+    /// 両方のキーを持ち、`parent_id` が存在するドキュメントを指すものだけを移行する
+    fn backfill_chunk_parents(&self) -> Result<()> {
+        self.conn.execute(
+            "UPDATE documents SET parent_id = json_extract(metadata, '$.parent_id')
+             WHERE json_valid(metadata)
+               AND json_type(metadata, '$.parent_id') = 'integer'
+               AND json_type(metadata, '$.chunk_index') = 'integer'
+               AND json_extract(metadata, '$.parent_id') IN (SELECT id FROM documents)",
+            [],
         )?;
 
         Ok(())
@@ -84,6 +540,7 @@ impl Database {
     // コレクション管理
 
     pub fn create_collection(&self, name: &str, description: Option<&str>) -> Result<i64> {
+        self.ensure_writable()?;
         self.conn.execute(
             "INSERT INTO collections (name, description) VALUES (?1, ?2)",
             params![name, description],
@@ -91,61 +548,78 @@ impl Database {
         Ok(self.conn.last_insert_rowid())
     }
 
+    /// Embeddingモデルを指定してコレクションを作成（Noneの場合はインスタンスのモデルを使う）
+    pub fn create_collection_with_model(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        model: Option<&str>,
+    ) -> Result<i64> {
+        self.ensure_writable()?;
+        self.conn.execute(
+            "INSERT INTO collections (name, description, embedding_model) VALUES (?1, ?2, ?3)",
+            params![name, description, model],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Embeddingモデルを指定したコレクションのIDとモデル名
+    pub fn get_collection_models(&self) -> Result<HashMap<i64, String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, embedding_model FROM collections WHERE embedding_model IS NOT NULL",
+        )?;
+        let models = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<HashMap<_, _>, _>>()?;
+
+        Ok(models)
+    }
+
     pub fn get_collection(&self, name: &str) -> Result<Collection> {
         let mut stmt = self.conn.prepare(
             "SELECT c.id, c.name, c.description,
                     COUNT(d.id) as document_count,
-                    c.created_at, c.updated_at
+                    c.created_at, c.updated_at, c.embedding_model
              FROM collections c
              LEFT JOIN documents d ON c.id = d.collection_id
              WHERE c.name = ?1
              GROUP BY c.id",
         )?;
 
-        let collection = stmt.query_row(params![name], |row| {
-            Ok(Collection::new(
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-                row.get(4)?,
-                row.get(5)?,
-            ))
-        })?;
-
-        Ok(collection)
+        match stmt.query_row(params![name], collection_from_row) {
+            Ok(collection) => Ok(collection),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(Error::CollectionNotFound(format!("Collection '{}' not found", name)))
+            }
+            Err(e) => Err(e.into()),
+        }
     }
 
     pub fn get_collection_by_id(&self, id: i64) -> Result<Collection> {
         let mut stmt = self.conn.prepare(
             "SELECT c.id, c.name, c.description,
                     COUNT(d.id) as document_count,
-                    c.created_at, c.updated_at
+                    c.created_at, c.updated_at, c.embedding_model
              FROM collections c
              LEFT JOIN documents d ON c.id = d.collection_id
              WHERE c.id = ?1
              GROUP BY c.id",
         )?;
 
-        let collection = stmt.query_row(params![id], |row| {
-            Ok(Collection::new(
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-                row.get(4)?,
-                row.get(5)?,
-            ))
-        })?;
-
-        Ok(collection)
+        match stmt.query_row(params![id], collection_from_row) {
+            Ok(collection) => Ok(collection),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(Error::CollectionNotFound(format!("Collection {} not found", id)))
+            }
+            Err(e) => Err(e.into()),
+        }
     }
 
     pub fn list_collections(&self) -> Result<Vec<Collection>> {
         let mut stmt = self.conn.prepare(
             "SELECT c.id, c.name, c.description,
                     COUNT(d.id) as document_count,
-                    c.created_at, c.updated_at
+                    c.created_at, c.updated_at, c.embedding_model
              FROM collections c
              LEFT JOIN documents d ON c.id = d.collection_id
              GROUP BY c.id
@@ -153,146 +627,795 @@ impl Database {
         )?;
 
         let collections = stmt
-            .query_map([], |row| {
-                Ok(Collection::new(
-                    row.get(0)?,
-                    row.get(1)?,
-                    row.get(2)?,
-                    row.get(3)?,
-                    row.get(4)?,
-                    row.get(5)?,
-                ))
-            })?
+            .query_map([], collection_from_row)?
             .collect::<std::result::Result<Vec<_>, _>>()?;
 
         Ok(collections)
     }
 
+    /// キーワード検索（FTS）の対象にするメタデータのフィールド名を取得（未設定の場合は空）
+    pub fn get_indexed_metadata_fields(&self, collection_id: i64) -> Result<Vec<String>> {
+        let fields: Option<String> = self.conn.query_row(
+            "SELECT indexed_metadata_fields FROM collections WHERE id = ?1",
+            params![collection_id],
+            |row| row.get(0),
+        )?;
+
+        Ok(match fields {
+            Some(json) => serde_json::from_str(&json)?,
+            None => Vec::new(),
+        })
+    }
+
+    /// キーワード検索（FTS）の対象にするメタデータのフィールド名を設定し、
+    /// コレクション内の既存ドキュメントのFTSインデックスを作り直す
+    ///
+    /// 空の場合は本文のみを対象にする（デフォルト）
+    pub fn set_indexed_metadata_fields(&self, collection_id: i64, fields: &[String]) -> Result<()> {
+        self.ensure_writable()?;
+
+        let fields_json = if fields.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(fields)?)
+        };
+
+        self.transaction(|| {
+            self.conn.execute(
+                "UPDATE collections
+                 SET indexed_metadata_fields = ?1, updated_at = CURRENT_TIMESTAMP
+                 WHERE id = ?2",
+                params![fields_json, collection_id],
+            )?;
+            self.reindex_collection_fts(collection_id)
+        })
+    }
+
     pub fn delete_collection(&self, name: &str) -> Result<bool> {
-        let rows_affected = self
-            .conn
-            .execute("DELETE FROM collections WHERE name = ?1", params![name])?;
-        Ok(rows_affected > 0)
+        self.ensure_writable()?;
+        self.transaction(|| {
+            self.conn.execute(
+                "DELETE FROM ann_indexes
+                 WHERE collection_id IN (SELECT id FROM collections WHERE name = ?1)",
+                params![name],
+            )?;
+            // 外部キー制約（ON DELETE CASCADE）が無効な接続でも残らないよう、
+            // ドキュメントとそのFTSの行は明示的に削除する
+            if self.fts_available {
+                self.conn.execute(
+                    "DELETE FROM documents_fts
+                     WHERE document_id IN (
+                         SELECT d.id FROM documents d
+                         JOIN collections c ON d.collection_id = c.id
+                         WHERE c.name = ?1
+                     )",
+                    params![name],
+                )?;
+            }
+            self.conn.execute(
+                "DELETE FROM documents
+                 WHERE collection_id IN (SELECT id FROM collections WHERE name = ?1)",
+                params![name],
+            )?;
+            let rows_affected = self
+                .conn
+                .execute("DELETE FROM collections WHERE name = ?1", params![name])?;
+            Ok(rows_affected > 0)
+        })
     }
 
-    // ドキュメント管理
+    // 近似最近傍探索（ANN）のインデックス
 
-    pub fn add_document(
-        &self,
-        collection_id: i64,
-        content: &str,
-        embedding: &[f32],
-        metadata: Option<&serde_json::Value>,
-    ) -> Result<i64> {
-        let embedding_bytes = embedding
-            .iter()
-            .flat_map(|f| f.to_le_bytes())
-            .collect::<Vec<u8>>();
+    /// 保存済みのANNインデックスと、保存時のコレクションの変更世代（ない場合はNone）
+    ///
+    /// 読み取り専用で開いた、ANNインデックスに対応する前のデータベースにはテーブルがないため、
+    /// その場合もNoneを返す。変更世代を記録する前に保存したインデックスの世代はNone
+    pub fn load_ann_index(&self, collection_id: i64) -> Result<Option<(Vec<u8>, Option<i64>)>> {
+        let has_table: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'ann_indexes')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_table {
+            return Ok(None);
+        }
 
-        let metadata_json = metadata.map(|m| serde_json::to_string(m)).transpose()?;
+        let query = if self.has_column("ann_indexes", "generation")? {
+            "SELECT data, generation FROM ann_indexes WHERE collection_id = ?1"
+        } else {
+            "SELECT data, NULL FROM ann_indexes WHERE collection_id = ?1"
+        };
+        let mut stmt = self.conn.prepare(query)?;
+        let mut rows = stmt.query_map(params![collection_id], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        Ok(rows.next().transpose()?)
+    }
 
-        self.conn.execute(
-            "INSERT INTO documents (collection_id, content, embedding, metadata)
-             VALUES (?1, ?2, ?3, ?4)",
-            params![collection_id, content, embedding_bytes, metadata_json],
+    /// 設定値を取得（未設定の場合はNone）
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        // 読み取り専用で開いた古いデータベースには設定テーブルがないことがある
+        let has_table: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'settings')",
+            [],
+            |row| row.get(0),
         )?;
+        if !has_table {
+            return Ok(None);
+        }
 
-        let document_id = self.conn.last_insert_rowid();
+        let mut stmt = self
+            .conn
+            .prepare("SELECT value FROM settings WHERE key = ?1")?;
+        let mut rows = stmt.query_map(params![key], |row| row.get(0))?;
+        Ok(rows.next().transpose()?.flatten())
+    }
 
-        // FTSテーブルにも挿入（キーワード検索用のインデックスを構築）
-        // documentsテーブルとdocuments_ftsテーブルの同期を保つ
+    /// 設定値を保存（既にあれば置き換える）
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        self.ensure_writable()?;
         self.conn.execute(
-            "INSERT INTO documents_fts (document_id, content) VALUES (?1, ?2)",
-            params![document_id, content],
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
         )?;
+        Ok(())
+    }
 
-        Ok(document_id)
+    /// ANNインデックスを、反映済みのコレクションの変更世代とともに保存（既にあれば置き換える）
+    pub fn save_ann_index(
+        &self,
+        collection_id: i64,
+        data: &[u8],
+        generation: Option<i64>,
+    ) -> Result<()> {
+        self.ensure_writable()?;
+        self.conn.execute(
+            "INSERT INTO ann_indexes (collection_id, data, generation) VALUES (?1, ?2, ?3)
+             ON CONFLICT(collection_id) DO UPDATE
+             SET data = excluded.data, generation = excluded.generation,
+                 updated_at = CURRENT_TIMESTAMP",
+            params![collection_id, data, generation],
+        )?;
+        Ok(())
     }
 
-    pub fn get_document(&self, document_id: i64) -> Result<Document> {
-        let mut stmt = self.conn.prepare(
-            "SELECT d.id, d.collection_id, c.name, d.content, d.metadata,
-                    d.created_at, d.updated_at
-             FROM documents d
-             JOIN collections c ON d.collection_id = c.id
-             WHERE d.id = ?1",
+    /// ANNインデックスを削除（削除した場合はtrue）
+    pub fn delete_ann_index(&self, collection_id: i64) -> Result<bool> {
+        self.ensure_writable()?;
+        let rows_affected = self.conn.execute(
+            "DELETE FROM ann_indexes WHERE collection_id = ?1",
+            params![collection_id],
         )?;
+        Ok(rows_affected > 0)
+    }
 
-        let document = stmt.query_row(params![document_id], |row| {
-            let metadata_str: Option<String> = row.get(4)?;
-            let metadata = metadata_str
-                .map(|s| serde_json::from_str(&s))
-                .transpose()
-                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    // Embeddingキャッシュ
 
-            Ok(Document::new(
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-                metadata,
-                row.get(5)?,
-                row.get(6)?,
-            ))
-        })?;
+    /// キャッシュ済みのEmbedding（入力のハッシュ → ベクトル、キャッシュにないものは含めない）
+    ///
+    /// モデル名と次元数が一致するものだけを返すため、モデルを切り替えると使われなくなる。
+    /// 読み取り専用で開いた、キャッシュに対応する前のデータベースでは常に空
+    pub fn get_cached_embeddings(
+        &self,
+        model: &str,
+        dimension: usize,
+        input_hashes: &[String],
+    ) -> Result<HashMap<String, Vec<f32>>> {
+        let has_table: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'embedding_cache')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_table {
+            return Ok(HashMap::new());
+        }
 
-        Ok(document)
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT embedding FROM embedding_cache
+             WHERE input_hash = ?1 AND model = ?2 AND dimension = ?3",
+        )?;
+        let mut cached = HashMap::new();
+        for hash in input_hashes {
+            let mut rows = stmt.query(params![hash, model, dimension as i64])?;
+            if let Some(row) = rows.next()? {
+                let bytes: Vec<u8> = row.get(0)?;
+                cached.insert(
+                    hash.clone(),
+                    embedding_from_bytes(&bytes, EmbeddingFormat::F32),
+                );
+            }
+        }
+        Ok(cached)
     }
 
-    pub fn list_documents(
+    /// Embeddingをキャッシュに保存（同じキーがあれば置き換える）
+    pub fn put_cached_embeddings(
         &self,
-        collection_id: Option<i64>,
-        limit: i64,
-        offset: i64,
-    ) -> Result<Vec<Document>> {
-        let query = if let Some(cid) = collection_id {
-            format!(
-                "SELECT d.id, d.collection_id, c.name, d.content, d.metadata,
-                        d.created_at, d.updated_at
-                 FROM documents d
-                 JOIN collections c ON d.collection_id = c.id
-                 WHERE d.collection_id = {}
-                 ORDER BY d.created_at DESC
-                 LIMIT {} OFFSET {}",
-                cid, limit, offset
-            )
-        } else {
-            format!(
-                "SELECT d.id, d.collection_id, c.name, d.content, d.metadata,
-                        d.created_at, d.updated_at
-                 FROM documents d
-                 JOIN collections c ON d.collection_id = c.id
-                 ORDER BY d.created_at DESC
-                 LIMIT {} OFFSET {}",
-                limit, offset
-            )
-        };
+        model: &str,
+        dimension: usize,
+        entries: &[(String, Vec<f32>)],
+    ) -> Result<()> {
+        self.ensure_writable()?;
+        let mut stmt = self.conn.prepare_cached(
+            "INSERT OR REPLACE INTO embedding_cache (input_hash, model, dimension, embedding)
+             VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (hash, embedding) in entries {
+            let bytes: Vec<u8> = embedding.iter().flat_map(|f| f.to_le_bytes()).collect();
+            stmt.execute(params![hash, model, dimension as i64, bytes])?;
+        }
+        Ok(())
+    }
 
-        let mut stmt = self.conn.prepare(&query)?;
+    /// Embeddingキャッシュを空にして、削除した件数を返す
+    pub fn clear_embedding_cache(&self) -> Result<usize> {
+        self.ensure_writable()?;
+        Ok(self.conn.execute("DELETE FROM embedding_cache", [])?)
+    }
 
-        let documents = stmt
-            .query_map([], |row| {
-                let metadata_str: Option<String> = row.get(4)?;
-                let metadata = metadata_str
-                    .map(|s| serde_json::from_str(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
-
-                Ok(Document::new(
-                    row.get(0)?,
-                    row.get(1)?,
-                    row.get(2)?,
-                    row.get(3)?,
-                    metadata,
-                    row.get(5)?,
-                    row.get(6)?,
+    /// コレクションのドキュメントID
+    pub fn collection_document_ids(&self, collection_id: i64) -> Result<Vec<i64>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id FROM documents WHERE collection_id = ?1")?;
+        let ids = stmt
+            .query_map(params![collection_id], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<i64>, _>>()?;
+        Ok(ids)
+    }
+
+    /// コレクションの変更世代（ドキュメントの追加・Embeddingの更新・削除のたびに増える）
+    ///
+    /// 読み取り専用で開いた、変更世代に対応する前のデータベースではNone
+    pub fn collection_generation(&self, collection_id: i64) -> Result<Option<i64>> {
+        if !self.has_column("collections", "ann_generation")? {
+            return Ok(None);
+        }
+        let mut stmt = self
+            .conn
+            .prepare("SELECT ann_generation FROM collections WHERE id = ?1")?;
+        let mut rows = stmt.query_map(params![collection_id], |row| row.get(0))?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// 変更世代 `since` より後に追加・Embeddingを更新したドキュメントのID
+    pub fn changed_document_ids(&self, collection_id: i64, since: i64) -> Result<Vec<i64>> {
+        if !self.has_column("documents", "ann_generation")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM documents WHERE collection_id = ?1 AND ann_generation > ?2",
+        )?;
+        let ids = stmt
+            .query_map(params![collection_id, since], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<i64>, _>>()?;
+        Ok(ids)
+    }
+
+    /// LIKE検索向けの言語のドキュメントがあるかどうかを記録する（未記録の場合のみ）
+    ///
+    /// 以降はトリガーで記録されるため、記録する前のデータベースを最初に開いたときに1回だけ調べる
+    fn init_like_lang_documents(&self) -> Result<()> {
+        if self.get_setting(LIKE_LANG_DOCUMENTS_SETTING)?.is_some() {
+            return Ok(());
+        }
+        let exists: bool = self.conn.query_row(
+            &format!(
+                "SELECT EXISTS(SELECT 1 FROM documents d WHERE {})",
+                like_lang_sql("d.metadata")
+            ),
+            [],
+            |row| row.get(0),
+        )?;
+        self.set_setting(LIKE_LANG_DOCUMENTS_SETTING, if exists { "1" } else { "0" })
+    }
+
+    /// キーワード検索でLIKE検索向けの言語のドキュメントを振り分ける必要があるかどうか
+    ///
+    /// そのようなドキュメントが1件も追加されていなければfalse（振り分けのためのLIKEによる
+    /// 全件走査を省く）。記録する前のデータベースを読み取り専用で開いた場合は常にtrue
+    fn routes_like_langs(&self) -> Result<bool> {
+        Ok(self.get_setting(LIKE_LANG_DOCUMENTS_SETTING)?.as_deref() != Some("0"))
+    }
+
+    /// 削除済みのコレクションに属するドキュメント（とそのFTSの行）を削除
+    ///
+    /// 外部キー制約を有効にする前のバージョンでは、コレクションを削除してもドキュメントが
+    /// 残っていたため、マイグレーションとして最初に開いたときに1回だけ取り除く
+    /// （以降は外部キー制約で削除されるため、実行済みであることを設定に記録する）
+    fn remove_orphaned_documents(&self) -> Result<()> {
+        if self.get_setting(ORPHANS_REMOVED_SETTING)?.is_some() {
+            return Ok(());
+        }
+        self.transaction(|| {
+            if self.fts_available {
+                self.conn.execute(
+                    "DELETE FROM documents_fts
+                     WHERE document_id IN (
+                         SELECT id FROM documents
+                         WHERE collection_id NOT IN (SELECT id FROM collections)
+                     )",
+                    [],
+                )?;
+            }
+            self.conn.execute(
+                "DELETE FROM documents WHERE collection_id NOT IN (SELECT id FROM collections)",
+                [],
+            )?;
+            self.set_setting(ORPHANS_REMOVED_SETTING, "1")
+        })
+    }
+
+    // ドキュメント管理
+
+    /// embedding_norm 列がない頃に追加されたドキュメントのノルムを計算して保存
+    fn backfill_embedding_norms(&self) -> Result<()> {
+        self.transaction(|| {
+            let mut select = self.conn.prepare(
+                "SELECT id, embedding, embedding_format FROM documents
+                 WHERE embedding_norm IS NULL",
+            )?;
+            let mut update = self
+                .conn
+                .prepare("UPDATE documents SET embedding_norm = ?1 WHERE id = ?2")?;
+
+            let rows = select
+                .query_map([], |row| Ok((row.get::<_, i64>(0)?, embedding_from_row(row, 1, 2)?)))?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            for (id, embedding) in rows {
+                let norm = vector_norm(&embedding);
+                update.execute(params![norm as f64, id])?;
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn add_document(
+        &self,
+        collection_id: i64,
+        content: &str,
+        embedding: &[f32],
+        metadata: Option<&serde_json::Value>,
+    ) -> Result<i64> {
+        self.insert_document(collection_id, content, embedding, metadata, None)
+    }
+
+    /// 外部ID（呼び出し側が管理するID）付きでドキュメントを追加
+    ///
+    /// 外部IDは全コレクションで一意。既に使われている場合はエラーになる
+    pub fn add_document_with_external_id(
+        &self,
+        collection_id: i64,
+        content: &str,
+        embedding: &[f32],
+        metadata: Option<&serde_json::Value>,
+        external_id: &str,
+    ) -> Result<i64> {
+        self.insert_document(collection_id, content, embedding, metadata, Some(external_id))
+    }
+
+    fn insert_document(
+        &self,
+        collection_id: i64,
+        content: &str,
+        embedding: &[f32],
+        metadata: Option<&serde_json::Value>,
+        external_id: Option<&str>,
+    ) -> Result<i64> {
+        self.ensure_writable()?;
+
+        let format = self.embedding_format.get();
+        let (embedding_bytes, norm) = encode_embedding(embedding, format);
+
+        let metadata_json = metadata.map(|m| serde_json::to_string(m)).transpose()?;
+
+        self.conn.execute(
+            "INSERT INTO documents
+                 (collection_id, content, embedding, metadata, content_hash, embedding_norm,
+                  embedding_format, external_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                collection_id,
+                content,
+                embedding_bytes,
+                metadata_json,
+                content_hash(content),
+                norm as f64,
+                format.as_str(),
+                external_id
+            ],
+        )?;
+
+        let document_id = self.conn.last_insert_rowid();
+
+        // FTSテーブルにも挿入（キーワード検索用のインデックスを構築）
+        // documentsテーブルとdocuments_ftsテーブルの同期を保つ
+        // バルクロード中は end_bulk_load でまとめて再構築するためスキップ
+        if self.fts_available && !self.bulk_loading.get() {
+            let fields = self.get_indexed_metadata_fields(collection_id)?;
+            self.conn.execute(
+                "INSERT INTO documents_fts (document_id, content) VALUES (?1, ?2)",
+                params![document_id, fts_text(content, metadata, &fields)],
+            )?;
+        }
+
+        Ok(document_id)
+    }
+
+    /// 同じコレクション内で内容が同一のドキュメントを検索
+    ///
+    /// 前後の空白と連続する空白の違いは無視して比較する
+    ///
+    /// # 戻り値
+    /// 一致するドキュメントのうち最も古いもののID（なければNone）
+    pub fn find_duplicate(&self, collection_id: i64, content: &str) -> Result<Option<i64>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM documents
+             WHERE collection_id = ?1 AND content_hash = ?2
+             ORDER BY id
+             LIMIT 1",
+        )?;
+
+        let mut rows = stmt.query(params![collection_id, content_hash(content)])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// クロージャ内の操作を1つのトランザクションで実行
+    ///
+    /// クロージャがエラーを返した場合やパニックした場合はロールバックする。
+    /// `BEGIN` ではなく `SAVEPOINT` を使うため入れ子にでき、内側でロールバックした場合は
+    /// 内側の変更のみが取り消される（外側のトランザクションは継続）
+    pub fn transaction<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        self.conn.execute_batch("SAVEPOINT doredore_tx")?;
+
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(Ok(value)) => match self.conn.execute_batch("RELEASE doredore_tx") {
+                Ok(()) => Ok(value),
+                Err(e) => {
+                    // コミットに失敗した（例: データベースがロックされている）場合も取り消す
+                    self.rollback_savepoint();
+                    Err(e.into())
+                }
+            },
+            Ok(Err(e)) => {
+                self.rollback_savepoint();
+                Err(e)
+            }
+            Err(payload) => {
+                self.rollback_savepoint();
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+
+    /// `transaction` のSAVEPOINTまでの変更を取り消して解放
+    fn rollback_savepoint(&self) {
+        // 元のエラー（パニック）を返すことを優先し、ロールバック自体の失敗は無視する
+        let _ = self
+            .conn
+            .execute_batch("ROLLBACK TO doredore_tx; RELEASE doredore_tx");
+    }
+
+    /// バルクロードを開始（FTSインデックスへの挿入を保留）
+    ///
+    /// FTS5は1行ごとにインデックスを更新するため、大量挿入時はまとめて
+    /// 再構築した方が速い。`end_bulk_load` を呼ぶまでに追加したドキュメントは
+    /// キーワード検索（およびハイブリッド検索のキーワード部分）でヒットしない
+    pub fn begin_bulk_load(&self) {
+        self.bulk_loading.set(true);
+    }
+
+    /// バルクロードを終了し、FTSインデックスを再構築
+    pub fn end_bulk_load(&self) -> Result<()> {
+        self.bulk_loading.set(false);
+        self.rebuild_fts_index()
+    }
+
+    /// バルクロード中かどうか
+    pub fn is_bulk_loading(&self) -> bool {
+        self.bulk_loading.get()
+    }
+
+    /// documentsテーブルの内容からFTSインデックスを再構築
+    ///
+    /// FTSインデックスを空にしてから全ドキュメントを1つのトランザクションで再挿入する。
+    /// 外部のツールで `documents` に直接書き込んだ場合など、インデックスとずれたときに使う。
+    /// 件数に比例して時間がかかる
+    pub fn rebuild_fts_index(&self) -> Result<()> {
+        self.ensure_writable()?;
+        if !self.fts_available {
+            return Ok(());
+        }
+        self.transaction(|| {
+            self.conn.execute("DELETE FROM documents_fts", [])?;
+            // メタデータを対象にしないコレクションはSQLだけでまとめて挿入
+            self.conn.execute(
+                "INSERT INTO documents_fts (document_id, content)
+                 SELECT d.id, d.content FROM documents d
+                 JOIN collections c ON d.collection_id = c.id
+                 WHERE c.indexed_metadata_fields IS NULL",
+                [],
+            )?;
+
+            let mut stmt = self
+                .conn
+                .prepare("SELECT id FROM collections WHERE indexed_metadata_fields IS NOT NULL")?;
+            let collection_ids = stmt
+                .query_map([], |row| row.get::<_, i64>(0))?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            for collection_id in collection_ids {
+                self.insert_collection_fts(collection_id)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// コレクション内のドキュメントのFTSインデックスを作り直す
+    fn reindex_collection_fts(&self, collection_id: i64) -> Result<()> {
+        if !self.fts_available {
+            return Ok(());
+        }
+        self.conn.execute(
+            "DELETE FROM documents_fts
+             WHERE document_id IN (SELECT id FROM documents WHERE collection_id = ?1)",
+            params![collection_id],
+        )?;
+        self.insert_collection_fts(collection_id)
+    }
+
+    /// コレクション内の全ドキュメントを（設定したメタデータと合わせて）FTSテーブルに挿入
+    fn insert_collection_fts(&self, collection_id: i64) -> Result<()> {
+        let fields = self.get_indexed_metadata_fields(collection_id)?;
+        let mut select = self
+            .conn
+            .prepare("SELECT id, content, metadata FROM documents WHERE collection_id = ?1")?;
+        let mut insert = self
+            .conn
+            .prepare("INSERT INTO documents_fts (document_id, content) VALUES (?1, ?2)")?;
+
+        let rows = select
+            .query_map(params![collection_id], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
                 ))
             })?
             .collect::<std::result::Result<Vec<_>, _>>()?;
+        for (id, content, metadata) in rows {
+            let metadata = metadata.and_then(|m| serde_json::from_str(&m).ok());
+            insert.execute(params![id, fts_text(&content, metadata.as_ref(), &fields)])?;
+        }
+
+        Ok(())
+    }
+
+    /// 1件のドキュメントのFTSインデックスを現在の本文・メタデータで作り直す
+    fn reindex_document_fts(&self, document_id: i64) -> Result<()> {
+        if !self.fts_available {
+            return Ok(());
+        }
+        let (collection_id, content, metadata) = self.conn.query_row(
+            "SELECT collection_id, content, metadata FROM documents WHERE id = ?1",
+            params![document_id],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            },
+        )?;
+        let metadata = metadata.and_then(|m| serde_json::from_str(&m).ok());
+        let fields = self.get_indexed_metadata_fields(collection_id)?;
+
+        self.conn.execute(
+            "DELETE FROM documents_fts WHERE document_id = ?1",
+            params![document_id],
+        )?;
+        self.conn.execute(
+            "INSERT INTO documents_fts (document_id, content) VALUES (?1, ?2)",
+            params![document_id, fts_text(&content, metadata.as_ref(), &fields)],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_document(&self, document_id: i64) -> Result<Document> {
+        let mut stmt = self.conn.prepare(
+            "SELECT d.id, d.collection_id, c.name, d.content, d.metadata,
+                    d.created_at, d.updated_at, d.external_id
+             FROM documents d
+             JOIN collections c ON d.collection_id = c.id
+             WHERE d.id = ?1",
+        )?;
+
+        match stmt.query_row(params![document_id], document_from_row) {
+            Ok(document) => Ok(document),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Err(Error::DocumentNotFound(document_id)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 指定したドキュメントを一括取得（存在しないIDは含まない）
+    pub fn get_documents(&self, document_ids: &[i64]) -> Result<HashMap<i64, Document>> {
+        if document_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let placeholders = document_ids
+            .iter()
+            .map(|_| "?")
+            .collect::<Vec<_>>()
+            .join(",");
+        let query = format!(
+            "SELECT d.id, d.collection_id, c.name, d.content, d.metadata,
+                    d.created_at, d.updated_at, d.external_id
+             FROM documents d
+             JOIN collections c ON d.collection_id = c.id
+             WHERE d.id IN ({})",
+            placeholders
+        );
+
+        let mut stmt = self.conn.prepare(&query)?;
+        let documents = stmt
+            .query_map(rusqlite::params_from_iter(document_ids), document_from_row)?
+            .map(|document| document.map(|d| (d.id, d)))
+            .collect::<std::result::Result<HashMap<_, _>, _>>()?;
+
+        Ok(documents)
+    }
+
+    /// 外部IDでドキュメントを取得（見つからない場合はNone）
+    pub fn get_document_by_external_id(&self, external_id: &str) -> Result<Option<Document>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT d.id, d.collection_id, c.name, d.content, d.metadata,
+                    d.created_at, d.updated_at, d.external_id
+             FROM documents d
+             JOIN collections c ON d.collection_id = c.id
+             WHERE d.external_id = ?1",
+        )?;
+
+        let mut rows = stmt.query_map(params![external_id], document_from_row)?;
+        Ok(rows.next().transpose()?)
+    }
+
+    pub fn list_documents(
+        &self,
+        collection_id: Option<i64>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Document>> {
+        let query = if let Some(cid) = collection_id {
+            format!(
+                "SELECT d.id, d.collection_id, c.name, d.content, d.metadata,
+                        d.created_at, d.updated_at, d.external_id
+                 FROM documents d
+                 JOIN collections c ON d.collection_id = c.id
+                 WHERE d.collection_id = {}
+                 ORDER BY d.created_at DESC
+                 LIMIT {} OFFSET {}",
+                cid, limit, offset
+            )
+        } else {
+            format!(
+                "SELECT d.id, d.collection_id, c.name, d.content, d.metadata,
+                        d.created_at, d.updated_at, d.external_id
+                 FROM documents d
+                 JOIN collections c ON d.collection_id = c.id
+                 ORDER BY d.created_at DESC
+                 LIMIT {} OFFSET {}",
+                limit, offset
+            )
+        };
+
+        let mut stmt = self.conn.prepare(&query)?;
+
+        let documents = stmt
+            .query_map([], document_from_row)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(documents)
+    }
+
+    /// ドキュメント数（`collection_id` がNoneの場合は全コレクション）
+    pub fn count_documents(&self, collection_id: Option<i64>) -> Result<i64> {
+        let count = self.conn.query_row(
+            "SELECT COUNT(*) FROM documents WHERE ?1 IS NULL OR collection_id = ?1",
+            params![collection_id],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    /// 本文が空（スペース・タブ・改行のみを含む）のドキュメントをID順に取得
+    pub fn find_empty_documents(&self, collection_id: Option<i64>) -> Result<Vec<Document>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT d.id, d.collection_id, c.name, d.content, d.metadata,
+                    d.created_at, d.updated_at, d.external_id
+             FROM documents d
+             JOIN collections c ON d.collection_id = c.id
+             WHERE trim(d.content, ' ' || char(9, 10, 13)) = ''
+               AND (?1 IS NULL OR d.collection_id = ?1)
+             ORDER BY d.id",
+        )?;
+
+        let documents = stmt
+            .query_map(params![collection_id], document_from_row)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
 
         Ok(documents)
     }
 
+    /// 親ドキュメントIDに紐づくチャンクを元テキストの順序（ID順）で取得
+    ///
+    /// チャンクの対応付けは `parent_id` 列に記録されている（親ドキュメント自身も含む）
+    pub fn get_chunks(&self, parent_id: i64) -> Result<Vec<Document>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT d.id, d.collection_id, c.name, d.content, d.metadata,
+                    d.created_at, d.updated_at, d.external_id
+             FROM documents d
+             JOIN collections c ON d.collection_id = c.id
+             WHERE d.parent_id = ?1
+             ORDER BY d.id",
+        )?;
+
+        let documents = stmt
+            .query_map(params![parent_id], document_from_row)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(documents)
+    }
+
+    /// ドキュメントをチャンクとして親ドキュメントに紐づける
+    pub fn set_chunk_parent(&self, document_id: i64, parent_id: i64) -> Result<()> {
+        self.ensure_writable()?;
+
+        self.conn.execute(
+            "UPDATE documents SET parent_id = ?2 WHERE id = ?1",
+            params![document_id, parent_id],
+        )?;
+
+        Ok(())
+    }
+
+    /// 指定したドキュメントのうちチャンクであるものの親ドキュメントID
+    ///
+    /// チャンクでないドキュメントと存在しないIDは結果に含まれない
+    pub fn get_chunk_parents(&self, document_ids: &[i64]) -> Result<HashMap<i64, i64>> {
+        if document_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let placeholders = document_ids
+            .iter()
+            .map(|_| "?")
+            .collect::<Vec<_>>()
+            .join(",");
+        let query = format!(
+            "SELECT id, parent_id FROM documents
+             WHERE parent_id IS NOT NULL AND id IN ({})",
+            placeholders
+        );
+
+        let mut stmt = self.conn.prepare(&query)?;
+        let parents = stmt
+            .query_map(rusqlite::params_from_iter(document_ids), |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<std::result::Result<HashMap<_, _>, _>>()?;
+
+        Ok(parents)
+    }
+
     pub fn update_document(
         &self,
         document_id: i64,
@@ -300,6 +1423,8 @@ impl Database {
         embedding: Option<&[f32]>,
         metadata: Option<&serde_json::Value>,
     ) -> Result<bool> {
+        self.ensure_writable()?;
+
         if content.is_none() && embedding.is_none() && metadata.is_none() {
             return Ok(false);
         }
@@ -310,12 +1435,19 @@ impl Database {
         if let Some(c) = content {
             updates.push("content = ?");
             params_vec.push(Box::new(c.to_string()));
+            updates.push("content_hash = ?");
+            params_vec.push(Box::new(content_hash(c)));
         }
 
         if let Some(e) = embedding {
+            let format = self.embedding_format.get();
+            let (embedding_bytes, norm) = encode_embedding(e, format);
             updates.push("embedding = ?");
-            let embedding_bytes = e.iter().flat_map(|f| f.to_le_bytes()).collect::<Vec<u8>>();
             params_vec.push(Box::new(embedding_bytes));
+            updates.push("embedding_norm = ?");
+            params_vec.push(Box::new(norm as f64));
+            updates.push("embedding_format = ?");
+            params_vec.push(Box::new(format.as_str()));
         }
 
         if let Some(m) = metadata {
@@ -336,33 +1468,193 @@ impl Database {
         let params_refs: Vec<&dyn rusqlite::ToSql> =
             params_vec.iter().map(|b| b.as_ref()).collect();
 
-        let rows_affected = self.conn.execute(&query, params_refs.as_slice())?;
+        self.transaction(|| {
+            let rows_affected = self.conn.execute(&query, params_refs.as_slice())?;
 
-        Ok(rows_affected > 0)
+            // 本文・メタデータが変わった場合はキーワード検索の対象も同じトランザクションで更新する
+            // （FTSの行が欠けていても作り直される。バルクロード中は end_bulk_load でまとめて再構築される）
+            if rows_affected > 0
+                && (content.is_some() || metadata.is_some())
+                && !self.bulk_loading.get()
+            {
+                self.reindex_document_fts(document_id)?;
+            }
+
+            Ok(rows_affected > 0)
+        })
+    }
+
+    /// コレクション内の全ドキュメントのメタデータを一括更新
+    ///
+    /// 単一のUPDATE文で実行するため、全件が更新されるか1件も更新されないかのどちらか
+    ///
+    /// # 引数
+    /// * `collection_id` - 対象コレクションID
+    /// * `patch` - 適用するメタデータ
+    /// * `merge` - trueの場合はJSON Merge Patch（RFC 7396）として既存メタデータにマージ
+    ///   （値が `null` のキーは削除される）、falseの場合は `patch` で置き換える
+    ///
+    /// # 戻り値
+    /// 更新したドキュメント数
+    pub fn update_collection_metadata(
+        &self,
+        collection_id: i64,
+        patch: &serde_json::Value,
+        merge: bool,
+    ) -> Result<usize> {
+        self.ensure_writable()?;
+
+        let patch_json = serde_json::to_string(patch)?;
+
+        let query = if merge {
+            "UPDATE documents
+             SET metadata = json_patch(COALESCE(metadata, '{}'), ?1),
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE collection_id = ?2"
+        } else {
+            "UPDATE documents
+             SET metadata = ?1,
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE collection_id = ?2"
+        };
+
+        self.transaction(|| {
+            let rows_affected = self
+                .conn
+                .execute(query, params![patch_json, collection_id])?;
+
+            // メタデータをキーワード検索の対象にしている場合はFTSインデックスも更新する
+            if !self.get_indexed_metadata_fields(collection_id)?.is_empty()
+                && !self.bulk_loading.get()
+            {
+                self.reindex_collection_fts(collection_id)?;
+            }
+
+            Ok(rows_affected)
+        })
     }
 
     pub fn delete_document(&self, document_id: i64) -> Result<bool> {
-        let rows_affected = self
-            .conn
-            .execute("DELETE FROM documents WHERE id = ?1", params![document_id])?;
-        Ok(rows_affected > 0)
+        self.ensure_writable()?;
+        self.transaction(|| {
+            // FTSインデックスに残すと、キーワード検索のBM25の統計や件数に影響する
+            if self.fts_available {
+                self.conn.execute(
+                    "DELETE FROM documents_fts WHERE document_id = ?1",
+                    params![document_id],
+                )?;
+            }
+            let rows_affected = self
+                .conn
+                .execute("DELETE FROM documents WHERE id = ?1", params![document_id])?;
+            Ok(rows_affected > 0)
+        })
+    }
+
+    /// 外部IDでドキュメントを削除（削除した場合はtrue）
+    pub fn delete_document_by_external_id(&self, external_id: &str) -> Result<bool> {
+        self.ensure_writable()?;
+        self.transaction(|| {
+            if self.fts_available {
+                self.conn.execute(
+                    "DELETE FROM documents_fts
+                     WHERE document_id IN (SELECT id FROM documents WHERE external_id = ?1)",
+                    params![external_id],
+                )?;
+            }
+            let rows_affected = self.conn.execute(
+                "DELETE FROM documents WHERE external_id = ?1",
+                params![external_id],
+            )?;
+            Ok(rows_affected > 0)
+        })
+    }
+
+    /// Embeddingの次元数ごとのドキュメント数を取得 `(次元数, 件数)`（件数の降順）
+    ///
+    /// 次元数はBLOBのバイト数から求める（f32 = 4バイト）
+    pub fn embedding_dimension_counts(&self) -> Result<Vec<(usize, usize)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT CASE WHEN embedding_format = 'int8' THEN length(embedding) - 8
+                         ELSE length(embedding) / 4 END AS dimension,
+                    COUNT(*) AS count
+             FROM documents
+             GROUP BY dimension
+             ORDER BY count DESC, dimension",
+        )?;
+
+        let counts = stmt
+            .query_map([], |row| {
+                let dimension: i64 = row.get(0)?;
+                let count: i64 = row.get(1)?;
+                Ok((dimension as usize, count as usize))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(counts)
+    }
+
+    /// データベースの最適化（大量削除後の領域回収とインデックス整理）
+    ///
+    /// 1. `VACUUM`: 削除済みページを回収してファイルサイズを縮小
+    /// 2. FTS5 `optimize`: 断片化した全文検索インデックスを統合
+    /// 3. `ANALYZE`: クエリプランナー用の統計情報を更新
+    ///
+    /// # 注意
+    /// `VACUUM` はデータベース全体を書き直すため、実行中は排他アクセスが必要で
+    /// 大きなデータベースでは時間がかかる
+    pub fn optimize(&self) -> Result<()> {
+        self.ensure_writable()?;
+        self.conn.execute_batch("VACUUM;")?;
+        self.optimize_indexes()
+    }
+
+    /// インデックスの整理（FTS5 `optimize` + `ANALYZE`）
+    ///
+    /// `optimize` から `VACUUM` を除いたもの。ファイル全体を書き直さないため、
+    /// 稼働中のサーバーで定期的に実行してもキーワード検索の速度を保てる
+    pub fn optimize_indexes(&self) -> Result<()> {
+        self.ensure_writable()?;
+        if self.fts_available {
+            self.conn
+                .execute("INSERT INTO documents_fts(documents_fts) VALUES('optimize')", [])?;
+        }
+        self.conn.execute_batch("ANALYZE;")?;
+        Ok(())
     }
 
     pub fn get_all_documents_with_embeddings(
         &self,
         collection_ids: Option<&[i64]>,
-    ) -> Result<Vec<(i64, String, Vec<f32>, String)>> {
+    ) -> Result<Vec<EmbeddedDocumentRow>> {
+        Ok(self
+            .get_all_documents_with_norms(collection_ids)?
+            .into_iter()
+            .map(|(row, _)| row)
+            .collect())
+    }
+
+    /// Embedding付きのドキュメントを、保存済みのベクトルのノルムと一緒に取得
+    ///
+    /// ノルムは追加・更新時に計算して保存しているため、検索時に全次元を走査せずに
+    /// コサイン類似度の上限を見積もれる（[`BoundedCosine`](crate::core::search::BoundedCosine)）
+    pub fn get_all_documents_with_norms(
+        &self,
+        collection_ids: Option<&[i64]>,
+    ) -> Result<Vec<(EmbeddedDocumentRow, f32)>> {
         let query = if let Some(cids) = collection_ids {
             let placeholders = cids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
             format!(
-                "SELECT d.id, d.content, d.embedding, c.name
+                "SELECT d.id, d.content, d.embedding, c.name, d.metadata, d.embedding_norm,
+                        d.embedding_format
                  FROM documents d
                  JOIN collections c ON d.collection_id = c.id
                  WHERE d.collection_id IN ({})",
                 placeholders
             )
         } else {
-            "SELECT d.id, d.content, d.embedding, c.name
+            "SELECT d.id, d.content, d.embedding, c.name, d.metadata, d.embedding_norm,
+                    d.embedding_format
              FROM documents d
              JOIN collections c ON d.collection_id = c.id"
                 .to_string()
@@ -370,74 +1662,389 @@ impl Database {
 
         let mut stmt = self.conn.prepare(&query)?;
 
-        let row_mapper = |row: &rusqlite::Row| -> rusqlite::Result<(i64, String, Vec<f32>, String)> {
+        let row_mapper = |row: &rusqlite::Row| -> rusqlite::Result<(EmbeddedDocumentRow, f32)> {
             let id: i64 = row.get(0)?;
             let content: String = row.get(1)?;
-            let embedding_bytes: Vec<u8> = row.get(2)?;
+            let embedding = embedding_from_row(row, 2, 6)?;
             let collection_name: String = row.get(3)?;
+            let metadata = metadata_from_row(row, 4)?;
+            let stored_norm: Option<f64> = row.get(5)?;
+
+            let norm = stored_norm.map_or_else(|| vector_norm(&embedding), |n| n as f32);
+
+            Ok(((id, content, embedding, collection_name, metadata), norm))
+        };
+
+        let results = if let Some(cids) = collection_ids {
+            let params_refs: Vec<&dyn rusqlite::ToSql> =
+                cids.iter().map(|c| c as &dyn rusqlite::ToSql).collect();
+            stmt.query_map(params_refs.as_slice(), row_mapper)?
+        } else {
+            stmt.query_map([], row_mapper)?
+        };
+
+        Ok(results.collect::<std::result::Result<Vec<_>, _>>()?)
+    }
+
+    /// 指定したドキュメントのEmbeddingを取得
+    ///
+    /// 存在しないIDは結果に含まれない
+    pub fn get_embeddings(&self, document_ids: &[i64]) -> Result<HashMap<i64, Vec<f32>>> {
+        if document_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let placeholders = document_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+        let query = format!(
+            "SELECT id, embedding, embedding_format FROM documents WHERE id IN ({})",
+            placeholders
+        );
+
+        let mut stmt = self.conn.prepare(&query)?;
+        let embeddings = stmt
+            .query_map(rusqlite::params_from_iter(document_ids), |row| {
+                Ok((row.get(0)?, embedding_from_row(row, 1, 2)?))
+            })?
+            .collect::<std::result::Result<HashMap<_, _>, _>>()?;
+
+        Ok(embeddings)
+    }
+
+    /// 指定したドキュメントのEmbeddingとそのノルムを一括取得（存在しないIDは含まない）
+    ///
+    /// ノルムは保存済みの値を使い、未保存の場合のみ計算する
+    pub fn get_embeddings_with_norms(
+        &self,
+        document_ids: &[i64],
+    ) -> Result<HashMap<i64, (Vec<f32>, f32)>> {
+        if document_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let placeholders = document_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+        let query = format!(
+            "SELECT id, embedding, embedding_norm, embedding_format
+             FROM documents WHERE id IN ({})",
+            placeholders
+        );
+
+        let mut stmt = self.conn.prepare(&query)?;
+        let embeddings = stmt
+            .query_map(rusqlite::params_from_iter(document_ids), |row| {
+                let embedding = embedding_from_row(row, 1, 3)?;
+                let stored_norm: Option<f64> = row.get(2)?;
+                let norm = stored_norm.map_or_else(|| vector_norm(&embedding), |n| n as f32);
+                Ok((row.get(0)?, (embedding, norm)))
+            })?
+            .collect::<std::result::Result<HashMap<_, _>, _>>()?;
+
+        Ok(embeddings)
+    }
+
+    /// メタデータ条件に一致するドキュメントIDを取得
+    ///
+    /// # 引数
+    /// * `collection_ids` - 対象のコレクションID（Noneの場合は全コレクション）
+    /// * `filter` - メタデータ条件（すべてAND）
+    pub fn filter_document_ids(
+        &self,
+        collection_ids: Option<&[i64]>,
+        filter: &MetadataFilter,
+    ) -> Result<HashSet<i64>> {
+        let (collection_sql, collection_params) = document_filter_clause(collection_ids, &[]);
+        let (filter_sql, filter_params) = filter.to_sql();
+
+        let query = format!(
+            "SELECT d.id FROM documents d WHERE d.metadata IS NOT NULL{}{}",
+            collection_sql, filter_sql
+        );
+
+        let mut params = collection_params;
+        params.extend(filter_params.iter().map(|p| p.as_ref()));
+
+        let mut stmt = self.conn.prepare(&query)?;
+        let ids = stmt
+            .query_map(params.as_slice(), |row| row.get(0))?
+            .collect::<std::result::Result<HashSet<i64>, _>>()?;
+
+        Ok(ids)
+    }
+
+    /// キーワード検索（FTS5 + LIKE検索の2段階フォールバック）
+    ///
+    /// 英語と日本語の両方に対応した柔軟なキーワード検索を実装
+    ///
+    /// # 検索戦略
+    /// 1. **第1段階: FTS5検索（高速・高精度）**
+    ///    - SQLiteのFull-Text Search 5を使用
+    ///    - BM25アルゴリズムでランキング
+    ///    - 英語の単語分割に最適化
+    ///    - 速度: O(log n)（インデックス使用）
+    ///
+    /// 2. **第2段階: LIKE検索（フォールバック）**
+    ///    - FTS5で結果がない場合に自動的に実行
+    ///    - 日本語やCJK言語に対応
+    ///    - パターンマッチング: `%キーワード%`（空白で区切った語ごと、すべての語を含むもの）
+    ///    - 速度: O(n)（全件スキャン）
+    ///
+    /// SQLiteがFTS5に対応していない場合（[`Database::fts_available`]）は、
+    /// 第1段階を省略して最初からLIKE検索を行う
+    ///
+    /// # 言語による振り分け
+    /// メタデータの `lang` が [`crate::core::language::LIKE_SEARCH_LANGS`] の言語
+    /// （日本語など）のドキュメントは、FTS5の対象から外して常にLIKE検索で照合し、
+    /// FTS5の結果とスコア順にマージする。英語と日本語が混在するコレクションでも、
+    /// 英語の結果があるために日本語のドキュメントが検索されない、ということがなくなる
+    /// （この照合のため、FTS5に結果があってもLIKEによる全件走査が1回行われる。
+    /// そのような言語のドキュメントが1件もなければ走査しない）
+    ///
+    /// # 引数
+    /// * `query` - 検索キーワード
+    /// * `collection_ids` - 検索対象のコレクションID（Noneの場合は全コレクション）
+    /// * `exclude_ids` - 検索対象から除外するドキュメントID
+    ///
+    /// # 戻り値
+    /// `Vec<(document_id, content, score, collection_name)>`
+    /// * FTS5の場合: スコアはBM25スコア（負の値、小さいほど良い）
+    /// * LIKE検索の場合: キーワードの出現回数から計算した値（BM25と同じ向きの負の値）
+    /// * 両方の結果をマージする場合: それぞれの最良のスコアが-10になるようスケーリングした値
+    ///   （[`scale_to_best_score`] を参照）
+    pub fn keyword_search(
+        &self,
+        query: &str,
+        collection_ids: Option<&[i64]>,
+        exclude_ids: &[i64],
+    ) -> Result<Vec<ScoredDocumentRow>> {
+        self.keyword_search_matching(query, collection_ids, exclude_ids, KeywordMatch::All)
+    }
+
+    /// 複数の語の組み合わせ方を指定したキーワード検索
+    ///
+    /// `keyword_search` と同じ検索戦略で、空白で区切った語を `keyword_match` に応じて
+    /// AND（すべての語を含む）またはOR（いずれかの語を含む）で照合する。
+    /// ダブルクォートで囲んだ部分は分割せず1つの語として扱う（[`keyword_terms`] を参照）
+    pub fn keyword_search_matching(
+        &self,
+        query: &str,
+        collection_ids: Option<&[i64]>,
+        exclude_ids: &[i64],
+        keyword_match: KeywordMatch,
+    ) -> Result<Vec<ScoredDocumentRow>> {
+        // FTS5が使えない環境では最初からLIKE検索のみ
+        if !self.fts_available {
+            self.last_keyword_backend.set(Some(KeywordBackend::Like));
+            return self.keyword_search_like(
+                query,
+                collection_ids,
+                exclude_ids,
+                keyword_match,
+                false,
+            );
+        }
+
+        // まずFTS5で検索を試みる（英語などに最適）
+        // クエリは fts_match_query で引用するため、ここでのエラーは keyword_count と同じく返す
+        let route_langs = self.routes_like_langs()?;
+        let fts_results = self.keyword_search_fts5(
+            query,
+            collection_ids,
+            exclude_ids,
+            keyword_match,
+            route_langs,
+        )?;
+
+        // LIKE検索向けの言語のドキュメントは常にLIKEで照合
+        let routed_results = if route_langs {
+            self.keyword_search_like(query, collection_ids, exclude_ids, keyword_match, true)?
+        } else {
+            Vec::new()
+        };
+
+        if !fts_results.is_empty() || !routed_results.is_empty() {
+            self.last_keyword_backend.set(Some(
+                match (fts_results.is_empty(), routed_results.is_empty()) {
+                    (false, true) => KeywordBackend::Fts5,
+                    (true, false) => KeywordBackend::Like,
+                    _ => KeywordBackend::Fts5AndLike,
+                },
+            ));
+            // BM25とLIKEの出現回数は尺度が異なるため、両方ある場合はそれぞれの中で
+            // スケーリングしてから、同じ向き（小さいほど高関連）のスコアとして並べ替える
+            // （安定ソートのため、同スコアはそれぞれの検索での順序を保つ）
+            let mut results = fts_results;
+            let mut routed_results = routed_results;
+            if !results.is_empty() && !routed_results.is_empty() {
+                scale_to_best_score(&mut results);
+                scale_to_best_score(&mut routed_results);
+            }
+            results.extend(routed_results);
+            results.sort_by(|a, b| a.2.total_cmp(&b.2));
+            return Ok(results);
+        }
+
+        // FTS5の結果が空の場合、LIKE検索にフォールバック
+        // 日本語やCJK言語でも確実にマッチングできる
+        self.last_keyword_backend.set(Some(KeywordBackend::Like));
+        self.keyword_search_like(query, collection_ids, exclude_ids, keyword_match, false)
+    }
+
+    /// 最後の `keyword_search` で使った照合方法（検索していない場合はNone）
+    pub fn last_keyword_backend(&self) -> Option<KeywordBackend> {
+        self.last_keyword_backend.get()
+    }
+
+    /// 綴りの誤りを許容するキーワード検索（全件スキャン）
+    ///
+    /// 対象の全ドキュメントの本文を読み込み、クエリの単語と編集距離の近い単語を含む
+    /// ドキュメントを返す（[`crate::core::fuzzy::fuzzy_similarity`] を参照）。
+    /// インデックスを使わないため、ドキュメント数・本文の長さに比例して遅くなる
+    ///
+    /// スコアはBM25と同じ向き（小さいほど高関連）になるよう、一致度（0〜1）を-10倍した値
+    pub fn fuzzy_keyword_search(
+        &self,
+        query: &str,
+        collection_ids: Option<&[i64]>,
+        exclude_ids: &[i64],
+    ) -> Result<Vec<ScoredDocumentRow>> {
+        let (filter_sql, filter_params) = document_filter_clause(collection_ids, exclude_ids);
+
+        let query_sql = format!(
+            "SELECT d.id, d.content, c.name, d.metadata
+             FROM documents d
+             JOIN collections c ON d.collection_id = c.id
+             WHERE 1 = 1{}
+             ORDER BY d.id DESC",
+            filter_sql
+        );
 
-            let embedding: Vec<f32> = embedding_bytes
-                .chunks_exact(4)
-                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
-                .collect();
+        let mut stmt = self.conn.prepare(&query_sql)?;
 
-            Ok((id, content, embedding, collection_name))
+        let row_mapper = |row: &rusqlite::Row| -> rusqlite::Result<Option<ScoredDocumentRow>> {
+            let content: String = row.get(1)?;
+            let Some(similarity) = fuzzy_similarity(query, &content) else {
+                return Ok(None);
+            };
+            Ok(Some((
+                row.get(0)?,
+                content,
+                -10.0 * similarity,
+                row.get(2)?,
+                metadata_from_row(row, 3)?,
+            )))
         };
 
-        let results = if let Some(cids) = collection_ids {
-            let params_refs: Vec<&dyn rusqlite::ToSql> =
-                cids.iter().map(|c| c as &dyn rusqlite::ToSql).collect();
-            stmt.query_map(params_refs.as_slice(), row_mapper)?
-        } else {
-            stmt.query_map([], row_mapper)?
-        };
+        let mut results = stmt
+            .query_map(filter_params.as_slice(), row_mapper)?
+            .filter_map(|row| row.transpose())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
 
-        Ok(results.collect::<std::result::Result<Vec<_>, _>>()?)
+        // スコアの昇順（小さい = 高関連）。安定ソートのため同スコアはIDの降順のまま
+        results.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+        Ok(results)
     }
 
-    /// キーワード検索（FTS5 + LIKE検索の2段階フォールバック）
-    ///
-    /// 英語と日本語の両方に対応した柔軟なキーワード検索を実装
-    ///
-    /// # 検索戦略
-    /// 1. **第1段階: FTS5検索（高速・高精度）**
-    ///    - SQLiteのFull-Text Search 5を使用
-    ///    - BM25アルゴリズムでランキング
-    ///    - 英語の単語分割に最適化
-    ///    - 速度: O(log n)（インデックス使用）
+    /// キーワード検索にマッチするドキュメント数を取得
     ///
-    /// 2. **第2段階: LIKE検索（フォールバック）**
-    ///    - FTS5で結果がない場合に自動的に実行
-    ///    - 日本語やCJK言語に対応
-    ///    - パターンマッチング: `%キーワード%`
-    ///    - 速度: O(n)（全件スキャン）
+    /// `keyword_search` と同じFTS5 → LIKE のフォールバック戦略で、
+    /// 本文を取得せずに `COUNT(*)` だけを実行する
     ///
     /// # 引数
     /// * `query` - 検索キーワード
     /// * `collection_ids` - 検索対象のコレクションID（Noneの場合は全コレクション）
+    pub fn keyword_count(&self, query: &str, collection_ids: Option<&[i64]>) -> Result<usize> {
+        let counts = self.keyword_matches(query, collection_ids, KeywordMatches::Count)?;
+        Ok(counts.iter().sum::<i64>() as usize)
+    }
+
+    /// キーワード検索にマッチするドキュメントのIDを取得
     ///
-    /// # 戻り値
-    /// `Vec<(document_id, content, score, collection_name)>`
-    /// * FTS5の場合: スコアはBM25スコア（負の値、小さいほど良い）
-    /// * LIKE検索の場合: スコアは固定値1.0
-    pub fn keyword_search(
+    /// `keyword_count` と同じ条件で、本文やスコアを取得せずにIDだけを返す（順序は不定）
+    pub fn keyword_match_ids(
         &self,
         query: &str,
         collection_ids: Option<&[i64]>,
-    ) -> Result<Vec<(i64, String, f32, String)>> {
-        // まずFTS5で検索を試みる（英語などに最適）
-        let fts_results = self.keyword_search_fts5(query, collection_ids);
+    ) -> Result<Vec<i64>> {
+        self.keyword_matches(query, collection_ids, KeywordMatches::Ids)
+    }
 
-        // FTS5が成功して結果があればそれを返す
-        if let Ok(results) = &fts_results {
-            if !results.is_empty() {
-                return Ok(results.clone());
-            }
+    /// `keyword_count` / `keyword_match_ids` の本体
+    ///
+    /// 各SQLの結果（件数、またはドキュメントID）を連結して返す
+    fn keyword_matches(
+        &self,
+        query: &str,
+        collection_ids: Option<&[i64]>,
+        matches: KeywordMatches,
+    ) -> Result<Vec<i64>> {
+        let (filter_sql, filter_params) = document_filter_clause(collection_ids, &[]);
+        let run = |sql: &str, params: &[&dyn rusqlite::ToSql]| -> Result<Vec<i64>> {
+            let mut stmt = self.conn.prepare(sql)?;
+            let values = stmt
+                .query_map(params, |row| row.get(0))?
+                .collect::<std::result::Result<Vec<i64>, _>>()?;
+            Ok(values)
+        };
+
+        // LIKE検索（日本語・CJK言語対応）
+        let (like_sql, like_patterns) = like_clause(&keyword_terms(query), KeywordMatch::All);
+        let like_matches = |lang_only: bool| -> Result<Vec<i64>> {
+            let lang_sql = if lang_only {
+                format!(" AND {}", like_lang_sql("d.metadata"))
+            } else {
+                String::new()
+            };
+            let sql = format!(
+                "SELECT {}
+                 FROM documents d
+                 WHERE {}{}{}",
+                matches.column(),
+                like_sql,
+                lang_sql,
+                filter_sql
+            );
+            let mut params: Vec<&dyn rusqlite::ToSql> = like_patterns
+                .iter()
+                .map(|p| p as &dyn rusqlite::ToSql)
+                .collect();
+            params.extend(filter_params.iter().copied());
+            run(&sql, &params)
+        };
+
+        // FTS5が使えない環境では最初からLIKE検索のみ（keyword_searchと同じ）
+        if !self.fts_available {
+            return like_matches(false);
         }
 
-        // FTS5が失敗または結果が空の場合、LIKE検索にフォールバック
-        // 日本語やCJK言語でも確実にマッチングできる
-        self.keyword_search_like(query, collection_ids)
+        // FTS5で照合（検索できる語がない場合はMATCHを実行せず0件）
+        let route_langs = self.routes_like_langs()?;
+        let match_query = fts_match_query(query, KeywordMatch::All);
+        let mut found = if match_query.is_empty() {
+            Vec::new()
+        } else {
+            let fts_sql = format!(
+                "SELECT {}
+                 FROM documents_fts fts
+                 JOIN documents d ON fts.document_id = d.id
+                 WHERE documents_fts MATCH ?1{}{}",
+                matches.column(),
+                not_like_lang_sql(route_langs),
+                filter_sql
+            );
+            let mut params: Vec<&dyn rusqlite::ToSql> = vec![&match_query];
+            params.extend(filter_params.iter().copied());
+            run(&fts_sql, &params)?
+        };
+
+        // LIKE検索向けの言語のドキュメントは常にLIKEで照合する（keyword_searchと同じ振り分け）
+        if route_langs {
+            found.extend(like_matches(true)?);
+        }
+        if matches.any(&found) {
+            return Ok(found);
+        }
+
+        like_matches(false)
     }
 
     /// FTS5による全文検索
@@ -456,59 +2063,58 @@ impl Database {
     /// - 後で正規化が必要（enricher.rsで実施）
     ///
     /// # 引数
-    /// * `query` - 検索クエリ（FTS5クエリ構文）
+    /// * `query` - 検索キーワード（[`fts_match_query`] で変換するため、FTS5の演算子は使えない）
     /// * `collection_ids` - 検索対象のコレクションID
+    /// * `keyword_match` - 複数の語をAND / ORのどちらで組み合わせるか
+    ///
+    /// * `route_langs` - trueの場合、LIKE検索向けの言語のドキュメント（メタデータの `lang` で判定）は
+    ///   対象外（[`Database::routes_like_langs`]）
     fn keyword_search_fts5(
         &self,
         query: &str,
         collection_ids: Option<&[i64]>,
-    ) -> Result<Vec<(i64, String, f32, String)>> {
+        exclude_ids: &[i64],
+        keyword_match: KeywordMatch,
+        route_langs: bool,
+    ) -> Result<Vec<ScoredDocumentRow>> {
+        // 検索できる語がない場合はMATCHを実行せず0件（空のMATCHは構文エラーになる）
+        let match_query = fts_match_query(query, keyword_match);
+        if match_query.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // コレクション・除外IDの絞り込み条件
+        let (filter_sql, filter_params) = document_filter_clause(collection_ids, exclude_ids);
+
         // SQLクエリを構築
         // MATCH演算子: FTS5の全文検索を実行
         // bm25(documents_fts): BM25スコアを計算（負の値）
-        let query_sql = if let Some(cids) = collection_ids {
-            // 特定のコレクションに絞り込む場合
-            let placeholders = cids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-            format!(
-                "SELECT fts.document_id, d.content, bm25(documents_fts) as score, c.name
-                 FROM documents_fts fts
-                 JOIN documents d ON fts.document_id = d.id
-                 JOIN collections c ON d.collection_id = c.id
-                 WHERE documents_fts MATCH ?1 AND d.collection_id IN ({})
-                 ORDER BY score",  // BM25スコアの昇順（小さい = 高関連）
-                placeholders
-            )
-        } else {
-            // 全コレクションを対象にする場合
-            "SELECT fts.document_id, d.content, bm25(documents_fts) as score, c.name
+        let query_sql = format!(
+            "SELECT fts.document_id, d.content, bm25(documents_fts) as score, c.name, d.metadata
              FROM documents_fts fts
              JOIN documents d ON fts.document_id = d.id
              JOIN collections c ON d.collection_id = c.id
-             WHERE documents_fts MATCH ?1
-             ORDER BY score"
-                .to_string()
-        };
+             WHERE documents_fts MATCH ?1{}{}
+             ORDER BY score, fts.document_id",  // BM25スコアの昇順（小さい = 高関連）、同スコアはID順
+            not_like_lang_sql(route_langs),
+            filter_sql
+        );
 
         let mut stmt = self.conn.prepare(&query_sql)?;
 
-        let row_mapper = |row: &rusqlite::Row| -> rusqlite::Result<(i64, String, f32, String)> {
+        let row_mapper = |row: &rusqlite::Row| -> rusqlite::Result<ScoredDocumentRow> {
             Ok((
                 row.get(0)?,
                 row.get(1)?,
                 row.get(2)?,
                 row.get(3)?,
+                metadata_from_row(row, 4)?,
             ))
         };
 
-        let results = if let Some(cids) = collection_ids {
-            let mut params: Vec<&dyn rusqlite::ToSql> = vec![&query];
-            let cid_params: Vec<&dyn rusqlite::ToSql> =
-                cids.iter().map(|c| c as &dyn rusqlite::ToSql).collect();
-            params.extend(cid_params);
-            stmt.query_map(params.as_slice(), row_mapper)?
-        } else {
-            stmt.query_map([query], row_mapper)?
-        };
+        let mut params: Vec<&dyn rusqlite::ToSql> = vec![&match_query];
+        params.extend(filter_params);
+        let results = stmt.query_map(params.as_slice(), row_mapper)?;
 
         Ok(results.collect::<std::result::Result<Vec<_>, _>>()?)
     }
@@ -522,6 +2128,8 @@ impl Database {
     /// - パターン: `%キーワード%`
     /// - 前方・後方に任意の文字列を許可
     /// - 部分一致検索を実現
+    /// - クエリは空白で語に分割し、語ごとのパターンをAND / ORでつなぐ
+    ///   （`"永代供養 費用"` のようにダブルクォートで囲んだ部分はそのままの並びで照合）
     ///
     /// # パフォーマンス
     /// - 計算量: O(n × m)
@@ -531,60 +2139,1065 @@ impl Database {
     /// - 小〜中規模データセット向け（〜10万件程度）
     ///
     /// # スコアリング
-    /// - 固定値1.0を返す（マッチした = 関連あり）
-    /// - ランキングはドキュメントIDの降順（新しい順）
+    /// - キーワードの出現回数をBM25と同じ向き（負の値、小さいほど高関連）にした値
+    ///   （[`like_score`] を参照）
+    /// - 出現回数が多いほど上位。同スコアはドキュメントIDの降順（新しい順）
     ///
     /// # 引数
     /// * `query` - 検索キーワード
     /// * `collection_ids` - 検索対象のコレクションID
+    /// * `keyword_match` - 複数の語をAND / ORのどちらで組み合わせるか
+    /// * `lang_only` - trueの場合、LIKE検索向けの言語のドキュメントのみを対象にする
     fn keyword_search_like(
         &self,
         query: &str,
         collection_ids: Option<&[i64]>,
-    ) -> Result<Vec<(i64, String, f32, String)>> {
-        // LIKEパターンを作成: "キーワード" -> "%キーワード%"
-        let like_pattern = format!("%{}%", query);
-
-        let query_sql = if let Some(cids) = collection_ids {
-            let placeholders = cids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-            format!(
-                "SELECT d.id, d.content, 1.0 as score, c.name
-                 FROM documents d
-                 JOIN collections c ON d.collection_id = c.id
-                 WHERE d.content LIKE ?1 AND d.collection_id IN ({})
-                 ORDER BY d.id DESC",
-                placeholders
-            )
+        exclude_ids: &[i64],
+        keyword_match: KeywordMatch,
+        lang_only: bool,
+    ) -> Result<Vec<ScoredDocumentRow>> {
+        // 語ごとのLIKEパターンを作成: "キーワード" -> "%キーワード%"
+        let terms = keyword_terms(query);
+        let (like_sql, like_patterns) = like_clause(&terms, keyword_match);
+
+        // コレクション・除外IDの絞り込み条件
+        let (filter_sql, filter_params) = document_filter_clause(collection_ids, exclude_ids);
+        let lang_sql = if lang_only {
+            format!(" AND {}", like_lang_sql("d.metadata"))
         } else {
-            "SELECT d.id, d.content, 1.0 as score, c.name
+            String::new()
+        };
+
+        let query_sql = format!(
+            "SELECT d.id, d.content, c.name, d.metadata
              FROM documents d
              JOIN collections c ON d.collection_id = c.id
-             WHERE d.content LIKE ?1
-             ORDER BY d.id DESC"
-                .to_string()
-        };
+             WHERE {}{}{}
+             ORDER BY d.id DESC",
+            like_sql, lang_sql, filter_sql
+        );
 
         let mut stmt = self.conn.prepare(&query_sql)?;
 
-        let row_mapper = |row: &rusqlite::Row| -> rusqlite::Result<(i64, String, f32, String)> {
-            Ok((
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-            ))
+        let row_mapper = |row: &rusqlite::Row| -> rusqlite::Result<ScoredDocumentRow> {
+            let content: String = row.get(1)?;
+            let score = like_score(&content, &terms);
+            Ok((row.get(0)?, content, score, row.get(2)?, metadata_from_row(row, 3)?))
         };
 
-        let results = if let Some(cids) = collection_ids {
-            let mut params: Vec<&dyn rusqlite::ToSql> = vec![&like_pattern];
-            let cid_params: Vec<&dyn rusqlite::ToSql> =
-                cids.iter().map(|c| c as &dyn rusqlite::ToSql).collect();
-            params.extend(cid_params);
-            stmt.query_map(params.as_slice(), row_mapper)?
+        let mut params: Vec<&dyn rusqlite::ToSql> = like_patterns
+            .iter()
+            .map(|p| p as &dyn rusqlite::ToSql)
+            .collect();
+        params.extend(filter_params);
+        let mut results = stmt
+            .query_map(params.as_slice(), row_mapper)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        // スコアの昇順（小さい = 高関連）。安定ソートのため同スコアはIDの降順のまま
+        results.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+        Ok(results)
+    }
+}
+
+/// FTS5が使えない場合の警告（1プロセスで1回のみ）
+fn warn_fts_unavailable() {
+    FTS_UNAVAILABLE_WARNING.call_once(|| {
+        tracing::warn!(
+            "SQLite was built without FTS5; keyword search falls back to LIKE matching \
+             (slower on large collections)"
+        );
+    });
+}
+
+/// LIKE検索のスコア（BM25と同じ向きの負の値、小さいほど高関連）
+///
+/// 語ごとの出現回数の合計を負にした値（本文の長さでは割らない）。
+/// LIKEと同様に英字の大文字・小文字は区別しない
+fn like_score(content: &str, terms: &[String]) -> f32 {
+    let content = content.to_lowercase();
+    let occurrences: usize = terms
+        .iter()
+        .map(|term| content.matches(term.to_lowercase().as_str()).count())
+        .sum();
+
+    // LIKEでマッチしている以上、少なくとも1回は出現している
+    -(occurrences.max(1) as f32)
+}
+
+/// キーワード検索のマッチの取得方法（[`Database::keyword_count`] / [`Database::keyword_match_ids`]）
+#[derive(Debug, Clone, Copy)]
+enum KeywordMatches {
+    /// 件数（各SQLが `COUNT(*)` の1行を返す）
+    Count,
+    /// ドキュメントID（各SQLがマッチしたドキュメントごとに1行を返す）
+    Ids,
+}
+
+impl KeywordMatches {
+    /// SELECTする列
+    fn column(self) -> &'static str {
+        match self {
+            KeywordMatches::Count => "COUNT(*)",
+            KeywordMatches::Ids => "d.id",
+        }
+    }
+
+    /// 結果にマッチがあるかどうか
+    fn any(self, values: &[i64]) -> bool {
+        match self {
+            KeywordMatches::Count => values.iter().any(|&count| count > 0),
+            KeywordMatches::Ids => !values.is_empty(),
+        }
+    }
+}
+
+/// FTS5の検索からLIKE検索向けの言語のドキュメントを除くSQL条件（振り分けない場合は空）
+fn not_like_lang_sql(route_langs: bool) -> String {
+    if route_langs {
+        format!(" AND NOT {}", like_lang_sql("d.metadata"))
+    } else {
+        String::new()
+    }
+}
+
+/// 結果の中で最良（最小）のスコアが-10になるようにスケーリング（スコアの比率は保つ）
+///
+/// FTS5のBM25とLIKE検索の出現回数は尺度が異なり、そのままでは比較できないため、
+/// マージする前にそれぞれの結果に適用する。最良のスコアが負でない場合は変更しない
+fn scale_to_best_score(results: &mut [ScoredDocumentRow]) {
+    let best = results.iter().map(|r| r.2).fold(0.0, f32::min);
+    if best < 0.0 {
+        for result in results {
+            result.2 = -10.0 * result.2 / best;
+        }
+    }
+}
+
+/// 重複チェック用のコンテンツハッシュ（SHA-256の16進文字列）
+///
+/// 前後の空白を除き、連続する空白（改行を含む）を1つのスペースにまとめてから計算する
+fn content_hash(content: &str) -> String {
+    let normalized = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+/// ユーザーの入力をFTS5の `MATCH` に渡せるクエリに変換
+///
+/// [`keyword_terms`] で分割した語をそれぞれダブルクォートで囲んだフレーズにする（語の中の `"` は
+/// `""` にエスケープ）。ダブルクォートで囲んだ部分は1つのフレーズになり、語がその並びのまま
+/// 隣接する場合のみマッチする。`C++` の `+`、末尾の `-`、`*` などがFTS5の演算子として
+/// 解釈されて構文エラーになるのを防ぐ。英数字を含まない語はトークンにならないため除外する。
+/// 語は `keyword_match` に応じてAND条件（空白区切り）またはOR条件になる
+fn fts_match_query(query: &str, keyword_match: KeywordMatch) -> String {
+    let separator = match keyword_match {
+        KeywordMatch::All => " ",
+        KeywordMatch::Any => " OR ",
+    };
+    keyword_terms(query)
+        .iter()
+        .filter(|term| term.chars().any(char::is_alphanumeric))
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// LIKE検索で照合する語（空白で分割し、ダブルクォートで囲んだ部分は分割しない）
+///
+/// - `永代供養 費用` -> `["永代供養", "費用"]`
+/// - `"永代供養 費用" 相場` -> `["永代供養 費用", "相場"]`（閉じていない `"` は末尾までを1つの語にする）
+///
+/// 語が1つもない場合（空白だけのクエリなど）はクエリ全体を1つの語にする
+fn keyword_terms(query: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut in_phrase = false;
+    for c in query.chars() {
+        if c == '"' || (c.is_whitespace() && !in_phrase) {
+            // フレーズの開始・終了、またはフレーズ外の空白で語を区切る
+            if !current.trim().is_empty() {
+                terms.push(current.trim().to_string());
+            }
+            current.clear();
+            if c == '"' {
+                in_phrase = !in_phrase;
+            }
         } else {
-            stmt.query_map([&like_pattern], row_mapper)?
+            current.push(c);
+        }
+    }
+    if !current.trim().is_empty() {
+        terms.push(current.trim().to_string());
+    }
+
+    if terms.is_empty() {
+        terms.push(query.to_string());
+    }
+    terms
+}
+
+/// LIKE検索の条件（語ごとの `d.content LIKE ?` を `keyword_match` に応じてAND / ORでつないだもの）と
+/// 各語のパターン `%語%`
+fn like_clause(terms: &[String], keyword_match: KeywordMatch) -> (String, Vec<String>) {
+    let separator = match keyword_match {
+        KeywordMatch::All => " AND ",
+        KeywordMatch::Any => " OR ",
+    };
+    let sql = terms
+        .iter()
+        .map(|_| "d.content LIKE ?")
+        .collect::<Vec<_>>()
+        .join(separator);
+    let patterns = terms.iter().map(|term| format!("%{}%", term)).collect();
+    (format!("({})", sql), patterns)
+}
+
+/// FTSテーブルに登録するテキスト（本文 + 指定したメタデータフィールドの値）
+///
+/// 文字列・数値・真偽値はそのまま、配列（タグなど）は要素ごとに追加する。
+/// フィールドがない・オブジェクトやnullの値は無視する
+fn fts_text(content: &str, metadata: Option<&serde_json::Value>, fields: &[String]) -> String {
+    fn push_value(text: &mut String, value: &serde_json::Value) {
+        match value {
+            serde_json::Value::String(s) => {
+                text.push('\n');
+                text.push_str(s);
+            }
+            serde_json::Value::Number(n) => {
+                text.push('\n');
+                text.push_str(&n.to_string());
+            }
+            serde_json::Value::Bool(b) => {
+                text.push('\n');
+                text.push_str(&b.to_string());
+            }
+            serde_json::Value::Array(values) => {
+                values.iter().for_each(|v| push_value(text, v));
+            }
+            serde_json::Value::Object(_) | serde_json::Value::Null => {}
+        }
+    }
+
+    let mut text = content.to_string();
+    if let Some(metadata) = metadata {
+        for field in fields {
+            if let Some(value) = metadata.get(field) {
+                push_value(&mut text, value);
+            }
+        }
+    }
+    text
+}
+
+/// `SELECT c.id, c.name, c.description, COUNT(d.id), c.created_at, c.updated_at,
+/// c.embedding_model` の行をコレクションに変換
+fn collection_from_row(row: &rusqlite::Row) -> rusqlite::Result<Collection> {
+    Ok(Collection {
+        embedding_model: row.get(6)?,
+        ..Collection::new(
+            row.get(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+        )
+    })
+}
+
+/// `SELECT d.id, d.collection_id, c.name, d.content, d.metadata, d.created_at, d.updated_at,
+/// d.external_id` の行をドキュメントに変換
+fn document_from_row(row: &rusqlite::Row) -> rusqlite::Result<Document> {
+    Ok(Document {
+        id: row.get(0)?,
+        collection_id: row.get(1)?,
+        collection_name: row.get(2)?,
+        content: row.get(3)?,
+        metadata: metadata_from_row(row, 4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        external_id: row.get(7)?,
+    })
+}
+
+/// メタデータ列（JSON文字列）をパース
+fn metadata_from_row(row: &rusqlite::Row, idx: usize) -> rusqlite::Result<Option<serde_json::Value>> {
+    let metadata_str: Option<String> = row.get(idx)?;
+    metadata_str
+        .map(|s| serde_json::from_str(&s))
+        .transpose()
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+/// ベクトルを保存形式のバイト列に変換し、読み込み時に復元されるベクトルのノルムと一緒に返す
+///
+/// ベクトルはノルムを1に正規化してから保存する（コサイン類似度は変わらない）。検索時は
+/// 内積を保存済みのノルムで割るだけで済み、正規化せずに保存された既存のベクトルも
+/// 同じ計算で正しいスコアになる。量子化した場合は復元後のベクトルのノルムを保存するため、
+/// 検索時の枝刈りの上限が実際のスコアを下回らない
+fn encode_embedding(embedding: &[f32], format: EmbeddingFormat) -> (Vec<u8>, f32) {
+    let embedding = normalized(embedding);
+    match format {
+        EmbeddingFormat::F32 => {
+            let bytes = embedding.iter().flat_map(|f| f.to_le_bytes()).collect();
+            (bytes, vector_norm(&embedding))
+        }
+        EmbeddingFormat::Int8 => {
+            let (min, max) = embedding
+                .iter()
+                .fold(None, |range: Option<(f32, f32)>, &x| match range {
+                    Some((min, max)) => Some((min.min(x), max.max(x))),
+                    None => Some((x, x)),
+                })
+                .unwrap_or((0.0, 0.0));
+            let scale = (max - min) / 255.0;
+
+            let mut bytes = Vec::with_capacity(8 + embedding.len());
+            bytes.extend_from_slice(&min.to_le_bytes());
+            bytes.extend_from_slice(&max.to_le_bytes());
+            bytes.extend(embedding.iter().map(|&x| {
+                if scale > 0.0 {
+                    ((x - min) / scale).round() as u8
+                } else {
+                    0
+                }
+            }));
+
+            let norm = vector_norm(&embedding_from_bytes(&bytes, EmbeddingFormat::Int8));
+            (bytes, norm)
+        }
+    }
+}
+
+/// embedding列を保存形式に従ってベクトルに変換
+fn embedding_from_bytes(bytes: &[u8], format: EmbeddingFormat) -> Vec<f32> {
+    match format {
+        EmbeddingFormat::F32 => bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect(),
+        EmbeddingFormat::Int8 => {
+            if bytes.len() < 8 {
+                return Vec::new();
+            }
+            let min = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            let max = f32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+            let scale = (max - min) / 255.0;
+            bytes[8..].iter().map(|&q| min + q as f32 * scale).collect()
+        }
+    }
+}
+
+/// embedding列と embedding_format 列（NULLはf32）からベクトルを復元
+fn embedding_from_row(
+    row: &rusqlite::Row,
+    bytes_idx: usize,
+    format_idx: usize,
+) -> rusqlite::Result<Vec<f32>> {
+    let bytes: Vec<u8> = row.get(bytes_idx)?;
+    let format = match row.get::<_, Option<String>>(format_idx)? {
+        None => EmbeddingFormat::F32,
+        Some(name) => EmbeddingFormat::parse(&name).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                format_idx,
+                rusqlite::types::Type::Text,
+                format!("unknown embedding format: '{}'", name).into(),
+            )
+        })?,
+    };
+    Ok(embedding_from_bytes(&bytes, format))
+}
+
+/// ドキュメントの絞り込み条件（対象コレクション・除外ID）を構築
+///
+/// `WHERE` 句の末尾に連結する ` AND ...` 形式のSQL断片と、
+/// その位置パラメータ（`?`）に対応する値を返す
+fn document_filter_clause<'a>(
+    collection_ids: Option<&'a [i64]>,
+    exclude_ids: &'a [i64],
+) -> (String, Vec<&'a dyn rusqlite::ToSql>) {
+    let mut sql = String::new();
+    let mut params: Vec<&dyn rusqlite::ToSql> = Vec::new();
+
+    if let Some(cids) = collection_ids {
+        let placeholders = cids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+        sql.push_str(&format!(" AND d.collection_id IN ({})", placeholders));
+        params.extend(cids.iter().map(|c| c as &dyn rusqlite::ToSql));
+    }
+
+    if !exclude_ids.is_empty() {
+        let placeholders = exclude_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+        sql.push_str(&format!(" AND d.id NOT IN ({})", placeholders));
+        params.extend(exclude_ids.iter().map(|id| id as &dyn rusqlite::ToSql));
+    }
+
+    (sql, params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    /// FTS5なしのSQLiteで作成したデータベースを再現する
+    ///
+    /// 同梱のSQLiteはFTS5付きでビルドされているため、仮想テーブルを削除して
+    /// FTS5が使えない状態にする（読み取り専用で開き直すと検出される）
+    fn database_without_fts(path: &Path) -> Database {
+        let mut db = Database::new(path).unwrap();
+        db.conn.execute("DROP TABLE documents_fts", []).unwrap();
+        db.fts_available = false;
+        db
+    }
+
+    /// FTSインデックスの行数
+    fn fts_row_count(db: &Database) -> i64 {
+        db.conn
+            .query_row("SELECT COUNT(*) FROM documents_fts", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_delete_document_removes_fts_row() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let collection_id = db.create_collection("faq", None).unwrap();
+
+        let kept = db
+            .add_document(collection_id, "Rust ownership rules", &[1.0, 0.0], None)
+            .unwrap();
+        let deleted = db
+            .add_document(collection_id, "Rust borrow checker", &[0.0, 1.0], None)
+            .unwrap();
+        assert_eq!(fts_row_count(&db), 2);
+
+        assert!(db.delete_document(deleted).unwrap());
+        assert_eq!(fts_row_count(&db), 1);
+        assert!(matches!(
+            db.get_document(deleted),
+            Err(Error::DocumentNotFound(id)) if id == deleted
+        ));
+
+        let results = db.keyword_search("rust", None, &[]).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, kept);
+        assert_eq!(db.keyword_count("rust", None).unwrap(), 1);
+
+        // 外部IDでの削除も同様
+        db.add_document_with_external_id(
+            collection_id,
+            "Rust lifetimes",
+            &[1.0, 1.0],
+            None,
+            "ext-1",
+        )
+        .unwrap();
+        assert_eq!(fts_row_count(&db), 2);
+        assert!(db.delete_document_by_external_id("ext-1").unwrap());
+        assert_eq!(fts_row_count(&db), 1);
+        assert_eq!(db.keyword_search("rust", None, &[]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_update_document_resyncs_fts() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let collection_id = db.create_collection("faq", None).unwrap();
+        let doc_id = db
+            .add_document(collection_id, "Rust ownership rules", &[1.0, 0.0], None)
+            .unwrap();
+
+        assert!(db
+            .update_document(doc_id, Some("Tokio async runtime"), None, None)
+            .unwrap());
+        assert_eq!(fts_row_count(&db), 1);
+        let results = db.keyword_search("tokio", None, &[]).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, doc_id);
+        assert!(db
+            .keyword_search("ownership", None, &[])
+            .unwrap()
+            .is_empty());
+
+        // FTSの行が欠けている場合も作り直される
+        db.conn
+            .execute(
+                "DELETE FROM documents_fts WHERE document_id = ?1",
+                params![doc_id],
+            )
+            .unwrap();
+        db.update_document(doc_id, Some("Serde serialization framework"), None, None)
+            .unwrap();
+        assert_eq!(fts_row_count(&db), 1);
+        assert_eq!(db.keyword_search("serde", None, &[]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_keyword_search_without_fts5() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = database_without_fts(temp_db.path());
+        assert!(!db.fts_available());
+
+        let collection_id = db.create_collection("faq", None).unwrap();
+        let id = db
+            .add_document(collection_id, "Rust is a systems language", &[1.0, 0.0], None)
+            .unwrap();
+        db.add_document(collection_id, "Pasta recipes", &[0.0, 1.0], None)
+            .unwrap();
+        db.update_document(id, Some("Rust is a systems programming language"), None, None)
+            .unwrap();
+        db.set_indexed_metadata_fields(collection_id, &["title".to_string()])
+            .unwrap();
+        db.rebuild_fts_index().unwrap();
+        db.optimize_indexes().unwrap();
+
+        let results = db.keyword_search("programming", None, &[]).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, id);
+        assert_eq!(db.keyword_count("programming", None).unwrap(), 1);
+
+        // 読み取り専用で開き直すと、FTS5のテーブルが使えないことを検出する
+        drop(db);
+        let reader = Database::open_read_only(temp_db.path()).unwrap();
+        assert!(!reader.fts_available());
+        let results = reader.keyword_search("programming", None, &[]).unwrap();
+        assert_eq!(results.len(), 1);
+
+        // FTS5が使える環境で開き直すと、既存ドキュメントのインデックスを作成する
+        drop(reader);
+        let db = Database::new(temp_db.path()).unwrap();
+        assert!(db.fts_available());
+        let results = db
+            .keyword_search_fts5("programming", None, &[], KeywordMatch::All, true)
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, id);
+    }
+
+    #[test]
+    fn test_int8_embeddings_mixed_with_f32() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let collection_id = db.create_collection("faq", None).unwrap();
+        let original = [0.5, -0.25, 0.125, -1.0];
+
+        let f32_id = db
+            .add_document(collection_id, "stored as f32", &original, None)
+            .unwrap();
+        db.set_embedding_format(EmbeddingFormat::Int8);
+        let int8_id = db
+            .add_document(collection_id, "stored as int8", &original, None)
+            .unwrap();
+
+        let blob_len = |id: i64| -> i64 {
+            db.conn
+                .query_row(
+                    "SELECT length(embedding) FROM documents WHERE id = ?1",
+                    params![id],
+                    |row| row.get(0),
+                )
+                .unwrap()
         };
+        assert_eq!(blob_len(f32_id), 16);
+        assert_eq!(blob_len(int8_id), 12);
+
+        // 正規化して保存され、f32はそのまま、int8は範囲の1/510以内の誤差で復元される
+        let expected = normalized(&original);
+        let embeddings = db.get_embeddings_with_norms(&[f32_id, int8_id]).unwrap();
+        assert_eq!(embeddings[&f32_id].0, expected);
+        let (restored, norm) = &embeddings[&int8_id];
+        assert_eq!(restored.len(), expected.len());
+        for (r, &o) in restored.iter().zip(&expected) {
+            assert!((r - o).abs() <= 1.5 / 510.0 + 1e-6, "{} vs {}", r, o);
+        }
+        assert!((norm - vector_norm(restored)).abs() < 1e-6);
+
+        // 次元数は形式によらず同じ
+        assert_eq!(db.embedding_dimension_counts().unwrap(), vec![(4, 2)]);
+
+        // 更新時は現在の形式で保存し直す
+        db.update_document(f32_id, None, Some(&original), None)
+            .unwrap();
+        assert_eq!(blob_len(f32_id), 12);
+        db.set_embedding_format(EmbeddingFormat::F32);
+        db.update_document(int8_id, None, Some(&original), None)
+            .unwrap();
+        assert_eq!(db.get_embeddings(&[int8_id]).unwrap()[&int8_id], expected);
+    }
 
-        Ok(results.collect::<std::result::Result<Vec<_>, _>>()?)
+    #[test]
+    fn test_unnormalized_embeddings_score_like_before() {
+        use crate::core::search::{cosine_similarity, BoundedCosine};
+
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let collection_id = db.create_collection("faq", None).unwrap();
+        let embeddings: Vec<Vec<f32>> = vec![
+            vec![3.0, -1.5, 0.75, 2.0],
+            vec![0.2, 0.1, -0.4, 0.05],
+            vec![-8.0, 4.0, 1.0, 0.5],
+        ];
+
+        let mut ids = Vec::new();
+        for (i, embedding) in embeddings.iter().enumerate() {
+            let id = db
+                .add_document(collection_id, &format!("doc {}", i), embedding, None)
+                .unwrap();
+            ids.push(id);
+        }
+
+        // 追加時に正規化して保存される
+        let stored = db.get_embeddings_with_norms(&ids).unwrap();
+        for id in &ids {
+            let (embedding, norm) = &stored[id];
+            assert!((norm - 1.0).abs() < 1e-6);
+            assert!((vector_norm(embedding) - 1.0).abs() < 1e-6);
+        }
+
+        // 以前のバージョンは正規化せずに保存していた（ノルム未保存の行もある）
+        let f32_bytes = |v: &[f32]| v.iter().flat_map(|f| f.to_le_bytes()).collect::<Vec<u8>>();
+        db.conn
+            .execute(
+                "UPDATE documents SET embedding = ?1, embedding_norm = ?2 WHERE id = ?3",
+                params![
+                    f32_bytes(&embeddings[1]),
+                    vector_norm(&embeddings[1]) as f64,
+                    ids[1]
+                ],
+            )
+            .unwrap();
+        db.conn
+            .execute(
+                "UPDATE documents SET embedding = ?1, embedding_norm = NULL WHERE id = ?2",
+                params![f32_bytes(&embeddings[2]), ids[2]],
+            )
+            .unwrap();
+
+        // どちらの行も、正規化前のベクトルとのコサイン類似度と1e-5以内で一致する
+        let query = [1.0, 0.5, -2.0, 0.25];
+        let scorer = BoundedCosine::new(&query, -1.0);
+        let rows = db.get_all_documents_with_norms(None).unwrap();
+        assert_eq!(rows.len(), embeddings.len());
+        for ((id, _, embedding, ..), norm) in &rows {
+            let original = &embeddings[ids.iter().position(|i| i == id).unwrap()];
+            let expected = cosine_similarity(&query, original);
+            let score = scorer.score(embedding, *norm).unwrap();
+            assert!((score - expected).abs() < 1e-5, "{} vs {}", score, expected);
+        }
+    }
+
+    #[test]
+    fn test_keyword_search_with_fts5_special_characters() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let collection_id = db.create_collection("faq", None).unwrap();
+        let cpp = db
+            .add_document(
+                collection_id,
+                "C++ templates and generics",
+                &[1.0, 0.0],
+                None,
+            )
+            .unwrap();
+        let quoted = db
+            .add_document(
+                collection_id,
+                "He said \"quoted phrase\" twice",
+                &[0.0, 1.0],
+                None,
+            )
+            .unwrap();
+        let rust = db
+            .add_document(collection_id, "Rust ownership rules", &[1.0, 1.0], None)
+            .unwrap();
+
+        // FTS5の演算子として解釈される文字を含んでもエラーにならない
+        for (query, expected) in [
+            ("C++", cpp),
+            ("\"quoted phrase\"", quoted),
+            ("rust -", rust),
+        ] {
+            let results = db
+                .keyword_search_fts5(query, None, &[], KeywordMatch::All, true)
+                .unwrap();
+            assert_eq!(results.len(), 1, "query: {}", query);
+            assert_eq!(results[0].0, expected);
+            assert_eq!(db.keyword_search(query, None, &[]).unwrap()[0].0, expected);
+            assert_eq!(db.keyword_count(query, None).unwrap(), 1);
+        }
+
+        assert_eq!(
+            fts_match_query("say \"hi\" -", KeywordMatch::All),
+            "\"say\" \"hi\""
+        );
+        assert_eq!(
+            fts_match_query("\"rust memory\" safety", KeywordMatch::All),
+            "\"rust memory\" \"safety\""
+        );
+        assert_eq!(
+            fts_match_query("rust tokio", KeywordMatch::Any),
+            "\"rust\" OR \"tokio\""
+        );
+    }
+
+    #[test]
+    fn test_keyword_search_and_count_propagate_fts_errors() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let coll = db.create_collection("docs", None).unwrap();
+        db.add_document(coll, "C++ templates", &[1.0, 0.0], None).unwrap();
+
+        // 検索できる語がないクエリはMATCHを実行せずLIKEで数える
+        assert_eq!(db.keyword_count("++", None).unwrap(), 1);
+
+        assert_eq!(db.keyword_search("++", None, &[]).unwrap().len(), 1);
+
+        // FTSテーブルが壊れている場合は0件やLIKE検索の結果ではなくエラーを返す
+        db.conn.execute("DROP TABLE documents_fts", []).unwrap();
+        assert!(db.keyword_count("templates", None).is_err());
+        assert!(db.keyword_search("templates", None, &[]).is_err());
+    }
+
+    #[test]
+    fn test_keyword_match_ids_agree_with_keyword_search() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let rust = db.create_collection("rust", None).unwrap();
+        let other = db.create_collection("other", None).unwrap();
+        db.add_document(rust, "Rust ownership rules", &[1.0, 0.0], None)
+            .unwrap();
+        db.add_document(rust, "Rust borrow checker", &[0.0, 1.0], None)
+            .unwrap();
+        db.add_document(other, "Rust in production", &[1.0, 1.0], None)
+            .unwrap();
+        db.add_document(other, "Python typing", &[1.0, 0.0], None)
+            .unwrap();
+
+        for (query, collection_ids) in [
+            ("rust", None),
+            ("rust", Some(&[rust][..])),
+            ("ownership", None),
+            ("golang", None),
+        ] {
+            let mut ids = db.keyword_match_ids(query, collection_ids).unwrap();
+            ids.sort();
+            let mut expected: Vec<i64> = db
+                .keyword_search(query, collection_ids, &[])
+                .unwrap()
+                .iter()
+                .map(|r| r.0)
+                .collect();
+            expected.sort();
+            assert_eq!(ids, expected, "query: {}", query);
+            assert_eq!(db.keyword_count(query, collection_ids).unwrap(), ids.len());
+        }
+    }
+
+    #[test]
+    fn test_fts_quoted_terms_match_as_phrase() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let coll = db.create_collection("docs", None).unwrap();
+        let adjacent = db
+            .add_document(coll, "The rust memory model is strict", &[1.0, 0.0], None)
+            .unwrap();
+        let apart = db
+            .add_document(
+                coll,
+                "Memory safety is what rust is known for",
+                &[0.0, 1.0],
+                None,
+            )
+            .unwrap();
+
+        let ids = |query: &str| {
+            let mut ids: Vec<i64> = db
+                .keyword_search_fts5(query, None, &[], KeywordMatch::All, true)
+                .unwrap()
+                .iter()
+                .map(|r| r.0)
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids("rust memory"), vec![adjacent, apart]);
+        assert_eq!(ids("\"rust memory\""), vec![adjacent]);
+        assert_eq!(db.keyword_count("\"rust memory\"", None).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_keyword_search_scales_fts_and_like_scores_before_merging() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let coll = db.create_collection("faq", None).unwrap();
+        let jpn = serde_json::json!({"lang": "jpn"});
+
+        let english = db
+            .add_document(coll, "Rust is fast", &[1.0, 0.0], None)
+            .unwrap();
+        db.add_document(coll, "Python is popular", &[1.0, 0.0], None)
+            .unwrap();
+        let thrice = db
+            .add_document(
+                coll,
+                "Rustの入門。Rustの所有権とRustの借用",
+                &[0.0, 1.0],
+                Some(&jpn),
+            )
+            .unwrap();
+        let once = db
+            .add_document(coll, "Rustの入門", &[0.0, 1.0], Some(&jpn))
+            .unwrap();
+
+        // BM25（0に近い負の値）と出現回数（-3など）をそのまま並べず、それぞれの最良のスコアを揃える
+        let results = db.keyword_search("rust", None, &[]).unwrap();
+        let score = |id: i64| results.iter().find(|r| r.0 == id).unwrap().2;
+        assert_eq!(db.last_keyword_backend(), Some(KeywordBackend::Fts5AndLike));
+        assert_eq!(score(english), -10.0);
+        assert_eq!(score(thrice), -10.0);
+        assert!((score(once) + 10.0 / 3.0).abs() < 1e-5);
+        assert_eq!(results.last().unwrap().0, once);
+    }
+
+    #[test]
+    fn test_like_lang_documents_setting_tracks_routed_languages() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let coll = db.create_collection("faq", None).unwrap();
+
+        // LIKE検索向けの言語のドキュメントがなければ、振り分けのためのLIKE検索は行わない
+        let english = db
+            .add_document(coll, "Rust is fast", &[1.0, 0.0], None)
+            .unwrap();
+        assert!(!db.routes_like_langs().unwrap());
+        db.keyword_search("rust", None, &[]).unwrap();
+        assert_eq!(db.last_keyword_backend(), Some(KeywordBackend::Fts5));
+
+        // メタデータの更新でLIKE検索向けの言語になった場合も記録される
+        let jpn = serde_json::json!({"lang": "jpn"});
+        db.update_document(english, None, None, Some(&jpn)).unwrap();
+        assert!(db.routes_like_langs().unwrap());
+        db.keyword_search("rust", None, &[]).unwrap();
+        assert_eq!(db.last_keyword_backend(), Some(KeywordBackend::Like));
+
+        // 記録する前に追加されたドキュメントは、最初に開いたときに調べる
+        db.conn
+            .execute(
+                "DELETE FROM settings WHERE key = ?1",
+                [LIKE_LANG_DOCUMENTS_SETTING],
+            )
+            .unwrap();
+        drop(db);
+        let db = Database::new(temp_db.path()).unwrap();
+        assert_eq!(
+            db.get_setting(LIKE_LANG_DOCUMENTS_SETTING)
+                .unwrap()
+                .as_deref(),
+            Some("1")
+        );
+
+        // 追加でも記録される
+        let other = NamedTempFile::new().unwrap();
+        let db = Database::new(other.path()).unwrap();
+        let coll = db.create_collection("faq", None).unwrap();
+        db.add_document(coll, "Rustの入門", &[0.0, 1.0], Some(&jpn))
+            .unwrap();
+        assert!(db.routes_like_langs().unwrap());
+        assert_eq!(db.keyword_count("rust", None).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_keyword_terms_splits_on_whitespace_and_keeps_phrases() {
+        assert_eq!(keyword_terms("永代供養　 費用"), vec!["永代供養", "費用"]);
+        assert_eq!(
+            keyword_terms("\"永代供養 費用\" 相場"),
+            vec!["永代供養 費用", "相場"]
+        );
+        assert_eq!(
+            keyword_terms("相場 \"永代供養 費"),
+            vec!["相場", "永代供養 費"]
+        );
+        assert_eq!(keyword_terms("  "), vec!["  "]);
+    }
+
+    #[test]
+    fn test_delete_collection_removes_documents() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let journal_mode: String = db
+            .conn
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+
+        let faq = db.create_collection("faq", None).unwrap();
+        let kept = db.create_collection("kept", None).unwrap();
+        let deleted_id = db
+            .add_document(faq, "Rust ownership rules", &[1.0, 0.0], None)
+            .unwrap();
+        db.add_document(faq, "Rust borrow checker", &[0.0, 1.0], None)
+            .unwrap();
+        let kept_id = db
+            .add_document(kept, "Rust lifetimes", &[1.0, 1.0], None)
+            .unwrap();
+
+        assert!(db.delete_collection("faq").unwrap());
+        let document_count: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM documents", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(document_count, 1);
+        assert_eq!(fts_row_count(&db), 1);
+        assert!(matches!(
+            db.get_document(deleted_id),
+            Err(Error::DocumentNotFound(_))
+        ));
+        assert_eq!(db.get_document(kept_id).unwrap().id, kept_id);
+
+        // 外部キー制約なしで削除された（以前のバージョンの）ドキュメントは、
+        // マイグレーション前のデータベースを最初に開いたときに削除される
+        db.conn.pragma_update(None, "foreign_keys", false).unwrap();
+        db.conn
+            .execute("DELETE FROM collections WHERE id = ?1", params![kept])
+            .unwrap();
+        assert_eq!(
+            db.get_setting(ORPHANS_REMOVED_SETTING).unwrap().as_deref(),
+            Some("1")
+        );
+        db.conn
+            .execute(
+                "DELETE FROM settings WHERE key = ?1",
+                params![ORPHANS_REMOVED_SETTING],
+            )
+            .unwrap();
+        drop(db);
+        let db = Database::new(temp_db.path()).unwrap();
+        assert!(db.get_embeddings(&[kept_id]).unwrap().is_empty());
+        assert_eq!(fts_row_count(&db), 0);
+
+        // 実行済みのデータベースでは、開くたびに削除し直すことはない
+        let collection_id = db.create_collection("faq", None).unwrap();
+        let id = db
+            .add_document(collection_id, "Rust traits", &[1.0, 0.0], None)
+            .unwrap();
+        db.conn.pragma_update(None, "foreign_keys", false).unwrap();
+        db.conn
+            .execute(
+                "DELETE FROM collections WHERE id = ?1",
+                params![collection_id],
+            )
+            .unwrap();
+        drop(db);
+        let db = Database::new(temp_db.path()).unwrap();
+        assert_eq!(db.get_embeddings(&[id]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_delete_collection_without_foreign_keys() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        db.conn.pragma_update(None, "foreign_keys", false).unwrap();
+
+        let collection_id = db.create_collection("faq", None).unwrap();
+        let id = db
+            .add_document(collection_id, "Rust ownership rules", &[1.0, 0.0], None)
+            .unwrap();
+        db.add_document(collection_id, "Rust borrow checker", &[0.0, 1.0], None)
+            .unwrap();
+
+        assert!(db.delete_collection("faq").unwrap());
+        assert!(db.keyword_search("rust", None, &[]).unwrap().is_empty());
+        assert_eq!(db.keyword_count("rust", None).unwrap(), 0);
+        assert!(db.get_all_documents_with_embeddings(None).unwrap().is_empty());
+        assert!(db.get_embeddings(&[id]).unwrap().is_empty());
+        assert_eq!(fts_row_count(&db), 0);
+    }
+
+    #[test]
+    fn test_missing_rows_return_not_found_errors() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let faq = db.create_collection("faq", None).unwrap();
+        let id = db
+            .add_document(faq, "Rust ownership rules", &[1.0, 0.0], None)
+            .unwrap();
+
+        assert_eq!(db.get_collection("faq").unwrap().id, faq);
+        assert_eq!(db.get_collection_by_id(faq).unwrap().name, "faq");
+        assert_eq!(db.get_document(id).unwrap().content, "Rust ownership rules");
+
+        assert!(matches!(
+            db.get_document(id + 1),
+            Err(Error::DocumentNotFound(missing)) if missing == id + 1
+        ));
+        assert!(matches!(
+            db.get_collection("missing"),
+            Err(Error::CollectionNotFound(message)) if message.contains("'missing'")
+        ));
+        assert!(matches!(
+            db.get_collection_by_id(faq + 1),
+            Err(Error::CollectionNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_count_documents_per_collection() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let faq = db.create_collection("faq", None).unwrap();
+        let blog = db.create_collection("blog", None).unwrap();
+        let empty = db.create_collection("empty", None).unwrap();
+
+        for i in 0..3 {
+            db.add_document(faq, &format!("FAQ {}", i), &[1.0, 0.0], None)
+                .unwrap();
+        }
+        for i in 0..5 {
+            db.add_document(blog, &format!("Blog {}", i), &[0.0, 1.0], None)
+                .unwrap();
+        }
+
+        assert_eq!(db.count_documents(Some(faq)).unwrap(), 3);
+        assert_eq!(db.count_documents(Some(blog)).unwrap(), 5);
+        assert_eq!(db.count_documents(Some(empty)).unwrap(), 0);
+        assert_eq!(db.count_documents(None).unwrap(), 8);
+
+        // ページングしても総件数は変わらない
+        let page = db.list_documents(Some(blog), 2, 4).unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(db.count_documents(Some(blog)).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_collection_generation_tracks_embedding_changes() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_db.path()).unwrap();
+        let faq = db.create_collection("faq", None).unwrap();
+        let blog = db.create_collection("blog", None).unwrap();
+        let generation = |id| db.collection_generation(id).unwrap().unwrap();
+        assert_eq!(generation(faq), 0);
+
+        let first = db.add_document(faq, "First", &[1.0, 0.0], None).unwrap();
+        let second = db.add_document(faq, "Second", &[0.0, 1.0], None).unwrap();
+        db.add_document(blog, "Other", &[1.0, 0.0], None).unwrap();
+        let added = generation(faq);
+        assert_eq!(added, 2);
+        assert_eq!(db.changed_document_ids(faq, 1).unwrap(), vec![second]);
+
+        // メタデータだけの更新では進めない
+        let metadata = serde_json::json!({ "lang": "en" });
+        db.update_document(first, None, None, Some(&metadata))
+            .unwrap();
+        assert_eq!(generation(faq), added);
+
+        // Embeddingの更新と削除で進む
+        db.update_document(first, Some("Updated"), Some(&[0.5, 0.5]), None)
+            .unwrap();
+        assert_eq!(generation(faq), added + 1);
+        assert_eq!(db.changed_document_ids(faq, added).unwrap(), vec![first]);
+        db.delete_document(second).unwrap();
+        assert_eq!(generation(faq), added + 2);
+        assert_eq!(generation(blog), 1);
     }
 }
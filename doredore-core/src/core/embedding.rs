@@ -1,64 +1,442 @@
 use crate::error::{Error, Result};
-use fastembed::{EmbeddingModel as FastEmbedModel, InitOptions, TextEmbedding};
+use fastembed::{
+    EmbeddingModel as FastEmbedModel, InitOptions, InitOptionsUserDefined, ModelInfo,
+    TextEmbedding, TokenizerFiles, UserDefinedEmbeddingModel,
+};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
-pub struct EmbeddingModel {
-    model: Arc<TextEmbedding>,
+/// 短い名前で指定できるfastembedのモデルと次元数
+///
+/// ここにないfastembedのモデルも、バリアント名やHugging Faceのモデル名で指定できる
+/// （[`EmbeddingModel::new`] を参照）
+const KNOWN_MODELS: &[(&str, FastEmbedModel, usize)] = &[
+    ("bge-small-en-v1.5", FastEmbedModel::BGESmallENV15, 384),
+    ("bge-base-en-v1.5", FastEmbedModel::BGEBaseENV15, 768),
+    ("bge-large-en-v1.5", FastEmbedModel::BGELargeENV15, 1024),
+    ("bge-small-zh-v1.5", FastEmbedModel::BGESmallZHV15, 512),
+    ("bge-large-zh-v1.5", FastEmbedModel::BGELargeZHV15, 1024),
+    (
+        "multilingual-e5-small",
+        FastEmbedModel::MultilingualE5Small,
+        384,
+    ),
+    (
+        "multilingual-e5-base",
+        FastEmbedModel::MultilingualE5Base,
+        768,
+    ),
+    (
+        "multilingual-e5-large",
+        FastEmbedModel::MultilingualE5Large,
+        1024,
+    ),
+    ("all-minilm-l6-v2", FastEmbedModel::AllMiniLML6V2, 384),
+    ("all-minilm-l12-v2", FastEmbedModel::AllMiniLML12V2, 384),
+    (
+        "paraphrase-multilingual-minilm-l12-v2",
+        FastEmbedModel::ParaphraseMLMiniLML12V2,
+        384,
+    ),
+    (
+        "paraphrase-multilingual-mpnet-base-v2",
+        FastEmbedModel::ParaphraseMLMpnetBaseV2,
+        768,
+    ),
+    ("nomic-embed-text-v1", FastEmbedModel::NomicEmbedTextV1, 768),
+    (
+        "nomic-embed-text-v1.5",
+        FastEmbedModel::NomicEmbedTextV15,
+        768,
+    ),
+    (
+        "mxbai-embed-large-v1",
+        FastEmbedModel::MxbaiEmbedLargeV1,
+        1024,
+    ),
+    ("gte-base-en-v1.5", FastEmbedModel::GTEBaseENV15, 768),
+    ("gte-large-en-v1.5", FastEmbedModel::GTELargeENV15, 1024),
+    (
+        "jina-embeddings-v2-base-code",
+        FastEmbedModel::JinaEmbeddingsV2BaseCode,
+        768,
+    ),
+    (
+        "modernbert-embed-large",
+        FastEmbedModel::ModernBertEmbedLarge,
+        1024,
+    ),
+];
+
+/// E5系モデルの学習時の入力形式に合わせて付ける接頭辞（クエリ、パッセージ）
+const E5_PREFIXES: (&str, &str) = ("query: ", "passage: ");
+
+/// nomic-embed-text系モデルの学習時の入力形式に合わせて付ける接頭辞（クエリ、パッセージ）
+const NOMIC_PREFIXES: (&str, &str) = ("search_query: ", "search_document: ");
+
+/// fastembedのモデルのロード方法（[`EmbeddingModel::with_load_options`] で使用）
+#[derive(Debug, Clone)]
+pub struct ModelLoadOptions {
+    /// モデルのダウンロードを試行する最大回数（0は1として扱う）
+    ///
+    /// キャッシュ済みのモデルファイルを読み込めない場合（破損など）は再試行しない
+    pub max_attempts: usize,
+    /// 最初の再試行までの待ち時間（再試行のたびに2倍になる）
+    pub retry_delay: Duration,
+    /// ダウンロード済みのモデルファイルを置いたディレクトリ
+    ///
+    /// 指定するとキャッシュディレクトリとネットワークを使わず、このディレクトリから読み込む。
+    /// Hugging Faceのリポジトリと同じ配置（`onnx/model.onnx` と `tokenizer.json`・`config.json`・
+    /// `special_tokens_map.json`・`tokenizer_config.json`）にする
+    pub model_dir: Option<PathBuf>,
+}
+
+impl Default for ModelLoadOptions {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            retry_delay: Duration::from_secs(1),
+            model_dir: None,
+        }
+    }
+}
+
+/// Embeddingを生成するバックエンド
+///
+/// fastembedのモデル以外（テスト用の [`crate::MockEmbedder`] や外部のEmbedding APIなど）を
+/// 使う場合に実装し、[`EmbeddingModel::from_embedder`] または
+/// `Doredore::new_with_embedder` に渡す
+pub trait Embedder: Send + Sync {
+    /// 複数のテキストをまとめてベクトル化（入力と同じ件数・順序で返す）
+    fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>>;
+
+    /// 生成するベクトルの次元数
+    fn dimension(&self) -> usize;
+
+    /// モデル名（ログや `Doredore::model_name` に使用）
+    fn model_name(&self) -> &str;
+}
+
+/// fastembedのモデルによるEmbedder（`EmbeddingModel::new` で使用）
+struct FastEmbedder {
+    model: TextEmbedding,
+    model_name: String,
     dimension: usize,
 }
 
+impl Embedder for FastEmbedder {
+    fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.model
+            .embed(texts, None)
+            .map_err(|e| Error::Embedding(format!("Failed to generate embeddings: {}", e)))
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model_name
+    }
+}
+
+/// Embeddingモデル
+///
+/// 内部のモデルは `Arc` で共有されるため、`clone` はモデルを再ロードせず
+/// 同じモデルを参照する（複数の `Doredore` インスタンスで共有可能）
+#[derive(Clone)]
+pub struct EmbeddingModel {
+    embedder: Arc<dyn Embedder>,
+    max_input_chars: Option<usize>,
+    query_prefix: String,
+    passage_prefix: String,
+}
+
 impl EmbeddingModel {
+    /// Embeddingモデルを初期化
+    ///
+    /// # キャッシュディレクトリの決定順序
+    /// 1. 引数 `cache_dir`
+    /// 2. 環境変数 `FASTEMBED_CACHE_PATH`
+    /// 3. OS標準のキャッシュディレクトリ配下の `doredore/models`
+    ///    （例: Linux `~/.cache/doredore/models`、macOS `~/Library/Caches/doredore/models`）
+    /// 4. カレントディレクトリの `.fastembed_cache`（OSのキャッシュディレクトリが不明な場合）
+    ///
+    /// ディレクトリが存在しなければ作成し、書き込みできない場合は
+    /// モデルのダウンロード前に `Error::Io` を返す
+    ///
+    /// # モデル名
+    /// 1. [`EmbeddingModel::list_supported_models`] の短い名前（`gte-base-en-v1.5` など）
+    /// 2. fastembedの `EmbeddingModel` のバリアント名（`GTEBaseENV15Q` など、量子化版も指定できる）
+    /// 3. Hugging Faceのモデル名（`Alibaba-NLP/gte-base-en-v1.5` など、大文字小文字は区別しない）。
+    ///    量子化版と同じ名前で1つに決まらない場合はバリアント名で指定する
+    ///
+    /// 次元数は短い名前の一覧にあればその値、なければfastembedのモデル情報から取得する
+    ///
+    /// モデルのダウンロードに失敗した場合は間隔を空けて最大3回まで試行する
+    /// （変更する場合やダウンロード済みのモデルを使う場合は [`EmbeddingModel::with_load_options`]）
     pub fn new(model_name: Option<&str>, cache_dir: Option<&str>) -> Result<Self> {
-        let model_type = match model_name {
-            Some("bge-small-en-v1.5") | None => FastEmbedModel::BGESmallENV15,
-            Some("bge-base-en-v1.5") => FastEmbedModel::BGEBaseENV15,
-            Some("bge-large-en-v1.5") => FastEmbedModel::BGELargeENV15,
-            Some("multilingual-e5-small") => FastEmbedModel::MultilingualE5Small,
-            Some("multilingual-e5-base") => FastEmbedModel::MultilingualE5Base,
-            Some(name) => {
-                return Err(Error::InvalidInput(format!(
-                    "Unsupported model: {}",
-                    name
-                )))
+        Self::with_load_options(model_name, cache_dir, &ModelLoadOptions::default())
+    }
+
+    /// ロード方法を指定してEmbeddingモデルを初期化
+    ///
+    /// `options.model_dir` を指定した場合は `cache_dir` を使わない。
+    /// ダウンロードの失敗（ネットワークの問題）とキャッシュ済みのモデルファイルの読み込み失敗
+    /// （キャッシュの破損）は、それぞれ異なるメッセージの `Error::Embedding` になる
+    ///
+    /// ```no_run
+    /// use doredore_core::{EmbeddingModel, ModelLoadOptions};
+    ///
+    /// let options = ModelLoadOptions {
+    ///     model_dir: Some("./models/bge-small-en-v1.5".into()),
+    ///     ..Default::default()
+    /// };
+    /// let model = EmbeddingModel::with_load_options(Some("bge-small-en-v1.5"), None, &options)?;
+    /// # Ok::<(), doredore_core::Error>(())
+    /// ```
+    pub fn with_load_options(
+        model_name: Option<&str>,
+        cache_dir: Option<&str>,
+        options: &ModelLoadOptions,
+    ) -> Result<Self> {
+        let model_name = model_name.unwrap_or("bge-small-en-v1.5");
+        let model_type = Self::model_type(model_name).ok_or_else(|| {
+            Error::InvalidInput(format!("Unsupported model: {}", model_name))
+        })?;
+
+        let dimension = Self::get_model_dimension(&model_type).ok_or_else(|| {
+            Error::InvalidInput(format!("Unknown dimension for model: {}", model_name))
+        })?;
+
+        let model = match &options.model_dir {
+            Some(model_dir) => load_from_model_dir(&model_type, model_dir)?,
+            None => {
+                let cache_dir = resolve_cache_dir(cache_dir);
+                ensure_writable_dir(&cache_dir)?;
+                load_from_cache(&model_type, &cache_dir, options)?
             }
         };
 
-        let dimension = Self::get_model_dimension(&model_type);
+        Ok(Self::from_embedder(FastEmbedder {
+            model,
+            model_name: model_name.to_string(),
+            dimension,
+        }))
+    }
 
-        let mut options = InitOptions::new(model_type);
-        if let Some(dir) = cache_dir {
-            options = options.with_cache_dir(dir.into());
+    /// 任意の [`Embedder`] からEmbeddingモデルを作成
+    ///
+    /// fastembedのモデルをロードしないため、テスト（[`crate::MockEmbedder`]）や
+    /// 外部のEmbedding APIを使う場合に使用する。
+    /// クエリ・パッセージの接頭辞はモデル名から決まる（[`EmbeddingModel::with_input_prefixes`] を参照）
+    pub fn from_embedder<E: Embedder + 'static>(embedder: E) -> Self {
+        let (query_prefix, passage_prefix) = default_prefixes(embedder.model_name());
+        Self {
+            embedder: Arc::new(embedder),
+            max_input_chars: None,
+            query_prefix: query_prefix.to_string(),
+            passage_prefix: passage_prefix.to_string(),
         }
+    }
 
-        let model = TextEmbedding::try_new(options)
-            .map_err(|e| Error::Embedding(format!("Failed to initialize embedding model: {}", e)))?;
+    /// サポートしているモデル名と次元数の一覧
+    ///
+    /// いずれのモデル名も `EmbeddingModel::new` にそのまま渡せる。
+    /// 一覧にないfastembedのモデルも指定できる（[`EmbeddingModel::is_supported`] で確認できる）
+    pub fn list_supported_models() -> Vec<(&'static str, usize)> {
+        KNOWN_MODELS
+            .iter()
+            .map(|(name, _, dimension)| (*name, *dimension))
+            .collect()
+    }
 
-        Ok(Self {
-            model: Arc::new(model),
-            dimension,
-        })
+    /// `EmbeddingModel::new` に渡せるモデル名かどうか（モデルはロードしない）
+    pub fn is_supported(model_name: &str) -> bool {
+        Self::model_type(model_name).is_some()
     }
 
-    fn get_model_dimension(model: &FastEmbedModel) -> usize {
-        match model {
-            FastEmbedModel::BGESmallENV15 => 384,
-            FastEmbedModel::BGEBaseENV15 => 768,
-            FastEmbedModel::BGELargeENV15 => 1024,
-            FastEmbedModel::MultilingualE5Small => 384,
-            FastEmbedModel::MultilingualE5Base => 768,
-            _ => 384, // デフォルト
+    fn model_type(model_name: &str) -> Option<FastEmbedModel> {
+        if let Some((_, model, _)) = KNOWN_MODELS.iter().find(|(name, ..)| *name == model_name) {
+            return Some(model.clone());
+        }
+
+        // 一覧にないモデルはfastembedのモデル情報からバリアント名・Hugging Faceのモデル名で探す
+        let models = TextEmbedding::list_supported_models();
+        if let Some(info) = models
+            .iter()
+            .find(|info| format!("{:?}", info.model) == model_name)
+        {
+            return Some(info.model.clone());
+        }
+        let mut matches = models
+            .iter()
+            .filter(|info| info.model_code.eq_ignore_ascii_case(model_name));
+        match (matches.next(), matches.next()) {
+            (Some(info), None) => Some(info.model.clone()),
+            _ => None,
         }
     }
 
+    fn get_model_dimension(model: &FastEmbedModel) -> Option<usize> {
+        KNOWN_MODELS
+            .iter()
+            .find(|(_, known, _)| known == model)
+            .map(|(.., dimension)| *dimension)
+            .or_else(|| model_info(model).map(|info| info.dim))
+    }
+
     pub fn dimension(&self) -> usize {
-        self.dimension
+        self.embedder.dimension()
     }
 
+    /// モデルが一度に処理できる最大トークン数
+    ///
+    /// | モデル | 最大トークン数 |
+    /// |--------|----------------|
+    /// | bge-small-en-v1.5 / bge-base-en-v1.5 / bge-large-en-v1.5 | 512 |
+    /// | multilingual-e5-small / multilingual-e5-base | 512 |
+    ///
+    /// これを超える部分はモデル内部で黙って切り捨てられる。
+    /// 文字数の目安は英語で1トークン約4文字（512トークン ≒ 2,000文字）、
+    /// 日本語では1文字が1トークン以上になることが多い（512トークン ≒ 500文字以下）
+    pub fn max_input_tokens(&self) -> usize {
+        512
+    }
+
+    /// Embedding生成時の最大入力文字数を設定（`None` で無制限）
+    ///
+    /// 設定すると、これを超える入力は先頭から `max_chars` 文字で切り詰めてから
+    /// Embeddingを生成し、`tracing` で警告を出す。モデル内部の切り捨てと異なり
+    /// 切り詰め位置が文字数で決まるため、どこまでがEmbeddingに反映されたかが明確になる。
+    /// 長文を全体として検索対象にしたい場合は `Doredore::add_document_chunked` で分割する
+    pub fn with_max_input_chars(mut self, max_chars: Option<usize>) -> Self {
+        self.max_input_chars = max_chars;
+        self
+    }
+
+    /// 最大入力文字数（`None` は無制限）
+    pub fn max_input_chars(&self) -> Option<usize> {
+        self.max_input_chars
+    }
+
+    /// [`EmbeddingModel::embed_query`] と [`EmbeddingModel::embed_passage`] で入力に付ける接頭辞を設定
+    ///
+    /// 既定ではモデル名に `e5-` を含むモデル（`multilingual-e5-small` など）は
+    /// `"query: "` / `"passage: "`、`nomic-embed-text` を含むモデルは
+    /// `"search_query: "` / `"search_document: "`、それ以外（BGEなど）は接頭辞なし
+    pub fn with_input_prefixes(mut self, query_prefix: &str, passage_prefix: &str) -> Self {
+        self.query_prefix = query_prefix.to_string();
+        self.passage_prefix = passage_prefix.to_string();
+        self
+    }
+
+    /// 検索クエリに付ける接頭辞
+    pub fn query_prefix(&self) -> &str {
+        &self.query_prefix
+    }
+
+    /// 登録するドキュメントに付ける接頭辞
+    pub fn passage_prefix(&self) -> &str {
+        &self.passage_prefix
+    }
+
+    /// 入力が最大入力文字数を超えていて、Embedding生成時に切り詰められるかどうか
+    pub fn exceeds_max_input(&self, text: &str) -> bool {
+        self.max_input_chars
+            .is_some_and(|max| text.chars().nth(max).is_some())
+    }
+
+    /// 最大入力文字数で切り詰める位置（バイト単位、超えていなければNone）
+    fn truncation_end(&self, text: &str) -> Option<usize> {
+        let max = self.max_input_chars?;
+        text.char_indices().nth(max).map(|(end, _)| end)
+    }
+
+    /// 最大入力文字数で切り詰める（超えていなければそのまま）
+    fn truncate_input<'a>(&self, text: &'a str) -> &'a str {
+        match self.truncation_end(text) {
+            Some(end) => {
+                tracing::warn!(
+                    "Embedding input truncated from {} to {} chars (model: {})",
+                    text.chars().count(),
+                    self.max_input_chars.unwrap_or_default(),
+                    self.model_name()
+                );
+                &text[..end]
+            }
+            None => text,
+        }
+    }
+
+    /// [`EmbeddingModel::embed_passage`] でモデルに渡す入力（接頭辞 + 切り詰めたテキスト）の
+    /// SHA-256の16進文字列
+    ///
+    /// Embeddingキャッシュのキーに使う。接頭辞や最大入力文字数を変えると別のキーになる
+    pub fn passage_input_hash(&self, text: &str) -> String {
+        let end = self.truncation_end(text).unwrap_or(text.len());
+        let mut hasher = Sha256::new();
+        hasher.update(self.passage_prefix.as_bytes());
+        hasher.update(&text.as_bytes()[..end]);
+        format!("{:x}", hasher.finalize())
+    }
+
+    pub fn model_name(&self) -> &str {
+        self.embedder.model_name()
+    }
+
+    /// 同じEmbedderと設定を使うモデルか（クローン同士は同じモデルになる）
+    ///
+    /// 名前が同じでも別に登録したモデルは区別する
+    pub(crate) fn is_same_model(&self, other: &EmbeddingModel) -> bool {
+        Arc::ptr_eq(&self.embedder, &other.embedder)
+            && self.max_input_chars == other.max_input_chars
+            && self.query_prefix == other.query_prefix
+    }
+
+    /// テキストをそのままベクトル化（接頭辞を付けない）
     pub fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed_with_prefix("", text)
+    }
+
+    /// 複数のテキストをそのままベクトル化（接頭辞を付けない）
+    pub fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embed_batch_with_prefix("", texts)
+    }
+
+    /// 検索クエリをベクトル化（E5系モデルでは `"query: "` を付ける）
+    pub fn embed_query(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed_with_prefix(&self.query_prefix, text)
+    }
+
+    /// 複数の検索クエリをベクトル化
+    pub fn embed_query_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embed_batch_with_prefix(&self.query_prefix, texts)
+    }
+
+    /// 登録するドキュメントをベクトル化（E5系モデルでは `"passage: "` を付ける）
+    pub fn embed_passage(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed_with_prefix(&self.passage_prefix, text)
+    }
+
+    /// 複数のドキュメントをベクトル化
+    pub fn embed_passage_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embed_batch_with_prefix(&self.passage_prefix, texts)
+    }
+
+    /// 最大入力文字数で切り詰めたテキストに接頭辞を付ける（接頭辞は文字数に含めない）
+    fn prepare_input(&self, prefix: &str, text: &str) -> String {
+        format!("{}{}", prefix, self.truncate_input(text))
+    }
+
+    fn embed_with_prefix(&self, prefix: &str, text: &str) -> Result<Vec<f32>> {
+        // Embedding生成の所要時間の計測用（サーバーの `/metrics` などで集計する）
+        let _span = tracing::debug_span!("embed", texts = 1).entered();
         let embeddings = self
-            .model
-            .embed(vec![text.to_string()], None)
-            .map_err(|e| Error::Embedding(format!("Failed to generate embedding: {}", e)))?;
+            .embedder
+            .embed_batch(vec![self.prepare_input(prefix, text)])?;
 
         embeddings
             .into_iter()
@@ -66,19 +444,213 @@ impl EmbeddingModel {
             .ok_or_else(|| Error::Embedding("No embedding generated".to_string()))
     }
 
-    pub fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
-        let embeddings = self
-            .model
-            .embed(texts, None)
-            .map_err(|e| Error::Embedding(format!("Failed to generate embeddings: {}", e)))?;
+    fn embed_batch_with_prefix(&self, prefix: &str, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let texts = if self.max_input_chars.is_some() || !prefix.is_empty() {
+            texts
+                .iter()
+                .map(|text| self.prepare_input(prefix, text))
+                .collect()
+        } else {
+            texts
+        };
 
-        Ok(embeddings)
+        let _span = tracing::debug_span!("embed", texts = texts.len()).entered();
+        self.embedder.embed_batch(texts)
     }
 }
 
+/// モデル名から既定の接頭辞（クエリ、パッセージ）を決定
+fn default_prefixes(model_name: &str) -> (&'static str, &'static str) {
+    if model_name.contains("e5-") {
+        E5_PREFIXES
+    } else if model_name.contains("nomic-embed-text") {
+        NOMIC_PREFIXES
+    } else {
+        ("", "")
+    }
+}
+
+/// fastembedのモデル情報（Hugging Faceのモデル名・ファイル名）
+fn model_info(model: &FastEmbedModel) -> Option<ModelInfo<FastEmbedModel>> {
+    TextEmbedding::list_supported_models()
+        .into_iter()
+        .find(|info| &info.model == model)
+}
+
+/// キャッシュディレクトリからモデルを読み込む（なければダウンロードし、失敗したら再試行する）
+fn load_from_cache(
+    model: &FastEmbedModel,
+    cache_dir: &Path,
+    options: &ModelLoadOptions,
+) -> Result<TextEmbedding> {
+    let init = || {
+        TextEmbedding::try_new(
+            InitOptions::new(model.clone()).with_cache_dir(cache_dir.to_path_buf()),
+        )
+    };
+
+    // キャッシュ済みのファイルを読み込めないのはネットワークの問題ではないため再試行しない
+    if is_cached(model, cache_dir) {
+        return init().map_err(|e| {
+            Error::Embedding(format!(
+                "Failed to load embedding model from cache directory '{}' \
+                 (the cached files may be corrupt; delete them to download again): {}",
+                cache_dir.display(),
+                e
+            ))
+        });
+    }
+
+    let max_attempts = options.max_attempts.max(1);
+    retry_with_backoff(max_attempts, options.retry_delay, init).map_err(|e| {
+        Error::Embedding(format!(
+            "Failed to download embedding model after {} attempt(s) \
+             (check the network connection, or set `ModelLoadOptions::model_dir` \
+             to a pre-downloaded model): {}",
+            max_attempts, e
+        ))
+    })
+}
+
+/// `f` が成功するまで最大 `max_attempts` 回試行する（待ち時間は `delay` から毎回2倍）
+fn retry_with_backoff<T, E: std::fmt::Display>(
+    max_attempts: usize,
+    delay: Duration,
+    mut f: impl FnMut() -> std::result::Result<T, E>,
+) -> std::result::Result<T, E> {
+    let mut delay = delay;
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < max_attempts => {
+                tracing::warn!(
+                    attempt,
+                    max_attempts,
+                    error = %e,
+                    "Failed to download embedding model, retrying in {:?}",
+                    delay
+                );
+                std::thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// モデルファイルがキャッシュディレクトリにダウンロード済みかどうか
+///
+/// fastembed（hf-hub）のキャッシュの配置 `models--{組織}--{名前}/snapshots/{コミット}/` を見る。
+/// fastembedと同じく環境変数 `HF_HOME` が設定されていればそちらを優先する
+fn is_cached(model: &FastEmbedModel, cache_dir: &Path) -> bool {
+    let Some(info) = model_info(model) else {
+        return false;
+    };
+    let cache_dir = std::env::var("HF_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| cache_dir.to_path_buf());
+    let repo_dir = cache_dir.join(format!("models--{}", info.model_code.replace('/', "--")));
+    let Ok(commit) = std::fs::read_to_string(repo_dir.join("refs").join("main")) else {
+        return false;
+    };
+    repo_dir
+        .join("snapshots")
+        .join(commit.trim())
+        .join(&info.model_file)
+        .is_file()
+}
+
+/// ダウンロード済みのモデルファイルを置いたディレクトリからモデルを読み込む（ネットワークを使わない）
+fn load_from_model_dir(model: &FastEmbedModel, model_dir: &Path) -> Result<TextEmbedding> {
+    let info = model_info(model).ok_or_else(|| {
+        Error::InvalidInput(format!("No model information for {:?}", model))
+    })?;
+    if !info.additional_files.is_empty() {
+        // 外部データファイルに分割されたONNXモデルはメモリから読み込めない
+        return Err(Error::InvalidInput(format!(
+            "{:?} cannot be loaded from a model directory; use the cache directory instead",
+            model
+        )));
+    }
+
+    let read = |name: &str| {
+        std::fs::read(model_dir.join(name)).map_err(|e| {
+            Error::Io(std::io::Error::new(
+                e.kind(),
+                format!(
+                    "Model directory '{}' has no readable '{}': {}",
+                    model_dir.display(),
+                    name,
+                    e
+                ),
+            ))
+        })
+    };
+    let onnx_file = read(&info.model_file)?;
+    let tokenizer_files = TokenizerFiles {
+        tokenizer_file: read("tokenizer.json")?,
+        config_file: read("config.json")?,
+        special_tokens_map_file: read("special_tokens_map.json")?,
+        tokenizer_config_file: read("tokenizer_config.json")?,
+    };
+
+    let mut user_defined = UserDefinedEmbeddingModel::new(onnx_file, tokenizer_files)
+        .with_quantization(TextEmbedding::get_quantization_mode(model));
+    if let Some(pooling) = TextEmbedding::get_default_pooling_method(model) {
+        user_defined = user_defined.with_pooling(pooling);
+    }
+
+    TextEmbedding::try_new_from_user_defined(user_defined, InitOptionsUserDefined::new()).map_err(
+        |e| {
+            Error::Embedding(format!(
+                "Failed to load embedding model from '{}' (the model files may be corrupt): {}",
+                model_dir.display(),
+                e
+            ))
+        },
+    )
+}
+
+/// モデルキャッシュディレクトリを決定（決定順序は `EmbeddingModel::new` を参照）
+fn resolve_cache_dir(cache_dir: Option<&str>) -> PathBuf {
+    if let Some(dir) = cache_dir {
+        return PathBuf::from(dir);
+    }
+
+    if let Ok(dir) = std::env::var("FASTEMBED_CACHE_PATH") {
+        return PathBuf::from(dir);
+    }
+
+    dirs::cache_dir()
+        .map(|dir| dir.join("doredore").join("models"))
+        .unwrap_or_else(|| PathBuf::from(".fastembed_cache"))
+}
+
+/// ディレクトリを作成し、書き込み可能であることを確認
+fn ensure_writable_dir(dir: &Path) -> Result<()> {
+    let with_context = |e: std::io::Error| {
+        Error::Io(std::io::Error::new(
+            e.kind(),
+            format!("Cache directory '{}' is not usable: {}", dir.display(), e),
+        ))
+    };
+
+    std::fs::create_dir_all(dir).map_err(with_context)?;
+
+    // 実際にファイルを書き込めるか確認（読み取り専用マウントなどを検出）
+    let probe = dir.join(".doredore_write_test");
+    std::fs::write(&probe, b"").map_err(with_context)?;
+    let _ = std::fs::remove_file(&probe);
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::search::cosine_similarity;
 
     #[test]
     fn test_embedding_model_initialization() {
@@ -86,6 +658,145 @@ mod tests {
         assert!(model.is_ok());
     }
 
+    #[test]
+    fn test_unusable_cache_dir() {
+        // 通常ファイルの配下にはディレクトリを作成できない
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let cache_dir = file.path().join("models");
+
+        let result = EmbeddingModel::new(Some("bge-small-en-v1.5"), cache_dir.to_str());
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_corrupt_cache_is_reported_without_retrying() {
+        // ダウンロード済みに見えるが中身が壊れているキャッシュ
+        let cache_dir = tempfile::tempdir().unwrap();
+        let repo_dir = cache_dir.path().join("models--Xenova--bge-small-en-v1.5");
+        std::fs::create_dir_all(repo_dir.join("refs")).unwrap();
+        std::fs::write(repo_dir.join("refs").join("main"), "abc123").unwrap();
+        let snapshot = repo_dir.join("snapshots").join("abc123");
+        std::fs::create_dir_all(snapshot.join("onnx")).unwrap();
+        for file in [
+            "onnx/model.onnx",
+            "tokenizer.json",
+            "config.json",
+            "special_tokens_map.json",
+            "tokenizer_config.json",
+        ] {
+            std::fs::write(snapshot.join(file), b"not a model").unwrap();
+        }
+
+        let options = ModelLoadOptions {
+            retry_delay: Duration::from_secs(60),
+            ..Default::default()
+        };
+        let result = EmbeddingModel::with_load_options(
+            Some("bge-small-en-v1.5"),
+            cache_dir.path().to_str(),
+            &options,
+        );
+        let message = match result {
+            Err(Error::Embedding(message)) => message,
+            other => panic!(
+                "unexpected result: {:?}",
+                other.map(|m| m.model_name().to_string())
+            ),
+        };
+        assert!(message.contains("may be corrupt"), "{}", message);
+        assert!(message.contains(&cache_dir.path().display().to_string()));
+    }
+
+    #[test]
+    fn test_incomplete_model_dir() {
+        let model_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(model_dir.path().join("onnx")).unwrap();
+        std::fs::write(model_dir.path().join("onnx").join("model.onnx"), b"").unwrap();
+
+        let options = ModelLoadOptions {
+            model_dir: Some(model_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let result = EmbeddingModel::with_load_options(Some("bge-small-en-v1.5"), None, &options);
+        match result {
+            Err(Error::Io(e)) => assert!(e.to_string().contains("tokenizer.json"), "{}", e),
+            other => panic!(
+                "unexpected result: {:?}",
+                other.map(|m| m.model_name().to_string())
+            ),
+        }
+    }
+
+    #[test]
+    fn test_retry_with_backoff() {
+        let mut calls = 0;
+        let result = retry_with_backoff(3, Duration::ZERO, || {
+            calls += 1;
+            if calls < 3 {
+                Err("network")
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result, Ok(3));
+
+        let mut calls = 0;
+        let result: std::result::Result<(), _> = retry_with_backoff(2, Duration::ZERO, || {
+            calls += 1;
+            Err("network")
+        });
+        assert_eq!(result, Err("network"));
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_list_supported_models() {
+        let models = EmbeddingModel::list_supported_models();
+        assert_eq!(models.len(), KNOWN_MODELS.len());
+        assert!(models.contains(&("bge-small-en-v1.5", 384)));
+        assert!(models.contains(&("bge-large-en-v1.5", 1024)));
+        assert!(models.contains(&("gte-base-en-v1.5", 768)));
+        assert!(models.contains(&("jina-embeddings-v2-base-code", 768)));
+
+        // 一覧の次元数はfastembedのモデル情報と一致する
+        for (name, model, dimension) in KNOWN_MODELS {
+            let info = TextEmbedding::list_supported_models()
+                .into_iter()
+                .find(|info| &info.model == model)
+                .unwrap();
+            assert_eq!(info.dim, *dimension, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_resolve_models_outside_the_list() {
+        // バリアント名（量子化版）とHugging Faceのモデル名は、fastembedのモデル情報から次元数を得る
+        let model = EmbeddingModel::model_type("GTELargeENV15Q").unwrap();
+        assert_eq!(model, FastEmbedModel::GTELargeENV15Q);
+        assert_eq!(EmbeddingModel::get_model_dimension(&model), Some(1024));
+
+        let model = EmbeddingModel::model_type("xenova/bge-small-zh-v1.5").unwrap();
+        assert_eq!(model, FastEmbedModel::BGESmallZHV15);
+        assert_eq!(EmbeddingModel::get_model_dimension(&model), Some(512));
+
+        // 量子化版と同じHugging Faceのモデル名は1つに決まらない
+        assert!(!EmbeddingModel::is_supported(
+            "Alibaba-NLP/gte-base-en-v1.5"
+        ));
+        assert!(!EmbeddingModel::is_supported("unknown-model"));
+        assert!(matches!(
+            EmbeddingModel::new(Some("unknown-model"), None),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_newly_supported_model_dimension() {
+        let model = EmbeddingModel::new(Some("all-minilm-l6-v2"), None).unwrap();
+        assert_eq!(model.dimension(), 384);
+        assert_eq!(model.embed("Hello, world!").unwrap().len(), 384);
+    }
+
     #[test]
     fn test_embed_single_text() {
         let model = EmbeddingModel::new(Some("bge-small-en-v1.5"), None).unwrap();
@@ -95,6 +806,134 @@ mod tests {
         assert_eq!(embedding.len(), 384);
     }
 
+    #[test]
+    fn test_max_input_chars_truncates_deterministically() {
+        let model = EmbeddingModel::new(Some("bge-small-en-v1.5"), None)
+            .unwrap()
+            .with_max_input_chars(Some(20));
+
+        // マルチバイト文字の途中で切らないこと
+        let long_text = "永代供養について".repeat(1000);
+        assert!(model.exceeds_max_input(&long_text));
+        assert!(!model.exceeds_max_input(&long_text[..60]));
+        assert_eq!(model.truncate_input(&long_text).chars().count(), 20);
+
+        // 切り詰め後の先頭部分だけをEmbeddingした結果と一致する
+        let prefix: String = long_text.chars().take(20).collect();
+        let truncated = model.embed(&long_text).unwrap();
+        assert_eq!(truncated, model.embed(&prefix).unwrap());
+        assert_eq!(model.embed_batch(vec![long_text]).unwrap()[0], truncated);
+    }
+
+    /// 受け取った入力を記録するEmbedder
+    struct RecordingEmbedder {
+        model_name: &'static str,
+        inputs: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl Embedder for RecordingEmbedder {
+        fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+            let embeddings = texts.iter().map(|_| vec![1.0]).collect();
+            self.inputs.lock().unwrap().extend(texts);
+            Ok(embeddings)
+        }
+
+        fn dimension(&self) -> usize {
+            1
+        }
+
+        fn model_name(&self) -> &str {
+            self.model_name
+        }
+    }
+
+    #[test]
+    fn test_query_and_passage_prefixes() {
+        let inputs = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = |model_name| {
+            let model = EmbeddingModel::from_embedder(RecordingEmbedder {
+                model_name,
+                inputs: inputs.clone(),
+            })
+            .with_max_input_chars(Some(5));
+            model.embed_query("shipping time").unwrap();
+            model.embed_passage("delivery").unwrap();
+            model.embed_query_batch(vec!["a".to_string()]).unwrap();
+            model.embed_passage_batch(vec!["b".to_string()]).unwrap();
+            model.embed("raw").unwrap();
+            std::mem::take(&mut *inputs.lock().unwrap())
+        };
+
+        // E5系モデルは接頭辞を付ける（最大入力文字数は接頭辞を除いた本文に適用）
+        assert_eq!(
+            recorded("multilingual-e5-small"),
+            vec![
+                "query: shipp",
+                "passage: deliv",
+                "query: a",
+                "passage: b",
+                "raw"
+            ]
+        );
+        assert_eq!(
+            recorded("nomic-embed-text-v1.5"),
+            vec![
+                "search_query: shipp",
+                "search_document: deliv",
+                "search_query: a",
+                "search_document: b",
+                "raw"
+            ]
+        );
+        // BGEモデルは入力を変えない
+        assert_eq!(
+            recorded("bge-small-en-v1.5"),
+            vec!["shipp", "deliv", "a", "b", "raw"]
+        );
+
+        let model = EmbeddingModel::from_embedder(RecordingEmbedder {
+            model_name: "custom",
+            inputs: inputs.clone(),
+        })
+        .with_input_prefixes("Represent this query: ", "");
+        assert_eq!(model.query_prefix(), "Represent this query: ");
+        model.embed_query("q").unwrap();
+        assert_eq!(*inputs.lock().unwrap(), vec!["Represent this query: q"]);
+    }
+
+    #[test]
+    fn test_e5_prefixes_scores() {
+        let model = EmbeddingModel::new(Some("multilingual-e5-small"), None).unwrap();
+        let query = "How long does shipping take?";
+        let relevant = "Orders are usually delivered within 3 to 5 business days.";
+        let irrelevant = "Our office is closed on national holidays.";
+
+        let scores = |query: Vec<f32>, passages: Vec<Vec<f32>>| {
+            (
+                cosine_similarity(&query, &passages[0]),
+                cosine_similarity(&query, &passages[1]),
+            )
+        };
+        let passages = vec![relevant.to_string(), irrelevant.to_string()];
+        let with_prefixes = scores(
+            model.embed_query(query).unwrap(),
+            model.embed_passage_batch(passages.clone()).unwrap(),
+        );
+        let without_prefixes = scores(
+            model.embed(query).unwrap(),
+            model.embed_batch(passages).unwrap(),
+        );
+
+        // 接頭辞の有無でベクトルが変わり、接頭辞付きでは関連するドキュメントのスコアが高い
+        assert_ne!(with_prefixes, without_prefixes);
+        assert!(
+            with_prefixes.0 > with_prefixes.1,
+            "with prefixes: {:?}, without prefixes: {:?}",
+            with_prefixes,
+            without_prefixes
+        );
+    }
+
     #[test]
     fn test_embed_batch() {
         let model = EmbeddingModel::new(Some("bge-small-en-v1.5"), None).unwrap();
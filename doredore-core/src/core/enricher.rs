@@ -1,16 +1,64 @@
 use crate::core::{
-    collection::{Collection, Document},
-    database::Database,
-    embedding::EmbeddingModel,
-    search::{cosine_similarity, EnrichResult, SearchResult, SearchMode},
+    ann::{HnswIndex, DEFAULT_EF_SEARCH},
+    chunking::{split_into_chunks, Chunk},
+    collection::{
+        AddDocumentsResult, AddOptions, Collection, CsvImportOptions, DatabaseInfo, Document,
+    },
+    database::{Database, EmbeddedDocumentRow, EmbeddingFormat, ScoredDocumentRow},
+    embedding::{Embedder, EmbeddingModel},
+    evaluation::{sweep_thresholds, EvalPoint},
+    language::ingest_language,
+    preprocess::{normalize_content, ContentType},
+    search::{
+        cosine_similarity, cosine_similarity_matrix, min_max_keyword_scores,
+        normalize_keyword_score, reciprocal_rank_score, BoundedCosine, EnrichResult, FusionMethod,
+        KeywordNormalization, SearchDiagnostics, SearchMode, SearchOptions, SearchResult,
+    },
+    store::Store,
 };
 use crate::error::{Error, Result};
+use std::cell::RefCell;
 use std::path::Path;
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// スコア付きの検索候補 `(document_id, content, score, collection_name, metadata)`
+type ScoredDocument = ScoredDocumentRow;
+
+/// [`Doredore::import_csv_with_progress`] で1回にEmbeddingを生成・追加する件数
+pub const IMPORT_PROGRESS_BATCH_SIZE: usize = 64;
+
+/// インスタンスのモデルで最初にドキュメントを追加したときのモデル名を保存する設定のキー
+const EMBEDDING_MODEL_SETTING: &str = "embedding_model";
+
+/// 同じく、そのモデルの次元数を保存する設定のキー
+const EMBEDDING_DIMENSION_SETTING: &str = "embedding_dimension";
 
 pub struct Doredore {
-    db: Database,
+    store: Box<dyn Store>,
     embedding_model: EmbeddingModel,
+    /// コレクションごとのEmbeddingモデル（モデル名 → モデル、初めて使うときにロード）
+    /// （[`Doredore::share_models_with`] で他のインスタンスと共有できる）
+    models: Arc<Mutex<HashMap<String, EmbeddingModel>>>,
+    /// コレクションごとのモデルをロードする際のキャッシュディレクトリ
+    cache_dir: Option<String>,
+    /// ドキュメントのEmbeddingをストアにキャッシュするかどうか
+    /// （[`Doredore::set_embedding_cache`]）
+    embedding_cache: bool,
+    /// コレクションごとのANNインデックス（コレクションID → インデックス、初めて使うときに
+    /// ストアから読み込む。`None` は構築されていないことを確認済み）
+    ann_indexes: RefCell<HashMap<i64, Option<AnnIndex>>>,
+}
+
+/// 読み込み済みのANNインデックス
+struct AnnIndex {
+    index: HnswIndex,
+    /// 保存後に変更されたかどうか（[`Doredore::save_ann_indexes`] で保存する）
+    modified: bool,
+    /// 反映済みのコレクションの変更世代（[`Store::collection_generation`]、不明な場合はNone）
+    generation: Option<i64>,
 }
 
 impl Doredore {
@@ -19,31 +67,333 @@ impl Doredore {
         model: Option<&str>,
         cache_dir: Option<&str>,
     ) -> Result<Self> {
-        let db = Database::new(db_path)?;
         let embedding_model = EmbeddingModel::new(model, cache_dir)?;
+        let mut rag = Self::with_shared_model(db_path, embedding_model)?;
+        rag.cache_dir = cache_dir.map(String::from);
+        Ok(rag)
+    }
+
+    /// ロード済みのEmbeddingモデルを使ってインスタンスを作成
+    ///
+    /// `EmbeddingModel` は内部で `Arc` を使っているため、`clone()` したモデルを
+    /// 渡せば複数のインスタンス（マルチテナントなど）で1つのモデルを共有でき、
+    /// テナント数に比例したメモリ消費を避けられる
+    ///
+    /// ```no_run
+    /// use doredore_core::{Doredore, EmbeddingModel};
+    ///
+    /// let model = EmbeddingModel::new(Some("bge-small-en-v1.5"), None)?;
+    /// let tenant_a = Doredore::with_shared_model("./tenant_a.db", model.clone())?;
+    /// let tenant_b = Doredore::with_shared_model("./tenant_b.db", model)?;
+    /// # Ok::<(), doredore_core::Error>(())
+    /// ```
+    ///
+    /// # エラー
+    /// データベースに保存されたEmbeddingの次元数（インスタンスのモデルで最初にドキュメントを
+    /// 追加したときに記録する）とモデルの次元数が異なる場合は `Error::InvalidInput`。
+    /// 異なる次元数のベクトルとの類似度は常に0になり、検索結果が得られないため
+    pub fn with_shared_model<P: AsRef<Path>>(
+        db_path: P,
+        embedding_model: EmbeddingModel,
+    ) -> Result<Self> {
+        let db = Database::new(db_path)?;
+
+        let rag = Self::with_store(db, embedding_model);
+        rag.check_embedding_dimension()?;
+        Ok(rag)
+    }
 
-        Ok(Self {
-            db,
+    /// 任意の [`Store`]（ストレージバックエンド）を使ってインスタンスを作成
+    ///
+    /// SQLite以外のデータベースやインメモリのストアに差し替える場合に使う。
+    /// 通常は `new` などのコンストラクタ（SQLiteの [`Database`]）を使えばよい
+    pub fn with_store<S: Store + 'static>(store: S, embedding_model: EmbeddingModel) -> Self {
+        Self {
+            store: Box::new(store),
             embedding_model,
+            models: Arc::new(Mutex::new(HashMap::new())),
+            cache_dir: None,
+            embedding_cache: false,
+            ann_indexes: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// 任意の [`Embedder`] を使ってインスタンスを作成
+    ///
+    /// fastembedのモデルをダウンロード・ロードしないため、テストでは
+    /// [`crate::MockEmbedder`]（`mock-embedder` フィーチャー）と組み合わせて
+    /// ネットワークに依存しない高速なテストを書ける
+    pub fn new_with_embedder<P: AsRef<Path>, E: Embedder + 'static>(
+        db_path: P,
+        embedder: E,
+    ) -> Result<Self> {
+        Self::with_shared_model(db_path, EmbeddingModel::from_embedder(embedder))
+    }
+
+    /// 既存のデータベースを読み取り専用で開いてインスタンスを作成
+    ///
+    /// 検索専用のノード（読み取りレプリカなど）向け。`SQLITE_OPEN_READONLY` で開くため
+    /// 読み取り専用のボリューム上のファイルも扱え、誤って書き込むこともない。
+    /// スキーマの作成・マイグレーションは行わないため、ファイルは書き込み可能なインスタンスで
+    /// 作成済みである必要がある
+    ///
+    /// 検索・取得系のメソッドは通常どおり使える。コレクション・ドキュメントの追加/更新/削除、
+    /// `optimize` などの変更操作は `Error::ReadOnly` を返す
+    ///
+    /// ```no_run
+    /// use doredore_core::{Doredore, SearchMode};
+    ///
+    /// let rag = Doredore::new_read_only("./knowledge.db", Some("bge-small-en-v1.5"), None)?;
+    /// let results = rag.search("memorial fee", None, None, 5, 0.5, SearchMode::Semantic, None)?;
+    /// # Ok::<(), doredore_core::Error>(())
+    /// ```
+    pub fn new_read_only<P: AsRef<Path>>(
+        db_path: P,
+        model: Option<&str>,
+        cache_dir: Option<&str>,
+    ) -> Result<Self> {
+        let embedding_model = EmbeddingModel::new(model, cache_dir)?;
+        let mut rag = Self::with_shared_model_read_only(db_path, embedding_model)?;
+        rag.cache_dir = cache_dir.map(String::from);
+        Ok(rag)
+    }
+
+    /// ロード済みのEmbeddingモデルを使って既存のデータベースを読み取り専用で開く
+    ///
+    /// 同じファイルに書き込み用のインスタンスと検索用の読み取り専用インスタンスを
+    /// 別々の接続で開く場合などに、モデルを1つだけロードして共有できる
+    ///
+    /// ```no_run
+    /// use doredore_core::{Doredore, EmbeddingModel};
+    ///
+    /// let model = EmbeddingModel::new(Some("bge-small-en-v1.5"), None)?;
+    /// let writer = Doredore::with_shared_model("./knowledge.db", model.clone())?;
+    /// let reader = Doredore::with_shared_model_read_only("./knowledge.db", model)?;
+    /// # Ok::<(), doredore_core::Error>(())
+    /// ```
+    pub fn with_shared_model_read_only<P: AsRef<Path>>(
+        db_path: P,
+        embedding_model: EmbeddingModel,
+    ) -> Result<Self> {
+        let db = Database::open_read_only(db_path)?;
+
+        let rag = Self::with_store(db, embedding_model);
+        rag.check_embedding_dimension()?;
+        Ok(rag)
+    }
+
+    /// 既存のデータベースを診断（モデルをロードせずに実行できる）
+    ///
+    /// 保存済みのEmbeddingの長さから次元数を推定し、ドキュメント数・コレクション数と
+    /// 次元数の混在（複数のモデルで生成したEmbeddingが混ざっている兆候）を報告する。
+    /// どのモデルで開き直せばよいか分からないデータベースを引き継いだ場合に使う
+    ///
+    /// ```no_run
+    /// use doredore_core::Doredore;
+    ///
+    /// let info = Doredore::inspect_database("./knowledge.db")?;
+    /// if info.mixed_dimensions {
+    ///     eprintln!("Mixed embedding dimensions: {:?}", info.dimension_counts);
+    /// }
+    /// println!("Candidate models: {:?}", info.compatible_models);
+    /// # Ok::<(), doredore_core::Error>(())
+    /// ```
+    ///
+    /// データベースは読み取り専用で開くため、スキーマの作成・マイグレーションや
+    /// ジャーナルモードの変更など、ファイルへの書き込みは行わない
+    ///
+    /// # エラー
+    /// ファイルが存在しない場合は `Error::Io`（新しいデータベースを作成しない）
+    pub fn inspect_database<P: AsRef<Path>>(db_path: P) -> Result<DatabaseInfo> {
+        let path = db_path.as_ref();
+        if !path.exists() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Database file '{}' not found", path.display()),
+            )));
+        }
+
+        let db = Database::open_read_only(path)?;
+        let dimension_counts = db.embedding_dimension_counts()?;
+        let dimension = dimension_counts.first().map(|&(dim, _)| dim);
+
+        let compatible_models = EmbeddingModel::list_supported_models()
+            .into_iter()
+            .filter(|&(_, dim)| Some(dim) == dimension)
+            .map(|(name, _)| name.to_string())
+            .collect();
+
+        Ok(DatabaseInfo {
+            collection_count: db.list_collections()?.len(),
+            document_count: dimension_counts.iter().map(|&(_, count)| count).sum(),
+            dimension,
+            mixed_dimensions: dimension_counts.len() > 1,
+            dimension_counts,
+            compatible_models,
         })
     }
 
+    /// このインスタンスが使用しているEmbeddingモデル
+    /// （`clone()` して他のインスタンスと共有できる）
+    pub fn embedding_model(&self) -> &EmbeddingModel {
+        &self.embedding_model
+    }
+
+    /// Embedding生成時の最大入力文字数を設定（`None` で無制限）
+    ///
+    /// これを超えるドキュメントはEmbedding生成時に切り詰められ、
+    /// メタデータに `"truncated": true` が記録される（本文は切り詰めずに保存する）。
+    /// 詳細は [`EmbeddingModel::with_max_input_chars`] を参照
+    pub fn set_max_input_chars(&mut self, max_chars: Option<usize>) {
+        self.embedding_model = self.embedding_model.clone().with_max_input_chars(max_chars);
+        for model in self.models.lock().unwrap().values_mut() {
+            *model = model.clone().with_max_input_chars(max_chars);
+        }
+    }
+
+    /// 以降に追加・更新するドキュメントのEmbeddingの保存形式を設定（デフォルト: f32）
+    ///
+    /// [`EmbeddingFormat::Int8`] にするとデータベースのサイズを大きく減らせる代わりに、
+    /// スコアにわずかな誤差が出る。通常は `new` の直後に設定する。保存済みのドキュメントは
+    /// 変換されず、形式の異なるドキュメントが混在しても検索できる
+    pub fn set_embedding_format(&mut self, format: EmbeddingFormat) -> Result<()> {
+        self.store.set_embedding_format(format)
+    }
+
+    /// ドキュメントのEmbeddingをキャッシュするかどうかを設定（デフォルト: 無効）
+    ///
+    /// 有効にすると、ドキュメントの追加・更新時に生成したEmbeddingを、モデルへの入力
+    /// （接頭辞・切り詰めを含む）のハッシュとモデル名・次元数をキーにストアに保存し、
+    /// 同じテキストを再び取り込むときはモデルを呼び出さずに再利用する。同じドキュメントを
+    /// 繰り返し取り込み直す開発時などに使う。モデルを切り替えると別のキーになるため、
+    /// 古いモデルのEmbeddingは使われない。ドキュメントを削除してもキャッシュは残る
+    /// （[`Doredore::clear_embedding_cache`] で削除する）
+    pub fn set_embedding_cache(&mut self, enabled: bool) {
+        self.embedding_cache = enabled;
+    }
+
+    /// Embeddingキャッシュを空にして、削除した件数を返す
+    pub fn clear_embedding_cache(&self) -> Result<usize> {
+        self.store.clear_embedding_cache()
+    }
+
+    /// コレクションごとのモデルとして使うEmbeddingモデルを名前付きで登録
+    ///
+    /// [`Doredore::create_collection_with_model`] でこの名前を指定したコレクションは、
+    /// fastembedのモデルをロードせずにこのモデルを使う。ロード済みのモデルの共有や、
+    /// 独自の [`Embedder`]（外部のEmbedding APIなど）をコレクション単位で使う場合に登録する
+    pub fn register_model(&mut self, name: &str, model: EmbeddingModel) {
+        let model = model.with_max_input_chars(self.embedding_model.max_input_chars());
+        self.models.lock().unwrap().insert(name.to_string(), model);
+    }
+
+    /// コレクションごとのEmbeddingモデルの登録先を `other` と共有する
+    ///
+    /// 以降、どちらかのインスタンスで登録・ロードしたモデルはもう一方でも使われ、
+    /// 同じファイルを開いた複数のインスタンス（読み取り専用のプールなど）で
+    /// コレクションごとのモデルを重複してロードしない。このインスタンスに登録済みのモデルは破棄される。
+    /// ANNインデックスはインスタンスごとに読み込まれる（共有されない）
+    pub fn share_models_with(&mut self, other: &Doredore) {
+        self.models = Arc::clone(&other.models);
+    }
+
+    // インスタンス情報
+
+    /// 使用中のEmbeddingモデル名
+    pub fn model_name(&self) -> &str {
+        self.embedding_model.model_name()
+    }
+
+    /// Embeddingベクトルの次元数
+    pub fn dimension(&self) -> usize {
+        self.embedding_model.dimension()
+    }
+
+    /// テキストをインスタンスのモデルでベクトル化（デバッグ・外部での類似度計算用）
+    ///
+    /// 検索品質を調べる際に、Embedding自体が期待どおりかを確認するためのもの。
+    /// E5系モデルの接頭辞は付けないため、検索時のクエリと同じベクトルが必要な場合は
+    /// `embedding_model().embed_query` を使う
+    pub fn embed_text(&self, text: &str) -> Result<Vec<f32>> {
+        self.embedding_model.embed(text)
+    }
+
+    /// 読み取り専用で開いているかどうか（[`Doredore::new_read_only`]）
+    pub fn is_read_only(&self) -> bool {
+        self.store.is_read_only()
+    }
+
+    /// データベースファイルのパス
+    pub fn db_path(&self) -> &Path {
+        self.store.path()
+    }
+
     // コレクション管理
 
     pub fn create_collection(&self, name: &str, description: Option<&str>) -> Result<i64> {
-        self.db.create_collection(name, description)
+        self.store.create_collection(name, description)
+    }
+
+    /// Embeddingモデルを指定してコレクションを作成
+    ///
+    /// コレクションへの追加・検索ではこのモデルでEmbeddingを生成する（初めて使うときに
+    /// ロードし、以降はインスタンス内で使い回す）。`model` がNoneの場合は `create_collection` と
+    /// 同じくインスタンスのモデルを使う。英語のFAQと多言語のフォーラムのように、
+    /// コレクションごとに適したモデルを使い分ける用途
+    ///
+    /// 複数のモデルのコレクションをまとめて検索した場合、クエリはモデルごとにベクトル化し、
+    /// 同じモデルのドキュメントとだけ比較する（スコアは各モデルのコサイン類似度のまま統合する）
+    ///
+    /// ```no_run
+    /// use doredore_core::Doredore;
+    ///
+    /// let rag = Doredore::new("./knowledge.db", Some("bge-small-en-v1.5"), None)?;
+    /// rag.create_collection("faq", None)?;
+    /// rag.create_collection_with_model("forum", None, Some("multilingual-e5-small"))?;
+    /// # Ok::<(), doredore_core::Error>(())
+    /// ```
+    ///
+    /// # エラー
+    /// `model` がサポート対象（[`EmbeddingModel::is_supported`]）でも、
+    /// [`Doredore::register_model`] で登録した名前でもない場合は `Error::InvalidInput`
+    pub fn create_collection_with_model(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        model: Option<&str>,
+    ) -> Result<i64> {
+        if let Some(model) = model {
+            let known = model == self.model_name()
+                || self.models.lock().unwrap().contains_key(model)
+                || EmbeddingModel::is_supported(model);
+            if !known {
+                return Err(Error::InvalidInput(format!(
+                    "Unsupported embedding model: '{}'",
+                    model
+                )));
+            }
+        }
+
+        self.store.create_collection_with_model(name, description, model)
     }
 
     pub fn get_collection(&self, name: &str) -> Result<Collection> {
-        self.db.get_collection(name)
+        self.store.get_collection(name)
     }
 
     pub fn list_collections(&self) -> Result<Vec<Collection>> {
-        self.db.list_collections()
+        self.store.list_collections()
     }
 
     pub fn delete_collection(&self, name: &str) -> Result<bool> {
-        self.db.delete_collection(name)
+        let collection_id = match self.store.get_collection(name) {
+            Ok(coll) => coll.id,
+            Err(Error::CollectionNotFound(_)) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let deleted = self.store.delete_collection(name)?;
+        self.ann_indexes.borrow_mut().remove(&collection_id);
+        Ok(deleted)
     }
 
     // ドキュメント管理
@@ -55,616 +405,6577 @@ impl Doredore {
         metadata: Option<&serde_json::Value>,
     ) -> Result<i64> {
         // コレクションIDを取得
-        let coll = self.db.get_collection(collection).map_err(|_| {
-            Error::CollectionNotFound(format!("Collection '{}' not found", collection))
-        })?;
+        let coll = self.store.get_collection(collection)?;
 
         // Embedding生成
-        let embedding = self.embedding_model.embed(content)?;
+        let embedding = self.embed_passage(&self.collection_model(&coll)?, content)?;
+        let annotated = self.annotate_metadata(content, metadata);
 
         // ドキュメント追加
-        self.db
-            .add_document(coll.id, content, &embedding, metadata)
+        let id = self.store.add_document(
+            coll.id,
+            content,
+            &embedding,
+            annotated.as_ref().or(metadata),
+        )?;
+        self.ann_insert(coll.id, id, &embedding);
+        Ok(id)
     }
 
-    pub fn add_documents(
+    /// 同じ内容のドキュメントがなければ追加（重複スキップ版の `add_document`）
+    ///
+    /// 同じコレクションに内容が同一（前後・連続する空白の違いは無視）のドキュメントが
+    /// 既にある場合は、追加せずに既存のドキュメントIDを返す（Embeddingも生成しない）。
+    /// 既存ドキュメントのメタデータは更新しない
+    pub fn add_document_unique(
         &self,
-        documents: Vec<String>,
+        content: &str,
         collection: &str,
-        metadata: Option<Vec<serde_json::Value>>,
-    ) -> Result<Vec<i64>> {
-        // コレクションIDを取得
-        let coll = self.db.get_collection(collection).map_err(|_| {
-            Error::CollectionNotFound(format!("Collection '{}' not found", collection))
-        })?;
+        metadata: Option<&serde_json::Value>,
+    ) -> Result<i64> {
+        match self.find_duplicate(content, collection)? {
+            Some(id) => Ok(id),
+            None => self.add_document(content, collection, metadata),
+        }
+    }
 
-        // Embeddingをバッチ生成
-        let embeddings = self.embedding_model.embed_batch(documents.clone())?;
+    /// 同じコレクションで内容が同一（前後・連続する空白の違いは無視）のドキュメントのID
+    ///
+    /// 内容のSHA-256ハッシュ（インデックス付きの `content_hash` 列）で探すため、
+    /// ドキュメント数によらず高速。複数ある場合は最も古いもの
+    pub fn find_duplicate(&self, content: &str, collection: &str) -> Result<Option<i64>> {
+        let coll = self.store.get_collection(collection)?;
+        self.store.find_duplicate(coll.id, content)
+    }
 
-        // ドキュメントを追加
-        let mut ids = Vec::new();
-        for (i, (doc, emb)) in documents.iter().zip(embeddings.iter()).enumerate() {
-            let meta = metadata.as_ref().and_then(|m| m.get(i));
-            let id = self.db.add_document(coll.id, doc, emb, meta)?;
-            ids.push(id);
+    /// 外部ID（UUIDやURLなど呼び出し側が管理するID）付きでドキュメントを追加・更新
+    ///
+    /// 同じ外部IDのドキュメントがなければ追加し、あれば内容とメタデータを更新して
+    /// そのIDを返す（upsert）。外部システムとの同期を何度実行しても結果が変わらない。
+    /// 内容が変わっていなければEmbeddingは再生成せず、`metadata` がNoneなら既存の
+    /// メタデータを残す
+    ///
+    /// 外部IDは全コレクションで一意。別のコレクションで使われている場合はエラーになる
+    pub fn add_document_with_external_id(
+        &self,
+        content: &str,
+        collection: &str,
+        metadata: Option<&serde_json::Value>,
+        external_id: &str,
+    ) -> Result<i64> {
+        let coll = self.store.get_collection(collection)?;
+
+        if let Some(existing) = self.store.get_document_by_external_id(external_id)? {
+            if existing.collection_id != coll.id {
+                return Err(Error::InvalidInput(format!(
+                    "External ID '{}' is already used in collection '{}'",
+                    external_id, existing.collection_name
+                )));
+            }
+
+            let changed_content = (existing.content != content).then_some(content);
+            self.update_document(existing.id, changed_content, metadata)?;
+            return Ok(existing.id);
         }
 
-        Ok(ids)
-    }
+        let embedding = self.embed_passage(&self.collection_model(&coll)?, content)?;
+        let annotated = self.annotate_metadata(content, metadata);
 
-    pub fn get_document(&self, document_id: i64) -> Result<Document> {
-        self.db.get_document(document_id)
+        let id = self.store.add_document_with_external_id(
+            coll.id,
+            content,
+            &embedding,
+            annotated.as_ref().or(metadata),
+            external_id,
+        )?;
+        self.ann_insert(coll.id, id, &embedding);
+        Ok(id)
     }
 
-    pub fn list_documents(
+    /// 取り込み時に自動で記録するメタデータを追加したものを返す
+    ///
+    /// - `truncated`: Embedding生成時に最大入力文字数で切り詰められる場合に `true`
+    /// - `lang`: 判定した言語コード（`lang-detect` フィーチャー有効時のみ、指定済みなら上書きしない）
+    ///
+    /// 追加するものがない場合や、メタデータがJSONオブジェクト以外の場合はNone（元のメタデータを使う）
+    fn annotate_metadata(
         &self,
-        collection: Option<&str>,
-        limit: i64,
-        offset: i64,
-    ) -> Result<Vec<Document>> {
-        let collection_id = if let Some(coll_name) = collection {
-            Some(self.db.get_collection(coll_name)?.id)
-        } else {
-            None
+        content: &str,
+        metadata: Option<&serde_json::Value>,
+    ) -> Option<serde_json::Value> {
+        let mut annotations = serde_json::Map::new();
+        if self.embedding_model.exceeds_max_input(content) {
+            annotations.insert("truncated".to_string(), serde_json::Value::Bool(true));
+        }
+        if let Some(lang) = ingest_language(content, metadata) {
+            annotations.insert("lang".to_string(), serde_json::Value::from(lang));
+        }
+
+        if annotations.is_empty() {
+            return None;
+        }
+
+        let mut meta = match metadata {
+            Some(serde_json::Value::Object(map)) => map.clone(),
+            Some(serde_json::Value::Null) | None => serde_json::Map::new(),
+            Some(_) => return None,
         };
+        meta.extend(annotations);
 
-        self.db.list_documents(collection_id, limit, offset)
+        Some(serde_json::Value::Object(meta))
     }
 
-    pub fn update_document(
+    /// HTML/Markdownをプレーンテキストに変換してから追加
+    ///
+    /// タグや記法を除去したテキストを本文として保存するため、Embeddingと
+    /// キーワード検索の対象は表示上のテキストのみになる。
+    /// `keep_original` がtrueの場合、変換前のコンテンツをメタデータの
+    /// `original_content` に保存する（`metadata` はJSONオブジェクトのみ）
+    pub fn add_document_as(
         &self,
-        document_id: i64,
-        content: Option<&str>,
+        content: &str,
+        collection: &str,
         metadata: Option<&serde_json::Value>,
-    ) -> Result<bool> {
-        let embedding = if let Some(c) = content {
-            Some(self.embedding_model.embed(c)?)
-        } else {
-            None
+        content_type: ContentType,
+        keep_original: bool,
+    ) -> Result<i64> {
+        let text = content_type.to_plain_text(content);
+
+        if !keep_original || content_type == ContentType::Plain {
+            return self.add_document(&text, collection, metadata);
+        }
+
+        let mut meta = match metadata {
+            Some(serde_json::Value::Object(map)) => map.clone(),
+            Some(serde_json::Value::Null) | None => serde_json::Map::new(),
+            Some(_) => {
+                return Err(Error::InvalidInput(
+                    "Metadata must be a JSON object to keep the original content".to_string(),
+                ))
+            }
         };
+        meta.insert(
+            "original_content".to_string(),
+            serde_json::Value::String(content.to_string()),
+        );
 
-        self.db.update_document(
-            document_id,
-            content,
-            embedding.as_deref(),
-            metadata,
-        )
+        self.add_document(&text, collection, Some(&serde_json::Value::Object(meta)))
     }
 
-    pub fn delete_document(&self, document_id: i64) -> Result<bool> {
-        self.db.delete_document(document_id)
+    /// 複数ドキュメントを一括追加
+    ///
+    /// 挿入は1つのトランザクションで行う（1件ごとのコミットを避けるため大量投入時に高速）。
+    /// 途中で失敗した場合はバッチ全体を取り消し、1件も追加されない
+    pub fn add_documents(
+        &self,
+        documents: Vec<String>,
+        collection: &str,
+        metadata: Option<Vec<serde_json::Value>>,
+    ) -> Result<Vec<i64>> {
+        self.add_documents_with_options(documents, collection, metadata, &AddOptions::default())
     }
 
-    // ==================== 検索・エンリッチ ====================
-
-    /// マルチモーダル検索のエントリーポイント
-    ///
-    /// 3種類の検索モード（Semantic / Keyword / Hybrid）を統一APIで提供
+    /// オプションを指定して複数ドキュメントを一括追加
     ///
-    /// # 引数
-    /// * `query` - 検索クエリ文字列
-    /// * `collection` - 検索対象の単一コレクション名
-    /// * `collections` - 検索対象の複数コレクション名（collectionと排他）
-    /// * `top_k` - 返す結果の最大数
-    /// * `threshold` - セマンティック検索の最小スコア閾値（0.0〜1.0）
-    /// * `mode` - 検索モード（Semantic / Keyword / Hybrid）
-    /// * `hybrid_weights` - ハイブリッド検索の重み `(semantic_weight, keyword_weight)`
+    /// `options.bulk` がtrueの場合、挿入中はFTSインデックスの更新を保留し、
+    /// 最後に一度だけ再構築する（大量投入時に高速）。
+    /// 再構築は全ドキュメントが対象になるため、少量の追加では `false` の方が速い
     ///
-    /// # 検索モード
-    /// - **Semantic**: 意味ベースの検索（埋め込みベクトル + コサイン類似度）
-    /// - **Keyword**: キーワードベースの検索（FTS5 BM25 or LIKE）
-    /// - **Hybrid**: 両方を組み合わせた検索（加重平均）
+    /// `options.skip_duplicates` がtrueの場合、同じコレクションに内容が同一のドキュメントが
+    /// 既にあれば（同じバッチ内の先行ドキュメントも含む）追加せずにそのIDを返す
+    /// （[`Doredore::add_document_unique`] を参照）。戻り値は常に入力と同じ件数・順序
     ///
-    /// # 戻り値
-    /// スコア降順でソートされた検索結果のリスト
-    pub fn search(
+    /// 挿入は1つのトランザクションで行い、途中で失敗した場合はバッチ全体を取り消す
+    pub fn add_documents_with_options(
         &self,
-        query: &str,
-        collection: Option<&str>,
-        collections: Option<&[String]>,
-        top_k: usize,
-        threshold: f32,
-        mode: SearchMode,
-        hybrid_weights: Option<(f32, f32)>,
-    ) -> Result<Vec<SearchResult>> {
-        let collection_ids = self.get_collection_ids(collection, collections)?;
+        documents: Vec<String>,
+        collection: &str,
+        metadata: Option<Vec<serde_json::Value>>,
+        options: &AddOptions,
+    ) -> Result<Vec<i64>> {
+        let AddOptions {
+            bulk,
+            skip_duplicates,
+        } = *options;
 
-        // 検索モードに応じて適切な検索関数を呼び出す
-        match mode {
-            SearchMode::Semantic => {
-                self.semantic_search(query, collection_ids.as_deref(), top_k, threshold)
-            }
-            SearchMode::Keyword => {
-                self.keyword_search(query, collection_ids.as_deref(), top_k)
+        // コレクションIDを取得
+        let coll = self.store.get_collection(collection)?;
+
+        // 既存のドキュメントと重複するものはEmbeddingを生成しない
+        let existing: Vec<Option<i64>> = if skip_duplicates {
+            documents
+                .iter()
+                .map(|doc| self.store.find_duplicate(coll.id, doc))
+                .collect::<Result<_>>()?
+        } else {
+            vec![None; documents.len()]
+        };
+
+        // Embeddingをバッチ生成
+        let new_documents = documents
+            .iter()
+            .zip(&existing)
+            .filter(|(_, id)| id.is_none())
+            .map(|(doc, _)| doc.clone())
+            .collect();
+        let embeddings = self.embed_passage_batch(&self.collection_model(&coll)?, new_documents)?;
+
+        if bulk {
+            self.store.begin_bulk_load();
+        }
+
+        // ドキュメントを1つのトランザクションで追加
+        // （ANNインデックスはロールバックできないため、コミット後に反映する）
+        let mut inserted = Vec::new();
+        let result = self.transaction(|_| {
+            let mut ids = Vec::new();
+            let mut embeddings = embeddings.iter();
+            for (i, (doc, existing_id)) in documents.iter().zip(&existing).enumerate() {
+                if let Some(id) = existing_id {
+                    ids.push(*id);
+                    continue;
+                }
+                let emb = embeddings
+                    .next()
+                    .ok_or_else(|| Error::Embedding("No embedding generated".to_string()))?;
+
+                // 同じバッチ内で先に追加したドキュメントとの重複
+                if skip_duplicates {
+                    if let Some(id) = self.store.find_duplicate(coll.id, doc)? {
+                        ids.push(id);
+                        continue;
+                    }
+                }
+
+                let meta = metadata.as_ref().and_then(|m| m.get(i));
+                let annotated = self.annotate_metadata(doc, meta);
+                let id = self
+                    .store
+                    .add_document(coll.id, doc, emb, annotated.as_ref().or(meta))?;
+                inserted.push((id, emb));
+                ids.push(id);
             }
-            SearchMode::Hybrid => {
-                // デフォルト重み: セマンティック70% + キーワード30%
-                let (semantic_weight, keyword_weight) = hybrid_weights.unwrap_or((0.7, 0.3));
-                self.hybrid_search(
-                    query,
-                    collection_ids.as_deref(),
-                    top_k,
-                    threshold,
-                    semantic_weight,
-                    keyword_weight,
-                )
+            Ok(ids)
+        });
+
+        // 途中で失敗した場合もバルクロードのフラグを戻す
+        if bulk {
+            self.store.end_bulk_load()?;
+        }
+
+        if result.is_ok() {
+            for (id, emb) in inserted {
+                self.ann_insert(coll.id, id, emb);
             }
         }
+        result
     }
 
-    /// セマンティック検索（意味ベース検索）
-    ///
-    /// Dense Embedding + Cosine Similarityを使った意味的類似性検索
+    /// 耐障害モードでの一括追加
     ///
-    /// # アルゴリズム
-    /// 1. クエリをベクトル化（BGE/E5モデル）
-    /// 2. 全ドキュメントのベクトルを取得
-    /// 3. コサイン類似度を計算（O(n × d)）
-    /// 4. スコアでソートしてtop-kを返す
+    /// `add_documents` は1件でもEmbedding生成に失敗すると全体がエラーになる（全件 or 0件）。
+    /// このメソッドは `batch_size` 件ずつEmbeddingを生成し、バッチが失敗した場合は
+    /// そのバッチ内のドキュメントを1件ずつ再試行して、失敗したものだけをスキップする
     ///
-    /// # 特徴
-    /// - **長所**: 言い換え・類義語に対応、多言語対応
-    /// - **短所**: 計算量O(n × d)、完全一致が保証されない
+    /// # 戻り値
+    /// 追加されたドキュメントIDと、失敗したドキュメントのインデックス・エラー内容
+    pub fn add_documents_resilient(
+        &self,
+        documents: Vec<String>,
+        collection: &str,
+        metadata: Option<Vec<serde_json::Value>>,
+        batch_size: usize,
+    ) -> Result<AddDocumentsResult> {
+        // コレクションIDを取得
+        let coll = self.store.get_collection(collection)?;
+
+        let model = self.collection_model(&coll)?;
+        let embeddings = embed_resilient(&documents, batch_size, |texts| {
+            self.embed_passage_batch(&model, texts)
+        });
+
+        // Embeddingに成功したドキュメントのみ追加
+        let mut result = AddDocumentsResult::default();
+        for (i, (doc, emb)) in documents.iter().zip(embeddings).enumerate() {
+            match emb {
+                Ok(emb) => {
+                    let meta = metadata.as_ref().and_then(|m| m.get(i));
+                    let annotated = self.annotate_metadata(doc, meta);
+                    let id = self
+                        .store
+                        .add_document(coll.id, doc, &emb, annotated.as_ref().or(meta))?;
+                    self.ann_insert(coll.id, id, &emb);
+                    result.ids.push(id);
+                }
+                Err(message) => result.failures.push((i, message)),
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// 長文をオーバーラップ付きチャンクに分割して追加
     ///
-    /// # スコアリング
-    /// - コサイン類似度（0.0〜1.0、まれに負の値）
-    /// - 1.0に近いほど意味的に類似
+    /// 各チャンクは個別のEmbeddingとFTS行を持つドキュメントとして保存される。
+    /// 先頭チャンクのIDを親ドキュメントIDとして各チャンクの `parent_id` 列に記録し、
+    /// メタデータには元テキストへの対応付けを自動で記録する:
+    /// ```json
+    /// { "parent_id": 1, "chunk_index": 0, "char_start": 0, "char_end": 500 }
+    /// ```
+    /// `metadata` を指定した場合（JSONオブジェクトのみ）は上記のキーとマージされる
     ///
     /// # 引数
-    /// * `query` - 検索クエリ
-    /// * `collection_ids` - 対象コレクションID
-    /// * `top_k` - 返す結果数
-    /// * `threshold` - 最小スコア閾値
-    fn semantic_search(
+    /// * `max_chars` - 1チャンクの最大文字数
+    /// * `overlap` - 隣接チャンク間で重複させる文字数
+    ///
+    /// # 戻り値
+    /// 親ドキュメントID（[`Doredore::get_chunks`] に渡す）
+    pub fn add_document_chunked(
         &self,
-        query: &str,
-        collection_ids: Option<&[i64]>,
-        top_k: usize,
-        threshold: f32,
-    ) -> Result<Vec<SearchResult>> {
-        // クエリのEmbeddingを生成（384次元ベクトル）
-        let query_embedding = self.embedding_model.embed(query)?;
+        content: &str,
+        collection: &str,
+        metadata: Option<&serde_json::Value>,
+        max_chars: usize,
+        overlap: usize,
+    ) -> Result<i64> {
+        let mut base_metadata = match metadata {
+            Some(serde_json::Value::Object(map)) => map.clone(),
+            Some(serde_json::Value::Null) | None => serde_json::Map::new(),
+            Some(_) => {
+                return Err(Error::InvalidInput(
+                    "Metadata for chunked documents must be a JSON object".to_string(),
+                ))
+            }
+        };
+        // 言語は元テキスト全体で判定し、全チャンクに記録する
+        if let Some(lang) = ingest_language(content, metadata) {
+            base_metadata.insert("lang".to_string(), serde_json::Value::from(lang));
+        }
 
-        // 全ドキュメントとEmbeddingを取得（Linear Search）
-        let documents = self.db.get_all_documents_with_embeddings(collection_ids)?;
+        let chunks = split_into_chunks(content, max_chars, overlap)?;
+        if chunks.is_empty() {
+            return Err(Error::InvalidInput("Content must not be empty".to_string()));
+        }
 
-        // 各ドキュメントとの類似度を計算
-        let mut results: Vec<(i64, String, f32, String)> = documents
-            .into_iter()
-            .map(|(id, content, embedding, coll_name)| {
-                // コサイン類似度を計算
-                let score = cosine_similarity(&query_embedding, &embedding);
-                (id, content, score, coll_name)
-            })
-            // 閾値未満のドキュメントを除外
-            .filter(|(_, _, score, _)| *score >= threshold)
-            .collect();
+        // コレクションIDを取得
+        let coll = self.store.get_collection(collection)?;
 
-        // スコアの降順でソート（高い = より類似）
-        results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+        // Embeddingをバッチ生成
+        let texts = chunks.iter().map(|c| c.content.clone()).collect();
+        let embeddings = self.embed_passage_batch(&self.collection_model(&coll)?, texts)?;
 
-        // Top-K を取得してSearchResult構造体に変換
-        let top_results: Vec<SearchResult> = results
-            .into_iter()
-            .take(top_k)
-            .map(|(id, content, score, coll_name)| {
-                // メタデータを取得（オプショナル）
-                let doc = self.db.get_document(id).ok();
-                let metadata = doc.and_then(|d| d.metadata);
-                SearchResult::new(id, content, score, metadata, coll_name)
-            })
-            .collect();
+        let chunk_metadata = |chunk: &Chunk, parent_id: i64| {
+            let mut meta = base_metadata.clone();
+            if let serde_json::Value::Object(offsets) = chunk.metadata(parent_id) {
+                meta.extend(offsets);
+            }
+            if self.embedding_model.exceeds_max_input(&chunk.content) {
+                meta.insert("truncated".to_string(), serde_json::Value::Bool(true));
+            }
+            serde_json::Value::Object(meta)
+        };
 
-        Ok(top_results)
+        // 全チャンクを1つのトランザクションで追加
+        // （ANNインデックスはロールバックできないため、コミット後に反映する）
+        let ids = self.transaction(|_| {
+            // 先頭チャンクを追加し、そのIDを親ドキュメントIDとする
+            let parent_id = self
+                .store
+                .add_document(coll.id, &chunks[0].content, &embeddings[0], None)?;
+            self.store.update_document(
+                parent_id,
+                None,
+                None,
+                Some(&chunk_metadata(&chunks[0], parent_id)),
+            )?;
+            self.store.set_chunk_parent(parent_id, parent_id)?;
+
+            // 残りのチャンクを追加
+            let mut ids = vec![parent_id];
+            for (chunk, emb) in chunks.iter().zip(embeddings.iter()).skip(1) {
+                let meta = chunk_metadata(chunk, parent_id);
+                let id = self
+                    .store
+                    .add_document(coll.id, &chunk.content, emb, Some(&meta))?;
+                self.store.set_chunk_parent(id, parent_id)?;
+                ids.push(id);
+            }
+            Ok(ids)
+        })?;
+
+        for (id, emb) in ids.iter().zip(embeddings.iter()) {
+            self.ann_insert(coll.id, *id, emb);
+        }
+
+        Ok(ids[0])
     }
 
-    /// キーワード検索（FTS5 BM25 + LIKE フォールバック）
+    /// 親ドキュメントIDに紐づくチャンクを元テキストの順序で取得
     ///
-    /// 完全一致・部分一致ベースの検索
+    /// 各チャンクのメタデータの `char_start` / `char_end` を使えば
+    /// 元テキストの再構築や該当箇所のハイライトが可能
+    pub fn get_chunks(&self, parent_id: i64) -> Result<Vec<Document>> {
+        self.store.get_chunks(parent_id)
+    }
+
+    pub fn get_document(&self, document_id: i64) -> Result<Document> {
+        self.store.get_document(document_id)
+    }
+
+    /// 外部IDでドキュメントを取得（見つからない場合はNone）
+    pub fn get_document_by_external_id(&self, external_id: &str) -> Result<Option<Document>> {
+        self.store.get_document_by_external_id(external_id)
+    }
+
+    pub fn list_documents(
+        &self,
+        collection: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Document>> {
+        let collection_id = if let Some(coll_name) = collection {
+            Some(self.store.get_collection(coll_name)?.id)
+        } else {
+            None
+        };
+
+        self.store.list_documents(collection_id, limit, offset)
+    }
+
+    /// ドキュメント数（`collection` がNoneの場合は全コレクション）
     ///
-    /// # アルゴリズム
-    /// 1. FTS5でBM25検索を試行（英語に最適）
-    /// 2. 結果がなければLIKE検索にフォールバック（日本語対応）
+    /// [`Self::list_documents`] でページングする際の総件数として使う
+    pub fn count_documents(&self, collection: Option<&str>) -> Result<i64> {
+        let collection_id = match collection {
+            Some(name) => Some(self.store.get_collection(name)?.id),
+            None => None,
+        };
+
+        self.store.count_documents(collection_id)
+    }
+
+    /// 本文が空（空白のみを含む）のドキュメントを取得（ID順、`collection` がNoneの場合は全コレクション）
     ///
-    /// # 特徴
-    /// - **長所**: 正確なキーワードマッチング、高速（FTS5使用時）
-    /// - **短所**: 言い換えや類義語に対応できない
+    /// 空のドキュメントは検索結果にノイズとして混ざりやすいため、削除や本文の補完の
+    /// 対象を探す用途。検索時に除外するだけなら [`SearchOptions::exclude_empty`] を使う
+    pub fn find_empty_documents(&self, collection: Option<&str>) -> Result<Vec<Document>> {
+        let collection_id = match collection {
+            Some(name) => Some(self.store.get_collection(name)?.id),
+            None => None,
+        };
+
+        self.store.find_empty_documents(collection_id)
+    }
+
+    pub fn update_document(
+        &self,
+        document_id: i64,
+        content: Option<&str>,
+        metadata: Option<&serde_json::Value>,
+    ) -> Result<bool> {
+        let document = match self.store.get_document(document_id) {
+            Ok(document) => document,
+            // 存在しないドキュメントは更新しない（Embeddingも生成しない）
+            Err(Error::DocumentNotFound(_)) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        // 内容を変更する場合は、ドキュメントが属するコレクションのモデルで再生成する
+        let embedding = match content {
+            Some(c) => {
+                let coll = self.store.get_collection(&document.collection_name)?;
+                Some(self.embed_passage(&self.collection_model(&coll)?, c)?)
+            }
+            None => None,
+        };
+
+        let updated = self.store.update_document(
+            document_id,
+            content,
+            embedding.as_deref(),
+            metadata,
+        )?;
+        if let (true, Some(embedding)) = (updated, &embedding) {
+            self.ann_insert(document.collection_id, document_id, embedding);
+        }
+        Ok(updated)
+    }
+
+    pub fn delete_document(&self, document_id: i64) -> Result<bool> {
+        let deleted = self.store.delete_document(document_id)?;
+        if deleted {
+            self.ann_remove(document_id);
+        }
+        Ok(deleted)
+    }
+
+    /// 外部IDでドキュメントを削除（削除した場合はtrue）
+    pub fn delete_document_by_external_id(&self, external_id: &str) -> Result<bool> {
+        // 読み込み済みのANNインデックスから除くため、削除前にIDを調べる
+        let has_ann_index = self.ann_indexes.borrow().values().any(Option::is_some);
+        let document_id = if has_ann_index {
+            self.store
+                .get_document_by_external_id(external_id)?
+                .map(|d| d.id)
+        } else {
+            None
+        };
+
+        let deleted = self.store.delete_document_by_external_id(external_id)?;
+        if let (true, Some(id)) = (deleted, document_id) {
+            self.ann_remove(id);
+        }
+        Ok(deleted)
+    }
+
+    /// コレクション内の全ドキュメントのメタデータを一括更新（再Embeddingなし）
     ///
-    /// # スコアリング
-    /// - FTS5: BM25スコア → Sigmoid正規化（0〜1）
-    /// - LIKE: 固定値1.0 → Sigmoid正規化（0〜1）
+    /// * `merge = true`: JSON Merge Patchとして既存メタデータにマージ
+    ///   （例: `{"reviewed": true}` を全ドキュメントに追加、`null` 値のキーは削除）
+    /// * `merge = false`: 全ドキュメントのメタデータを `patch` で置き換え
     ///
-    /// # 引数
-    /// * `query` - 検索キーワード
-    /// * `collection_ids` - 対象コレクションID
-    /// * `top_k` - 返す結果数
-    fn keyword_search(
+    /// # 戻り値
+    /// 更新したドキュメント数
+    ///
+    /// # エラー
+    /// `patch` がJSONオブジェクトでない場合は `Error::InvalidInput`（どちらのモードでも）
+    pub fn update_collection_metadata(
         &self,
-        query: &str,
-        collection_ids: Option<&[i64]>,
-        top_k: usize,
-    ) -> Result<Vec<SearchResult>> {
-        // データベース層でFTS5 → LIKE のフォールバック検索を実行
-        let results = self.db.keyword_search(query, collection_ids)?;
+        name: &str,
+        patch: &serde_json::Value,
+        merge: bool,
+    ) -> Result<usize> {
+        let coll = self.store.get_collection(name)?;
 
-        // BM25スコアを正規化（負の値 or 固定値を0-1に）
-        let top_results: Vec<SearchResult> = results
-            .into_iter()
-            .take(top_k)
-            .map(|(id, content, bm25_score, coll_name)| {
-                // BM25スコアは負の値（小さいほど良い）
-                // Sigmoid関数で0-1の範囲に正規化
-                // 式: σ(x) = 1 / (1 + e^(-x/10))
-                // -x/10: スケーリング係数（大きな負の値を扱いやすくする）
-                let normalized_score = 1.0 / (1.0 + (-bm25_score / 10.0).exp());
-
-                // メタデータを取得
-                let doc = self.db.get_document(id).ok();
-                let metadata = doc.and_then(|d| d.metadata);
-
-                SearchResult::new(id, content, normalized_score, metadata, coll_name)
-            })
-            .collect();
+        // 置き換えでもオブジェクト以外を許すと、全ドキュメントのメタデータが壊れる（nullなど）
+        if !patch.is_object() {
+            return Err(Error::InvalidInput(
+                "Metadata patch must be a JSON object".to_string(),
+            ));
+        }
 
-        Ok(top_results)
+        self.store.update_collection_metadata(coll.id, patch, merge)
     }
 
-    /// ハイブリッド検索（セマンティック + キーワード）
+    /// キーワード検索の対象に加えるメタデータのフィールドを設定
     ///
-    /// 意味ベース検索と完全一致検索の長所を組み合わせる
+    /// 通常のキーワード検索（およびハイブリッド検索のキーワード部分）は本文のみを対象にするが、
+    /// `["title", "tags"]` のように指定すると、それらの値にしか含まれない単語でもヒットする。
+    /// 配列の値は要素ごとに対象になる。設定するとコレクション内の既存ドキュメントの
+    /// インデックスを作り直す（件数に比例して時間がかかる）。空の場合は本文のみに戻す
     ///
-    /// # アルゴリズム
-    /// 1. セマンティック検索でtop_k×2件取得
-    /// 2. キーワード検索でtop_k×2件取得
-    /// 3. ドキュメントIDごとにスコアをマージ
-    /// 4. 加重平均でハイブリッドスコアを計算
-    /// 5. 再ランキングしてtop-kを返す
+    /// FTS5の対象外の言語（日本語など）のドキュメントはLIKE検索で本文のみと照合する
+    pub fn set_indexed_metadata_fields(&self, collection: &str, fields: &[String]) -> Result<()> {
+        let coll = self.store.get_collection(collection)?;
+
+        self.store.set_indexed_metadata_fields(coll.id, fields)
+    }
+
+    /// キーワード検索の対象にしているメタデータのフィールド（未設定の場合は空）
+    pub fn indexed_metadata_fields(&self, collection: &str) -> Result<Vec<String>> {
+        let coll = self.store.get_collection(collection)?;
+
+        self.store.get_indexed_metadata_fields(coll.id)
+    }
+
+    /// 複数の変更操作を1つのトランザクションでまとめて実行
     ///
-    /// # スコア統合式
-    /// ```text
-    /// hybrid_score = w_s × semantic_score + w_k × keyword_score
-    /// デフォルト: 0.7 × semantic + 0.3 × keyword
+    /// クロージャが `Err` を返すかパニックした場合は、クロージャ内の変更をすべて取り消す。
+    /// ドキュメントとそのチャンクを同時に登録する場合など、途中で失敗したときに
+    /// 中途半端な状態を残したくない操作に使う
+    ///
+    /// # 注意
+    /// - 対象はデータベースへの変更操作（コレクション・ドキュメントの追加/更新/削除）のみ。
+    ///   Embeddingモデルの状態やバルクロードのフラグなどは取り消されない
+    /// - トランザクション中は他の接続からの書き込みがブロックされるため、
+    ///   クロージャ内で時間のかかる処理（外部APIの呼び出しなど）を行わない
+    /// - `optimize`（`VACUUM`）はトランザクション内では実行できずエラーになる
+    /// - 入れ子にでき、内側のトランザクションのロールバックは内側の変更のみを取り消す
+    ///
+    /// ```no_run
+    /// use doredore_core::Doredore;
+    ///
+    /// let rag = Doredore::new("./knowledge.db", None, None)?;
+    /// rag.transaction(|rag| {
+    ///     rag.add_document("Permanent memorial services", "faq", None)?;
+    ///     rag.add_document("Memorial service fees", "faq", None)?;
+    ///     Ok(())
+    /// })?;
+    /// # Ok::<(), doredore_core::Error>(())
     /// ```
+    pub fn transaction<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Self) -> Result<T>,
+    {
+        // Store::transaction はトレイトオブジェクトで呼べるよう FnMut と () を受け取るため、
+        // FnOnce のクロージャと戻り値をOptionで受け渡す
+        let mut f = Some(f);
+        let mut value = None;
+        self.store.transaction(&mut || {
+            if let Some(f) = f.take() {
+                value = Some(f(self)?);
+            }
+            Ok(())
+        })?;
+
+        value.ok_or_else(|| Error::Other("Store did not run the transaction".to_string()))
+    }
+
+    /// データベースの最適化（VACUUM + FTSインデックス統合 + ANALYZE）
     ///
-    /// # 特徴
-    /// - 意味的な理解と正確なマッチングのバランス
-    /// - 片方だけに出現するドキュメントも含まれる（欠損値は0.0）
-    /// - 重み調整でユースケースに最適化可能
+    /// 大量のドキュメント削除後に実行すると、ファイルサイズの縮小と検索の高速化が見込める。
+    /// `VACUUM` は排他アクセスが必要で、大きなデータベースでは時間がかかる点に注意
+    pub fn optimize(&self) -> Result<()> {
+        self.store.optimize()
+    }
+
+    /// インデックスの整理のみ（FTSインデックス統合 + ANALYZE、VACUUMなし）
+    ///
+    /// 追加・削除を繰り返すと断片化するFTSインデックスを統合し、統計情報を更新する。
+    /// `optimize` より軽いので、長時間稼働するプロセスでの定期実行に向く
+    pub fn optimize_indexes(&self) -> Result<()> {
+        self.store.optimize_indexes()
+    }
+
+    /// キーワード検索のインデックス（SQLiteではFTS5）をドキュメントから作り直す
+    ///
+    /// 外部のツールでデータベースに直接ドキュメントを書き込むと、インデックスに反映されず
+    /// キーワード検索でヒットしない。そのような一括投入の後に呼ぶ。
+    /// 全ドキュメントを再登録するため、件数に比例して時間がかかる
+    pub fn rebuild_keyword_index(&self) -> Result<()> {
+        self.store.rebuild_keyword_index()
+    }
+
+    // ==================== 近似最近傍探索（ANN） ====================
+
+    /// コレクションの近似最近傍探索（HNSW）のインデックスを構築して保存
+    ///
+    /// 構築後は [`SearchMode::SemanticAnn`] での検索が、全件スキャンの代わりにインデックスを
+    /// たどって候補を探す。インデックスはデータベース（`ann_indexes` テーブル）に保存され、
+    /// 次に開いたときに読み込まれる。既にある場合は作り直す
+    ///
+    /// このインスタンスでのドキュメントの追加・更新・削除はインデックスにも反映される。
+    /// 他の接続による追加・更新・削除は、次の検索時にコレクションの変更世代の変化で検出して反映する
+    ///
+    /// 読み取り専用のインスタンスでは保存せず、このインスタンスでのみ使う
+    ///
+    /// # 戻り値
+    /// インデックスに追加したドキュメント数（次元数がコレクションのモデルと異なるEmbeddingは除く）
+    pub fn build_ann_index(&self, collection: &str) -> Result<usize> {
+        let coll = self.store.get_collection(collection)?;
+        // ドキュメントを読む前の世代を記録する（読んでいる間の変更は次の検索時に反映される）
+        let generation = self.store.collection_generation(coll.id)?;
+
+        let mut index = HnswIndex::new(self.collection_model(&coll)?.dimension());
+        for ((id, _, embedding, ..), _) in
+            self.store.get_all_documents_with_norms(Some(&[coll.id]))?
+        {
+            if embedding.len() == index.dimension() {
+                index.insert(id, &embedding)?;
+            }
+        }
+
+        if !self.store.is_read_only() {
+            self.store
+                .save_ann_index(coll.id, &index.to_bytes(), generation)?;
+        }
+        let count = index.len();
+        self.ann_indexes.borrow_mut().insert(
+            coll.id,
+            Some(AnnIndex {
+                index,
+                modified: false,
+                generation,
+            }),
+        );
+
+        Ok(count)
+    }
+
+    /// コレクションのANNインデックスを削除（削除した場合はtrue）
+    ///
+    /// 以降の [`SearchMode::SemanticAnn`] での検索は全件スキャンになる
+    pub fn drop_ann_index(&self, collection: &str) -> Result<bool> {
+        let coll = self.store.get_collection(collection)?;
+
+        let loaded = self.ann_indexes.borrow_mut().insert(coll.id, None);
+        let stored = self.store.delete_ann_index(coll.id)?;
+        Ok(stored || matches!(loaded, Some(Some(_))))
+    }
+
+    /// 前回の保存後に変更されたANNインデックスを保存
+    ///
+    /// ドキュメントの追加・削除のたびにインデックス全体を書き込まないよう、変更はメモリ上に
+    /// 溜めておき、このメソッドかインスタンスの破棄時にまとめて保存する
+    /// （保存前にプロセスが終了しても、次に開いたときに差分が反映される）
+    ///
+    /// 読み取り専用のインスタンスでは何もしない（反映した差分はメモリ上にのみ持つ）
+    pub fn save_ann_indexes(&self) -> Result<()> {
+        if self.store.is_read_only() {
+            return Ok(());
+        }
+        for (collection_id, entry) in self.ann_indexes.borrow_mut().iter_mut() {
+            if let Some(entry) = entry.as_mut().filter(|e| e.modified) {
+                self.store.save_ann_index(
+                    *collection_id,
+                    &entry.index.to_bytes(),
+                    entry.generation,
+                )?;
+                entry.modified = false;
+            }
+        }
+        Ok(())
+    }
+
+    // ==================== 検索・エンリッチ ====================
+
+    /// マルチモーダル検索のエントリーポイント
+    ///
+    /// 3種類の検索モード（Semantic / Keyword / Hybrid）を統一APIで提供
     ///
     /// # 引数
-    /// * `query` - 検索クエリ
-    /// * `collection_ids` - 対象コレクションID
-    /// * `top_k` - 最終的に返す結果数
-    /// * `threshold` - セマンティック検索の閾値
-    /// * `semantic_weight` - セマンティックスコアの重み（0.0〜1.0）
-    /// * `keyword_weight` - キーワードスコアの重み（0.0〜1.0）
-    fn hybrid_search(
+    /// * `query` - 検索クエリ文字列
+    /// * `collection` - 検索対象の単一コレクション名
+    /// * `collections` - 検索対象の複数コレクション名（collectionと排他）
+    /// * `top_k` - 返す結果の最大数
+    /// * `threshold` - セマンティック検索の最小スコア閾値（0.0〜1.0）
+    /// * `mode` - 検索モード（Semantic / Keyword / Hybrid）
+    /// * `hybrid_weights` - ハイブリッド検索の重み `(semantic_weight, keyword_weight)`
+    ///
+    /// # 検索モード
+    /// - **Semantic**: 意味ベースの検索（埋め込みベクトル + コサイン類似度）
+    /// - **Keyword**: キーワードベースの検索（FTS5 BM25 or LIKE）
+    /// - **Hybrid**: 両方を組み合わせた検索（加重平均）
+    ///
+    /// # 戻り値
+    /// スコア降順でソートされた検索結果のリスト
+    pub fn search(
         &self,
         query: &str,
-        collection_ids: Option<&[i64]>,
+        collection: Option<&str>,
+        collections: Option<&[String]>,
         top_k: usize,
         threshold: f32,
-        semantic_weight: f32,
-        keyword_weight: f32,
+        mode: SearchMode,
+        hybrid_weights: Option<(f32, f32)>,
     ) -> Result<Vec<SearchResult>> {
-        // 両方の検索を実行（top_k×2で多めに取得）
-        // 後でマージして再ランキングするため、候補を多めに取る
-        let semantic_results = self.semantic_search(query, collection_ids, top_k * 2, threshold)?;
-        let keyword_results = self.keyword_search(query, collection_ids, top_k * 2)?;
-
-        // ドキュメントIDをキーにしたスコアマップを作成
-        // 値: (content, semantic_score, keyword_score, collection_name, metadata)
-        let mut score_map: HashMap<i64, (String, f32, f32, String, Option<serde_json::Value>)> =
-            HashMap::new();
-
-        // セマンティック検索の結果を追加
-        for result in semantic_results {
-            score_map.insert(
-                result.document_id,
-                (
-                    result.content.clone(),
-                    result.score,  // semantic_score
-                    0.0,           // keyword_score（まだない）
-                    result.collection_name.clone(),
-                    result.metadata.clone(),
-                ),
-            );
-        }
+        let options = SearchOptions {
+            mode,
+            threshold,
+            hybrid_weights,
+            ..Default::default()
+        };
+        self.search_with_options(query, collection, collections, top_k, &options)
+    }
 
-        // キーワード検索の結果を追加/更新
-        for result in keyword_results {
-            score_map
-                .entry(result.document_id)
-                .and_modify(|e| e.2 = result.score) // 既存エントリのkeyword_scoreを更新
-                .or_insert((
-                    // 新規エントリを作成（semantic_scoreは0.0）
-                    result.content.clone(),
-                    0.0,
-                    result.score,
-                    result.collection_name.clone(),
-                    result.metadata.clone(),
-                ));
-        }
-
-        // ハイブリッドスコアを計算
-        let mut hybrid_results: Vec<(i64, String, f32, String, Option<serde_json::Value>)> =
-            score_map
-                .into_iter()
-                .map(|(id, (content, semantic_score, keyword_score, coll_name, metadata))| {
-                    // 加重平均でハイブリッドスコアを計算
-                    let hybrid_score =
-                        semantic_weight * semantic_score + keyword_weight * keyword_score;
-                    (id, content, hybrid_score, coll_name, metadata)
-                })
-                .collect();
+    /// 追加オプション付きの検索
+    ///
+    /// `search` の `threshold` / `mode` / `hybrid_weights` を [`SearchOptions`] で指定し、
+    /// あわせて絞り込み条件などを指定できる（例: `exclude_ids` で既に提示したドキュメントを除外）
+    pub fn search_with_options(
+        &self,
+        query: &str,
+        collection: Option<&str>,
+        collections: Option<&[String]>,
+        top_k: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        let (results, _) =
+            self.search_recorded(query, collection, collections, top_k, options, false)?;
+        Ok(results)
+    }
+
+    /// `search_with_options` の本体
+    ///
+    /// `explain` がtrueの場合は各段階の所要時間・候補数を記録した [`SearchDiagnostics`] も返す
+    fn search_recorded(
+        &self,
+        query: &str,
+        collection: Option<&str>,
+        collections: Option<&[String]>,
+        top_k: usize,
+        options: &SearchOptions,
+        explain: bool,
+    ) -> Result<(Vec<SearchResult>, Option<SearchDiagnostics>)> {
+        let started = Instant::now();
+        let mode = options.mode;
+        let mut diagnostics = explain.then(|| SearchDiagnostics {
+            mode,
+            fusion: (mode == SearchMode::Hybrid).then_some(options.fusion),
+            ..Default::default()
+        });
+
+        let (scored, backfilled) = self.scored_search(
+            query,
+            collection,
+            collections,
+            top_k,
+            options,
+            diagnostics.as_mut(),
+        )?;
+
+        let mut results = to_search_results(scored);
+        for result in results.iter_mut() {
+            result.backfilled = backfilled.contains(&result.document_id);
+        }
+        if options.include_embedding {
+            self.attach_embeddings(&mut results)?;
+        }
+        if options.normalize_output {
+            for result in results.iter_mut() {
+                result.content = normalize_content(&result.content);
+            }
+        }
+        if let Some(diagnostics) = diagnostics.as_mut() {
+            diagnostics.total_ms = millis(started.elapsed());
+        }
+
+        Ok((results, diagnostics))
+    }
+
+    /// 診断情報付きの検索
+    ///
+    /// `search_with_options` と同じ結果に加え、クエリのEmbedding生成・セマンティック検索・
+    /// キーワード検索それぞれの所要時間と候補数、キーワード検索の照合方法（FTS5かLIKEか）、
+    /// ハイブリッド検索の統合方法を [`SearchDiagnostics`] で返す。閾値や重みの調整に使う
+    pub fn search_with_diagnostics(
+        &self,
+        query: &str,
+        collection: Option<&str>,
+        collections: Option<&[String]>,
+        top_k: usize,
+        options: &SearchOptions,
+    ) -> Result<(Vec<SearchResult>, SearchDiagnostics)> {
+        let (results, diagnostics) =
+            self.search_recorded(query, collection, collections, top_k, options, true)?;
+        Ok((results, diagnostics.unwrap_or_default()))
+    }
+
+    /// 検索結果に各ドキュメントのEmbeddingを設定（1回のクエリでまとめて取得）
+    fn attach_embeddings(&self, results: &mut [SearchResult]) -> Result<()> {
+        let ids: Vec<i64> = results.iter().map(|r| r.document_id).collect();
+        let mut embeddings = self.store.get_embeddings(&ids)?;
+
+        for result in results.iter_mut() {
+            result.embedding = embeddings.remove(&result.document_id);
+        }
+
+        Ok(())
+    }
+
+    /// ドキュメントIDとスコアのみを返す検索
+    ///
+    /// `search_with_options` と同じ順序・スコアだが、結果ごとのメタデータ取得（1件ずつのSELECT）を
+    /// 行わないため、リランカーやIDベースのキャッシュなど本文が不要な用途で高速
+    /// （結果の内容に関するオプション（`include_embedding` など）は使用しない）
+    ///
+    /// # 戻り値
+    /// `(document_id, score)` のリスト（スコア降順）
+    pub fn search_ids(
+        &self,
+        query: &str,
+        collection: Option<&str>,
+        collections: Option<&[String]>,
+        top_k: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<(i64, f32)>> {
+        let (scored, _) =
+            self.scored_search(query, collection, collections, top_k, options, None)?;
+
+        Ok(scored.into_iter().map(|(id, _, score, ..)| (id, score)).collect())
+    }
+
+    /// 検索モードに応じてスコア付きの候補を取得（メタデータは取得しない）
+    ///
+    /// 戻り値の2つ目は [`SearchOptions::min_results`] を満たすために補ったドキュメントのID
+    fn scored_search(
+        &self,
+        query: &str,
+        collection: Option<&str>,
+        collections: Option<&[String]>,
+        top_k: usize,
+        options: &SearchOptions,
+        mut diagnostics: Option<&mut SearchDiagnostics>,
+    ) -> Result<(Vec<ScoredDocument>, HashSet<i64>)> {
+        let (mode, threshold) = (options.mode, options.threshold);
+        // 検索の所要時間の計測用（クエリのEmbedding生成を含む）
+        let _span = tracing::debug_span!("search", mode = ?mode).entered();
+
+        // 無限大・NaNの重みを掛けるとスコアがNaNになり、並べ替えられなくなる
+        if let Some((name, weight)) = options
+            .collection_weights
+            .iter()
+            .find(|(_, weight)| !weight.is_finite())
+        {
+            return Err(Error::InvalidInput(format!(
+                "Collection weight for '{}' must be a finite number, got {}",
+                name, weight
+            )));
+        }
+        let collection_ids =
+            self.get_collection_ids(collection, collections, options.skip_missing)?;
+
+        // 指定されたコレクションがすべて存在しない（スキップされた）場合は結果なし
+        if collection_ids.as_ref().is_some_and(|ids| ids.is_empty()) {
+            return Ok((Vec::new(), HashSet::new()));
+        }
+
+        // 補完やチャンクの集約で検索を繰り返しても、クエリのベクトル化は1回だけ行う
+        let cached_query = CachedQuery::new(query);
+
+        // 検索モードに応じて適切な検索関数を呼び出す
+        let mut run = |top_k: usize, threshold: f32| match mode {
+            SearchMode::Semantic => self.semantic_search(
+                &cached_query,
+                collection_ids.as_deref(),
+                top_k,
+                threshold,
+                options,
+                diagnostics.as_deref_mut(),
+            ),
+            SearchMode::SemanticAnn => self.semantic_ann_search(
+                &cached_query,
+                collection_ids.as_deref(),
+                top_k,
+                threshold,
+                options,
+                diagnostics.as_deref_mut(),
+            ),
+            SearchMode::Keyword => self.keyword_search(
+                query,
+                collection_ids.as_deref(),
+                top_k,
+                options,
+                diagnostics.as_deref_mut(),
+            ),
+            SearchMode::Hybrid => self.hybrid_search(
+                &cached_query,
+                collection_ids.as_deref(),
+                top_k,
+                threshold,
+                options,
+                diagnostics.as_deref_mut(),
+            ),
+        };
+
+        // チャンクを親ごとにまとめる場合は、まとめた後に top_k 件に届くまで取得件数を増やす
+        let mut search = |top_k: usize, threshold: f32| -> Result<Vec<ScoredDocument>> {
+            if !options.collapse_chunks {
+                return run(top_k, threshold);
+            }
+            let mut fetch = top_k;
+            loop {
+                let results = run(fetch, threshold)?;
+                let exhausted = results.len() < fetch;
+                let ids: Vec<i64> = results.iter().map(|(id, ..)| *id).collect();
+                let parents = self.store.get_chunk_parents(&ids)?;
+                let mut collapsed = collapse_chunks(results, &parents);
+                if collapsed.len() >= top_k || exhausted {
+                    collapsed.truncate(top_k);
+                    return Ok(collapsed);
+                }
+                fetch = fetch.saturating_mul(2);
+            }
+        };
+
+        let mut results = search(top_k, threshold)?;
+        let mut backfilled = HashSet::new();
+
+        // 閾値で最低件数に満たなくなった場合は、閾値を無視した結果の上位から補う
+        let wanted = options.min_results.min(top_k);
+        if results.len() < wanted && mode != SearchMode::Keyword {
+            let found: HashSet<i64> = results.iter().map(|(id, ..)| *id).collect();
+            let extra: Vec<ScoredDocument> = search(top_k, f32::NEG_INFINITY)?
+                .into_iter()
+                .filter(|(id, ..)| !found.contains(id))
+                .take(wanted - results.len())
+                .collect();
+            backfilled.extend(extra.iter().map(|(id, ..)| *id));
+            results.extend(extra);
+            // ハイブリッド検索ではキーワードのみのヒットより補った結果のスコアが高いことがある
+            results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap().then(a.0.cmp(&b.0)));
+        }
+
+        Ok((results, backfilled))
+    }
+
+    /// 事前計算済みのベクトルで検索（セマンティック検索のみ）
+    ///
+    /// クエリのEmbeddingを外部で生成・キャッシュしている場合に使用する。
+    /// E5系モデルではクエリ用の接頭辞が必要なため、[`EmbeddingModel::embed_query`] で生成する
+    ///
+    /// # エラー
+    /// `vector` の次元数がモデルの次元数（[`Doredore::dimension`]）と一致しない場合は
+    /// `Error::InvalidInput`（次元が異なるとコサイン類似度が全件0.0になり、
+    /// 誤った結果を黙って返してしまうため）
+    ///
+    /// ベクトルはインスタンスのモデルで生成したものとみなすため、別のモデルを指定した
+    /// コレクション（[`Doredore::create_collection_with_model`]）は検索対象にならない
+    pub fn search_by_vector(
+        &self,
+        vector: &[f32],
+        collection: Option<&str>,
+        collections: Option<&[String]>,
+        top_k: usize,
+        threshold: f32,
+    ) -> Result<Vec<SearchResult>> {
+        let _span = tracing::debug_span!("search", mode = "vector").entered();
+        if vector.len() != self.dimension() {
+            return Err(Error::InvalidInput(format!(
+                "Vector dimension mismatch: expected {}, got {}",
+                self.dimension(),
+                vector.len()
+            )));
+        }
+
+        // ベクトルはインスタンスのモデルで生成したものとみなし、同じモデルのコレクションのみ検索する
+        let collection_ids = self.get_collection_ids(collection, collections, false)?;
+        let default_group = self
+            .model_groups(collection_ids.as_deref())?
+            .into_iter()
+            .find(|(model, _)| model.model_name() == self.model_name());
+        let Some((_, collection_ids)) = default_group else {
+            return Ok(Vec::new());
+        };
+        let scored = self.rank_by_embedding(
+            vector,
+            collection_ids.as_deref(),
+            top_k,
+            threshold,
+            &SearchOptions::default(),
+        )?;
+
+        Ok(to_search_results(scored))
+    }
+
+    /// 複数のクエリで検索し、スコアを統合して返す（クエリフュージョン、セマンティック検索のみ）
+    ///
+    /// クエリ拡張（言い換えを複数生成して検索する手法）向け。クエリをまとめてベクトル化し、
+    /// ドキュメントごとに各クエリとのコサイン類似度の加重平均をスコアとする
+    ///
+    /// ```text
+    /// score = Σ(w_i × similarity_i) / Σ(w_i)
+    /// ```
+    ///
+    /// # 引数
+    /// * `queries` - 検索クエリのリスト
+    /// * `weights` - クエリごとの重み（Noneの場合は均等＝単純平均）
+    /// * `collection` / `collections` / `top_k` - `search` と同じ
+    /// * `threshold` - 統合後のスコアに対する最小閾値
+    ///
+    /// # エラー
+    /// `queries` が空の場合、`weights` の件数が `queries` と一致しない場合、
+    /// 重みに負の値がある・合計が0の場合は `Error::InvalidInput`
+    pub fn search_multi_query(
+        &self,
+        queries: &[String],
+        weights: Option<&[f32]>,
+        collection: Option<&str>,
+        collections: Option<&[String]>,
+        top_k: usize,
+        threshold: f32,
+    ) -> Result<Vec<SearchResult>> {
+        let _span = tracing::debug_span!("search", mode = "multi_query").entered();
+        if queries.is_empty() {
+            return Err(Error::InvalidInput("No queries given".to_string()));
+        }
+
+        let weights = match weights {
+            Some(weights) if weights.len() != queries.len() => {
+                return Err(Error::InvalidInput(format!(
+                    "Weight count mismatch: expected {}, got {}",
+                    queries.len(),
+                    weights.len()
+                )));
+            }
+            Some(weights) => weights.to_vec(),
+            None => vec![1.0; queries.len()],
+        };
+
+        let weight_sum: f32 = weights.iter().sum();
+        if weights.iter().any(|w| *w < 0.0) || weight_sum <= 0.0 {
+            return Err(Error::InvalidInput(
+                "Weights must be non-negative and sum to a positive value".to_string(),
+            ));
+        }
+
+        let collection_ids = self.get_collection_ids(collection, collections, false)?;
+
+        // コレクションのモデルごとにクエリをベクトル化して比較し、結果を統合する
+        let mut scored = Vec::new();
+        for (model, ids) in self.model_groups(collection_ids.as_deref())? {
+            let query_embeddings = model.embed_query_batch(queries.to_vec())?;
+            scored.extend(self.rank_by(
+                |embedding, _norm| {
+                    let score = query_embeddings
+                        .iter()
+                        .zip(&weights)
+                        .map(|(query_embedding, weight)| {
+                            weight * cosine_similarity(query_embedding, embedding)
+                        })
+                        .sum::<f32>()
+                        / weight_sum;
+                    (score >= threshold).then_some(score)
+                },
+                ids.as_deref(),
+                top_k,
+                &SearchOptions::default(),
+            )?);
+        }
+        scored.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap().then(a.0.cmp(&b.0)));
+        scored.truncate(top_k);
+
+        Ok(to_search_results(scored))
+    }
+
+    /// 閾値ごとの適合率・再現率・F1を計算（閾値チューニング用）
+    ///
+    /// 正解ドキュメントIDが分かっているクエリで検索し、`thresholds` の各値で
+    /// 結果を絞り込んだ場合の精度を返す。検索はクエリごとに1回だけ行う
+    ///
+    /// # 引数
+    /// * `queries` - `(クエリ, 正解ドキュメントID)` のリスト
+    /// * `thresholds` - 評価する閾値（この順序で結果を返す）
+    /// * `mode` - 検索モード
+    /// * `collection` - 検索対象のコレクション（Noneの場合は全コレクション）
+    /// * `top_k` - クエリごとに取得する最大件数（本番と同じ値にすると実際の精度に近くなる）
+    ///
+    /// ```no_run
+    /// use doredore_core::{Doredore, SearchMode};
+    ///
+    /// let rag = Doredore::new("./knowledge.db", None, None)?;
+    /// let queries = vec![("permanent memorial fee".to_string(), vec![1, 4])];
+    /// let points = rag.evaluate(&queries, &[0.5, 0.6, 0.7], SearchMode::Semantic, None, 5)?;
+    /// let best = points.iter().max_by(|a, b| a.f1.total_cmp(&b.f1));
+    /// # Ok::<(), doredore_core::Error>(())
+    /// ```
+    pub fn evaluate(
+        &self,
+        queries: &[(String, Vec<i64>)],
+        thresholds: &[f32],
+        mode: SearchMode,
+        collection: Option<&str>,
+        top_k: usize,
+    ) -> Result<Vec<EvalPoint>> {
+        if thresholds.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // 最も低い閾値で一度だけ検索し、各閾値での絞り込みは結果から計算する
+        let options = SearchOptions {
+            mode,
+            threshold: thresholds.iter().copied().fold(f32::INFINITY, f32::min),
+            ..Default::default()
+        };
+
+        let runs = queries
+            .iter()
+            .map(|(query, relevant)| {
+                let results = self.search_ids(query, collection, None, top_k, &options)?;
+                Ok((results, relevant.iter().copied().collect()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(sweep_thresholds(&runs, thresholds))
+    }
+
+    /// 指定したドキュメント同士のコサイン類似度行列
+    ///
+    /// `matrix[i][j]` は `ids[i]` と `ids[j]` のEmbeddingのコサイン類似度（対称行列、
+    /// 対角成分は約1.0）。クラスタリングや可視化（ヒートマップなど）向け。
+    /// Embeddingとノルムは1回のクエリでまとめて取得し、保存済みのノルムを使い回す
+    ///
+    /// ```no_run
+    /// use doredore_core::Doredore;
+    ///
+    /// let rag = Doredore::new("./knowledge.db", Some("bge-small-en-v1.5"), None)?;
+    /// let matrix = rag.similarity_matrix(&[1, 2, 3])?;
+    /// assert_eq!(matrix[0][1], matrix[1][0]);
+    /// # Ok::<(), doredore_core::Error>(())
+    /// ```
+    ///
+    /// # 計算量
+    /// O(n² × d)（nはID数、dはEmbeddingの次元数）。384次元・1,000件で約2億回の乗算になり、
+    /// 行列もn²要素を保持するため、大量のドキュメントでは対象を絞って呼ぶこと
+    ///
+    /// # エラー
+    /// 存在しないIDが含まれる場合は `Error::DocumentNotFound`
+    pub fn similarity_matrix(&self, ids: &[i64]) -> Result<Vec<Vec<f32>>> {
+        let embeddings = self.store.get_embeddings_with_norms(ids)?;
+
+        let mut vectors = Vec::with_capacity(ids.len());
+        let mut norms = Vec::with_capacity(ids.len());
+        for id in ids {
+            let (embedding, norm) = embeddings.get(id).ok_or(Error::DocumentNotFound(*id))?;
+            vectors.push(embedding.as_slice());
+            norms.push(*norm);
+        }
+
+        Ok(cosine_similarity_matrix(&vectors, &norms))
+    }
+
+    /// 検索にマッチするドキュメント数のみを取得
+    ///
+    /// 本文やスコアを返さず件数だけを数える（「N件見つかりました」表示やファセット用）
+    ///
+    /// # カウント方法
+    /// - **Keyword**: FTS5 / LIKE のマッチに対する `COUNT(*)`（本文は取得しない）
+    /// - **Semantic**: コサイン類似度が `threshold` 以上のドキュメント数
+    /// - **Hybrid**: 上記いずれかにマッチするドキュメント数（重複は1件として数える）
+    ///
+    /// # 注意
+    /// SemanticおよびHybridモードでは全ドキュメントとの類似度計算が必要なため、
+    /// 通常の検索と同じく全件スキャン（O(n × d)）になる
+    ///
+    /// Hybridモードの件数は検索結果の件数の上限とは一致しない。検索では `threshold` を
+    /// 統合後のスコアに適用し、統合後のスコアは候補の中での正規化や重みによって変わるため、
+    /// 件数としてはいずれかの検索にマッチするドキュメントを数える（`threshold` は類似度に適用）
+    pub fn count_matches(
+        &self,
+        query: &str,
+        collection: Option<&str>,
+        mode: SearchMode,
+        threshold: f32,
+    ) -> Result<usize> {
+        let collection_ids = self.get_collection_ids(collection, None, false)?;
+
+        match mode {
+            SearchMode::Keyword => self.store.keyword_count(query, collection_ids.as_deref()),
+            SearchMode::Semantic | SearchMode::SemanticAnn => Ok(self
+                .semantic_match_ids(query, collection_ids.as_deref(), threshold)?
+                .len()),
+            SearchMode::Hybrid => {
+                let mut ids: HashSet<i64> = self
+                    .semantic_match_ids(query, collection_ids.as_deref(), threshold)?
+                    .into_iter()
+                    .collect();
+                ids.extend(
+                    self.store
+                        .keyword_match_ids(query, collection_ids.as_deref())?,
+                );
+                Ok(ids.len())
+            }
+        }
+    }
+
+    /// 類似度が閾値以上のドキュメントIDを取得（全件スキャン）
+    fn semantic_match_ids(
+        &self,
+        query: &str,
+        collection_ids: Option<&[i64]>,
+        threshold: f32,
+    ) -> Result<Vec<i64>> {
+        let mut ids = Vec::new();
+        for (model, group_ids) in self.model_groups(collection_ids)? {
+            let query_embedding = model.embed_query(query)?;
+            let scorer = BoundedCosine::new(&query_embedding, threshold);
+            let documents = self.store.get_all_documents_with_norms(group_ids.as_deref())?;
+
+            ids.extend(
+                documents
+                    .into_iter()
+                    .filter(|((_, _, embedding, ..), norm)| scorer.score(embedding, *norm).is_some())
+                    .map(|((id, ..), _)| id),
+            );
+        }
+
+        Ok(ids)
+    }
+
+    /// セマンティック検索（意味ベース検索）
+    ///
+    /// Dense Embedding + Cosine Similarityを使った意味的類似性検索
+    ///
+    /// # アルゴリズム
+    /// 1. クエリをベクトル化（BGE/E5モデル）
+    /// 2. 全ドキュメントのベクトルを取得
+    /// 3. コサイン類似度を計算（O(n × d)）
+    /// 4. スコアでソートしてtop-kを返す
+    ///
+    /// # 特徴
+    /// - **長所**: 言い換え・類義語に対応、多言語対応
+    /// - **短所**: 計算量O(n × d)、完全一致が保証されない
+    ///
+    /// # スコアリング
+    /// - コサイン類似度（0.0〜1.0、まれに負の値）
+    /// - 1.0に近いほど意味的に類似
+    ///
+    /// # 引数
+    /// * `query` - 検索クエリ
+    /// * `collection_ids` - 対象コレクションID
+    /// * `top_k` - 返す結果数
+    /// * `threshold` - 最小スコア閾値
+    /// * `options` - 追加オプション（除外IDなど）
+    /// * `diagnostics` - 所要時間・候補数の記録先（[`Doredore::search_with_diagnostics`]）
+    fn semantic_search(
+        &self,
+        query: &CachedQuery,
+        collection_ids: Option<&[i64]>,
+        top_k: usize,
+        threshold: f32,
+        options: &SearchOptions,
+        diagnostics: Option<&mut SearchDiagnostics>,
+    ) -> Result<Vec<ScoredDocument>> {
+        // MMRで再ランキングする場合は候補を多めに取得し、その中から選び直す
+        let candidates = match options.mmr_lambda {
+            Some(_) => top_k.saturating_mul(options.candidate_multiplier.max(1)),
+            None => top_k,
+        };
+
+        // コレクションのモデルごとにクエリのEmbeddingを生成して比較し、結果を統合する
+        let started = Instant::now();
+        let mut embedding_time = Duration::ZERO;
+        let mut results = Vec::new();
+        for (model, ids) in self.model_groups(collection_ids)? {
+            let embedding_started = Instant::now();
+            let query_embedding = query.embedding(&model)?;
+            embedding_time += embedding_started.elapsed();
+            let scorer = BoundedCosine::new(&query_embedding, threshold);
+            results.extend(self.rank_with_embeddings(
+                |embedding, norm| scorer.score(embedding, norm),
+                ids.as_deref(),
+                candidates,
+                options,
+            )?);
+        }
+
+        results.sort_by(|(a, _), (b, _)| b.2.partial_cmp(&a.2).unwrap().then(a.0.cmp(&b.0)));
+        results.truncate(candidates);
+        if let Some(diagnostics) = diagnostics {
+            diagnostics.embedding_ms += millis(embedding_time);
+            diagnostics.semantic_ms += millis(started.elapsed().saturating_sub(embedding_time));
+            diagnostics.semantic_candidates = Some(results.len());
+        }
+
+        Ok(match options.mmr_lambda {
+            Some(lambda) => maximal_marginal_relevance(results, lambda, top_k),
+            None => results.into_iter().map(|(doc, _)| doc).collect(),
+        })
+    }
+
+    /// 近似最近傍探索によるセマンティック検索（[`SearchMode::SemanticAnn`]）
+    ///
+    /// ANNインデックス（[`Doredore::build_ann_index`]）のあるコレクションはインデックスで
+    /// 候補を探し、ないコレクションは `semantic_search` と同じく全件スキャンする。
+    /// メタデータ条件を指定した場合は、条件に合うドキュメントがインデックスの候補から
+    /// 漏れやすいため、すべて全件スキャンで検索する
+    fn semantic_ann_search(
+        &self,
+        query: &CachedQuery,
+        collection_ids: Option<&[i64]>,
+        top_k: usize,
+        threshold: f32,
+        options: &SearchOptions,
+        diagnostics: Option<&mut SearchDiagnostics>,
+    ) -> Result<Vec<ScoredDocument>> {
+        if !options.metadata_filter.is_empty() {
+            return self.semantic_search(
+                query,
+                collection_ids,
+                top_k,
+                threshold,
+                options,
+                diagnostics,
+            );
+        }
+
+        // 除外するドキュメントが候補に含まれる分だけ多めに探す
+        let k = top_k + options.exclude_ids.len();
+        let started = Instant::now();
+        let mut embedding_time = Duration::ZERO;
+        let mut results = Vec::new();
+        for (model, ids) in self.model_groups(collection_ids)? {
+            let ids = match ids {
+                Some(ids) => ids,
+                None => self
+                    .store
+                    .list_collections()?
+                    .iter()
+                    .map(|c| c.id)
+                    .collect(),
+            };
+            let embedding_started = Instant::now();
+            let query_embedding = query.embedding(&model)?;
+            embedding_time += embedding_started.elapsed();
+
+            let mut scan_ids = Vec::new();
+            for id in ids {
+                let found = self.with_ann_index(id, |index| {
+                    index.search(&query_embedding, k, DEFAULT_EF_SEARCH)
+                })?;
+                match found {
+                    Some(found) => results.extend(self.ann_results(found, threshold, options)?),
+                    None => scan_ids.push(id),
+                }
+            }
+            if !scan_ids.is_empty() {
+                results.extend(self.rank_by_embedding(
+                    &query_embedding,
+                    Some(&scan_ids),
+                    top_k,
+                    threshold,
+                    options,
+                )?);
+            }
+        }
+
+        results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap().then(a.0.cmp(&b.0)));
+        results.truncate(top_k);
+        if let Some(diagnostics) = diagnostics {
+            diagnostics.embedding_ms += millis(embedding_time);
+            diagnostics.semantic_ms += millis(started.elapsed().saturating_sub(embedding_time));
+            diagnostics.semantic_candidates = Some(results.len());
+        }
+        Ok(results)
+    }
+
+    /// ANNインデックスの候補 `(ドキュメントID, 類似度)` を閾値・除外条件で絞り込み、本文などを取得
+    fn ann_results(
+        &self,
+        found: Vec<(i64, f32)>,
+        threshold: f32,
+        options: &SearchOptions,
+    ) -> Result<Vec<ScoredDocument>> {
+        let found: Vec<(i64, f32)> = found
+            .into_iter()
+            .filter(|(id, score)| *score >= threshold && !options.exclude_ids.contains(id))
+            .collect();
+        let ids: Vec<i64> = found.iter().map(|(id, _)| *id).collect();
+        let mut documents = self.store.get_documents(&ids)?;
+
+        let mut results = Vec::new();
+        for (id, score) in found {
+            // インデックスへの反映後にロールバックされたドキュメントは飛ばす
+            let Some(document) = documents.remove(&id) else {
+                continue;
+            };
+            if options.exclude_empty && is_empty_content(&document.content) {
+                continue;
+            }
+            results.push((
+                id,
+                document.content,
+                score * options.collection_weight(&document.collection_name),
+                document.collection_name,
+                document.metadata,
+            ));
+        }
+        Ok(results)
+    }
+
+    /// コレクションのANNインデックスで `f` を実行（インデックスがなければNone）
+    ///
+    /// 初めて使うときにストアから読み込み、他の接続による追加・削除で古くなっていれば
+    /// 差分を反映してから使う。保存済みのインデックスが壊れている場合は警告を出して
+    /// ないものとして扱う（全件スキャンになる）
+    fn with_ann_index<T>(
+        &self,
+        collection_id: i64,
+        f: impl FnOnce(&HnswIndex) -> Result<T>,
+    ) -> Result<Option<T>> {
+        let mut indexes = self.ann_indexes.borrow_mut();
+        if let Entry::Vacant(vacant) = indexes.entry(collection_id) {
+            let loaded = match self.store.load_ann_index(collection_id)? {
+                Some((data, generation)) => match HnswIndex::from_bytes(&data) {
+                    Ok(index) => Some(AnnIndex {
+                        index,
+                        modified: false,
+                        generation,
+                    }),
+                    Err(e) => {
+                        tracing::warn!("Ignoring ANN index of collection {}: {}", collection_id, e);
+                        None
+                    }
+                },
+                None => None,
+            };
+            vacant.insert(loaded);
+        }
+
+        let Some(Some(entry)) = indexes.get_mut(&collection_id) else {
+            return Ok(None);
+        };
+        self.sync_ann_index(collection_id, entry)?;
+        f(&entry.index).map(Some)
+    }
+
+    /// ストアにあってインデックスにないドキュメントと、反映済みの世代より後にEmbeddingが
+    /// 更新されたドキュメントを追加し、ストアにないものを除く
+    ///
+    /// コレクションの変更世代が反映済みの世代と一致していれば最新とみなし、何もしない
+    fn sync_ann_index(&self, collection_id: i64, entry: &mut AnnIndex) -> Result<()> {
+        // 差分を読む前の世代を記録する（読んでいる間の変更は次の検索時に反映される）
+        let generation = self.store.collection_generation(collection_id)?;
+        if generation.is_some() && generation == entry.generation {
+            return Ok(());
+        }
+
+        let ids: HashSet<i64> = self
+            .store
+            .collection_document_ids(collection_id)?
+            .into_iter()
+            .collect();
+        let removed: Vec<i64> = entry
+            .index
+            .document_ids()
+            .filter(|id| !ids.contains(id))
+            .collect();
+        for id in removed {
+            entry.index.remove(id);
+        }
+
+        // 追加されたドキュメントと、反映済みの世代より後にEmbeddingが更新されたドキュメント
+        let mut changed: Vec<i64> = ids
+            .into_iter()
+            .filter(|id| !entry.index.contains(*id))
+            .collect();
+        if let (Some(_), Some(since)) = (generation, entry.generation) {
+            changed.extend(self.store.changed_document_ids(collection_id, since)?);
+        }
+        for (id, embedding) in self.store.get_embeddings(&changed)? {
+            if embedding.len() == entry.index.dimension() {
+                entry.index.insert(id, &embedding)?;
+            }
+        }
+        entry.modified = true;
+        entry.generation = generation;
+
+        Ok(())
+    }
+
+    /// 追加・更新したドキュメントを読み込み済みのANNインデックスに反映
+    ///
+    /// 読み込んでいないインデックスには、読み込み時に差分として反映される
+    fn ann_insert(&self, collection_id: i64, document_id: i64, embedding: &[f32]) {
+        if let Some(Some(entry)) = self.ann_indexes.borrow_mut().get_mut(&collection_id) {
+            if entry.index.insert(document_id, embedding).is_ok() {
+                entry.modified = true;
+            }
+        }
+    }
+
+    /// 削除したドキュメントを読み込み済みのANNインデックスから除く
+    fn ann_remove(&self, document_id: i64) {
+        for entry in self.ann_indexes.borrow_mut().values_mut().flatten() {
+            if entry.index.remove(document_id) {
+                entry.modified = true;
+            }
+        }
+    }
+
+    /// クエリベクトルと全ドキュメントのコサイン類似度を計算し、上位K件を返す
+    ///
+    /// 閾値が正の場合は保存済みのノルムから類似度の上限を見積もり、閾値に届かない
+    /// ドキュメントのコサイン類似度の計算を途中で打ち切る（[`BoundedCosine`]）。
+    /// 結果は全件を計算した場合と同じ
+    fn rank_by_embedding(
+        &self,
+        query_embedding: &[f32],
+        collection_ids: Option<&[i64]>,
+        top_k: usize,
+        threshold: f32,
+        options: &SearchOptions,
+    ) -> Result<Vec<ScoredDocument>> {
+        let scorer = BoundedCosine::new(query_embedding, threshold);
+
+        self.rank_by(
+            |embedding, norm| scorer.score(embedding, norm),
+            collection_ids,
+            top_k,
+            options,
+        )
+    }
+
+    /// 全ドキュメントのEmbeddingを `score_fn` でスコアリングし、上位K件を返す
+    ///
+    /// `score_fn` はEmbeddingとそのノルムを受け取り、閾値以上ならスコアを、
+    /// 閾値未満なら `None` を返す。`parallel` フィーチャーでは複数スレッドから呼ばれる
+    fn rank_by<F>(
+        &self,
+        score_fn: F,
+        collection_ids: Option<&[i64]>,
+        top_k: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<ScoredDocument>>
+    where
+        F: Fn(&[f32], f32) -> Option<f32> + Sync,
+    {
+        let results = self.rank_with_embeddings(score_fn, collection_ids, top_k, options)?;
+        Ok(results.into_iter().map(|(doc, _)| doc).collect())
+    }
+
+    /// `rank_by` と同じだが、上位K件のEmbeddingも合わせて返す
+    ///
+    /// MMRでの再ランキングのように、結果どうしの類似度が必要な場合に
+    /// Embeddingを取得し直さずに済む
+    fn rank_with_embeddings<F>(
+        &self,
+        score_fn: F,
+        collection_ids: Option<&[i64]>,
+        top_k: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<(ScoredDocument, Vec<f32>)>>
+    where
+        F: Fn(&[f32], f32) -> Option<f32> + Sync,
+    {
+        // 全ドキュメントとEmbeddingを取得（Linear Search）
+        let documents = self.store.get_all_documents_with_norms(collection_ids)?;
+        let allowed_ids = self.metadata_filter_ids(collection_ids, options)?;
+
+        let score = |(row, norm): (EmbeddedDocumentRow, f32)| {
+            let (id, content, embedding, coll_name, metadata) = row;
+            // 除外IDやメタデータ条件に一致しないドキュメントはスコア計算の対象外
+            if options.exclude_ids.contains(&id)
+                || allowed_ids.as_ref().is_some_and(|ids| !ids.contains(&id))
+                || (options.exclude_empty && is_empty_content(&content))
+            {
+                return None;
+            }
+            // 閾値未満のドキュメントを除外（閾値は重みを掛ける前の類似度で判定）
+            let score = score_fn(&embedding, norm)? * options.collection_weight(&coll_name);
+            Some(((id, content, score, coll_name, metadata), embedding))
+        };
+
+        // 各ドキュメントとの類似度を計算
+        #[cfg(feature = "parallel")]
+        let mut results: Vec<(ScoredDocument, Vec<f32>)> = {
+            use rayon::prelude::*;
+            documents.into_par_iter().filter_map(score).collect()
+        };
+        #[cfg(not(feature = "parallel"))]
+        let mut results: Vec<(ScoredDocument, Vec<f32>)> =
+            documents.into_iter().filter_map(score).collect();
+
+        // スコアの降順でソート（高い = より類似）
+        // 同スコアはドキュメントIDの昇順にして、ページングや並列計算でも順序が変わらないようにする
+        results.sort_by(|(a, _), (b, _)| b.2.partial_cmp(&a.2).unwrap().then(a.0.cmp(&b.0)));
+
+        // Top-K を取得
+        results.truncate(top_k);
+
+        Ok(results)
+    }
+
+    /// キーワード検索（FTS5 BM25 + LIKE フォールバック）
+    ///
+    /// 完全一致・部分一致ベースの検索
+    ///
+    /// # アルゴリズム
+    /// 1. FTS5でBM25検索を試行（英語に最適）
+    /// 2. 結果がなければLIKE検索にフォールバック（日本語対応）
+    /// 3. `options.fuzzy` の場合、綴りの近い単語を含むドキュメントを通常の検索の結果の後に追加
+    ///    （全件スキャン）
+    ///
+    /// # 特徴
+    /// - **長所**: 正確なキーワードマッチング、高速（FTS5使用時）
+    /// - **短所**: 言い換えや類義語に対応できない
+    ///
+    /// # スコアリング
+    /// - FTS5: BM25スコア → 0〜1に正規化
+    /// - LIKE: キーワードの出現回数（BM25と同じ向きの負の値） → 0〜1に正規化
+    /// - 両方の結果がある場合は、マージする前にそれぞれの中でスケーリングされる
+    /// - デフォルト（[`KeywordNormalization::MinMax`]）では返す結果の中で0〜1にスケーリングする
+    ///   （[`min_max_keyword_scores`] を参照）
+    /// - `options.keyword_normalization` が [`KeywordNormalization::Sigmoid`] の場合は、
+    ///   `options.keyword_score_scale` を係数とするSigmoidで正規化する
+    ///   （[`normalize_keyword_score`] を参照）
+    /// - `options.collection_weights` を指定した場合は、正規化後のスコアにコレクションの重みを
+    ///   掛けて並べ替える
+    ///
+    /// # 引数
+    /// * `query` - 検索キーワード
+    /// * `collection_ids` - 対象コレクションID
+    /// * `top_k` - 返す結果数
+    /// * `options` - 追加オプション（除外IDなど）
+    /// * `diagnostics` - 所要時間・候補数の記録先（[`Doredore::search_with_diagnostics`]）
+    fn keyword_search(
+        &self,
+        query: &str,
+        collection_ids: Option<&[i64]>,
+        top_k: usize,
+        options: &SearchOptions,
+        diagnostics: Option<&mut SearchDiagnostics>,
+    ) -> Result<Vec<ScoredDocument>> {
+        // データベース層でFTS5 → LIKE のフォールバック検索を実行
+        let started = Instant::now();
+        let mut results = self.store.keyword_search_matching(
+            query,
+            collection_ids,
+            &options.exclude_ids,
+            options.keyword_match,
+        )?;
+
+        if options.fuzzy {
+            // 綴りの近い単語を含むドキュメントを、通常の検索で見つかったものの後に追加する
+            let found: HashSet<i64> = results.iter().map(|(id, ..)| *id).collect();
+            let mut fuzzy_results: Vec<ScoredDocument> = self
+                .store
+                .fuzzy_keyword_search(query, collection_ids, &options.exclude_ids)?
+                .into_iter()
+                .filter(|(id, ..)| !found.contains(id))
+                .collect();
+            // BM25とあいまい一致（-10 × 類似度）は尺度が異なるため、あいまい一致のスコアを
+            // 通常の検索の最も低いスコアから0までの範囲に収め、上回らないようにする
+            if let Some(worst) = results.iter().map(|r| r.2).reduce(f32::max) {
+                for result in &mut fuzzy_results {
+                    result.2 = worst * (result.2 / -10.0);
+                }
+            }
+            results.extend(fuzzy_results);
+        }
+        let allowed_ids = self.metadata_filter_ids(collection_ids, options)?;
+
+        // コレクションの重みで順位が変わるため、重みがある場合は全候補のスコアを求めてから選ぶ
+        let weighted = !options.collection_weights.is_empty();
+        let mut top_results: Vec<ScoredDocument> = results
+            .into_iter()
+            .filter(|(id, ..)| allowed_ids.as_ref().is_none_or(|ids| ids.contains(id)))
+            // メタデータをキーワード検索の対象にしている場合、本文が空でもヒットしうる
+            .filter(|(_, content, ..)| !(options.exclude_empty && is_empty_content(content)))
+            .take(if weighted { usize::MAX } else { top_k })
+            .collect();
+
+        // BM25スコア（LIKE検索のスコアも同じ向き）を0-1に正規化
+        // BM25スコアは負の値（小さいほど良い）なので、高関連ほど1.0に近づける
+        match options.keyword_normalization {
+            KeywordNormalization::Sigmoid => {
+                for result in &mut top_results {
+                    result.2 = normalize_keyword_score(result.2, options.keyword_score_scale);
+                }
+            }
+            KeywordNormalization::MinMax => {
+                let bm25_scores: Vec<f32> = top_results.iter().map(|r| r.2).collect();
+                for (result, score) in top_results
+                    .iter_mut()
+                    .zip(min_max_keyword_scores(&bm25_scores))
+                {
+                    result.2 = score;
+                }
+            }
+        }
+
+        if weighted {
+            for result in &mut top_results {
+                result.2 *= options.collection_weight(&result.3);
+            }
+            top_results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap().then(a.0.cmp(&b.0)));
+            top_results.truncate(top_k);
+        }
+        if let Some(diagnostics) = diagnostics {
+            diagnostics.keyword_ms += millis(started.elapsed());
+            diagnostics.keyword_candidates = Some(top_results.len());
+            diagnostics.keyword_backend = self.store.last_keyword_backend();
+        }
+
+        Ok(top_results)
+    }
+
+    /// ハイブリッド検索（セマンティック + キーワード）
+    ///
+    /// 意味ベース検索と完全一致検索の長所を組み合わせる
+    ///
+    /// # アルゴリズム
+    /// 1. セマンティック検索でtop_k×`candidate_multiplier`件取得（デフォルト2倍）
+    /// 2. キーワード検索でtop_k×`candidate_multiplier`件取得
+    /// 3. ドキュメントIDごとにスコアをマージ
+    /// 4. 加重平均でハイブリッドスコアを計算
+    /// 5. 再ランキングしてtop-kを返す
+    ///
+    /// # スコア統合式
+    /// ```text
+    /// hybrid_score = w_s × semantic_score + w_k × keyword_score
+    /// デフォルト: 0.7 × semantic + 0.3 × keyword
+    /// ```
+    ///
+    /// `options.fusion` が [`FusionMethod::Rrf`] の場合は、各スコアの代わりに
+    /// 各検索での順位から求めた [`reciprocal_rank_score`] を使う
+    ///
+    /// # 特徴
+    /// - 意味的な理解と正確なマッチングのバランス
+    /// - 片方だけに出現するドキュメントも含まれる（欠損値は0.0）
+    /// - 重み調整でユースケースに最適化可能
+    ///
+    /// # 引数
+    /// * `query` - 検索クエリ
+    /// * `collection_ids` - 対象コレクションID
+    /// * `top_k` - 最終的に返す結果数
+    /// * `threshold` - セマンティック検索の閾値（`options.threshold` ではなくこの値を使う）
+    /// * `options` - 追加オプション（重みは `options.hybrid_weights`、除外IDなど）
+    /// * `diagnostics` - 所要時間・候補数の記録先（[`Doredore::search_with_diagnostics`]）
+    fn hybrid_search(
+        &self,
+        query: &CachedQuery,
+        collection_ids: Option<&[i64]>,
+        top_k: usize,
+        threshold: f32,
+        options: &SearchOptions,
+        mut diagnostics: Option<&mut SearchDiagnostics>,
+    ) -> Result<Vec<ScoredDocument>> {
+        // デフォルト重み: セマンティック70% + キーワード30%
+        let (semantic_weight, keyword_weight) = options.hybrid_weights.unwrap_or((0.7, 0.3));
+
+        // 両方の検索を実行（top_k×candidate_multiplierで多めに取得）
+        // 後でマージして再ランキングするため、候補を多めに取る
+        let candidates = top_k.saturating_mul(options.candidate_multiplier.max(1));
+        let semantic_results = self.semantic_search(
+            query,
+            collection_ids,
+            candidates,
+            threshold,
+            options,
+            diagnostics.as_deref_mut(),
+        )?;
+        let keyword_results =
+            self.keyword_search(query.text, collection_ids, candidates, options, diagnostics)?;
+
+        let mut hybrid_results = fuse_hybrid_results(
+            semantic_results,
+            keyword_results,
+            semantic_weight,
+            keyword_weight,
+            options.fusion,
+        );
+
+        // Top-Kを取得
+        hybrid_results.truncate(top_k);
+
+        Ok(hybrid_results)
+    }
+
+
+    /// RAGエンリッチメント（LLMコンテキスト生成）
+    ///
+    /// 検索結果をLLMに渡しやすい形式に整形
+    ///
+    /// # 処理フロー
+    /// 1. 指定されたモードで検索を実行
+    /// 2. 検索結果を整形済みコンテキスト文字列に変換
+    /// 3. EnrichResultとして返す
+    ///
+    /// # 用途
+    /// LLMプロンプトに挿入するコンテキストを生成
+    /// ```text
+    /// [Source 1] (Score: 0.876, Collection: docs)
+    /// ドキュメントの内容...
+    ///
+    /// [Source 2] (Score: 0.754, Collection: docs)
+    /// ドキュメントの内容...
+    /// ```
+    ///
+    /// # 引数
+    /// * searchメソッドと同じパラメータ
+    ///
+    /// # 戻り値
+    /// EnrichResult（question, context, sources）
+    pub fn enrich(
+        &self,
+        query: &str,
+        collection: Option<&str>,
+        collections: Option<&[String]>,
+        top_k: usize,
+        threshold: f32,
+        mode: SearchMode,
+        hybrid_weights: Option<(f32, f32)>,
+    ) -> Result<EnrichResult> {
+        let options = SearchOptions {
+            mode,
+            threshold,
+            hybrid_weights,
+            ..Default::default()
+        };
+        self.enrich_with_options(query, collection, collections, top_k, &options)
+    }
+
+    /// 追加オプション付きのRAGエンリッチメント
+    ///
+    /// `search_with_options` と同じパラメータで検索し、[`SearchOptions`] のコンテキストの
+    /// 書式などを反映する
+    pub fn enrich_with_options(
+        &self,
+        query: &str,
+        collection: Option<&str>,
+        collections: Option<&[String]>,
+        top_k: usize,
+        options: &SearchOptions,
+    ) -> Result<EnrichResult> {
+        // 検索を実行
+        let sources = self.search_with_options(query, collection, collections, top_k, options)?;
+
+        // LLM向けに整形されたコンテキストを含むEnrichResultを生成
+        let descriptions = if options.include_collection_description {
+            self.collection_descriptions(&sources)?
+        } else {
+            HashMap::new()
+        };
+        // 前後に追加する文字列（ソースとは空行で区切る）の分をソースの文字数の上限から除く
+        let max_sources_chars = options.max_context_chars.map(|max| {
+            let wrapper_chars: usize = [&options.context_prefix, &options.context_suffix]
+                .into_iter()
+                .flatten()
+                .filter(|part| !part.is_empty())
+                .map(|part| part.chars().count() + 2)
+                .sum();
+            max.saturating_sub(wrapper_chars)
+        });
+        let mut result = EnrichResult::with_template(
+            query.to_string(),
+            sources,
+            &descriptions,
+            max_sources_chars,
+            options.context_template.as_deref(),
+            options.context_separator.as_deref(),
+        );
+        result.wrap_context(
+            options.context_prefix.as_deref(),
+            options.context_suffix.as_deref(),
+        );
+
+        Ok(result)
+    }
+
+    /// ソースのコレクション名から説明への対応を取得（説明が空のコレクションは含めない）
+    ///
+    /// 同じコレクションのソースが複数あっても、コレクションの取得は1回だけ行う
+    fn collection_descriptions(
+        &self,
+        sources: &[SearchResult],
+    ) -> Result<HashMap<String, String>> {
+        let mut looked_up = HashSet::new();
+        let mut descriptions = HashMap::new();
+
+        for source in sources {
+            if !looked_up.insert(source.collection_name.as_str()) {
+                continue;
+            }
+            let collection = self.store.get_collection(&source.collection_name)?;
+            if let Some(description) = collection.description.filter(|d| !d.trim().is_empty()) {
+                descriptions.insert(source.collection_name.clone(), description);
+            }
+        }
+
+        Ok(descriptions)
+    }
+
+    // ヘルパーメソッド
+
+    /// メタデータ条件に一致するドキュメントID（条件が空の場合はNone = 絞り込みなし）
+    fn metadata_filter_ids(
+        &self,
+        collection_ids: Option<&[i64]>,
+        options: &SearchOptions,
+    ) -> Result<Option<HashSet<i64>>> {
+        if options.metadata_filter.is_empty() {
+            return Ok(None);
+        }
+
+        self.store
+            .filter_document_ids(collection_ids, &options.metadata_filter)
+            .map(Some)
+    }
+
+    /// 存在しないコレクション名を取得（`skip_missing` で検索した際にスキップされる名前）
+    pub fn find_missing_collections(&self, names: &[String]) -> Result<Vec<String>> {
+        let existing: HashSet<String> = self
+            .store
+            .list_collections()?
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+
+        Ok(names
+            .iter()
+            .filter(|name| !existing.contains(*name))
+            .cloned()
+            .collect())
+    }
+
+    /// コレクション名をIDに変換
+    ///
+    /// `skip_missing` がtrueの場合、存在しないコレクションはエラーにせずスキップする
+    fn get_collection_ids(
+        &self,
+        collection: Option<&str>,
+        collections: Option<&[String]>,
+        skip_missing: bool,
+    ) -> Result<Option<Vec<i64>>> {
+        let names: Vec<&str> = if let Some(coll_name) = collection {
+            vec![coll_name]
+        } else if let Some(coll_names) = collections {
+            coll_names.iter().map(String::as_str).collect()
+        } else {
+            return Ok(None);
+        };
+
+        let mut ids = Vec::new();
+        for name in names {
+            match self.store.get_collection(name) {
+                Ok(coll) => ids.push(coll.id),
+                Err(Error::CollectionNotFound(_)) if skip_missing => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Some(ids))
+    }
+
+    /// コレクションのEmbeddingモデル（指定がなければインスタンスのモデル）
+    ///
+    /// インスタンスのモデルを使う場合は、そのモデル名と次元数をストアに記録する
+    /// （記録済みで次元数が異なる場合は `Error::InvalidInput`）
+    fn collection_model(&self, collection: &Collection) -> Result<EmbeddingModel> {
+        match collection.embedding_model.as_deref() {
+            Some(name) if name != self.model_name() => self.model_named(name),
+            _ => {
+                self.record_embedding_model()?;
+                Ok(self.embedding_model.clone())
+            }
+        }
+    }
+
+    /// 登録するドキュメントをベクトル化（Embeddingキャッシュが有効ならキャッシュを使う）
+    fn embed_passage(&self, model: &EmbeddingModel, text: &str) -> Result<Vec<f32>> {
+        self.embed_passage_batch(model, vec![text.to_string()])?
+            .pop()
+            .ok_or_else(|| Error::Embedding("No embedding generated".to_string()))
+    }
+
+    /// 複数のドキュメントをベクトル化（Embeddingキャッシュが有効ならキャッシュを使う）
+    ///
+    /// キャッシュにないテキストだけをまとめてモデルに渡し、生成したEmbeddingを保存する
+    fn embed_passage_batch(
+        &self,
+        model: &EmbeddingModel,
+        texts: Vec<String>,
+    ) -> Result<Vec<Vec<f32>>> {
+        if !self.embedding_cache {
+            return model.embed_passage_batch(texts);
+        }
+
+        let hashes: Vec<String> = texts
+            .iter()
+            .map(|text| model.passage_input_hash(text))
+            .collect();
+        let mut cached =
+            self.store
+                .get_cached_embeddings(model.model_name(), model.dimension(), &hashes)?;
+
+        // 同じバッチ内で重複するテキストも1回だけベクトル化する
+        let mut seen = HashSet::new();
+        let missing: Vec<usize> = (0..texts.len())
+            .filter(|&i| !cached.contains_key(&hashes[i]) && seen.insert(&hashes[i]))
+            .collect();
+        if !missing.is_empty() {
+            let embeddings =
+                model.embed_passage_batch(missing.iter().map(|&i| texts[i].clone()).collect())?;
+            let entries: Vec<(String, Vec<f32>)> = missing
+                .iter()
+                .map(|&i| hashes[i].clone())
+                .zip(embeddings)
+                .collect();
+            self.store
+                .put_cached_embeddings(model.model_name(), model.dimension(), &entries)?;
+            cached.extend(entries);
+        }
+
+        hashes
+            .iter()
+            .map(|hash| {
+                cached
+                    .get(hash)
+                    .cloned()
+                    .ok_or_else(|| Error::Embedding("No embedding generated".to_string()))
+            })
+            .collect()
+    }
+
+    /// 保存済みの次元数とインスタンスのモデルの次元数が一致するか確認
+    ///
+    /// 一致すればtrue、未記録ならfalse、異なる場合は `Error::InvalidInput`
+    fn check_embedding_dimension(&self) -> Result<bool> {
+        let Some(stored) = self.store.get_setting(EMBEDDING_DIMENSION_SETTING)? else {
+            return Ok(false);
+        };
+
+        if stored.parse::<usize>().ok() != Some(self.dimension()) {
+            let stored_model = self
+                .store
+                .get_setting(EMBEDDING_MODEL_SETTING)?
+                .unwrap_or_else(|| "unknown".to_string());
+            return Err(Error::InvalidInput(format!(
+                "Embedding dimension mismatch: the database was populated with '{}' ({} dimensions), \
+                 but the configured model '{}' produces {} dimensions",
+                stored_model,
+                stored,
+                self.model_name(),
+                self.dimension()
+            )));
+        }
+        Ok(true)
+    }
+
+    /// インスタンスのモデル名と次元数をストアに記録（記録済みなら次元数を確認する）
+    fn record_embedding_model(&self) -> Result<()> {
+        if self.check_embedding_dimension()? || self.store.is_read_only() {
+            return Ok(());
+        }
+
+        self.store.set_setting(EMBEDDING_MODEL_SETTING, self.model_name())?;
+        self.store.set_setting(EMBEDDING_DIMENSION_SETTING, &self.dimension().to_string())
+    }
+
+    /// 名前でEmbeddingモデルを取得（未ロードならロードして以降は使い回す）
+    fn model_named(&self, name: &str) -> Result<EmbeddingModel> {
+        // 共有している他のインスタンスが同時にロードしないよう、ロード中もロックを保持する
+        let mut models = self.models.lock().unwrap();
+        if let Some(model) = models.get(name) {
+            return Ok(model.clone());
+        }
+
+        let model = EmbeddingModel::new(Some(name), self.cache_dir.as_deref())?
+            .with_max_input_chars(self.embedding_model.max_input_chars());
+        models.insert(name.to_string(), model.clone());
+        Ok(model)
+    }
+
+    /// 検索対象のコレクションをEmbeddingモデルごとに分ける
+    ///
+    /// モデルが異なるベクトル同士の類似度には意味がないため、クエリはモデルごとに
+    /// ベクトル化して、同じモデルのコレクションのドキュメントとだけ比較する。
+    /// モデルを指定したコレクションが対象になければ、インスタンスのモデルと
+    /// `collection_ids` の1組のみ。対象のコレクションがないモデルは含めない
+    fn model_groups(
+        &self,
+        collection_ids: Option<&[i64]>,
+    ) -> Result<Vec<(EmbeddingModel, Option<Vec<i64>>)>> {
+        let custom: HashMap<i64, String> = self
+            .store
+            .get_collection_models()?
+            .into_iter()
+            .filter(|(id, name)| {
+                name != self.model_name() && collection_ids.is_none_or(|ids| ids.contains(id))
+            })
+            .collect();
+        if custom.is_empty() {
+            return Ok(vec![(
+                self.embedding_model.clone(),
+                collection_ids.map(<[i64]>::to_vec),
+            )]);
+        }
+
+        // インスタンスのモデルを使うコレクション（全コレクションが対象なら一覧から求める）
+        let default_ids: Vec<i64> = match collection_ids {
+            Some(ids) => ids.to_vec(),
+            None => self.store.list_collections()?.iter().map(|c| c.id).collect(),
+        };
+        let default_ids: Vec<i64> = default_ids
+            .into_iter()
+            .filter(|id| !custom.contains_key(id))
+            .collect();
+
+        let mut by_model: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+        for (id, name) in custom {
+            by_model.entry(name).or_default().push(id);
+        }
+
+        let mut groups = Vec::new();
+        if !default_ids.is_empty() {
+            groups.push((self.embedding_model.clone(), Some(default_ids)));
+        }
+        for (name, ids) in by_model {
+            groups.push((self.model_named(&name)?, Some(ids)));
+        }
+        Ok(groups)
+    }
+
+    // CSV インポート・エクスポート
+
+    /// CSVファイルからドキュメントをインポート
+    ///
+    /// `content_column` が空文字列の場合は本文カラムを自動検出する
+    /// （[`Doredore::import_csv_detailed`] を参照）
+    pub fn import_csv(
+        &self,
+        file_path: &str,
+        collection: &str,
+        content_column: &str,
+        metadata_columns: Option<Vec<String>>,
+    ) -> Result<usize> {
+        self.import_csv_with_options(
+            file_path,
+            collection,
+            content_column,
+            metadata_columns,
+            &AddOptions::default(),
+        )
+    }
+
+    /// オプションを指定してCSVファイルからドキュメントをインポート
+    ///
+    /// `options` は [`Doredore::add_documents_with_options`] と同じ
+    pub fn import_csv_with_options(
+        &self,
+        file_path: &str,
+        collection: &str,
+        content_column: &str,
+        metadata_columns: Option<Vec<String>>,
+        options: &AddOptions,
+    ) -> Result<usize> {
+        let csv_options = CsvImportOptions {
+            content_column: content_column.to_string(),
+            metadata_columns,
+            add_options: *options,
+            ..Default::default()
+        };
+        self.import_csv_detailed(file_path, collection, &csv_options)
+            .map(|(count, _)| count)
+    }
+
+    /// CSVファイルからドキュメントをインポートし、使用した本文カラム名も返す
+    ///
+    /// # 本文カラムの決定
+    /// - `options.content_column` が指定されていればそのカラムを使用（見つからなければエラー）
+    /// - 空文字列の場合は自動検出: セルの平均文字数が最も長いカラムを本文とみなす
+    ///
+    /// `options.add_options.bulk` がtrueの場合はFTSインデックスをインポート完了後にまとめて構築する
+    /// （[`Doredore::add_documents_with_options`] を参照）
+    ///
+    /// `options.content_type` にHTML/Markdownを指定すると、本文カラムをプレーンテキストに
+    /// 変換してから取り込む（[`Doredore::add_document_as`] を参照）
+    ///
+    /// # メタデータの型
+    /// `options.infer_types = true` の場合、メタデータカラムの値がJSONの数値（`2023`、`4.5`）や
+    /// 真偽値（`true` / `false`、大文字小文字は区別しない）として解釈できれば、その型で保存する
+    /// （メタデータフィルターで数値として比較できる）。数値に変換すると元の表記に戻らない値
+    /// （`007`、`1.10`、`-0`、`1e3`、i64/u64に収まらない整数）は、情報が失われないよう
+    /// 文字列のままにする。
+    /// `false` の場合はすべて文字列として保存する（[`Doredore::import_csv`] は `true`）
+    ///
+    /// # 取り込み元の記録
+    /// 検索結果からどのファイルのどの行か分かるよう、各ドキュメントのメタデータに
+    /// `source_file`（`file_path` の値）と `source_row`（ヘッダーを除いた1始まりの行番号）を
+    /// 自動で追加する。`metadata_columns` に同名のカラムがある場合はその値を優先する
+    ///
+    /// # 戻り値
+    /// `(インポート件数, 本文カラム名)`
+    pub fn import_csv_detailed(
+        &self,
+        file_path: &str,
+        collection: &str,
+        options: &CsvImportOptions,
+    ) -> Result<(usize, String)> {
+        let (documents, metadata_list, content_column) = read_csv_documents(file_path, options)?;
+
+        let count = documents.len();
+        self.add_documents_with_options(
+            documents,
+            collection,
+            Some(metadata_list),
+            &options.add_options,
+        )?;
+
+        Ok((count, content_column))
+    }
+
+    /// CSVファイルからドキュメントをインポートし、進捗を `on_progress(処理済み件数, 全件数)` で通知
+    ///
+    /// [`Doredore::import_csv_detailed`] と同じ取り込みを [`IMPORT_PROGRESS_BATCH_SIZE`] 件ずつ行い、
+    /// 各バッチのEmbedding生成・追加が終わるたびにコールバックを呼ぶ（最初に `(0, 全件数)` も通知する）。
+    /// 途中で失敗した場合、それまでのバッチで追加したドキュメントは残る
+    ///
+    /// ファイルは2回読む。1回目で本文カラムの決定と件数の計算だけを行い、2回目で
+    /// バッチごとに読みながら取り込むため、ファイル全体をメモリに読み込まない
+    ///
+    /// `options.add_options.bulk` がtrueの場合、FTSインデックスは全バッチの完了後（失敗時はその時点）に
+    /// 一度だけ構築する
+    pub fn import_csv_with_progress(
+        &self,
+        file_path: &str,
+        collection: &str,
+        options: &CsvImportOptions,
+        mut on_progress: impl FnMut(usize, usize),
+    ) -> Result<(usize, String)> {
+        let (headers, content_idx, total) = scan_csv(file_path, &options.content_column)?;
+        let rows = CsvRows {
+            file_path,
+            headers,
+            content_idx,
+            options,
+        };
+
+        // コレクションがなければ読み込みだけで終わらないよう、バッチ処理の前に確認する
+        self.store.get_collection(collection)?;
+
+        on_progress(0, total);
+
+        let bulk = options.add_options.bulk;
+        if bulk {
+            self.store.begin_bulk_load();
+        }
+
+        // FTSインデックスの構築はここでまとめて行うため、バッチごとの追加ではbulkにしない
+        let batch_options = AddOptions {
+            bulk: false,
+            ..options.add_options
+        };
+        let mut insert = || -> Result<usize> {
+            let mut reader = csv::Reader::from_path(file_path)?;
+            let mut records = reader.records().enumerate();
+            let mut processed = 0;
+            loop {
+                let mut documents = Vec::with_capacity(IMPORT_PROGRESS_BATCH_SIZE);
+                let mut metadata_list = Vec::with_capacity(IMPORT_PROGRESS_BATCH_SIZE);
+                for (row, record) in records.by_ref() {
+                    if let Some((content, metadata)) = rows.document(row, &record?) {
+                        documents.push(content);
+                        metadata_list.push(metadata);
+                    }
+                    if documents.len() == IMPORT_PROGRESS_BATCH_SIZE {
+                        break;
+                    }
+                }
+                if documents.is_empty() {
+                    return Ok(processed);
+                }
+
+                processed += documents.len();
+                self.add_documents_with_options(
+                    documents,
+                    collection,
+                    Some(metadata_list),
+                    &batch_options,
+                )?;
+                on_progress(processed, total);
+            }
+        };
+        let result = insert();
+
+        if bulk {
+            self.store.end_bulk_load()?;
+        }
+
+        result.map(|count| (count, rows.content_column()))
+    }
+
+    /// JSONL（1行に1つのJSONオブジェクト）ファイルからドキュメントをインポートし、件数を返す
+    ///
+    /// 各行の `content_field` の文字列を本文に、`metadata_field` のオブジェクトをそのまま
+    /// メタデータとして取り込む（CSVと異なり、数値・真偽値・入れ子の値も型を保持する）。
+    /// 空行は読み飛ばす。JSONとして解析できない行、本文が文字列でない行、メタデータが
+    /// オブジェクトでない行があれば、何も追加せずに行番号付きの `Error::InvalidInput` を返す
+    ///
+    /// CSVと同様、メタデータに `source_file` と `source_row`（ファイルの1始まりの行番号）を
+    /// 追加する（同名のキーがある場合はその値を優先する）
+    pub fn import_jsonl(
+        &self,
+        file_path: &str,
+        collection: &str,
+        content_field: &str,
+        metadata_field: Option<&str>,
+    ) -> Result<usize> {
+        let (documents, metadata_list) =
+            read_jsonl_documents(file_path, content_field, metadata_field)?;
+
+        let count = documents.len();
+        self.add_documents(documents, collection, Some(metadata_list))?;
+
+        Ok(count)
+    }
+
+    pub fn export_csv(
+        &self,
+        file_path: &str,
+        collection: Option<&str>,
+    ) -> Result<usize> {
+        let documents = self.list_documents(collection, 1000000, 0)?;
+
+        let mut writer = csv::Writer::from_path(file_path)?;
+
+        // ヘッダー
+        writer.write_record(&["id", "collection", "content", "metadata", "created_at"])?;
+
+        // データ
+        for doc in &documents {
+            let metadata_str = doc
+                .metadata
+                .as_ref()
+                .map(|m| serde_json::to_string(m).unwrap_or_default())
+                .unwrap_or_default();
+
+            writer.write_record(&[
+                doc.id.to_string(),
+                doc.collection_name.clone(),
+                doc.content.clone(),
+                metadata_str,
+                doc.created_at.clone(),
+            ])?;
+        }
+
+        writer.flush()?;
+
+        Ok(documents.len())
+    }
+}
+
+impl Drop for Doredore {
+    /// 変更されたANNインデックスを保存（失敗しても破棄は続ける）
+    ///
+    /// 読み取り専用のインスタンス（サーバーの検索用プールなど）は書き込まない
+    fn drop(&mut self) {
+        if self.store.is_read_only() {
+            return;
+        }
+        if let Err(e) = self.save_ann_indexes() {
+            tracing::warn!("Failed to save ANN indexes: {}", e);
+        }
+    }
+}
+
+/// 同じ親ドキュメント（`parents` はドキュメントID → 親ドキュメントID）のチャンクのうち、
+/// 先頭（最もスコアの高い）1件だけを残す。チャンクでないドキュメントは自身のIDを親として扱う
+fn collapse_chunks(
+    results: Vec<ScoredDocument>,
+    parents: &HashMap<i64, i64>,
+) -> Vec<ScoredDocument> {
+    let mut seen = HashSet::new();
+    results
+        .into_iter()
+        .filter(|(id, ..)| seen.insert(parents.get(id).copied().unwrap_or(*id)))
+        .collect()
+}
+
+/// Maximal Marginal Relevance（MMR）で候補から `top_k` 件を選び直す
+///
+/// 各ステップで `λ × クエリとの類似度 − (1 − λ) × 選択済みの結果との最大類似度` が
+/// 最大の候補を選ぶ。λ = 1.0 ではスコア順のまま、小さいほどすでに選んだ結果と
+/// 似たドキュメントが後回しになる。`candidates` はスコアの降順で、スコアは変更しない
+fn maximal_marginal_relevance(
+    candidates: Vec<(ScoredDocument, Vec<f32>)>,
+    lambda: f32,
+    top_k: usize,
+) -> Vec<ScoredDocument> {
+    let lambda = lambda.clamp(0.0, 1.0);
+    let mut remaining = candidates;
+    let mut selected: Vec<(ScoredDocument, Vec<f32>)> = Vec::new();
+
+    while selected.len() < top_k && !remaining.is_empty() {
+        let mmr_score = |(doc, embedding): &(ScoredDocument, Vec<f32>)| {
+            let redundancy = selected
+                .iter()
+                .map(|(_, chosen)| cosine_similarity(embedding, chosen))
+                .fold(0.0_f32, f32::max);
+            lambda * doc.2 - (1.0 - lambda) * redundancy
+        };
+        // 同点の場合は先頭（スコアの高い方）を選ぶ
+        let mut best = 0;
+        let mut best_score = mmr_score(&remaining[0]);
+        for (i, candidate) in remaining.iter().enumerate().skip(1) {
+            let score = mmr_score(candidate);
+            if score > best_score {
+                best = i;
+                best_score = score;
+            }
+        }
+        selected.push(remaining.remove(best));
+    }
+
+    selected.into_iter().map(|(doc, _)| doc).collect()
+}
+
+/// セマンティック検索とキーワード検索の結果（それぞれスコアの降順）を統合し、
+/// ハイブリッドスコアの降順で返す（`Doredore::hybrid_search` を参照）
+fn fuse_hybrid_results(
+    semantic_results: Vec<ScoredDocument>,
+    keyword_results: Vec<ScoredDocument>,
+    semantic_weight: f32,
+    keyword_weight: f32,
+    fusion: FusionMethod,
+) -> Vec<ScoredDocument> {
+    // 統合に使う値（加重平均ではスコア、RRFでは順位から求めたスコア）
+    let fused = |rank: usize, score: f32| match fusion {
+        FusionMethod::Weighted => score,
+        FusionMethod::Rrf => reciprocal_rank_score(rank),
+    };
+
+    // ドキュメントIDをキーにしたスコアマップを作成
+    // 値: ((content, collection_name, metadata), semantic_score, keyword_score)
+    type Entry = (String, String, Option<serde_json::Value>);
+    let mut score_map: HashMap<i64, (Entry, f32, f32)> = HashMap::new();
+
+    // セマンティック検索の結果を追加
+    for (rank, (id, content, score, coll_name, metadata)) in
+        semantic_results.into_iter().enumerate()
+    {
+        score_map.insert(
+            id,
+            (
+                (content, coll_name, metadata),
+                fused(rank, score), // semantic_score
+                0.0,                // keyword_score（まだない）
+            ),
+        );
+    }
+
+    // キーワード検索の結果を追加/更新
+    for (rank, (id, content, score, coll_name, metadata)) in keyword_results.into_iter().enumerate()
+    {
+        let score = fused(rank, score);
+        score_map
+            .entry(id)
+            .and_modify(|e| e.2 = score) // 既存エントリのkeyword_scoreを更新
+            .or_insert((
+                // 新規エントリを作成（semantic_scoreは0.0）
+                (content, coll_name, metadata),
+                0.0,
+                score,
+            ));
+    }
+
+    // ハイブリッドスコアを計算
+    let mut hybrid_results: Vec<ScoredDocument> = score_map
+        .into_iter()
+        .map(
+            |(id, ((content, coll_name, metadata), semantic_score, keyword_score))| {
+                // 加重平均でハイブリッドスコアを計算
+                let hybrid_score =
+                    semantic_weight * semantic_score + keyword_weight * keyword_score;
+                (id, content, hybrid_score, coll_name, metadata)
+            },
+        )
+        .collect();
+
+    // ハイブリッドスコアの降順でソート（同スコアはドキュメントIDの昇順）
+    // HashMapの走査順は毎回異なるため、タイブレークがないと順序が不安定になる
+    hybrid_results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap().then(a.0.cmp(&b.0)));
+
+    hybrid_results
+}
+
+/// CSVを読み込み、取り込むドキュメント本文・メタデータ・本文カラム名を返す
+///
+/// 本文カラムの決定とメタデータの構築は [`Doredore::import_csv_detailed`] を参照
+fn read_csv_documents(
+    file_path: &str,
+    options: &CsvImportOptions,
+) -> Result<(Vec<String>, Vec<serde_json::Value>, String)> {
+    let mut reader = csv::Reader::from_path(file_path)?;
+    let headers = reader.headers()?.clone();
+    let records = reader
+        .records()
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let content_idx = match find_content_column(&headers, &options.content_column)? {
+        Some(idx) => idx,
+        None => {
+            let mut detector = ContentColumnDetector::default();
+            records.iter().for_each(|record| detector.add(record));
+            detector.detect().ok_or_else(no_content_column_error)?
+        }
+    };
+    let rows = CsvRows {
+        file_path,
+        headers,
+        content_idx,
+        options,
+    };
+
+    let mut documents = Vec::new();
+    let mut metadata_list = Vec::new();
+    for (row, record) in records.iter().enumerate() {
+        if let Some((content, metadata)) = rows.document(row, record) {
+            documents.push(content);
+            metadata_list.push(metadata);
+        }
+    }
+
+    Ok((documents, metadata_list, rows.content_column()))
+}
+
+/// 本文カラムを決め、取り込む行数を数える（[`Doredore::import_csv_with_progress`] 用）
+///
+/// 行の内容は保持せずに読み進めるため、大きなファイルでもメモリをほとんど使わない
+///
+/// # 戻り値
+/// `(ヘッダー, 本文カラムの位置, 本文カラムのある行数)`
+fn scan_csv(file_path: &str, content_column: &str) -> Result<(csv::StringRecord, usize, usize)> {
+    let mut reader = csv::Reader::from_path(file_path)?;
+    let headers = reader.headers()?.clone();
+    let content_idx = find_content_column(&headers, content_column)?;
+
+    // カラムごとの、そのカラムのある行数
+    let mut row_counts: Vec<usize> = Vec::new();
+    let mut detector = ContentColumnDetector::default();
+    for record in reader.records() {
+        let record = record?;
+        if record.len() > row_counts.len() {
+            row_counts.resize(record.len(), 0);
+        }
+        for count in &mut row_counts[..record.len()] {
+            *count += 1;
+        }
+        if content_idx.is_none() {
+            detector.add(&record);
+        }
+    }
+
+    let content_idx = match content_idx {
+        Some(idx) => idx,
+        None => detector.detect().ok_or_else(no_content_column_error)?,
+    };
+    let count = row_counts.get(content_idx).copied().unwrap_or(0);
+
+    Ok((headers, content_idx, count))
+}
+
+/// 指定された本文カラムの位置（空文字列の場合は自動検出するためNone）
+fn find_content_column(headers: &csv::StringRecord, content_column: &str) -> Result<Option<usize>> {
+    if content_column.is_empty() {
+        return Ok(None);
+    }
+
+    headers
+        .iter()
+        .position(|h| h == content_column)
+        .map(Some)
+        .ok_or_else(|| Error::InvalidInput(format!("Content column '{}' not found", content_column)))
+}
+
+/// データ行がなく本文カラムを自動検出できない場合のエラー
+fn no_content_column_error() -> Error {
+    Error::InvalidInput("Could not detect content column: CSV has no data".to_string())
+}
+
+/// CSVの行を取り込むドキュメントに変換する（[`Doredore::import_csv_detailed`] を参照）
+struct CsvRows<'a> {
+    file_path: &'a str,
+    headers: csv::StringRecord,
+    content_idx: usize,
+    options: &'a CsvImportOptions,
+}
+
+impl CsvRows<'_> {
+    /// 本文カラムの名前
+    fn content_column(&self) -> String {
+        self.headers
+            .get(self.content_idx)
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// `row` 番目（ヘッダーを除いた0始まり）の行の本文とメタデータ（本文カラムがない行はNone）
+    fn document(
+        &self,
+        row: usize,
+        record: &csv::StringRecord,
+    ) -> Option<(String, serde_json::Value)> {
+        let content = record.get(self.content_idx)?;
+
+        // メタデータを構築
+        let mut meta_map = serde_json::Map::new();
+        if let Some(ref meta_cols) = self.options.metadata_columns {
+            for col_name in meta_cols {
+                if let Some(idx) = self.headers.iter().position(|h| h == col_name) {
+                    if let Some(value) = record.get(idx) {
+                        let value = if self.options.infer_types {
+                            infer_metadata_value(value)
+                        } else {
+                            serde_json::Value::String(value.to_string())
+                        };
+                        meta_map.insert(col_name.clone(), value);
+                    }
+                }
+            }
+        }
+
+        // 取り込み元（ユーザー指定のカラムと同名の場合は上書きしない）
+        meta_map
+            .entry("source_file")
+            .or_insert_with(|| serde_json::Value::String(self.file_path.to_string()));
+        meta_map
+            .entry("source_row")
+            .or_insert_with(|| serde_json::Value::from(row + 1));
+
+        Some((
+            self.options.content_type.to_plain_text(content),
+            serde_json::Value::Object(meta_map),
+        ))
+    }
+}
+
+/// CSVのセルをメタデータの値に変換（数値・真偽値として解釈できなければ文字列）
+///
+/// 変換の基準は [`Doredore::import_csv_detailed`] を参照
+fn infer_metadata_value(cell: &str) -> serde_json::Value {
+    if cell.eq_ignore_ascii_case("true") {
+        return serde_json::Value::Bool(true);
+    }
+    if cell.eq_ignore_ascii_case("false") {
+        return serde_json::Value::Bool(false);
+    }
+
+    // JSONの数値として読めても、元の表記に戻らない値（"1.10"、"-0"、"007"、桁あふれする整数）は
+    // 書式や桁が失われるため文字列のままにする
+    if let Ok(serde_json::Value::Number(number)) = serde_json::from_str(cell) {
+        if number.to_string() == cell {
+            return serde_json::Value::Number(number);
+        }
+    }
+
+    serde_json::Value::String(cell.to_string())
+}
+
+/// JSONLを読み込み、取り込むドキュメント本文とメタデータを返す
+///
+/// 各行の扱いは [`Doredore::import_jsonl`] を参照
+fn read_jsonl_documents(
+    file_path: &str,
+    content_field: &str,
+    metadata_field: Option<&str>,
+) -> Result<(Vec<String>, Vec<serde_json::Value>)> {
+    let text = std::fs::read_to_string(file_path)?;
+
+    let mut documents = Vec::new();
+    let mut metadata_list = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut record: serde_json::Value = serde_json::from_str(line).map_err(|e| {
+            Error::InvalidInput(format!("Line {}: invalid JSON: {}", line_number, e))
+        })?;
+
+        let content = match record.get(content_field) {
+            Some(serde_json::Value::String(content)) => content.clone(),
+            Some(_) => {
+                return Err(Error::InvalidInput(format!(
+                    "Line {}: field '{}' is not a string",
+                    line_number, content_field
+                )))
+            }
+            None => {
+                return Err(Error::InvalidInput(format!(
+                    "Line {}: field '{}' not found",
+                    line_number, content_field
+                )))
+            }
+        };
+
+        let mut meta_map = match metadata_field.and_then(|field| record.get_mut(field)) {
+            Some(serde_json::Value::Object(map)) => std::mem::take(map),
+            None | Some(serde_json::Value::Null) => serde_json::Map::new(),
+            Some(_) => {
+                return Err(Error::InvalidInput(format!(
+                    "Line {}: field '{}' is not an object",
+                    line_number,
+                    metadata_field.unwrap_or_default()
+                )))
+            }
+        };
+
+        // 取り込み元（メタデータに同名のキーがある場合は上書きしない）
+        meta_map
+            .entry("source_file")
+            .or_insert_with(|| serde_json::Value::String(file_path.to_string()));
+        meta_map
+            .entry("source_row")
+            .or_insert_with(|| serde_json::Value::from(line_number));
+
+        documents.push(content);
+        metadata_list.push(serde_json::Value::Object(meta_map));
+    }
+
+    Ok((documents, metadata_list))
+}
+
+/// 本文が空（スペース・タブ・改行のみを含む）かどうか
+///
+/// [`Database::find_empty_documents`] と同じ基準
+fn is_empty_content(content: &str) -> bool {
+    content.trim_matches([' ', '\t', '\n', '\r']).is_empty()
+}
+
+/// スコア付きの候補をSearchResultに変換し、並び順に1から順位を振る
+///
+/// メタデータは検索クエリで本文と一緒に取得済みのため、追加のクエリは発行しない
+fn to_search_results(scored: Vec<ScoredDocument>) -> Vec<SearchResult> {
+    scored
+        .into_iter()
+        .enumerate()
+        .map(|(i, (id, content, score, coll_name, metadata))| SearchResult {
+            rank: i + 1,
+            ..SearchResult::new(id, content, score, metadata, coll_name)
+        })
+        .collect()
+}
+
+/// 検索クエリと、コレクションのモデルごとに生成したクエリのEmbedding
+///
+/// [`SearchOptions::min_results`] による補完やチャンクの集約で同じクエリの検索を
+/// 繰り返す場合に、Embeddingを最初に生成したもので使い回す
+struct CachedQuery<'a> {
+    text: &'a str,
+    /// モデルごとのクエリのEmbedding
+    embeddings: RefCell<Vec<(EmbeddingModel, Vec<f32>)>>,
+}
+
+impl<'a> CachedQuery<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            embeddings: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// `model` でのクエリのEmbedding（初めて使うモデルの場合のみ生成する）
+    fn embedding(&self, model: &EmbeddingModel) -> Result<Vec<f32>> {
+        if let Some((_, embedding)) = self
+            .embeddings
+            .borrow()
+            .iter()
+            .find(|(cached, _)| cached.is_same_model(model))
+        {
+            return Ok(embedding.clone());
+        }
+
+        let embedding = model.embed_query(self.text)?;
+        self.embeddings
+            .borrow_mut()
+            .push((model.clone(), embedding.clone()));
+        Ok(embedding)
+    }
+}
+
+/// 所要時間をミリ秒に変換（[`SearchDiagnostics`] 用）
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// バッチ単位でEmbeddingを生成し、失敗したバッチは1件ずつ再試行する
+///
+/// # 戻り値
+/// 入力と同じ長さのリスト（各ドキュメントのEmbedding、または失敗時のエラーメッセージ）
+fn embed_resilient<F>(
+    documents: &[String],
+    batch_size: usize,
+    embed_batch: F,
+) -> Vec<std::result::Result<Vec<f32>, String>>
+where
+    F: Fn(Vec<String>) -> Result<Vec<Vec<f32>>>,
+{
+    let mut embeddings = Vec::with_capacity(documents.len());
+
+    for batch in documents.chunks(batch_size.max(1)) {
+        match embed_batch(batch.to_vec()) {
+            Ok(batch_embeddings) if batch_embeddings.len() == batch.len() => {
+                embeddings.extend(batch_embeddings.into_iter().map(Ok));
+            }
+            _ => {
+                // バッチが失敗した場合は1件ずつ再試行して原因のドキュメントを特定
+                for doc in batch {
+                    let embedding = match embed_batch(vec![doc.clone()]) {
+                        Ok(mut e) => e
+                            .pop()
+                            .ok_or_else(|| "No embedding generated".to_string()),
+                        Err(e) => Err(e.to_string()),
+                    };
+                    embeddings.push(embedding);
+                }
+            }
+        }
+    }
+
+    embeddings
+}
+
+/// 本文カラムの自動検出
+///
+/// 行を順に [`ContentColumnDetector::add`] で渡し、セルの平均文字数が最も長いカラムを選ぶ
+/// （同率の場合は左側のカラムを優先）。行の内容は保持しない
+#[derive(Default)]
+struct ContentColumnDetector {
+    /// カラムごとのセルの文字数の合計
+    total_chars: Vec<usize>,
+}
+
+impl ContentColumnDetector {
+    fn add(&mut self, record: &csv::StringRecord) {
+        if record.len() > self.total_chars.len() {
+            self.total_chars.resize(record.len(), 0);
+        }
+        for (idx, value) in record.iter().enumerate() {
+            self.total_chars[idx] += value.chars().count();
+        }
+    }
+
+    /// 本文カラムのインデックス（データ行がなければNone）
+    ///
+    /// 行数は全カラム共通のため、平均文字数の比較は合計の比較と同じ
+    fn detect(&self) -> Option<usize> {
+        let mut best: Option<(usize, usize)> = None;
+        for (idx, &chars) in self.total_chars.iter().enumerate() {
+            if best.is_none_or(|(_, best_chars)| chars > best_chars) {
+                best = Some((idx, chars));
+            }
+        }
+
+        best.map(|(idx, _)| idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::mock_embedder::MockEmbedder;
+    use crate::core::search::{KeywordBackend, KeywordMatch};
+    use tempfile::NamedTempFile;
+
+    /// モデルをロードしないインスタンス（単語の重なりで類似度が決まる）
+    ///
+    /// 意味的な類似性に依存しないテストで使い、オフラインでも高速に実行できるようにする
+    fn mock_doredore(db_path: &Path) -> Doredore {
+        Doredore::new_with_embedder(db_path, MockEmbedder::new(384)).unwrap()
+    }
+
+    #[test]
+    fn test_doredore_initialization() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let result = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_default_model_name_and_dimension() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), None, None).unwrap();
+        assert_eq!(rag.model_name(), "bge-small-en-v1.5");
+        assert_eq!(rag.dimension(), 384);
+    }
+
+    #[test]
+    fn test_embed_text_matches_model_dimension() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_db.path());
+
+        let vector = rag
+            .embed_text("Rust is a systems programming language")
+            .unwrap();
+        assert_eq!(vector.len(), rag.dimension());
+        assert_eq!(
+            vector,
+            rag.embedding_model()
+                .embed("Rust is a systems programming language")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_int8_embeddings_keep_search_recall() {
+        let f32_db = NamedTempFile::new().unwrap();
+        let int8_db = NamedTempFile::new().unwrap();
+        let f32_rag = mock_doredore(f32_db.path());
+        let mut int8_rag = mock_doredore(int8_db.path());
+        int8_rag
+            .set_embedding_format(EmbeddingFormat::Int8)
+            .unwrap();
+
+        let words = |i: usize| format!("w{} w{} w{} w{}", i % 23, i % 29 + 23, i % 31 + 52, i % 7);
+        let corpus: Vec<String> = (0..300).map(words).collect();
+        for rag in [&f32_rag, &int8_rag] {
+            rag.create_collection("docs", None).unwrap();
+            rag.add_documents_with_options(
+                corpus.clone(),
+                "docs",
+                None,
+                &AddOptions {
+                    bulk: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        }
+
+        let top_ids = |rag: &Doredore, query: &str| -> HashSet<i64> {
+            rag.search(
+                query,
+                Some("docs"),
+                None,
+                10,
+                0.0,
+                SearchMode::Semantic,
+                None,
+            )
+            .unwrap()
+            .into_iter()
+            .map(|r| r.document_id)
+            .collect()
+        };
+        let queries: Vec<String> = (0..20)
+            .map(|i| format!("w{} w{}", i % 23, i * 3 % 31 + 52))
+            .collect();
+        let hits: usize = queries
+            .iter()
+            .map(|q| top_ids(&f32_rag, q).intersection(&top_ids(&int8_rag, q)).count())
+            .sum();
+        let recall = hits as f32 / (queries.len() * 10) as f32;
+        assert!(recall >= 0.9, "recall@10 = {}", recall);
+
+        let size = |path: &Path| std::fs::metadata(path).unwrap().len();
+        drop((f32_rag, int8_rag));
+        assert!(size(int8_db.path()) < size(f32_db.path()));
+    }
+
+    #[test]
+    fn test_reopen_with_different_dimension_fails() {
+        let temp_db = NamedTempFile::new().unwrap();
+
+        // ドキュメントを追加するまでは次元数を記録しない
+        let rag = Doredore::new_with_embedder(temp_db.path(), MockEmbedder::new(384)).unwrap();
+        rag.create_collection("docs", None).unwrap();
+        drop(rag);
+        let rag = Doredore::new_with_embedder(temp_db.path(), MockEmbedder::new(384)).unwrap();
+        rag.add_document("first document", "docs", None).unwrap();
+        drop(rag);
+
+        // 次元数の異なるモデルで開き直すとエラー
+        let err = Doredore::new_with_embedder(temp_db.path(), MockEmbedder::new(768))
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InvalidInput(_)));
+        let message = err.to_string();
+        assert!(
+            message.contains("384") && message.contains("768"),
+            "{}",
+            message
+        );
+        assert!(matches!(
+            Doredore::with_shared_model_read_only(
+                temp_db.path(),
+                EmbeddingModel::from_embedder(MockEmbedder::new(768)),
+            ),
+            Err(Error::InvalidInput(_))
+        ));
+
+        // 同じ次元数なら開いて追加できる
+        let rag = Doredore::new_with_embedder(temp_db.path(), MockEmbedder::new(384)).unwrap();
+        rag.add_document("second document", "docs", None).unwrap();
+    }
+
+    #[test]
+    fn test_shared_model_across_instances() {
+        let model = EmbeddingModel::new(Some("bge-small-en-v1.5"), None).unwrap();
+
+        let temp_a = NamedTempFile::new().unwrap();
+        let temp_b = NamedTempFile::new().unwrap();
+        let rag_a = Doredore::with_shared_model(temp_a.path(), model.clone()).unwrap();
+        let rag_b = Doredore::with_shared_model(temp_b.path(), model).unwrap();
+
+        rag_a.create_collection("test", None).unwrap();
+        rag_b.create_collection("test", None).unwrap();
+        rag_a.add_document("Machine learning is a subset of AI.", "test", None)
+            .unwrap();
+        rag_b.add_document("Trees are planted in spring.", "test", None)
+            .unwrap();
+
+        let results_a = rag_a
+            .search("machine learning", Some("test"), None, 5, 0.0, SearchMode::Semantic, None)
+            .unwrap();
+        let results_b = rag_b
+            .search("planting trees", Some("test"), None, 5, 0.0, SearchMode::Semantic, None)
+            .unwrap();
+
+        // 各インスタンスは自分のデータベースのみを検索する
+        assert_eq!(results_a.len(), 1);
+        assert!(results_a[0].content.contains("Machine learning"));
+        assert_eq!(results_b.len(), 1);
+        assert!(results_b[0].content.contains("Trees"));
+    }
+
+    #[test]
+    fn test_collection_operations() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+
+        // Create collection
+        let id = rag.create_collection("test", Some("Test collection")).unwrap();
+        assert!(id > 0);
+
+        // Get collection
+        let coll = rag.get_collection("test").unwrap();
+        assert_eq!(coll.name, "test");
+
+        // List collections
+        let collections = rag.list_collections().unwrap();
+        assert_eq!(collections.len(), 1);
+
+        // Delete collection
+        let deleted = rag.delete_collection("test").unwrap();
+        assert!(deleted);
+    }
+
+    #[test]
+    fn test_document_operations() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+
+        rag.create_collection("test", None).unwrap();
+
+        // Add document
+        let id = rag.add_document("Hello, world!", "test", None).unwrap();
+        assert!(id > 0);
+
+        // Get document
+        let doc = rag.get_document(id).unwrap();
+        assert_eq!(doc.content, "Hello, world!");
+
+        // List documents
+        let docs = rag.list_documents(Some("test"), 10, 0).unwrap();
+        assert_eq!(docs.len(), 1);
+
+        // Delete document
+        let deleted = rag.delete_document(id).unwrap();
+        assert!(deleted);
+    }
+
+    #[test]
+    fn test_update_collection_metadata() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+
+        rag.create_collection("test", None).unwrap();
+        rag.create_collection("other", None).unwrap();
+        let id_a = rag
+            .add_document("First", "test", Some(&serde_json::json!({"category": "a"})))
+            .unwrap();
+        let id_b = rag.add_document("Second", "test", None).unwrap();
+        let id_other = rag.add_document("Third", "other", None).unwrap();
+
+        // マージ: 既存のキーは保持され、新しいキーが追加される
+        let updated = rag
+            .update_collection_metadata("test", &serde_json::json!({"reviewed": true}), true)
+            .unwrap();
+        assert_eq!(updated, 2);
+        let doc_a = rag.get_document(id_a).unwrap();
+        assert_eq!(
+            doc_a.metadata,
+            Some(serde_json::json!({"category": "a", "reviewed": true}))
+        );
+        let doc_b = rag.get_document(id_b).unwrap();
+        assert_eq!(doc_b.metadata, Some(serde_json::json!({"reviewed": true})));
+
+        // 置き換え: 既存のキーは失われる
+        rag.update_collection_metadata("test", &serde_json::json!({"status": "archived"}), false)
+            .unwrap();
+        let doc_a = rag.get_document(id_a).unwrap();
+        assert_eq!(doc_a.metadata, Some(serde_json::json!({"status": "archived"})));
+
+        // 他のコレクションには影響しない
+        let doc_other = rag.get_document(id_other).unwrap();
+        assert_eq!(doc_other.metadata, None);
+    }
+
+    #[test]
+    fn test_update_collection_metadata_rejects_non_object() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+        let id = rag
+            .add_document("First", "test", Some(&serde_json::json!({"category": "a"})))
+            .unwrap();
+
+        for merge in [true, false] {
+            for patch in [
+                serde_json::json!(null),
+                serde_json::json!([1]),
+                serde_json::json!("a"),
+            ] {
+                let result = rag.update_collection_metadata("test", &patch, merge);
+                assert!(
+                    matches!(result, Err(Error::InvalidInput(_))),
+                    "merge: {}, patch: {}",
+                    merge,
+                    patch
+                );
+            }
+        }
+        let doc = rag.get_document(id).unwrap();
+        assert_eq!(doc.metadata, Some(serde_json::json!({"category": "a"})));
+    }
+
+    #[test]
+    fn test_search() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+
+        rag.create_collection("test", None).unwrap();
+        rag.add_document("永代供養とは、お墓の管理を寺院に委託する供養形態です。", "test", None)
+            .unwrap();
+        rag.add_document("納骨堂には、ロッカー式、仏壇式、自動搬送式などがあります。", "test", None)
+            .unwrap();
+
+        let results = rag
+            .search("永代供養について", Some("test"), None, 5, 0.0, SearchMode::Semantic, None)
+            .unwrap();
+
+        assert!(!results.is_empty());
+        assert!(results[0].score > 0.0);
+    }
+
+    #[test]
+    fn test_enrich() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+
+        rag.create_collection("test", None).unwrap();
+        rag.add_document("永代供養とは、お墓の管理を寺院に委託する供養形態です。", "test", None)
+            .unwrap();
+
+        let result = rag
+            .enrich("永代供養について", Some("test"), None, 3, 0.0, SearchMode::Semantic, None)
+            .unwrap();
+
+        assert_eq!(result.question, "永代供養について");
+        assert!(!result.context.is_empty());
+        assert!(!result.sources.is_empty());
+    }
+
+    #[test]
+    fn test_enrich_includes_collection_description() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+
+        rag.create_collection("manuals", Some("Official product manuals"))
+            .unwrap();
+        rag.create_collection("forum", Some("Unverified user forum posts"))
+            .unwrap();
+        rag.create_collection("notes", None).unwrap();
+        rag.add_document("Reset the router by holding the button", "manuals", None)
+            .unwrap();
+        rag.add_document("Router reset did not work for me", "forum", None)
+            .unwrap();
+        rag.add_document("Router reset checklist", "notes", None).unwrap();
+
+        let collections = vec!["manuals".to_string(), "forum".to_string(), "notes".to_string()];
+        let options = SearchOptions {
+            include_collection_description: true,
+            ..Default::default()
+        };
+        let result = rag
+            .enrich_with_options("router reset", None, Some(&collections), 3, &options)
+            .unwrap();
+
+        assert_eq!(result.sources.len(), 3);
+        assert!(result
+            .context
+            .contains("Collection: manuals, Description: Official product manuals)"));
+        assert!(result
+            .context
+            .contains("Collection: forum, Description: Unverified user forum posts)"));
+        // 説明のないコレクションは従来の見出し
+        assert!(result.context.contains("Collection: notes)\n"));
+
+        // デフォルトでは説明を含めない
+        let plain = rag
+            .enrich("router reset", None, Some(&collections), 3, 0.0, SearchMode::Semantic, None)
+            .unwrap();
+        assert!(!plain.context.contains("Description:"));
+    }
+
+    #[test]
+    fn test_enrich_max_context_chars() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_db.path());
+        rag.create_collection("docs", None).unwrap();
+        for i in 0..5 {
+            let content = format!("router reset guide {} ", i).repeat(100);
+            rag.add_document(&content, "docs", None).unwrap();
+        }
+
+        let enrich = |max_context_chars| {
+            let options = SearchOptions {
+                max_context_chars,
+                context_prefix: Some("Answer using only the sources below.".to_string()),
+                ..Default::default()
+            };
+            rag.enrich_with_options("router reset", Some("docs"), None, 5, &options)
+            .unwrap()
+        };
+
+        let unlimited = enrich(None);
+        assert_eq!(unlimited.sources.len(), 5);
+        assert!(unlimited.context.chars().count() > 10_000);
+
+        // 前置きを含めて上限以下に収まり、収まらなかったソースは sources からも除外される
+        let limited = enrich(Some(5000));
+        assert!(limited.context.chars().count() <= 5000);
+        assert!(limited
+            .context
+            .starts_with("Answer using only the sources below.\n\n"));
+        assert!(limited.context.ends_with('…'));
+        assert_eq!(limited.sources.len(), 3);
+        assert_eq!(
+            limited.context.matches("[Source ").count(),
+            limited.sources.len()
+        );
+        let ids = |result: &EnrichResult| {
+            result
+                .sources
+                .iter()
+                .map(|s| s.document_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&limited), ids(&unlimited)[..3]);
+    }
+
+    #[test]
+    fn test_fuzzy_hits_rank_after_exact_hits() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+
+        let exact = rag
+            .add_document(
+                "Rust ownership rules explained for beginners.",
+                "test",
+                None,
+            )
+            .unwrap();
+        let typo = rag.add_document("Ownershp in Rust.", "test", None).unwrap();
+        rag.add_document("Bake the bread for forty minutes.", "test", None)
+            .unwrap();
+
+        // BM25のスコアは小さなコーパスでは0に近いが、綴りの近いヒットが上回ってはいけない
+        let options = SearchOptions {
+            fuzzy: true,
+            ..Default::default()
+        };
+        for mode in [SearchMode::Keyword, SearchMode::Hybrid] {
+            let results = rag
+                .search_with_options(
+                    "ownership",
+                    Some("test"),
+                    None,
+                    5,
+                    &SearchOptions {
+                        mode,
+                        hybrid_weights: Some((0.0, 1.0)),
+                        ..options.clone()
+                    },
+                )
+                .unwrap();
+            let ids: Vec<i64> = results.iter().map(|r| r.document_id).collect();
+            assert_eq!(ids, vec![exact, typo], "mode: {:?}", mode);
+            assert!(results[0].score > results[1].score, "mode: {:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_keyword_search_tolerates_typos() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+
+        let target = rag
+            .add_document("Machine learning is a subset of artificial intelligence.", "test", None)
+            .unwrap();
+        let partial = rag
+            .add_document("Deep learning uses neural networks.", "test", None)
+            .unwrap();
+        rag.add_document("Bake the bread for forty minutes.", "test", None)
+            .unwrap();
+
+        // 通常のキーワード検索では綴りの誤りで見つからない
+        let exact = rag
+            .search("machien lerning", Some("test"), None, 5, 0.0, SearchMode::Keyword, None)
+            .unwrap();
+        assert!(exact.is_empty());
+
+        let options = SearchOptions {
+            fuzzy: true,
+            ..Default::default()
+        };
+        for mode in [SearchMode::Keyword, SearchMode::Hybrid] {
+            let results = rag
+                .search_with_options(
+                    "machien lerning",
+                    Some("test"),
+                    None,
+                    5,
+                    &SearchOptions {
+                        mode,
+                        ..options.clone()
+                    },
+                )
+                .unwrap();
+            assert_eq!(results[0].document_id, target, "mode: {:?}", mode);
+        }
+
+        // 誤りが少ない（両方の単語が近い）ドキュメントほど上位、無関係なドキュメントは含まない
+        let results = rag
+            .search_with_options(
+                "machien lerning",
+                Some("test"),
+                None,
+                5,
+                &SearchOptions {
+                    mode: SearchMode::Keyword,
+                    ..options.clone()
+                },
+            )
+            .unwrap();
+        let ids: Vec<i64> = results.iter().map(|r| r.document_id).collect();
+        assert_eq!(ids, vec![target, partial]);
+    }
+
+    #[test]
+    fn test_exclude_empty_documents() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+        rag.create_collection("other", None).unwrap();
+
+        let doc_id = rag
+            .add_document("Machine learning is a subset of artificial intelligence.", "test", None)
+            .unwrap();
+        let empty_id = rag.add_document("", "test", None).unwrap();
+        let blank_id = rag.add_document(" \n\t", "test", None).unwrap();
+        let other_empty_id = rag.add_document("", "other", None).unwrap();
+
+        let ids = |docs: Vec<Document>| docs.iter().map(|d| d.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(rag.find_empty_documents(Some("test")).unwrap()),
+            vec![empty_id, blank_id]
+        );
+        assert_eq!(
+            ids(rag.find_empty_documents(None).unwrap()),
+            vec![empty_id, blank_id, other_empty_id]
+        );
+        assert!(rag.find_empty_documents(Some("missing")).is_err());
+
+        // デフォルトでは空のドキュメントも候補になる
+        let results = rag
+            .search("ai", Some("test"), None, 10, -1.0, SearchMode::Semantic, None)
+            .unwrap();
+        assert!(results.iter().any(|r| r.document_id == empty_id));
+
+        let options = SearchOptions {
+            exclude_empty: true,
+            ..Default::default()
+        };
+        for mode in [SearchMode::Semantic, SearchMode::Hybrid] {
+            let results = rag
+                .search_with_options(
+                    "ai",
+                    Some("test"),
+                    None,
+                    10,
+                    &SearchOptions {
+                        mode,
+                        threshold: -1.0,
+                        ..options.clone()
+                    },
+                )
+                .unwrap();
+            let result_ids: Vec<i64> = results.iter().map(|r| r.document_id).collect();
+            assert_eq!(result_ids, vec![doc_id], "mode: {:?}", mode);
+        }
+    }
+
+    /// テキストごとに決まったベクトルを返すEmbedder（類似度の期待値を手計算できる）
+    struct FixedEmbedder;
+
+    impl Embedder for FixedEmbedder {
+        fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| match text.as_str() {
+                    "east" => vec![1.0, 0.0, 0.0],
+                    "north" => vec![0.0, 1.0, 0.0],
+                    "northeast" => vec![1.0, 1.0, 0.0],
+                    "west" => vec![-2.0, 0.0, 0.0],
+                    _ => vec![0.0, 0.0, 1.0],
+                })
+                .collect())
+        }
+
+        fn dimension(&self) -> usize {
+            3
+        }
+
+        fn model_name(&self) -> &str {
+            "fixed"
+        }
+    }
+
+    #[test]
+    fn test_mmr_promotes_distinct_documents() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = Doredore::new_with_embedder(temp_db.path(), FixedEmbedder).unwrap();
+        rag.create_collection("docs", None).unwrap();
+
+        // 内容が同じ3件と、クエリとの類似度は同じだが向きの異なる1件
+        let duplicates: Vec<i64> = (0..3)
+            .map(|_| rag.add_document("east", "docs", None).unwrap())
+            .collect();
+        let distinct = rag.add_document("north", "docs", None).unwrap();
+
+        let search = |mmr_lambda: Option<f32>| {
+            let options = SearchOptions {
+                mmr_lambda,
+                ..Default::default()
+            };
+            rag.search_with_options("northeast", None, None, 3, &options)
+            .unwrap()
+            .iter()
+            .map(|r| r.document_id)
+            .collect::<Vec<_>>()
+        };
+
+        // 指定しない場合と λ = 1.0 では重複したドキュメントが上位を占める
+        assert_eq!(search(None), duplicates);
+        assert_eq!(search(Some(1.0)), duplicates);
+
+        // 多様性を優先すると、異なる内容のドキュメントが2番目に繰り上がる
+        assert_eq!(
+            search(Some(0.5)),
+            vec![duplicates[0], distinct, duplicates[1]]
+        );
+    }
+
+    /// E5系モデルのように接頭辞で入力の種類を区別するEmbedder
+    ///
+    /// `"query: "` と `"passage: "` 付きの入力だけが同じ方向のベクトルになる
+    struct PrefixAwareEmbedder;
+
+    impl Embedder for PrefixAwareEmbedder {
+        fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| match text.split_once(": ") {
+                    Some(("query" | "passage", _)) => vec![1.0, 0.0],
+                    _ => vec![0.0, 1.0],
+                })
+                .collect())
+        }
+
+        fn dimension(&self) -> usize {
+            2
+        }
+
+        fn model_name(&self) -> &str {
+            "multilingual-e5-small"
+        }
+    }
+
+    #[test]
+    fn test_e5_prefixes_for_documents_and_queries() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = Doredore::new_with_embedder(temp_db.path(), PrefixAwareEmbedder).unwrap();
+        rag.create_collection("faq", None).unwrap();
+
+        let id = rag
+            .add_document("shipping takes 3 days", "faq", None)
+            .unwrap();
+        rag.add_documents(vec!["returns are free".to_string()], "faq", None)
+            .unwrap();
+        let updated_id = rag.add_document("old content", "faq", None).unwrap();
+        rag.update_document(updated_id, Some("office hours"), None)
+            .unwrap();
+
+        // 登録時はパッセージ、検索時はクエリの接頭辞が付くため、全ドキュメントが一致する
+        for query in ["shipping", "返品"] {
+            let results = rag
+                .search(query, None, None, 10, 0.5, SearchMode::Semantic, None)
+                .unwrap();
+            assert_eq!(results.len(), 3);
+            assert!(results.iter().all(|r| (r.score - 1.0).abs() < 1e-6));
+        }
+        let results = rag
+            .search_multi_query(
+                &["a".to_string(), "b".to_string()],
+                None,
+                None,
+                None,
+                10,
+                0.5,
+            )
+            .unwrap();
+        assert_eq!(results.len(), 3);
+
+        // 接頭辞なしのベクトルでは一致しない
+        let raw = rag.embedding_model().embed("shipping").unwrap();
+        assert!(rag
+            .search_by_vector(&raw, None, None, 10, 0.5)
+            .unwrap()
+            .is_empty());
+        let query = rag.embedding_model().embed_query("shipping").unwrap();
+        assert_eq!(
+            rag.search_by_vector(&query, None, None, 10, 0.5).unwrap()[0].document_id,
+            id
+        );
+    }
+
+    #[test]
+    fn test_similarity_matrix() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = Doredore::new_with_embedder(temp_db.path(), FixedEmbedder).unwrap();
+        rag.create_collection("compass", None).unwrap();
+        let ids: Vec<i64> = ["east", "north", "northeast", "west"]
+            .iter()
+            .map(|text| rag.add_document(text, "compass", None).unwrap())
+            .collect();
+
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        let expected = [
+            [1.0, 0.0, s, -1.0],
+            [0.0, 1.0, s, 0.0],
+            [s, s, 1.0, -s],
+            [-1.0, 0.0, -s, 1.0],
+        ];
+        let matrix = rag.similarity_matrix(&ids).unwrap();
+        assert_eq!(matrix.len(), 4);
+        for (row, expected_row) in matrix.iter().zip(&expected) {
+            assert_eq!(row.len(), 4);
+            for (value, expected_value) in row.iter().zip(expected_row) {
+                assert!((value - expected_value).abs() < 1e-6, "{:?}", matrix);
+            }
+        }
+
+        // 並び順はIDの指定順に従う
+        let swapped = rag.similarity_matrix(&[ids[3], ids[0]]).unwrap();
+        assert!((swapped[0][1] + 1.0).abs() < 1e-6);
+
+        assert!(rag.similarity_matrix(&[]).unwrap().is_empty());
+        assert!(matches!(
+            rag.similarity_matrix(&[ids[0], 9999]),
+            Err(Error::DocumentNotFound(9999))
+        ));
+    }
+
+    #[test]
+    fn test_min_results_backfills_below_threshold() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_db.path());
+        rag.create_collection("docs", None).unwrap();
+        let exact = rag.add_document("rust programming", "docs", None).unwrap();
+        rag.add_document("rust systems programming language", "docs", None)
+            .unwrap();
+        rag.add_document("python programming", "docs", None).unwrap();
+        rag.add_document("boil the pasta", "docs", None).unwrap();
+
+        let search = |mode, min_results| {
+            let options = SearchOptions {
+                mode,
+                threshold: 0.99,
+                min_results,
+                ..Default::default()
+            };
+            rag.search_with_options("rust programming", None, None, 5, &options)
+                .unwrap()
+        };
+
+        // 閾値を満たすのは完全一致の1件のみ
+        let strict = search(SearchMode::Semantic, 0);
+        assert_eq!(strict.len(), 1);
+        assert!(!strict[0].backfilled);
+
+        // ハイブリッド検索ではキーワードで一致した "rust systems programming language" も
+        // 閾値によらず結果に含まれるため、補うのは1件
+        for (mode, expected_backfilled) in [(SearchMode::Semantic, 2), (SearchMode::Hybrid, 1)] {
+            let results = search(mode, 3);
+            assert_eq!(results.len(), 3, "{:?}", mode);
+            assert_eq!(results[0].document_id, exact);
+            assert!(!results[0].backfilled);
+            assert_eq!(results.iter().filter(|r| r.backfilled).count(), expected_backfilled);
+            // 補った結果も含めてスコアの降順・順位は連番
+            assert!(results.windows(2).all(|w| w[0].score >= w[1].score));
+            assert_eq!(results.iter().map(|r| r.rank).collect::<Vec<_>>(), vec![1, 2, 3]);
+        }
+
+        // top_kを超えて補わず、候補が足りなければ全件まで
+        let options = SearchOptions {
+            threshold: 0.99,
+            min_results: 10,
+            ..Default::default()
+        };
+        let results = rag
+            .search_with_options("rust", None, None, 2, &options)
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(search(SearchMode::Semantic, 10).len(), 4);
+    }
+
+    #[test]
+    fn test_min_results_reuses_query_embedding() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let temp_db = NamedTempFile::new().unwrap();
+        let embedded = Arc::new(AtomicUsize::new(0));
+        let rag = Doredore::new_with_embedder(
+            temp_db.path(),
+            CountingEmbedder {
+                inner: MockEmbedder::new(64),
+                name: "counting",
+                embedded: embedded.clone(),
+            },
+        )
+        .unwrap();
+        rag.create_collection("docs", None).unwrap();
+        rag.add_document("rust programming", "docs", None).unwrap();
+        rag.add_document("python programming", "docs", None)
+            .unwrap();
+        rag.add_document("boil the pasta", "docs", None).unwrap();
+
+        let options = SearchOptions {
+            min_results: 3,
+            ..Default::default()
+        };
+        for mode in [
+            SearchMode::Semantic,
+            SearchMode::SemanticAnn,
+            SearchMode::Hybrid,
+        ] {
+            embedded.store(0, Ordering::SeqCst);
+            let results = rag
+                .search_with_options(
+                    "rust programming",
+                    None,
+                    None,
+                    5,
+                    &SearchOptions {
+                        mode,
+                        threshold: 0.99,
+                        ..options.clone()
+                    },
+                )
+                .unwrap();
+            assert!(results.iter().any(|r| r.backfilled), "{:?}", mode);
+            // 補完のための再検索でクエリをベクトル化し直さない
+            assert_eq!(embedded.load(Ordering::SeqCst), 1, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_keyword_score_scale_spreads_scores() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_db.path());
+        rag.create_collection("docs", None).unwrap();
+        let best = rag.add_document("rust rust rust", "docs", None).unwrap();
+        rag.add_document("rust and the borrow checker", "docs", None)
+            .unwrap();
+        let worst = rag
+            .add_document(
+                "a long guide about cooking, gardening, travel, music and also a little rust",
+                "docs",
+                None,
+            )
+            .unwrap();
+        // 一致しないドキュメントがないとIDFがほぼ0になり、BM25の差が出ない
+        for i in 0..10 {
+            rag.add_document(&format!("unrelated note number {}", i), "docs", None)
+                .unwrap();
+        }
+
+        let keyword_scores = |scale| {
+            let options = SearchOptions {
+                mode: SearchMode::Keyword,
+                keyword_score_scale: scale,
+                keyword_normalization: KeywordNormalization::Sigmoid,
+                ..Default::default()
+            };
+            rag.search_with_options("rust", None, None, 5, &options)
+                .unwrap()
+        };
+        let spread = |results: &[SearchResult]| {
+            results.first().unwrap().score - results.last().unwrap().score
+        };
+
+        // 高関連ほど高スコア（BM25は負の値なので、正規化で向きを反転する）
+        let default = keyword_scores(10.0);
+        assert_eq!(default.len(), 3);
+        assert_eq!(default[0].document_id, best);
+        assert_eq!(default[2].document_id, worst);
+        assert!(default.windows(2).all(|w| w[0].score > w[1].score));
+        assert!(default.iter().all(|r| r.score > 0.5 && r.score < 1.0));
+
+        // 係数を小さくすると順位は変わらず、スコアの差が広がる
+        let tuned = keyword_scores(2.0);
+        let ids = |results: &[SearchResult]| results.iter().map(|r| r.document_id).collect::<Vec<_>>();
+        assert_eq!(ids(&tuned), ids(&default));
+        assert!(spread(&tuned) > 2.0 * spread(&default));
+
+        // 0以下はデフォルトの係数として扱う
+        let fallback = keyword_scores(0.0);
+        assert_eq!(fallback[0].score, default[0].score);
+        assert_eq!(normalize_keyword_score(0.0, 10.0), 0.5);
+    }
+
+    #[test]
+    fn test_min_max_keyword_normalization_spreads_scores() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_db.path());
+        rag.create_collection("docs", None).unwrap();
+        let ids: Vec<i64> = [
+            "rust rust rust rust",
+            "rust rust and cargo",
+            "rust and the borrow checker",
+            "a long guide about cooking, gardening, travel, music and also a little rust",
+        ]
+        .iter()
+        .map(|content| rag.add_document(content, "docs", None).unwrap())
+        .collect();
+        // 一致しないドキュメントがないとIDFがほぼ0になり、BM25の差が出ない
+        for i in 0..10 {
+            rag.add_document(&format!("unrelated note number {}", i), "docs", None)
+                .unwrap();
+        }
+
+        let keyword_results = |keyword_normalization| {
+            let options = SearchOptions {
+                mode: SearchMode::Keyword,
+                keyword_normalization,
+                ..Default::default()
+            };
+            rag.search_with_options("rust", None, None, 5, &options)
+            .unwrap()
+        };
+
+        // 出現頻度の高い順に並び、最上位が1.0・最下位が0.0になる
+        let min_max = keyword_results(KeywordNormalization::MinMax);
+        let sigmoid = keyword_results(KeywordNormalization::Sigmoid);
+        let result_ids = |results: &[SearchResult]| {
+            results.iter().map(|r| r.document_id).collect::<Vec<_>>()
+        };
+        assert_eq!(result_ids(&min_max), ids);
+        assert_eq!(result_ids(&sigmoid), ids);
+        assert_eq!(min_max[0].score, 1.0);
+        assert_eq!(min_max[3].score, 0.0);
+        assert!(min_max.windows(2).all(|w| w[0].score > w[1].score));
+
+        // 隣接する結果のスコアの差がSigmoidより大きい
+        for (m, s) in min_max.windows(2).zip(sigmoid.windows(2)) {
+            assert!(m[0].score - m[1].score > s[0].score - s[1].score);
+        }
+
+        assert_eq!(min_max_keyword_scores(&[-3.0]), vec![1.0]);
+        assert_eq!(
+            min_max_keyword_scores(&[-4.0, -3.0, -2.0]),
+            vec![1.0, 0.5, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_rrf_prefers_documents_ranked_by_both() {
+        let doc =
+            |id: i64, score: f32| (id, format!("doc {}", id), score, "docs".to_string(), None);
+        // 1: セマンティック検索でのみ1位（類似度が突出して高い）
+        // 2: 両方の検索で2位
+        // 3: キーワード検索でのみ1位
+        let semantic = vec![doc(1, 0.95), doc(2, 0.5), doc(4, 0.45)];
+        let keyword = vec![doc(3, 0.99), doc(2, 0.55), doc(5, 0.53)];
+        let ids = |fusion| {
+            fuse_hybrid_results(semantic.clone(), keyword.clone(), 0.7, 0.3, fusion)
+                .iter()
+                .map(|(id, ..)| *id)
+                .collect::<Vec<_>>()
+        };
+
+        // 加重平均ではセマンティックのスコアの大きさで1が勝つ
+        assert_eq!(ids(FusionMethod::Weighted)[0], 1);
+
+        // RRFでは両方の検索で上位の2が1位になる
+        let fused = fuse_hybrid_results(
+            semantic.clone(),
+            keyword.clone(),
+            0.7,
+            0.3,
+            FusionMethod::Rrf,
+        );
+        assert_eq!(ids(FusionMethod::Rrf), vec![2, 1, 4, 3, 5]);
+        assert!(fused
+            .iter()
+            .all(|(_, _, score, ..)| *score > 0.0 && *score <= 1.0));
+        assert_eq!(reciprocal_rank_score(0), 1.0);
+        assert!(reciprocal_rank_score(1) > reciprocal_rank_score(2));
+
+        // 検索経由でも統合方法を指定できる
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_db.path());
+        rag.create_collection("docs", None).unwrap();
+        let both = rag
+            .add_document("rust ownership and borrowing", "docs", None)
+            .unwrap();
+        rag.add_document("python scripting", "docs", None).unwrap();
+        let options = SearchOptions {
+            mode: SearchMode::Hybrid,
+            fusion: FusionMethod::Rrf,
+            ..Default::default()
+        };
+        let results = rag
+            .search_with_options("rust ownership", None, None, 5, &options)
+            .unwrap();
+        assert_eq!(results[0].document_id, both);
+        assert!((results[0].score - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_semantic_ann_search() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_db.path());
+        let faq_id = rag.create_collection("faq", None).unwrap();
+        rag.create_collection("forum", None).unwrap();
+        let documents = (0..40)
+            .map(|i| format!("note {} about topic{} with keyword{}", i, i % 5, i % 7))
+            .collect();
+        rag.add_documents(documents, "faq", None)
+            .unwrap();
+        rag.add_document("topic1 discussion in the forum", "forum", None)
+            .unwrap();
+
+        let search = |rag: &Doredore, query: &str, mode| {
+            rag.search(query, None, None, 5, 0.0, mode, None)
+                .unwrap()
+                .into_iter()
+                .map(|r| (r.document_id, r.content))
+                .collect::<Vec<_>>()
+        };
+        let query = "topic1 keyword3";
+        let exact = search(&rag, query, SearchMode::Semantic);
+
+        // インデックスがなければ全件スキャンと同じ
+        assert_eq!(search(&rag, query, SearchMode::SemanticAnn), exact);
+
+        // 件数が少なければ探索幅に全件が収まるため、近似でも結果は一致する
+        assert_eq!(rag.build_ann_index("faq").unwrap(), 40);
+        assert_eq!(search(&rag, query, SearchMode::SemanticAnn), exact);
+
+        // 追加・更新・削除はインデックスにも反映される
+        let added = rag
+            .add_document("brand new ferris crab entry", "faq", None)
+            .unwrap();
+        assert_eq!(
+            search(&rag, "ferris crab", SearchMode::SemanticAnn)[0].0,
+            added
+        );
+        rag.update_document(added, Some("completely rewritten walrus entry"), None)
+            .unwrap();
+        assert_eq!(search(&rag, "walrus", SearchMode::SemanticAnn)[0].0, added);
+        rag.delete_document(added).unwrap();
+        assert!(search(&rag, "walrus", SearchMode::SemanticAnn)
+            .iter()
+            .all(|(id, _)| *id != added));
+
+        // 除外IDはインデックスの候補にも適用される
+        let options = SearchOptions {
+            mode: SearchMode::SemanticAnn,
+            exclude_ids: vec![exact[0].0],
+            ..Default::default()
+        };
+        let results = rag
+            .search_with_options(query, None, None, 5, &options)
+            .unwrap();
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(|r| r.document_id != exact[0].0));
+        drop(rag);
+
+        // 保存したインデックスは次に開いたときに読み込まれ、他の接続での追加も反映される
+        let rag = mock_doredore(temp_db.path());
+        assert!(rag.store.load_ann_index(faq_id).unwrap().is_some());
+        let other = mock_doredore(temp_db.path());
+        let added = other
+            .add_document("another ferris crab entry", "faq", None)
+            .unwrap();
+        assert_eq!(
+            search(&rag, "ferris crab", SearchMode::SemanticAnn)[0].0,
+            added
+        );
+        assert_eq!(search(&rag, query, SearchMode::SemanticAnn), exact);
+
+        assert!(rag.drop_ann_index("faq").unwrap());
+        assert!(!rag.drop_ann_index("faq").unwrap());
+        assert!(rag.store.load_ann_index(faq_id).unwrap().is_none());
+        rag.build_ann_index("faq").unwrap();
+        rag.delete_collection("faq").unwrap();
+        assert!(rag.store.load_ann_index(faq_id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_ann_search_batches_lookups_and_read_only_does_not_save() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_db.path());
+        let faq_id = rag.create_collection("faq", None).unwrap();
+        let documents = (0..20)
+            .map(|i| format!("note {} about topic{}", i, i % 5))
+            .collect();
+        let ids = rag.add_documents(documents, "faq", None).unwrap();
+        rag.build_ann_index("faq").unwrap();
+        let saved = rag.store.load_ann_index(faq_id).unwrap();
+
+        // 一括取得は存在しないIDを含めない
+        let documents = rag.store.get_documents(&[ids[0], ids[1], -1]).unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[&ids[1]].content, "note 1 about topic1");
+
+        drop(rag);
+
+        // インデックスを読み込んでいない接続で追加すると、保存済みのインデックスは古くなる
+        let added = mock_doredore(temp_db.path())
+            .add_document("ferris crab entry", "faq", None)
+            .unwrap();
+
+        let reader = Doredore::with_shared_model_read_only(
+            temp_db.path(),
+            EmbeddingModel::from_embedder(MockEmbedder::new(384)),
+        )
+        .unwrap();
+        let results = reader
+            .search(
+                "ferris crab",
+                None,
+                None,
+                3,
+                0.0,
+                SearchMode::SemanticAnn,
+                None,
+            )
+            .unwrap();
+        assert_eq!(results[0].document_id, added);
+        assert_eq!(results[0].content, "ferris crab entry");
+
+        // 読み取り専用のインスタンスは、差分を反映したインデックスを破棄時に保存しない
+        drop(reader);
+        let rag = mock_doredore(temp_db.path());
+        assert_eq!(rag.store.load_ann_index(faq_id).unwrap(), saved);
+    }
+
+    #[test]
+    fn test_delete_collection_propagates_database_errors() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_db.path());
+        rag.create_collection("faq", None).unwrap();
+        assert!(!rag.delete_collection("missing").unwrap());
+
+        // コレクションを取得できない原因が「存在しない」以外ならエラーにする
+        let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+        conn.execute_batch("DROP TABLE ann_indexes; DROP TABLE collections;")
+            .unwrap();
+        assert!(matches!(
+            rag.delete_collection("faq"),
+            Err(Error::Database(_))
+        ));
+    }
+
+    #[test]
+    fn test_ann_index_reflects_updates_from_other_connections() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_db.path());
+        rag.create_collection("faq", None).unwrap();
+        let documents = (0..20)
+            .map(|i| format!("note {} about topic{}", i, i % 5))
+            .collect();
+        let ids = rag.add_documents(documents, "faq", None).unwrap();
+        rag.build_ann_index("faq").unwrap();
+
+        let search = |rag: &Doredore, query: &str| {
+            rag.search(query, None, None, 1, 0.0, SearchMode::SemanticAnn, None)
+                .unwrap()
+                .remove(0)
+        };
+        // 読み込んだ後に、別の接続がドキュメント数とIDを変えずに内容だけを更新する
+        let before = search(&rag, "walrus ferris crab");
+        let other = mock_doredore(temp_db.path());
+        other
+            .update_document(ids[3], Some("walrus ferris crab"), None)
+            .unwrap();
+
+        // 更新後のEmbeddingで採点される（全件スキャンと同じスコアになる）
+        let after = search(&rag, "walrus ferris crab");
+        assert_eq!(after.document_id, ids[3]);
+        assert!(after.score > before.score);
+        let exact = rag
+            .search(
+                "walrus ferris crab",
+                None,
+                None,
+                1,
+                0.0,
+                SearchMode::Semantic,
+                None,
+            )
+            .unwrap();
+        assert_eq!(exact[0].document_id, ids[3]);
+        assert!((after.score - exact[0].score).abs() < 1e-5);
+
+        // 削除も反映される
+        other.delete_document(ids[3]).unwrap();
+        assert_ne!(search(&rag, "walrus ferris crab").document_id, ids[3]);
+    }
+
+    #[test]
+    fn test_share_models_between_instances() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let mut writer = mock_doredore(temp_db.path());
+        writer.create_collection("faq", None).unwrap();
+        let faq_id = writer
+            .add_document("Rust programming tips", "faq", None)
+            .unwrap();
+
+        let mut reader =
+            Doredore::with_shared_model_read_only(temp_db.path(), writer.embedding_model().clone())
+                .unwrap();
+        reader.share_models_with(&writer);
+
+        // 共有後に書き込み用のインスタンスで登録したモデルを、読み取り専用のインスタンスでも使う
+        writer.register_model(
+            "mock-small",
+            EmbeddingModel::from_embedder(MockEmbedder::new(32)),
+        );
+        writer
+            .create_collection_with_model("forum", None, Some("mock-small"))
+            .unwrap();
+        let forum_id = writer
+            .add_document("Rust programming tips", "forum", None)
+            .unwrap();
+
+        let results = reader
+            .search(
+                "rust programming",
+                None,
+                None,
+                5,
+                0.5,
+                SearchMode::Semantic,
+                None,
+            )
+            .unwrap();
+        let mut ids: Vec<i64> = results.iter().map(|r| r.document_id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![faq_id, forum_id]);
+    }
+
+    #[test]
+    fn test_per_collection_embedding_model() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let mut rag = mock_doredore(temp_db.path());
+        rag.register_model("mock-small", EmbeddingModel::from_embedder(MockEmbedder::new(32)));
+
+        rag.create_collection("faq", None).unwrap();
+        rag.create_collection_with_model("forum", None, Some("mock-small"))
+            .unwrap();
+        assert!(matches!(
+            rag.create_collection_with_model("other", None, Some("no-such-model")),
+            Err(Error::InvalidInput(_))
+        ));
+        assert_eq!(rag.get_collection("faq").unwrap().embedding_model, None);
+        assert_eq!(
+            rag.get_collection("forum").unwrap().embedding_model.as_deref(),
+            Some("mock-small")
+        );
+
+        let faq_id = rag
+            .add_document("Rust is a systems programming language", "faq", None)
+            .unwrap();
+        let forum_ids = rag
+            .add_documents(
+                vec![
+                    "Rust programming tips".to_string(),
+                    "Boil the pasta in salted water".to_string(),
+                ],
+                "forum",
+                None,
+            )
+            .unwrap();
+
+        // 各コレクションのドキュメントはそのコレクションのモデルでベクトル化される
+        let embeddings = rag.store.get_embeddings(&[faq_id, forum_ids[0]]).unwrap();
+        assert_eq!(embeddings[&faq_id].len(), 384);
+        assert_eq!(embeddings[&forum_ids[0]].len(), 32);
+
+        // クエリもコレクションのモデルでベクトル化するため、次元が合って高い類似度になる
+        let search = |collection| {
+            rag.search("rust programming", collection, None, 5, 0.5, SearchMode::Semantic, None)
+                .unwrap()
+        };
+        let faq = search(Some("faq"));
+        assert_eq!(faq.len(), 1);
+        assert_eq!(faq[0].document_id, faq_id);
+        let forum = search(Some("forum"));
+        assert_eq!(forum.len(), 1);
+        assert_eq!(forum[0].document_id, forum_ids[0]);
+
+        // 全コレクションの検索ではモデルごとの結果をスコア順に統合する
+        let all = search(None);
+        let mut ids: Vec<i64> = all.iter().map(|r| r.document_id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![faq_id, forum_ids[0]]);
+        assert!(all.windows(2).all(|w| w[0].score >= w[1].score));
+        assert_eq!(rag.count_matches("rust programming", None, SearchMode::Semantic, 0.5).unwrap(), 2);
+
+        // 内容の更新でもコレクションのモデルで再生成する
+        rag.update_document(forum_ids[1], Some("Rust borrow checker"), None)
+            .unwrap();
+        let embeddings = rag.store.get_embeddings(&[forum_ids[1]]).unwrap();
+        assert_eq!(embeddings[&forum_ids[1]].len(), 32);
+
+        // 事前計算済みのベクトルはインスタンスのモデルのコレクションのみ検索する
+        let vector = rag
+            .embedding_model()
+            .embed_query("rust programming")
+            .unwrap();
+        let results = rag.search_by_vector(&vector, None, None, 5, 0.0).unwrap();
+        assert!(results.iter().all(|r| r.collection_name == "faq"));
+    }
+
+    #[test]
+    fn test_search_results_are_ranked() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+        for content in [
+            "Machine learning is a subset of artificial intelligence.",
+            "Deep learning uses neural networks.",
+            "Reinforcement learning trains agents with rewards.",
+            "Bake the bread for forty minutes.",
+        ] {
+            rag.add_document(content, "test", None).unwrap();
+        }
+
+        for mode in [SearchMode::Semantic, SearchMode::Keyword, SearchMode::Hybrid] {
+            let results = rag
+                .search("learning", Some("test"), None, 3, 0.0, mode, None)
+                .unwrap();
+            assert_eq!(results.len(), 3, "mode: {:?}", mode);
+
+            // 1..=N の連番で、結果の並び順と一致する
+            let ranks: Vec<usize> = results.iter().map(|r| r.rank).collect();
+            assert_eq!(ranks, vec![1, 2, 3], "mode: {:?}", mode);
+
+            // 順位はスコアの降順と一致する
+            assert!(
+                results.windows(2).all(|w| w[0].score >= w[1].score),
+                "mode: {:?}",
+                mode
+            );
+        }
+
+        // enrich のソースにも順位が付く
+        let result = rag
+            .enrich("learning", Some("test"), None, 2, 0.0, SearchMode::Semantic, None)
+            .unwrap();
+        let ranks: Vec<usize> = result.sources.iter().map(|r| r.rank).collect();
+        assert_eq!(ranks, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_search_excludes_ids() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+
+        rag.create_collection("test", None).unwrap();
+        let top_id = rag
+            .add_document("Machine learning cost ranges from 10k to 150k dollars.", "test", None)
+            .unwrap();
+        rag.add_document("Deep learning uses neural networks.", "test", None)
+            .unwrap();
+
+        let options = SearchOptions {
+            exclude_ids: vec![top_id],
+            ..Default::default()
+        };
+
+        for mode in [SearchMode::Semantic, SearchMode::Keyword, SearchMode::Hybrid] {
+            // 除外しない場合は最上位に来るドキュメント
+            let results = rag
+                .search("machine learning cost", Some("test"), None, 5, 0.0, mode, None)
+                .unwrap();
+            assert_eq!(results[0].document_id, top_id, "mode: {:?}", mode);
+
+            let results = rag
+                .search_with_options(
+                    "machine learning cost",
+                    Some("test"),
+                    None,
+                    5,
+                    &SearchOptions {
+                        mode,
+                        ..options.clone()
+                    },
+                )
+                .unwrap();
+            assert!(results.iter().all(|r| r.document_id != top_id));
+        }
+    }
+
+    #[test]
+    fn test_search_by_vector_dimension_mismatch() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+
+        rag.create_collection("test", None).unwrap();
+        let id = rag.add_document("Rust is a systems programming language", "test", None).unwrap();
+
+        // 768次元のベクトルを384次元のストアに渡すとエラー
+        let wrong = vec![0.1; 768];
+        let err = rag
+            .search_by_vector(&wrong, Some("test"), None, 5, 0.0)
+            .unwrap_err();
+        match err {
+            Error::InvalidInput(msg) => {
+                assert!(msg.contains("384"));
+                assert!(msg.contains("768"));
+            }
+            other => panic!("Expected InvalidInput, got {:?}", other),
+        }
+
+        // 正しい次元数なら検索できる
+        let vector = rag
+            .embedding_model()
+            .embed_query("systems programming")
+            .unwrap();
+        let results = rag
+            .search_by_vector(&vector, Some("test"), None, 5, 0.0)
+            .unwrap();
+        assert_eq!(results[0].document_id, id);
+    }
+
+    #[test]
+    fn test_transaction_rolls_back_on_error() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
+
+        // 途中でエラーになると、それまでの追加もすべて取り消される
+        let result: Result<()> = rag.transaction(|rag| {
+            rag.add_document("Rust is a systems programming language", "test", None)?;
+            rag.add_document("Python is a scripting language", "test", None)?;
+            rag.add_document("Missing collection", "no_such_collection", None)?;
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert!(rag.list_documents(Some("test"), 10, 0).unwrap().is_empty());
+        assert!(rag
+            .search("programming", Some("test"), None, 5, 0.0, SearchMode::Keyword, None)
+            .unwrap()
+            .is_empty());
+
+        // 成功した場合はまとめてコミットされる
+        let ids = rag
+            .transaction(|rag| {
+                Ok(vec![
+                    rag.add_document("Rust is a systems programming language", "test", None)?,
+                    rag.add_document("Python is a scripting language", "test", None)?,
+                ])
+            })
+            .unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(rag.list_documents(Some("test"), 10, 0).unwrap().len(), 2);
+
+        // パニックした場合もロールバックされる
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _ = rag.transaction(|rag| -> Result<()> {
+                rag.delete_document(ids[0])?;
+                panic!("interrupted")
+            });
+        }));
+        assert!(panicked.is_err());
+        assert!(rag.get_document(ids[0]).is_ok());
+    }
+
+    #[test]
+    fn test_search_multi_query() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
+
+        let rust_id = rag
+            .add_document("Rust guarantees memory safety without a garbage collector", "test", None)
+            .unwrap();
+        let python_id = rag
+            .add_document(
+                "Python is widely used for machine learning and data science",
+                "test",
+                None,
+            )
+            .unwrap();
+        rag.add_document("The temple offers permanent memorial services", "test", None)
+            .unwrap();
+        rag.add_document("Boil the pasta in salted water for ten minutes", "test", None)
+            .unwrap();
+
+        let queries = vec![
+            "memory safe language without garbage collection".to_string(),
+            "language for machine learning".to_string(),
+        ];
+        let relevant = [rust_id, python_id];
+        let recall = |results: &[SearchResult]| {
+            results
+                .iter()
+                .filter(|r| relevant.contains(&r.document_id))
+                .count()
+        };
+
+        // 複数クエリの統合結果は、どの単一クエリよりも正解を多く（少なくとも同数）含む
+        let multi = rag
+            .search_multi_query(&queries, None, Some("test"), None, 2, 0.0)
+            .unwrap();
+        assert_eq!(recall(&multi), 2);
+        for query in &queries {
+            let single = rag
+                .search(query, Some("test"), None, 2, 0.0, SearchMode::Semantic, None)
+                .unwrap();
+            assert!(recall(&single) <= recall(&multi));
+        }
+
+        // 重みが片方のクエリだけなら、そのクエリ単独の検索と同じ結果
+        let weighted = rag
+            .search_multi_query(&queries, Some(&[1.0, 0.0]), Some("test"), None, 4, 0.0)
+            .unwrap();
+        let single = rag
+            .search(&queries[0], Some("test"), None, 4, 0.0, SearchMode::Semantic, None)
+            .unwrap();
+        assert_eq!(
+            weighted.iter().map(|r| r.document_id).collect::<Vec<_>>(),
+            single.iter().map(|r| r.document_id).collect::<Vec<_>>()
+        );
+        for (w, s) in weighted.iter().zip(&single) {
+            assert!((w.score - s.score).abs() < 1e-5);
+        }
+
+        // 重みの件数が合わない・クエリが空の場合はエラー
+        assert!(matches!(
+            rag.search_multi_query(&queries, Some(&[1.0]), Some("test"), None, 2, 0.0),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            rag.search_multi_query(&[], None, Some("test"), None, 2, 0.0),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_search_with_metadata_filter() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
+
+        let old_id = rag
+            .add_document(
+                "Permanent memorial services at the temple",
+                "test",
+                Some(&serde_json::json!({"category": "legal", "year": 2018})),
+            )
+            .unwrap();
+        let new_id = rag
+            .add_document(
+                "Permanent memorial contracts and their terms",
+                "test",
+                Some(&serde_json::json!({"category": "legal", "year": "2022"})),
+            )
+            .unwrap();
+        rag.add_document(
+            "Permanent memorial pricing guide",
+            "test",
+            Some(&serde_json::json!({"category": "pricing", "year": 2023})),
+        )
+        .unwrap();
+
+        let (text, metadata_filter) =
+            crate::parse_search_query("category:legal year:>2020 permanent memorial");
+        let options = SearchOptions {
+            metadata_filter,
+            ..Default::default()
+        };
+
+        for mode in [SearchMode::Semantic, SearchMode::Keyword, SearchMode::Hybrid] {
+            let results = rag
+                .search_with_options(
+                    &text,
+                    Some("test"),
+                    None,
+                    5,
+                    &SearchOptions {
+                        mode,
+                        ..options.clone()
+                    },
+                )
+                .unwrap();
+            let ids: Vec<i64> = results.iter().map(|r| r.document_id).collect();
+            // 文字列の "2022" も数値として比較される
+            assert_eq!(ids, vec![new_id], "mode: {:?}", mode);
+            assert!(!ids.contains(&old_id));
+        }
+    }
+
+    #[test]
+    fn test_search_with_json_metadata_filter() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("mixed", None).unwrap();
+
+        let mut ja_ids = Vec::new();
+        for (content, metadata) in [
+            (
+                "memorial service guide",
+                serde_json::json!({"lang": "ja", "year": 2023, "published": true}),
+            ),
+            (
+                "memorial service guide",
+                serde_json::json!({"lang": "en", "year": 2023, "published": true}),
+            ),
+            (
+                "memorial service faq",
+                serde_json::json!({"lang": "ja", "year": 2022, "published": false}),
+            ),
+            (
+                "memorial service faq",
+                serde_json::json!({"lang": "en", "year": 2022, "published": 1}),
+            ),
+        ] {
+            let id = rag.add_document(content, "mixed", Some(&metadata)).unwrap();
+            if metadata["lang"] == "ja" {
+                ja_ids.push(id);
+            }
+        }
+        rag.add_document("memorial service notes", "mixed", None)
+            .unwrap();
+
+        let search = |filter: serde_json::Value, mode| {
+            let options = SearchOptions {
+                mode,
+                metadata_filter: crate::MetadataFilter::from_json(&filter).unwrap(),
+                ..Default::default()
+            };
+            let mut ids: Vec<i64> = rag
+                .search_with_options("memorial service", Some("mixed"), None, 10, &options)
+                .unwrap()
+                .iter()
+                .map(|r| r.document_id)
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        for mode in [
+            SearchMode::Semantic,
+            SearchMode::Keyword,
+            SearchMode::Hybrid,
+        ] {
+            assert_eq!(
+                search(serde_json::json!({"lang": "ja"}), mode),
+                ja_ids,
+                "mode: {:?}",
+                mode
+            );
+            // 複数のキーはANDで結合する
+            assert_eq!(
+                search(serde_json::json!({"lang": "ja", "year": 2023}), mode),
+                ja_ids[..1]
+            );
+            // 真偽値は数値の 1 / 0 とは一致しない
+            assert_eq!(
+                search(serde_json::json!({"lang": "ja", "published": false}), mode),
+                ja_ids[1..]
+            );
+            assert_eq!(
+                search(serde_json::json!({"published": true}), mode).len(),
+                2
+            );
+            assert!(search(serde_json::json!({"lang": "fr"}), mode).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_collection_weights_boost_authoritative_collection() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("manual", None).unwrap();
+        rag.create_collection("forum", None).unwrap();
+
+        let manual = rag
+            .add_document(
+                "rust memory safety guide for systems programmers",
+                "manual",
+                None,
+            )
+            .unwrap();
+        let forum = rag
+            .add_document("rust memory safety", "forum", None)
+            .unwrap();
+
+        let collections = vec!["manual".to_string(), "forum".to_string()];
+        let search = |options: &SearchOptions, mode: SearchMode| {
+            rag.search_with_options(
+                "rust memory safety",
+                None,
+                Some(&collections),
+                1,
+                &SearchOptions {
+                    mode,
+                    ..options.clone()
+                },
+            )
+            .unwrap()
+        };
+
+        // MinMaxでは最下位のキーワードスコアが0.0になり重みが効かないため、Sigmoidで比較する
+        let plain_options = SearchOptions {
+            keyword_normalization: KeywordNormalization::Sigmoid,
+            ..Default::default()
+        };
+        let weighted = SearchOptions {
+            collection_weights: HashMap::from([("manual".to_string(), 10.0)]),
+            ..plain_options.clone()
+        };
+        for mode in [
+            SearchMode::Semantic,
+            SearchMode::Keyword,
+            SearchMode::Hybrid,
+        ] {
+            // 重みなしでは本文が完全に一致するforumのドキュメントが上位
+            let plain = search(&plain_options, mode);
+            assert_eq!(plain[0].document_id, forum, "mode: {:?}", mode);
+
+            // スコアの低いmanualのドキュメントが、重みを掛けると上位になる（top_k=1でも漏れない）
+            let results = search(&weighted, mode);
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].document_id, manual, "mode: {:?}", mode);
+            assert_eq!(results[0].collection_name, "manual");
+        }
+
+        // 重みはスコアにそのまま掛かる（指定のないコレクションは1.0）
+        let all = SearchOptions {
+            collection_weights: HashMap::from([("manual".to_string(), 0.5)]),
+            ..Default::default()
+        };
+        let raw = rag
+            .search(
+                "rust memory safety",
+                None,
+                Some(&collections),
+                2,
+                0.0,
+                SearchMode::Semantic,
+                None,
+            )
+            .unwrap();
+        let halved = rag
+            .search_with_options("rust memory safety", None, Some(&collections), 2, &all)
+            .unwrap();
+        for result in &halved {
+            let original = raw
+                .iter()
+                .find(|r| r.document_id == result.document_id)
+                .unwrap();
+            let weight = if result.document_id == manual {
+                0.5
+            } else {
+                1.0
+            };
+            assert!((result.score - original.score * weight).abs() < 1e-6);
+        }
+
+        // 無限大・NaNの重みはスコアをNaNにするためエラー
+        for weight in [f32::INFINITY, f32::NAN] {
+            let invalid = SearchOptions {
+                collection_weights: HashMap::from([("manual".to_string(), weight)]),
+                ..Default::default()
+            };
+            for mode in [
+                SearchMode::Semantic,
+                SearchMode::Keyword,
+                SearchMode::Hybrid,
+            ] {
+                let result = rag.search_with_options(
+                    "rust memory safety",
+                    None,
+                    Some(&collections),
+                    1,
+                    &SearchOptions {
+                        mode,
+                        ..invalid.clone()
+                    },
+                );
+                assert!(
+                    matches!(result, Err(Error::InvalidInput(_))),
+                    "mode: {:?}",
+                    mode
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_skip_missing_propagates_database_errors() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("valid", None).unwrap();
+
+        // コレクションの取得自体が失敗する場合は、存在しないコレクションとしてスキップしない
+        let conn = rusqlite::Connection::open(temp_file.path()).unwrap();
+        conn.execute_batch("ALTER TABLE collections RENAME TO collections_old")
+            .unwrap();
+        drop(conn);
+
+        let options = SearchOptions {
+            skip_missing: true,
+            ..Default::default()
+        };
+        let names = vec!["valid".to_string()];
+        let result = rag.search_with_options("Rust", None, Some(&names), 5, &options);
+        assert!(matches!(result, Err(Error::Database(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_search_skip_missing_collections() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("valid", None).unwrap();
+        let id = rag
+            .add_document("Rust is a systems programming language", "valid", None)
+            .unwrap();
+
+        let names = vec!["valid".to_string(), "missing".to_string()];
+
+        // デフォルトは1つでも存在しなければエラー
+        assert!(rag
+            .search("Rust", None, Some(&names), 5, 0.0, SearchMode::Semantic, None)
+            .is_err());
+
+        // skip_missing: 存在するコレクションだけで検索
+        let options = SearchOptions {
+            skip_missing: true,
+            ..Default::default()
+        };
+        let results = rag
+            .search_with_options("Rust", None, Some(&names), 5, &options)
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].document_id, id);
+        assert_eq!(
+            rag.find_missing_collections(&names).unwrap(),
+            vec!["missing".to_string()]
+        );
+
+        // すべて存在しない場合は全コレクション検索ではなく結果なし
+        let results = rag
+            .search_with_options("Rust", Some("missing"), None, 5, &options)
+            .unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_equal_scores_have_stable_order() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+
+        // 同一内容のドキュメントは全モードで同スコアになる
+        let mut ids: Vec<i64> = (0..5)
+            .map(|_| {
+                rag.add_document("Permanent memorial service", "test", None)
+                    .unwrap()
+            })
+            .collect();
+        ids.sort();
+
+        for mode in [SearchMode::Semantic, SearchMode::Keyword, SearchMode::Hybrid] {
+            for _ in 0..5 {
+                let results = rag
+                    .search("memorial", Some("test"), None, 10, 0.0, mode, None)
+                    .unwrap();
+                let result_ids: Vec<i64> = results.iter().map(|r| r.document_id).collect();
+                assert_eq!(result_ids, ids, "mode: {:?}", mode);
+            }
+
+            // ページ分割しても重複・欠落がない
+            let first_page = rag
+                .search("memorial", Some("test"), None, 2, 0.0, mode, None)
+                .unwrap();
+            assert_eq!(
+                first_page.iter().map(|r| r.document_id).collect::<Vec<_>>(),
+                ids[..2]
+            );
+        }
+    }
+
+    #[test]
+    fn test_large_scan_has_deterministic_order() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+
+        // 同スコアのドキュメントが多数あっても（`parallel` では複数スレッドに分割されても）
+        // スコアの降順・ドキュメントIDの昇順で返る
+        let documents = (0..600)
+            .map(|i| format!("memorial service plan {}", i % 7))
+            .collect();
+        rag.add_documents(documents, "test", None)
+            .unwrap();
+
+        let search = || {
+            rag.search(
+                "memorial service plan 3",
+                Some("test"),
+                None,
+                600,
+                0.0,
+                SearchMode::Semantic,
+                None,
+            )
+            .unwrap()
+        };
+        let results = search();
+        assert_eq!(results.len(), 600);
+        assert!(results.windows(2).all(|w| {
+            w[0].score > w[1].score
+                || (w[0].score == w[1].score && w[0].document_id < w[1].document_id)
+        }));
+
+        let ids: Vec<i64> = results.iter().map(|r| r.document_id).collect();
+        for _ in 0..3 {
+            assert_eq!(
+                search().iter().map(|r| r.document_id).collect::<Vec<_>>(),
+                ids
+            );
+        }
+    }
+
+    #[test]
+    fn test_search_ids_matches_full_search() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+
+        rag.add_document("Rust is a systems programming language", "test", None)
+            .unwrap();
+        rag.add_document("Python is great for machine learning", "test", None)
+            .unwrap();
+        rag.add_document("Rust and Python can interoperate", "test", None)
+            .unwrap();
+
+        for mode in [SearchMode::Semantic, SearchMode::Keyword, SearchMode::Hybrid] {
+            let full = rag
+                .search("Rust programming", Some("test"), None, 3, 0.0, mode, None)
+                .unwrap();
+            let options = SearchOptions {
+                mode,
+                ..Default::default()
+            };
+            let ids = rag
+                .search_ids("Rust programming", Some("test"), None, 3, &options)
+                .unwrap();
+
+            let expected: Vec<(i64, f32)> =
+                full.iter().map(|r| (r.document_id, r.score)).collect();
+            assert_eq!(ids, expected, "mode: {:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_thresholded_search_matches_brute_force() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+
+        let words = ["rust", "python", "memory", "safety", "web", "server", "data", "model"];
+        for i in 0..words.len() {
+            for j in 0..words.len() {
+                let content = format!("{} {} {}", words[i], words[j], words[(i + j) % words.len()]);
+                rag.add_document(&content, "test", None).unwrap();
+            }
+        }
+
+        // 枝刈りなしで全件のコサイン類似度を計算した結果
+        let query = "rust memory safety";
+        let query_embedding = rag.embedding_model.embed_query(query).unwrap();
+        let documents = rag.store.get_all_documents_with_norms(None).unwrap();
+
+        for threshold in [-1.0, 0.0, 0.2, 0.4, 0.6, 0.8] {
+            let mut expected: Vec<(i64, f32)> = documents
+                .iter()
+                .map(|((id, _, embedding, ..), _)| {
+                    (*id, cosine_similarity(&query_embedding, embedding))
+                })
+                .filter(|(_, score)| *score >= threshold)
+                .collect();
+            expected.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+            expected.truncate(10);
+
+            let results = rag
+                .search(query, Some("test"), None, 10, threshold, SearchMode::Semantic, None)
+                .unwrap();
+            // スコアは正規化したクエリとの内積から求めるため、丸め誤差の範囲で一致する
+            assert_eq!(results.len(), expected.len(), "threshold: {}", threshold);
+            for (result, (id, score)) in results.iter().zip(&expected) {
+                assert_eq!(result.document_id, *id, "threshold: {}", threshold);
+                assert!(
+                    (result.score - score).abs() < 1e-5,
+                    "threshold: {}",
+                    threshold
+                );
+            }
+
+            let count = rag
+                .count_matches(query, Some("test"), SearchMode::Semantic, threshold)
+                .unwrap();
+            let expected_count = documents
+                .iter()
+                .filter(|((_, _, embedding, ..), _)| {
+                    cosine_similarity(&query_embedding, embedding) >= threshold
+                })
+                .count();
+            assert_eq!(count, expected_count, "threshold: {}", threshold);
+        }
+    }
+
+    #[test]
+    fn test_external_id_upsert_get_delete() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("faq", None).unwrap();
+        rag.create_collection("other", None).unwrap();
+
+        let meta = serde_json::json!({"source": "cms"});
+        let id = rag
+            .add_document_with_external_id("Opening hours are 9-17", "faq", Some(&meta), "page-1")
+            .unwrap();
+
+        let doc = rag.get_document_by_external_id("page-1").unwrap().unwrap();
+        assert_eq!(doc.id, id);
+        assert_eq!(doc.external_id.as_deref(), Some("page-1"));
+        assert_eq!(rag.get_document(id).unwrap().external_id.as_deref(), Some("page-1"));
+        assert!(rag.get_document_by_external_id("page-2").unwrap().is_none());
+
+        // 同じ外部IDでの再追加は既存ドキュメントの更新になる
+        let same_id = rag
+            .add_document_with_external_id("Opening hours are 10-18", "faq", None, "page-1")
+            .unwrap();
+        assert_eq!(same_id, id);
+        let doc = rag.get_document_by_external_id("page-1").unwrap().unwrap();
+        assert_eq!(doc.content, "Opening hours are 10-18");
+        assert_eq!(doc.metadata, Some(meta));
+        assert_eq!(rag.list_documents(Some("faq"), 10, 0).unwrap().len(), 1);
+
+        let results = rag
+            .search("hours 10-18", Some("faq"), None, 1, 0.0, SearchMode::Semantic, None)
+            .unwrap();
+        assert_eq!(results[0].document_id, id);
+
+        // 外部IDは全コレクションで一意
+        assert!(rag
+            .add_document_with_external_id("Other content", "other", None, "page-1")
+            .is_err());
+
+        // 外部IDのないドキュメントは何件でも追加できる
+        rag.add_document("No external id", "faq", None).unwrap();
+        rag.add_document("No external id either", "faq", None).unwrap();
+
+        assert!(rag.delete_document_by_external_id("page-1").unwrap());
+        assert!(!rag.delete_document_by_external_id("page-1").unwrap());
+        assert!(rag.get_document_by_external_id("page-1").unwrap().is_none());
+        assert_eq!(rag.list_documents(Some("faq"), 10, 0).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_search_results_include_metadata() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+
+        let metadata = serde_json::json!({"category": "language"});
+        rag.add_document("Rust is a systems programming language", "test", Some(&metadata))
+            .unwrap();
+
+        for mode in [SearchMode::Semantic, SearchMode::Keyword, SearchMode::Hybrid] {
+            let results = rag
+                .search("Rust programming", Some("test"), None, 1, 0.0, mode, None)
+                .unwrap();
+            assert_eq!(results[0].metadata, Some(metadata.clone()), "mode: {:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_threshold_sweep() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
+
+        let rust = rag
+            .add_document("Rust is a systems programming language", "test", None)
+            .unwrap();
+        let python = rag
+            .add_document("Python is great for machine learning", "test", None)
+            .unwrap();
+        rag.add_document("The temple garden is beautiful in spring", "test", None)
+            .unwrap();
+
+        let queries = vec![
+            ("systems programming in Rust".to_string(), vec![rust]),
+            ("machine learning with Python".to_string(), vec![python]),
+        ];
+        let thresholds = [0.0, 0.5, 1.01];
+        let points = rag
+            .evaluate(&queries, &thresholds, SearchMode::Semantic, Some("test"), 3)
+            .unwrap();
+
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].threshold, 0.0);
+
+        // 閾値0.0では全件取得するので再現率は1.0
+        assert_eq!(points[0].retrieved, 6);
+        assert_eq!(points[0].recall, 1.0);
+        assert!((points[0].precision - 2.0 / 6.0).abs() < 1e-6);
+
+        // 閾値を上げると適合率は下がらない
+        assert!(points[1].precision >= points[0].precision);
+
+        // 類似度が1.0を超えることはないので何も取得されない
+        assert_eq!(points[2].retrieved, 0);
+        assert_eq!(points[2].f1, 0.0);
+    }
+
+    #[test]
+    fn test_search_result_embedding_is_opt_in() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
+        rag.add_document("Rust is a systems programming language", "test", None)
+            .unwrap();
+
+        let search = |include_embedding: bool| {
+            let options = SearchOptions {
+                include_embedding,
+                ..Default::default()
+            };
+            rag.search_with_options("Rust", Some("test"), None, 1, &options)
+            .unwrap()
+        };
+
+        // デフォルトではシリアライズ結果にフィールド自体が含まれない
+        let default_json = serde_json::to_value(&search(false)[0]).unwrap();
+        assert!(default_json.get("embedding").is_none());
+
+        let results = search(true);
+        assert_eq!(results[0].embedding.as_ref().map(Vec::len), Some(384));
+        let json = serde_json::to_value(&results[0]).unwrap();
+        assert_eq!(json["embedding"].as_array().map(Vec::len), Some(384));
+    }
+
+    #[test]
+    fn test_normalize_output() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
+
+        let raw = "  Rust\tis a  systems   programming language \n\n\n\tMemory  safe\n\n";
+        rag.add_document(raw, "test", None).unwrap();
+
+        let options = |normalize_output: bool| SearchOptions {
+            normalize_output,
+            ..Default::default()
+        };
+        let search = |normalize_output: bool| {
+            rag.search_with_options("Rust", Some("test"), None, 1, &options(normalize_output))
+            .unwrap()
+        };
+
+        // デフォルトでは保存された内容そのまま
+        assert_eq!(search(false)[0].content, raw);
+
+        let expected = "Rust is a systems programming language\n\nMemory safe";
+        assert_eq!(search(true)[0].content, expected);
+
+        // enrichのコンテキストにも反映される
+        let enriched = rag
+            .enrich_with_options("Rust", Some("test"), None, 1, &options(true))
+            .unwrap();
+        assert!(enriched.context.contains(expected));
+        assert!(!enriched.context.contains('\t'));
+
+        // 保存されている内容は変わらない
+        let stored = rag.list_documents(Some("test"), 1, 0).unwrap();
+        assert_eq!(stored[0].content, raw);
+    }
+
+    #[test]
+    fn test_keyword_search_routes_like_languages() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
+
+        let english = rag
+            .add_document("Rust is a fast language", "test", None)
+            .unwrap();
+        // FTS5では "rustは高速な言語です" が1トークンになり "Rust" にマッチしない
+        let japanese = rag
+            .add_document(
+                "Rustは高速な言語です",
+                "test",
+                Some(&serde_json::json!({"lang": "jpn"})),
+            )
+            .unwrap();
+
+        let results = rag
+            .search("Rust", Some("test"), None, 5, 0.0, SearchMode::Keyword, None)
+            .unwrap();
+        let ids: Vec<i64> = results.iter().map(|r| r.document_id).collect();
+        assert_eq!(ids, vec![english, japanese]);
+
+        let count = rag
+            .count_matches("Rust", Some("test"), SearchMode::Keyword, 0.0)
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[cfg(feature = "lang-detect")]
+    #[test]
+    fn test_detected_language_is_stored() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
+
+        let japanese = rag
+            .add_document(
+                "永代供養とは、お寺が故人の遺骨を管理し供養を続けることです。",
+                "test",
+                None,
+            )
+            .unwrap();
+        let english = rag
+            .add_document(
+                "Permanent memorial services are offered by the temple every year.",
+                "test",
+                Some(&serde_json::json!({"category": "faq"})),
+            )
+            .unwrap();
+        // 指定済みの lang は上書きしない
+        let explicit = rag
+            .add_document(
+                "Permanent memorial services are offered by the temple every year.",
+                "test",
+                Some(&serde_json::json!({"lang": "fra"})),
+            )
+            .unwrap();
+
+        assert_eq!(
+            rag.get_document(japanese).unwrap().metadata,
+            Some(serde_json::json!({"lang": "jpn"}))
+        );
+        assert_eq!(
+            rag.get_document(english).unwrap().metadata,
+            Some(serde_json::json!({"category": "faq", "lang": "eng"}))
+        );
+        assert_eq!(
+            rag.get_document(explicit).unwrap().metadata,
+            Some(serde_json::json!({"lang": "fra"}))
+        );
+    }
+
+    #[test]
+    fn test_like_search_scores_by_term_frequency() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path()).unwrap();
+        let coll = db.create_collection("test", None).unwrap();
+
+        // 同じくらいの長さで、キーワードが2回出現するドキュメントを先に追加
+        let twice = db
+            .add_document(coll, "永代供養の費用と永代供養の申込方法", &[0.1; 384], None)
+            .unwrap();
+        let once = db
+            .add_document(coll, "永代供養の費用と納骨堂の申込方法", &[0.1; 384], None)
+            .unwrap();
+
+        let results = db.keyword_search("永代供養", None, &[]).unwrap();
+        let ids: Vec<i64> = results.iter().map(|(id, ..)| *id).collect();
+
+        // 新しい順ではなく、出現回数の多いドキュメントが上位
+        assert_eq!(ids, vec![twice, once]);
+        assert!(results[0].2 < results[1].2);
+    }
+
+    #[test]
+    fn test_like_search_ranks_by_match_count_not_recency() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+
+        // キーワードを3回含むドキュメントを先に（古いIDで）追加し、同じ文字数で1回だけ含む
+        // ドキュメントを後から追加する
+        let thrice = rag
+            .add_document("永代供養とは？永代供養の費用と永代供養の流れ", "test", None)
+            .unwrap();
+        let once = rag
+            .add_document("永代供養とは？納骨堂の費用とお墓の管理の流れ", "test", None)
+            .unwrap();
+
+        let results = rag
+            .search("永代供養", Some("test"), None, 5, 0.0, SearchMode::Keyword, None)
+            .unwrap();
+        let ids: Vec<i64> = results.iter().map(|r| r.document_id).collect();
+        assert_eq!(ids, vec![thrice, once]);
+        assert!(results[0].score > results[1].score);
+    }
+
+    #[test]
+    fn test_like_search_matches_multiple_terms_far_apart() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+        // 日本語のドキュメントとしてLIKE検索で照合させる
+        let ja = serde_json::json!({"lang": "jpn"});
+
+        let far_apart = rag
+            .add_document(
+                "永代供養とは、寺院や霊園が遺骨の管理と供養を続けることです。\
+                 お墓の継承者がいなくても安心で、一般的な費用は数十万円程度です。",
+                "test",
+                Some(&ja),
+            )
+            .unwrap();
+        let adjacent = rag
+            .add_document("永代供養 費用の目安", "test", Some(&ja))
+            .unwrap();
+        let one_term = rag
+            .add_document("納骨堂の費用について", "test", Some(&ja))
+            .unwrap();
+
+        let keyword_ids = |query: &str, keyword_match: KeywordMatch| -> Vec<i64> {
+            let options = SearchOptions {
+                mode: SearchMode::Keyword,
+                keyword_match,
+                ..Default::default()
+            };
+            let mut ids: Vec<i64> = rag
+                .search_with_options(query, Some("test"), None, 10, &options)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.document_id)
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        // 離れた位置にある2つの語も、両方を含めばマッチする（AND）
+        assert_eq!(
+            keyword_ids("永代供養 費用", KeywordMatch::All),
+            vec![far_apart, adjacent]
+        );
+        // ORではいずれかの語を含むものすべて
+        assert_eq!(
+            keyword_ids("永代供養 費用", KeywordMatch::Any),
+            vec![far_apart, adjacent, one_term]
+        );
+        // ダブルクォートで囲むと、その並びのまま照合する
+        assert_eq!(
+            keyword_ids("\"永代供養 費用\"", KeywordMatch::All),
+            vec![adjacent]
+        );
+    }
+
+    #[test]
+    fn test_search_diagnostics_reports_like_fallback() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+        rag.add_document("永代供養の費用は一括で納めます", "test", None)
+            .unwrap();
+        rag.add_document("Rust is a systems programming language", "test", None)
+            .unwrap();
+
+        // 日本語はFTS5のトークナイザーで単語に分割されないため、LIKE検索にフォールバックする
+        let options = SearchOptions {
+            mode: SearchMode::Keyword,
+            ..Default::default()
+        };
+        let (results, diagnostics) = rag
+            .search_with_diagnostics("永代供養", Some("test"), None, 5, &options)
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(diagnostics.mode, SearchMode::Keyword);
+        assert_eq!(diagnostics.keyword_backend, Some(KeywordBackend::Like));
+        assert_eq!(diagnostics.keyword_candidates, Some(1));
+        assert_eq!(diagnostics.semantic_candidates, None);
+        assert_eq!(diagnostics.fusion, None);
+        assert!(diagnostics.total_ms >= diagnostics.keyword_ms);
+
+        // 英語はFTS5で見つかる。ハイブリッド検索では両方の候補数と統合方法を返す
+        let options = SearchOptions {
+            mode: SearchMode::Hybrid,
+            threshold: -1.0,
+            ..Default::default()
+        };
+        let (_, diagnostics) = rag
+            .search_with_diagnostics("rust", Some("test"), None, 5, &options)
+            .unwrap();
+        assert_eq!(diagnostics.keyword_backend, Some(KeywordBackend::Fts5));
+        assert_eq!(diagnostics.keyword_candidates, Some(1));
+        assert_eq!(diagnostics.semantic_candidates, Some(2));
+        assert_eq!(diagnostics.fusion, Some(FusionMethod::Weighted));
+
+        // JSONでは列挙値をすべてスネークケースで出力する
+        let json = serde_json::to_value(&diagnostics).unwrap();
+        assert_eq!(json["mode"], "hybrid");
+        assert_eq!(json["keyword_backend"], "fts5");
+        assert_eq!(json["fusion"], "weighted");
+    }
+
+    #[test]
+    fn test_hybrid_candidate_multiplier() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
+
+        // `lang: jpn` のドキュメントはLIKE検索で照合され、キーワードスコアは同じ値・新しい順になる
+        let like = serde_json::json!({"lang": "jpn"});
+
+        // セマンティック検索では3位、キーワード検索では3位（最も古い）だが、統合後は1位になる
+        let target = rag
+            .add_document(
+                "The memorial fee is paid once at the temple office.",
+                "test",
+                Some(&like),
+            )
+            .unwrap();
+        // セマンティック検索の上位（キーワードにはマッチしない）
+        rag.add_document("Memorial fees", "test", None).unwrap();
+        rag.add_document("memorial fees.", "test", None).unwrap();
+        // キーワード検索の上位（内容はほぼ無関係）
+        for _ in 0..2 {
+            rag.add_document(
+                "Parking rules: cars use the north lot, bicycles go by the gate, \
+                 and the memorial fee desk closes at noon.",
+                "test",
+                Some(&like),
+            )
+            .unwrap();
+        }
+
+        let search = |candidate_multiplier: usize| {
+            let options = SearchOptions {
+                mode: SearchMode::Hybrid,
+                hybrid_weights: Some((0.1, 1.0)),
+                candidate_multiplier,
+                ..Default::default()
+            };
+            rag.search_with_options("memorial fee", Some("test"), None, 1, &options)
+            .unwrap()
+        };
+
+        // デフォルト（2倍）では各検索の上位2件にしか入らないため候補から漏れる
+        assert_ne!(search(2)[0].document_id, target);
+        assert_eq!(search(5)[0].document_id, target);
+    }
+
+    #[test]
+    fn test_read_only_instance() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let id = {
+            let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+            rag.create_collection("test", None).unwrap();
+            rag.add_document("Rust is a systems programming language", "test", None)
+                .unwrap()
+        };
+
+        let rag =
+            Doredore::new_read_only(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        assert!(rag.is_read_only());
+
+        // 読み取りは通常どおり
+        let results = rag
+            .search("Rust", Some("test"), None, 5, 0.0, SearchMode::Semantic, None)
+            .unwrap();
+        assert_eq!(results[0].document_id, id);
+        assert_eq!(rag.get_document(id).unwrap().content, "Rust is a systems programming language");
+        assert_eq!(rag.list_collections().unwrap().len(), 1);
+
+        // 変更操作はすべて ReadOnly エラー
+        assert!(matches!(
+            rag.add_document("Python is a scripting language", "test", None),
+            Err(Error::ReadOnly)
+        ));
+        assert!(matches!(rag.create_collection("other", None), Err(Error::ReadOnly)));
+        assert!(matches!(rag.delete_document(id), Err(Error::ReadOnly)));
+        assert!(matches!(rag.delete_collection("test"), Err(Error::ReadOnly)));
+        assert!(matches!(rag.optimize(), Err(Error::ReadOnly)));
+        assert!(matches!(rag.optimize_indexes(), Err(Error::ReadOnly)));
+        assert_eq!(rag.list_documents(Some("test"), 10, 0).unwrap().len(), 1);
+
+        // 存在しないファイルは作成せずにエラー
+        let missing = temp_file.path().with_extension("missing.db");
+        assert!(Doredore::new_read_only(&missing, Some("bge-small-en-v1.5"), None).is_err());
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn test_inspect_database() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path()).unwrap();
+        let faq = db.create_collection("faq", None).unwrap();
+        db.create_collection("docs", None).unwrap();
+
+        // 次元数が揃っている場合
+        for i in 0..3 {
+            db.add_document(faq, &format!("doc {}", i), &[0.1; 384], None)
+                .unwrap();
+        }
+        let info = Doredore::inspect_database(temp_file.path()).unwrap();
+        assert_eq!(info.collection_count, 2);
+        assert_eq!(info.document_count, 3);
+        assert_eq!(info.dimension, Some(384));
+        assert!(!info.mixed_dimensions);
+        assert!(info.compatible_models.contains(&"bge-small-en-v1.5".to_string()));
+        assert!(!info.compatible_models.contains(&"bge-base-en-v1.5".to_string()));
+
+        // 別のモデルのEmbeddingが混ざった場合
+        db.add_document(faq, "other model", &[0.1; 768], None)
+            .unwrap();
+        let info = Doredore::inspect_database(temp_file.path()).unwrap();
+        assert_eq!(info.document_count, 4);
+        assert_eq!(info.dimension, Some(384));
+        assert_eq!(info.dimension_counts, vec![(384, 3), (768, 1)]);
+        assert!(info.mixed_dimensions);
+
+        // 診断はファイルを変更しない（ジャーナルモードもWALに戻さない）
+        drop(db);
+        let conn = rusqlite::Connection::open(temp_file.path()).unwrap();
+        conn.pragma_update(None, "journal_mode", "DELETE").unwrap();
+        Doredore::inspect_database(temp_file.path()).unwrap();
+        let journal_mode: String = conn
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "delete");
+    }
+
+    #[test]
+    fn test_inspect_missing_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.db");
+
+        let result = Doredore::inspect_database(&path);
+        assert!(matches!(result, Err(Error::Io(_))));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_skip_duplicate_documents() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
+        rag.create_collection("other", None).unwrap();
+
+        let original = rag
+            .add_document_unique("Rust is a systems programming language", "test", None)
+            .unwrap();
+        // 空白の違いは無視して同一とみなす
+        let duplicate = rag
+            .add_document_unique("  Rust is a systems\nprogramming   language ", "test", None)
+            .unwrap();
+        assert_eq!(duplicate, original);
+        assert_eq!(rag.get_collection("test").unwrap().document_count, 1);
+
+        // 別のコレクションには追加される
+        let other = rag
+            .add_document_unique("Rust is a systems programming language", "other", None)
+            .unwrap();
+        assert_ne!(other, original);
+
+        // バッチ内の重複と既存ドキュメントとの重複
+        let documents = vec![
+            "Python is great for machine learning".to_string(),
+            "Rust is a systems programming language".to_string(),
+            "Python is great for machine learning".to_string(),
+        ];
+        let ids = rag
+            .add_documents_with_options(
+                documents,
+                "test",
+                None,
+                &AddOptions {
+                    skip_duplicates: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids[1], original);
+        assert_eq!(ids[2], ids[0]);
+        assert_eq!(rag.get_collection("test").unwrap().document_count, 2);
+    }
+
+    #[test]
+    fn test_add_same_document_twice_keeps_one_row() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("test", None).unwrap();
+
+        let content = "Rust is a systems programming language";
+        assert_eq!(rag.find_duplicate(content, "test").unwrap(), None);
+
+        let first = rag.add_document_unique(content, "test", None).unwrap();
+        let second = rag.add_document_unique(content, "test", None).unwrap();
+        assert_eq!(second, first);
+        assert_eq!(rag.count_documents(Some("test")).unwrap(), 1);
+        assert_eq!(rag.find_duplicate(content, "test").unwrap(), Some(first));
+
+        // 内容が異なれば通常どおり追加される
+        let distinct = rag
+            .add_document_unique("Python is great for machine learning", "test", None)
+            .unwrap();
+        assert_ne!(distinct, first);
+        assert_eq!(rag.count_documents(Some("test")).unwrap(), 2);
+    }
+
+    /// 呼び出しごとにベクトル化したテキストの件数を数えるEmbedder
+    struct CountingEmbedder {
+        inner: MockEmbedder,
+        name: &'static str,
+        embedded: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl Embedder for CountingEmbedder {
+        fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+            self.embedded
+                .fetch_add(texts.len(), std::sync::atomic::Ordering::SeqCst);
+            self.inner.embed_batch(texts)
+        }
+
+        fn dimension(&self) -> usize {
+            self.inner.dimension()
+        }
+
+        fn model_name(&self) -> &str {
+            self.name
+        }
+    }
+
+    #[test]
+    fn test_embedding_cache_skips_model_for_same_text() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let embedded = Arc::new(AtomicUsize::new(0));
+        let mut rag = Doredore::new_with_embedder(
+            temp_file.path(),
+            CountingEmbedder {
+                inner: MockEmbedder::new(64),
+                name: "counting",
+                embedded: embedded.clone(),
+            },
+        )
+        .unwrap();
+        let other_embedded = Arc::new(AtomicUsize::new(0));
+        rag.register_model(
+            "other",
+            EmbeddingModel::from_embedder(CountingEmbedder {
+                inner: MockEmbedder::new(64),
+                name: "other",
+                embedded: other_embedded.clone(),
+            }),
+        );
+        rag.create_collection("a", None).unwrap();
+        rag.create_collection("b", None).unwrap();
+        rag.create_collection_with_model("c", None, Some("other"))
+            .unwrap();
+
+        // 無効（デフォルト）の場合は毎回ベクトル化する
+        let text = "Rust is a systems programming language";
+        rag.add_document(text, "a", None).unwrap();
+        rag.add_document(text, "a", None).unwrap();
+        assert_eq!(embedded.load(Ordering::SeqCst), 2);
+
+        rag.set_embedding_cache(true);
+        embedded.store(0, Ordering::SeqCst);
+        let first = rag.add_document(text, "a", None).unwrap();
+        let second = rag.add_document(text, "b", None).unwrap();
+        assert_eq!(embedded.load(Ordering::SeqCst), 1);
+        let stored = rag.store.get_embeddings(&[first, second]).unwrap();
+        assert_eq!(stored[&first], stored[&second]);
+
+        // バッチ内の重複・キャッシュ済みのテキストはベクトル化しない
+        rag.add_documents(
+            vec![
+                text.to_string(),
+                "new text".to_string(),
+                "new text".to_string(),
+            ],
+            "a",
+            None,
+        )
+        .unwrap();
+        rag.update_document(first, Some("new text"), None).unwrap();
+        assert_eq!(embedded.load(Ordering::SeqCst), 2);
+
+        // モデルが異なればキャッシュを使わない
+        rag.add_document(text, "c", None).unwrap();
+        assert_eq!(other_embedded.load(Ordering::SeqCst), 1);
+
+        assert_eq!(rag.clear_embedding_cache().unwrap(), 3);
+        rag.add_document(text, "a", None).unwrap();
+        assert_eq!(embedded.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_update_missing_document_skips_embedding() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let embedded = Arc::new(AtomicUsize::new(0));
+        let rag = Doredore::new_with_embedder(
+            temp_file.path(),
+            CountingEmbedder {
+                inner: MockEmbedder::new(64),
+                name: "counting",
+                embedded: embedded.clone(),
+            },
+        )
+        .unwrap();
+        rag.create_collection("a", None).unwrap();
+
+        // 存在しないドキュメントはEmbeddingを生成せずにfalse
+        assert!(!rag.update_document(999, Some("new text"), None).unwrap());
+        assert_eq!(embedded.load(Ordering::SeqCst), 0);
+
+        // 存在するドキュメントは1回だけ再生成する
+        let id = rag.add_document("old text", "a", None).unwrap();
+        embedded.store(0, Ordering::SeqCst);
+        assert!(rag.update_document(id, Some("new text"), None).unwrap());
+        assert_eq!(embedded.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_content_hash_backfilled_for_existing_database() {
+        let temp_file = NamedTempFile::new().unwrap();
+
+        // content_hash 列がない頃のスキーマ
+        {
+            let conn = rusqlite::Connection::open(temp_file.path()).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE collections (
+                     id INTEGER PRIMARY KEY AUTOINCREMENT,
+                     name TEXT UNIQUE NOT NULL,
+                     description TEXT,
+                     created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                     updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                 );
+                 CREATE TABLE documents (
+                     id INTEGER PRIMARY KEY AUTOINCREMENT,
+                     collection_id INTEGER NOT NULL,
+                     content TEXT NOT NULL,
+                     embedding BLOB NOT NULL,
+                     metadata TEXT,
+                     created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                     updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                 );
+                 INSERT INTO collections (name) VALUES ('faq');
+                 INSERT INTO documents (collection_id, content, embedding)
+                 VALUES (1, 'Legacy document', x'00000000');",
+            )
+            .unwrap();
+        }
+
+        let db = Database::new(temp_file.path()).unwrap();
+        assert_eq!(db.find_duplicate(1, "Legacy  document").unwrap(), Some(1));
+        assert_eq!(db.find_duplicate(1, "Another document").unwrap(), None);
+    }
+
+    #[test]
+    fn test_chunk_parents_backfilled_for_existing_database() {
+        let temp_file = NamedTempFile::new().unwrap();
+
+        // parent_id 列がない頃のスキーマ（チャンクの対応付けはメタデータのみ）
+        {
+            let conn = rusqlite::Connection::open(temp_file.path()).unwrap();
+            conn.execute_batch(
+                r#"CREATE TABLE collections (
+                     id INTEGER PRIMARY KEY AUTOINCREMENT,
+                     name TEXT UNIQUE NOT NULL,
+                     description TEXT,
+                     created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                     updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                 );
+                 CREATE TABLE documents (
+                     id INTEGER PRIMARY KEY AUTOINCREMENT,
+                     collection_id INTEGER NOT NULL,
+                     content TEXT NOT NULL,
+                     embedding BLOB NOT NULL,
+                     metadata TEXT,
+                     created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                     updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                 );
+                 INSERT INTO collections (name) VALUES ('faq');
+                 INSERT INTO documents (collection_id, content, embedding, metadata) VALUES
+                     (1, 'First chunk', x'00000000', '{"parent_id": 1, "chunk_index": 0}'),
+                     (1, 'Second chunk', x'00000000', '{"parent_id": 1, "chunk_index": 1}'),
+                     (1, 'User document', x'00000000', '{"parent_id": 1}'),
+                     (1, 'Dangling chunk', x'00000000', '{"parent_id": 99, "chunk_index": 1}');"#,
+            )
+            .unwrap();
+        }
+
+        let db = Database::new(temp_file.path()).unwrap();
+        let chunks: Vec<i64> = db.get_chunks(1).unwrap().iter().map(|c| c.id).collect();
+        assert_eq!(chunks, vec![1, 2]);
+        assert_eq!(
+            db.get_chunk_parents(&[1, 2, 3, 4]).unwrap(),
+            HashMap::from([(1, 1), (2, 1)])
+        );
+    }
+
+    #[test]
+    fn test_count_matches() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+
+        rag.create_collection("test", None).unwrap();
+        rag.add_document("The cost of machine learning projects varies.", "test", None)
+            .unwrap();
+        rag.add_document("Cost estimates for deep learning are high.", "test", None)
+            .unwrap();
+        rag.add_document("Trees are planted in spring.", "test", None)
+            .unwrap();
+
+        // キーワード: "cost" を含む2件
+        let count = rag
+            .count_matches("cost", Some("test"), SearchMode::Keyword, 0.0)
+            .unwrap();
+        assert_eq!(count, 2);
+
+        // セマンティック: 閾値-1.0なら全件、閾値1.1なら0件
+        let all = rag
+            .count_matches("machine learning", Some("test"), SearchMode::Semantic, -1.0)
+            .unwrap();
+        assert_eq!(all, 3);
+        let none = rag
+            .count_matches("machine learning", Some("test"), SearchMode::Semantic, 1.1)
+            .unwrap();
+        assert_eq!(none, 0);
+
+        // 件数は通常の検索結果数と一致する
+        let results = rag
+            .search("machine learning", Some("test"), None, 10, 0.5, SearchMode::Semantic, None)
+            .unwrap();
+        let count = rag
+            .count_matches("machine learning", Some("test"), SearchMode::Semantic, 0.5)
+            .unwrap();
+        assert_eq!(count, results.len());
+    }
+
+    #[test]
+    fn test_optimize_after_bulk_delete() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+
+        rag.create_collection("test", None).unwrap();
+        let documents = (0..50)
+            .map(|i| format!("Temporary document number {}", i))
+            .collect();
+        let ids = rag.add_documents(documents, "test", None).unwrap();
+        let keep_id = rag
+            .add_document("Machine learning is a subset of artificial intelligence.", "test", None)
+            .unwrap();
+
+        for id in ids {
+            rag.delete_document(id).unwrap();
+        }
+
+        rag.optimize().unwrap();
+        rag.optimize_indexes().unwrap();
+
+        let docs = rag.list_documents(Some("test"), 100, 0).unwrap();
+        assert_eq!(docs.len(), 1);
+
+        let results = rag
+            .search("machine learning", Some("test"), None, 5, 0.0, SearchMode::Semantic, None)
+            .unwrap();
+        assert_eq!(results[0].document_id, keep_id);
+
+        let results = rag
+            .search("learning", Some("test"), None, 5, 0.0, SearchMode::Keyword, None)
+            .unwrap();
+        assert_eq!(results[0].document_id, keep_id);
+    }
+
+    #[test]
+    fn test_bulk_add_documents_keyword_search() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
+
+        let existing_id = rag
+            .add_document("Python is popular for machine learning", "test", None)
+            .unwrap();
+
+        let documents = vec![
+            "Rust is a systems programming language".to_string(),
+            "Go is used for cloud infrastructure".to_string(),
+            "Rust has zero-cost abstractions".to_string(),
+        ];
+        let ids = rag
+            .add_documents_with_options(
+                documents,
+                "test",
+                None,
+                &AddOptions {
+                    bulk: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(!rag.store.is_bulk_loading());
+
+        // バルクロード完了後はキーワード検索でヒットする
+        let results = rag
+            .search("Rust", Some("test"), None, 10, 0.0, SearchMode::Keyword, None)
+            .unwrap();
+        let mut found: Vec<i64> = results.iter().map(|r| r.document_id).collect();
+        found.sort();
+        assert_eq!(found, vec![ids[0], ids[2]]);
+
+        // 再構築で既存ドキュメントが重複しないこと
+        let results = rag
+            .search("Python", Some("test"), None, 10, 0.0, SearchMode::Keyword, None)
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].document_id, existing_id);
+    }
+
+    #[test]
+    fn test_add_documents_single_transaction() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("batch", None).unwrap();
+        let documents: Vec<String> = (0..10)
+            .map(|i| format!("Document number {}", i))
+            .collect();
+        let ids = rag
+            .add_documents(documents.clone(), "batch", None)
+            .unwrap();
+        assert_eq!(ids.len(), 10);
+
+        // 途中の1件が失敗すると、バッチ全体が取り消される
+        let conn = rusqlite::Connection::open(temp_file.path()).unwrap();
+        conn.execute_batch(
+            "CREATE TRIGGER reject_poison BEFORE INSERT ON documents
+             WHEN NEW.content = 'poison'
+             BEGIN SELECT RAISE(ABORT, 'poison document'); END;",
+        )
+        .unwrap();
+        drop(conn);
+
+        rag.create_collection("atomic", None).unwrap();
+        let mut documents = documents;
+        documents.insert(5, "poison".to_string());
+        assert!(rag
+            .add_documents_with_options(
+                documents,
+                "atomic",
+                None,
+                &AddOptions {
+                    bulk: true,
+                    ..Default::default()
+                }
+            )
+            .is_err());
+        assert!(!rag.store.is_bulk_loading());
+        assert_eq!(rag.count_documents(Some("atomic")).unwrap(), 0);
+        assert_eq!(rag.count_documents(None).unwrap(), 10);
+        assert!(rag
+            .search(
+                "Document",
+                Some("atomic"),
+                None,
+                10,
+                0.0,
+                SearchMode::Keyword,
+                None
+            )
+            .unwrap()
+            .is_empty());
+    }
+
+    /// 1,000件を1件ずつ追加した場合と `add_documents` でまとめて追加した場合の速度を比較
+    ///
+    /// 実行時間がディスクや負荷に左右されるため通常は実行しない:
+    /// `cargo test -p doredore-core --release --features mock-embedder add_documents_benchmark -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn add_documents_benchmark() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("single", None).unwrap();
+        rag.create_collection("batch", None).unwrap();
+        let documents: Vec<String> = (0..1000)
+            .map(|i| format!("Document number {}", i))
+            .collect();
+
+        let started = Instant::now();
+        for doc in &documents {
+            rag.add_document(doc, "single", None).unwrap();
+        }
+        let single = started.elapsed();
+
+        let started = Instant::now();
+        rag.add_documents(documents, "batch", None)
+            .unwrap();
+        let batch = started.elapsed();
+
+        println!("single: {:?}, batch: {:?}", single, batch);
+        // 1件ごとのコミット（WALへの書き込みと同期）がなくなる分、大幅に速い
+        assert!(batch * 2 < single);
+    }
+
+    #[test]
+    fn test_add_html_document_matches_visible_text() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
+
+        let html = r#"<div class="notice"><h2>Permanent memorial</h2><p>Managed by the temple</p></div>"#;
+        let id = rag
+            .add_document_as(html, "test", None, ContentType::Html, true)
+            .unwrap();
+
+        let doc = rag.get_document(id).unwrap();
+        assert_eq!(doc.content, "Permanent memorial\nManaged by the temple");
+        assert_eq!(doc.metadata.unwrap()["original_content"], html);
+
+        // 表示上のテキストにはマッチする
+        let results = rag
+            .search("temple", Some("test"), None, 5, 0.0, SearchMode::Keyword, None)
+            .unwrap();
+        assert_eq!(results[0].document_id, id);
+
+        // タグや属性にはマッチしない
+        for tag in ["div", "notice", "class"] {
+            let results = rag
+                .search(tag, Some("test"), None, 5, 0.0, SearchMode::Keyword, None)
+                .unwrap();
+            assert!(results.is_empty(), "matched tag text: {}", tag);
+        }
+    }
+
+    #[test]
+    fn test_over_length_document_is_truncated_and_flagged() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.set_max_input_chars(Some(100));
+        rag.create_collection("test", None).unwrap();
 
-        // ハイブリッドスコアの降順でソート
-        hybrid_results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+        let long_text = "Permanent memorial services are offered by the temple. ".repeat(1000);
+        let metadata = serde_json::json!({"source": "manual"});
+        let long_id = rag
+            .add_document(&long_text, "test", Some(&metadata))
+            .unwrap();
+        let short_id = rag.add_document("Short document", "test", None).unwrap();
 
-        // Top-Kを取得してSearchResult構造体に変換
-        let top_results: Vec<SearchResult> = hybrid_results
-            .into_iter()
-            .take(top_k)
-            .map(|(id, content, score, coll_name, metadata)| {
-                SearchResult::new(id, content, score, metadata, coll_name)
-            })
-            .collect();
+        // 本文は切り詰めずに保存し、メタデータにフラグを記録する
+        let long_doc = rag.get_document(long_id).unwrap();
+        assert_eq!(long_doc.content, long_text);
+        assert_eq!(
+            long_doc.metadata,
+            Some(serde_json::json!({"source": "manual", "truncated": true}))
+        );
+        assert_eq!(rag.get_document(short_id).unwrap().metadata, None);
 
-        Ok(top_results)
+        // Embeddingは先頭100文字から生成される
+        let prefix: String = long_text.chars().take(100).collect();
+        let results = rag
+            .search_by_vector(
+                &rag.embedding_model().embed_passage(&prefix).unwrap(),
+                Some("test"),
+                None,
+                1,
+                0.0,
+            )
+            .unwrap();
+        assert_eq!(results[0].document_id, long_id);
+        assert!((results[0].score - 1.0).abs() < 1e-4);
     }
 
-    /// RAGエンリッチメント（LLMコンテキスト生成）
-    ///
-    /// 検索結果をLLMに渡しやすい形式に整形
-    ///
-    /// # 処理フロー
-    /// 1. 指定されたモードで検索を実行
-    /// 2. 検索結果を整形済みコンテキスト文字列に変換
-    /// 3. EnrichResultとして返す
-    ///
-    /// # 用途
-    /// LLMプロンプトに挿入するコンテキストを生成
-    /// ```text
-    /// [Source 1] (Score: 0.876, Collection: docs)
-    /// ドキュメントの内容...
-    ///
-    /// [Source 2] (Score: 0.754, Collection: docs)
-    /// ドキュメントの内容...
-    /// ```
-    ///
-    /// # 引数
-    /// * searchメソッドと同じパラメータ
-    ///
-    /// # 戻り値
-    /// EnrichResult（question, context, sources）
-    pub fn enrich(
-        &self,
-        query: &str,
-        collection: Option<&str>,
-        collections: Option<&[String]>,
-        top_k: usize,
-        threshold: f32,
-        mode: SearchMode,
-        hybrid_weights: Option<(f32, f32)>,
-    ) -> Result<EnrichResult> {
-        // 検索を実行
-        let sources = self.search(
-            query,
-            collection,
-            collections,
-            top_k,
-            threshold,
-            mode,
-            hybrid_weights,
-        )?;
+    #[test]
+    fn test_add_document_chunked() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
 
-        // LLM向けに整形されたコンテキストを含むEnrichResultを生成
-        Ok(EnrichResult::new(query.to_string(), sources))
-    }
+        let content = "Machine learning is a subset of artificial intelligence. ".repeat(10);
+        let metadata = serde_json::json!({"source": "ml.txt"});
+        let parent_id = rag
+            .add_document_chunked(&content, "test", Some(&metadata), 100, 20)
+            .unwrap();
 
-    // ヘルパーメソッド
+        let chunks = rag.get_chunks(parent_id).unwrap();
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks[0].id, parent_id);
 
-    fn get_collection_ids(
-        &self,
-        collection: Option<&str>,
-        collections: Option<&[String]>,
-    ) -> Result<Option<Vec<i64>>> {
-        if let Some(coll_name) = collection {
-            let coll = self.db.get_collection(coll_name)?;
-            Ok(Some(vec![coll.id]))
-        } else if let Some(coll_names) = collections {
-            let mut ids = Vec::new();
-            for name in coll_names {
-                let coll = self.db.get_collection(name)?;
-                ids.push(coll.id);
-            }
-            Ok(Some(ids))
-        } else {
-            Ok(None)
+        // メタデータのオフセットから元テキストを再構築できること
+        let chars: Vec<char> = content.chars().collect();
+        let mut rebuilt = String::new();
+        let mut covered = 0;
+        for (i, chunk) in chunks.iter().enumerate() {
+            let meta = chunk.metadata.as_ref().unwrap();
+            assert_eq!(meta["parent_id"], parent_id);
+            assert_eq!(meta["chunk_index"], i);
+            assert_eq!(meta["source"], "ml.txt");
+
+            let start = meta["char_start"].as_u64().unwrap() as usize;
+            let end = meta["char_end"].as_u64().unwrap() as usize;
+            rebuilt.extend(&chars[covered.max(start)..end]);
+            covered = end;
         }
+        assert_eq!(rebuilt, content);
     }
 
-    // CSV インポート・エクスポート
+    #[test]
+    fn test_chunked_document_search_collapses_by_parent() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_db.path());
+        rag.create_collection("docs", None).unwrap();
 
-    pub fn import_csv(
-        &self,
-        file_path: &str,
-        collection: &str,
-        content_column: &str,
-        metadata_columns: Option<Vec<String>>,
-    ) -> Result<usize> {
-        let mut reader = csv::Reader::from_path(file_path)?;
-        let headers = reader.headers()?.clone();
+        // 約5000文字の長文（節ごとに固有の語を含む）
+        let content: String = (0..110)
+            .map(|i| format!("Section {:03} covers the widget topic{} in detail. ", i, i))
+            .collect();
+        assert!(content.chars().count() >= 5000);
+        let parent_id = rag
+            .add_document_chunked(&content, "docs", None, 500, 50)
+            .unwrap();
+        let other_id = rag
+            .add_document("The widget warranty lasts two years", "docs", None)
+            .unwrap();
+        // メタデータに parent_id キーがあるだけのドキュメントはチャンクとして扱わない
+        let unrelated_id = rag
+            .add_document(
+                "The widget manual is printed separately",
+                "docs",
+                Some(&serde_json::json!({"parent_id": parent_id})),
+            )
+            .unwrap();
 
-        let content_idx = headers
-            .iter()
-            .position(|h| h == content_column)
-            .ok_or_else(|| {
-                Error::InvalidInput(format!("Content column '{}' not found", content_column))
-            })?;
-
-        let mut documents = Vec::new();
-        let mut metadata_list = Vec::new();
-
-        for result in reader.records() {
-            let record = result?;
-
-            if let Some(content) = record.get(content_idx) {
-                documents.push(content.to_string());
-
-                // メタデータを構築
-                if let Some(ref meta_cols) = metadata_columns {
-                    let mut meta_map = serde_json::Map::new();
-                    for col_name in meta_cols {
-                        if let Some(idx) = headers.iter().position(|h| h == col_name) {
-                            if let Some(value) = record.get(idx) {
-                                meta_map.insert(
-                                    col_name.clone(),
-                                    serde_json::Value::String(value.to_string()),
-                                );
-                            }
-                        }
-                    }
-                    metadata_list.push(serde_json::Value::Object(meta_map));
+        let chunks = rag.get_chunks(parent_id).unwrap();
+        assert!(chunks.len() >= 10);
+        assert!(chunks.iter().all(|c| c.id != unrelated_id));
+        let chunk_ids: HashSet<i64> = chunks.iter().map(|c| c.id).collect();
+
+        // 末尾の節の語も、その節を含むチャンクとして検索できる
+        let results = rag
+            .search("topic107", None, None, 5, 0.0, SearchMode::Keyword, None)
+            .unwrap();
+        assert!(!results.is_empty());
+        assert!(results[0].content.contains("topic107"));
+        assert_eq!(
+            results[0].metadata.as_ref().unwrap()["parent_id"],
+            parent_id
+        );
+
+        // 指定しない場合は同じ親のチャンクが上位を占める
+        let search = |collapse_chunks: bool, mode: SearchMode| {
+            let options = SearchOptions {
+                mode,
+                collapse_chunks,
+                ..Default::default()
+            };
+            rag.search_with_options("widget", None, None, 5, &options)
+                .unwrap()
+        };
+        for mode in [SearchMode::Keyword, SearchMode::Semantic] {
+            assert_eq!(search(false, mode).len(), 5);
+
+            let parent_of = |r: &SearchResult| {
+                if chunk_ids.contains(&r.document_id) {
+                    parent_id
                 } else {
-                    metadata_list.push(serde_json::Value::Null);
+                    r.document_id
                 }
-            }
+            };
+            let mut parents: Vec<i64> = search(true, mode).iter().map(parent_of).collect();
+            parents.sort();
+            assert_eq!(
+                parents,
+                vec![parent_id, other_id, unrelated_id],
+                "mode: {:?}",
+                mode
+            );
         }
+    }
 
-        let count = documents.len();
-        self.add_documents(documents, collection, Some(metadata_list))?;
+    #[test]
+    fn test_rebuild_keyword_index() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_db.path());
+        rag.create_collection("faq", None).unwrap();
+        let indexed = rag
+            .add_document("Pasta sauce with tomatoes", "faq", None)
+            .unwrap();
+        let id = rag
+            .add_document("Boil the pasta in salted water", "faq", None)
+            .unwrap();
 
-        Ok(count)
+        // 外部のツールで書き込んだドキュメントと同じく、FTSインデックスに行がない状態にする
+        let conn = rusqlite::Connection::open(temp_db.path()).unwrap();
+        conn.execute("DELETE FROM documents_fts WHERE document_id = ?1", [id]).unwrap();
+        let search = || {
+            let mut ids: Vec<i64> = rag
+                .search("pasta", None, None, 5, 0.0, SearchMode::Keyword, None)
+                .unwrap()
+                .iter()
+                .map(|r| r.document_id)
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(search(), vec![indexed]);
+
+        rag.rebuild_keyword_index().unwrap();
+        assert_eq!(search(), vec![indexed, id]);
+
+        // 再構築しても重複した行はできない
+        rag.rebuild_keyword_index().unwrap();
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM documents_fts", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
     }
 
-    pub fn export_csv(
-        &self,
-        file_path: &str,
-        collection: Option<&str>,
-    ) -> Result<usize> {
-        let documents = self.list_documents(collection, 1000000, 0)?;
+    #[test]
+    fn test_embed_resilient_skips_failed_documents() {
+        let documents: Vec<String> = ["valid one", "<<bad>>", "valid two", "valid three"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
 
-        let mut writer = csv::Writer::from_path(file_path)?;
+        // "<<bad>>" を含むバッチはエラーになる疑似Embedding
+        let embed_batch = |texts: Vec<String>| -> Result<Vec<Vec<f32>>> {
+            if texts.iter().any(|t| t == "<<bad>>") {
+                return Err(Error::Embedding("malformed input".to_string()));
+            }
+            Ok(texts.iter().map(|t| vec![t.len() as f32]).collect())
+        };
 
-        // ヘッダー
-        writer.write_record(&["id", "collection", "content", "metadata", "created_at"])?;
+        let embeddings = embed_resilient(&documents, 2, embed_batch);
 
-        // データ
-        for doc in &documents {
-            let metadata_str = doc
-                .metadata
-                .as_ref()
-                .map(|m| serde_json::to_string(m).unwrap_or_default())
-                .unwrap_or_default();
+        assert_eq!(embeddings.len(), 4);
+        assert!(embeddings[0].is_ok());
+        assert!(embeddings[1]
+            .as_ref()
+            .is_err_and(|e| e.contains("malformed input")));
+        assert!(embeddings[2].is_ok());
+        assert!(embeddings[3].is_ok());
+    }
 
-            writer.write_record(&[
-                doc.id.to_string(),
-                doc.collection_name.clone(),
-                doc.content.clone(),
-                metadata_str,
-                doc.created_at.clone(),
-            ])?;
-        }
+    #[test]
+    fn test_import_csv_detects_content_column() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
 
-        writer.flush()?;
+        let csv_file = NamedTempFile::new().unwrap();
+        std::fs::write(
+            csv_file.path(),
+            "id,body,tag\n\
+             1,Machine learning is a subset of artificial intelligence.,ml\n\
+             2,Deep learning uses neural networks with many layers.,dl\n",
+        )
+        .unwrap();
 
-        Ok(documents.len())
-    }
-}
+        let (count, column) = rag
+            .import_csv_detailed(
+                csv_file.path().to_str().unwrap(),
+                "test",
+                &CsvImportOptions::default(),
+            )
+            .unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::NamedTempFile;
+        assert_eq!(count, 2);
+        assert_eq!(column, "body");
+
+        let docs = rag.list_documents(Some("test"), 10, 0).unwrap();
+        assert!(docs.iter().any(|d| d.content.starts_with("Machine learning")));
+    }
 
     #[test]
-    fn test_doredore_initialization() {
-        let temp_file = NamedTempFile::new().unwrap();
-        let result = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None);
-        assert!(result.is_ok());
+    fn test_content_column_detector_picks_longest_average() {
+        let mut detector = ContentColumnDetector::default();
+        assert_eq!(detector.detect(), None);
+
+        // 同率の場合は左側のカラム、セルの欠けた行は0文字として数える
+        for record in [vec!["1", "abc", "abc", "x"], vec!["2", "de", "de"]] {
+            detector.add(&csv::StringRecord::from(record));
+        }
+        assert_eq!(detector.detect(), Some(1));
+
+        detector.add(&csv::StringRecord::from(vec!["3", "", "", "long tag"]));
+        assert_eq!(detector.detect(), Some(3));
     }
 
     #[test]
-    fn test_collection_operations() {
+    fn test_import_csv_records_source() {
         let temp_file = NamedTempFile::new().unwrap();
         let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        rag.create_collection("test", None).unwrap();
 
-        // Create collection
-        let id = rag.create_collection("test", Some("Test collection")).unwrap();
-        assert!(id > 0);
+        let csv_file = NamedTempFile::new().unwrap();
+        let csv_path = csv_file.path().to_str().unwrap();
+        std::fs::write(
+            csv_file.path(),
+            "body,source_file\n\
+             Machine learning is a subset of artificial intelligence.,\n\
+             Deep learning uses neural networks with many layers.,handbook.pdf\n",
+        )
+        .unwrap();
 
-        // Get collection
-        let coll = rag.get_collection("test").unwrap();
-        assert_eq!(coll.name, "test");
+        let source = |content: &str| {
+            let docs = rag.list_documents(Some("test"), 10, 0).unwrap();
+            let doc = docs.into_iter().find(|d| d.content.starts_with(content)).unwrap();
+            let metadata = doc.metadata.unwrap();
+            (metadata["source_file"].clone(), metadata["source_row"].clone())
+        };
 
-        // List collections
-        let collections = rag.list_collections().unwrap();
-        assert_eq!(collections.len(), 1);
+        // metadata_columns を指定しなくても取り込み元が記録される
+        rag.import_csv(csv_path, "test", "body", None).unwrap();
+        assert_eq!(
+            source("Machine learning"),
+            (serde_json::json!(csv_path), serde_json::json!(1))
+        );
+        assert_eq!(
+            source("Deep learning"),
+            (serde_json::json!(csv_path), serde_json::json!(2))
+        );
 
-        // Delete collection
-        let deleted = rag.delete_collection("test").unwrap();
-        assert!(deleted);
+        // ユーザーが指定した同名のカラムは上書きしない
+        rag.create_collection("user", None).unwrap();
+        rag.import_csv(csv_path, "user", "body", Some(vec!["source_file".to_string()]))
+            .unwrap();
+        let docs = rag.list_documents(Some("user"), 10, 0).unwrap();
+        let deep = docs.iter().find(|d| d.content.starts_with("Deep")).unwrap();
+        let metadata = deep.metadata.as_ref().unwrap();
+        assert_eq!(metadata["source_file"], "handbook.pdf");
+        assert_eq!(metadata["source_row"], 2);
     }
 
     #[test]
-    fn test_document_operations() {
+    fn test_import_csv_with_progress() {
         let temp_file = NamedTempFile::new().unwrap();
-        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
-
+        let rag = mock_doredore(temp_file.path());
         rag.create_collection("test", None).unwrap();
 
-        // Add document
-        let id = rag.add_document("Hello, world!", "test", None).unwrap();
-        assert!(id > 0);
+        let total = IMPORT_PROGRESS_BATCH_SIZE * 2 + 1;
+        let mut csv = String::from("body\n");
+        for i in 0..total {
+            csv.push_str(&format!("Document number {}\n", i));
+        }
+        let csv_file = NamedTempFile::new().unwrap();
+        std::fs::write(csv_file.path(), csv).unwrap();
 
-        // Get document
-        let doc = rag.get_document(id).unwrap();
-        assert_eq!(doc.content, "Hello, world!");
+        let options = CsvImportOptions {
+            content_column: "body".to_string(),
+            add_options: AddOptions {
+                bulk: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut events = Vec::new();
+        let (count, column) = rag
+            .import_csv_with_progress(
+                csv_file.path().to_str().unwrap(),
+                "test",
+                &options,
+                |processed, total| events.push((processed, total)),
+            )
+            .unwrap();
 
-        // List documents
-        let docs = rag.list_documents(Some("test"), 10, 0).unwrap();
-        assert_eq!(docs.len(), 1);
+        assert_eq!(count, total);
+        assert_eq!(column, "body");
+        assert_eq!(
+            events,
+            vec![
+                (0, total),
+                (IMPORT_PROGRESS_BATCH_SIZE, total),
+                (IMPORT_PROGRESS_BATCH_SIZE * 2, total),
+                (total, total),
+            ]
+        );
+        assert_eq!(rag.list_documents(Some("test"), 1000, 0).unwrap().len(), total);
 
-        // Delete document
-        let deleted = rag.delete_document(id).unwrap();
-        assert!(deleted);
+        // bulk でもインポート後にキーワード検索できる
+        let results = rag
+            .search("number", Some("test"), None, 5, 0.0, SearchMode::Keyword, None)
+            .unwrap();
+        assert_eq!(results.len(), 5);
+
+        // 存在しないコレクションでは進捗を通知せずに失敗する
+        let mut called = false;
+        assert!(rag
+            .import_csv_with_progress(
+                csv_file.path().to_str().unwrap(),
+                "missing",
+                &options,
+                |_, _| called = true,
+            )
+            .is_err());
+        assert!(!called);
     }
 
     #[test]
-    fn test_search() {
+    fn test_import_csv_with_progress_matches_detailed() {
         let temp_file = NamedTempFile::new().unwrap();
-        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("streamed", None).unwrap();
+        rag.create_collection("loaded", None).unwrap();
 
-        rag.create_collection("test", None).unwrap();
-        rag.add_document("永代供養とは、お墓の管理を寺院に委託する供養形態です。", "test", None)
+        let mut csv = String::from("title,body,year\n");
+        for i in 0..IMPORT_PROGRESS_BATCH_SIZE + 3 {
+            csv.push_str(&format!(
+                "Q{},How long does step {} of the recipe take?,{}\n",
+                i, i, i
+            ));
+        }
+        let csv_file = NamedTempFile::new().unwrap();
+        let csv_path = csv_file.path().to_str().unwrap();
+        std::fs::write(csv_file.path(), csv).unwrap();
+        let options = CsvImportOptions {
+            metadata_columns: Some(vec!["title".to_string(), "year".to_string()]),
+            ..Default::default()
+        };
+
+        // 本文カラムの自動検出・メタデータの構築は一度に読み込む場合と同じ
+        let (count, column) = rag
+            .import_csv_with_progress(csv_path, "streamed", &options, |_, _| {})
             .unwrap();
-        rag.add_document("納骨堂には、ロッカー式、仏壇式、自動搬送式などがあります。", "test", None)
+        let expected = rag
+            .import_csv_detailed(csv_path, "loaded", &options)
+            .unwrap();
+        assert_eq!((count, column), expected);
+        assert_eq!(expected.1, "body");
+
+        let documents = |collection| {
+            let mut docs = rag.list_documents(Some(collection), 1000, 0).unwrap();
+            docs.sort_by_key(|d| d.metadata.as_ref().unwrap()["source_row"].as_u64());
+            docs.into_iter()
+                .map(|d| (d.content, d.metadata))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(documents("streamed"), documents("loaded"));
+
+        // データ行がなければ自動検出できない
+        std::fs::write(csv_file.path(), "title,body\n").unwrap();
+        let result = rag.import_csv_with_progress(
+            csv_path,
+            "streamed",
+            &CsvImportOptions::default(),
+            |_, _| {},
+        );
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_import_csv_infers_metadata_types() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("typed", None).unwrap();
+        rag.create_collection("strings", None).unwrap();
+
+        let csv_file = NamedTempFile::new().unwrap();
+        let csv_path = csv_file.path().to_str().unwrap();
+        std::fs::write(
+            csv_file.path(),
+            "body,year,rating,published,zip,title\n\
+             Boil the pasta,2023,4.5,true,007,2024 recipes\n\
+             Add salt,-12,1e3,FALSE,12345678901234567890123,\n\
+             Drain the water,-0,1.10,true,1.50,\n",
+        )
+        .unwrap();
+        let columns = ["year", "rating", "published", "zip", "title"].map(String::from);
+
+        rag.import_csv(csv_path, "typed", "body", Some(columns.to_vec()))
             .unwrap();
+        let docs = rag.list_documents(Some("typed"), 10, 0).unwrap();
+        let pasta = docs.iter().find(|d| d.content == "Boil the pasta").unwrap();
+        assert_eq!(
+            pasta.metadata.as_ref().unwrap(),
+            &serde_json::json!({
+                "year": 2023,
+                "rating": 4.5,
+                "published": true,
+                "zip": "007",
+                "title": "2024 recipes",
+                "source_file": csv_path,
+                "source_row": 1
+            })
+        );
+        let salt = docs.iter().find(|d| d.content == "Add salt").unwrap();
+        let metadata = salt.metadata.as_ref().unwrap();
+        assert_eq!(metadata["year"], -12);
+        assert_eq!(metadata["rating"], "1e3");
+        assert_eq!(metadata["published"], false);
+        assert_eq!(metadata["zip"], "12345678901234567890123");
+        assert_eq!(metadata["title"], "");
+
+        // 数値にすると表記が変わる値は文字列のまま
+        let drain = docs
+            .iter()
+            .find(|d| d.content == "Drain the water")
+            .unwrap();
+        let metadata = drain.metadata.as_ref().unwrap();
+        assert_eq!(metadata["year"], "-0");
+        assert_eq!(metadata["rating"], "1.10");
+        assert_eq!(metadata["zip"], "1.50");
 
+        // 数値として保存されたメタデータはフィルターで数値と一致する
+        let options = SearchOptions {
+            mode: SearchMode::Keyword,
+            metadata_filter: crate::MetadataFilter::from_json(&serde_json::json!({"year": 2023}))
+                .unwrap(),
+            ..Default::default()
+        };
         let results = rag
-            .search("永代供養について", Some("test"), None, 5, 0.0, SearchMode::Semantic, None)
+            .search_with_options("pasta", Some("typed"), None, 5, &options)
             .unwrap();
+        assert_eq!(results.len(), 1);
 
-        assert!(!results.is_empty());
-        assert!(results[0].score > 0.0);
+        // 無効にするとすべて文字列のまま
+        rag.import_csv_detailed(
+            csv_path,
+            "strings",
+            &CsvImportOptions {
+                content_column: "body".to_string(),
+                metadata_columns: Some(columns.to_vec()),
+                infer_types: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let docs = rag.list_documents(Some("strings"), 10, 0).unwrap();
+        let pasta = docs.iter().find(|d| d.content == "Boil the pasta").unwrap();
+        let metadata = pasta.metadata.as_ref().unwrap();
+        assert_eq!(metadata["year"], "2023");
+        assert_eq!(metadata["rating"], "4.5");
+        assert_eq!(metadata["published"], "true");
     }
 
     #[test]
-    fn test_enrich() {
+    fn test_import_jsonl_keeps_nested_metadata() {
         let temp_file = NamedTempFile::new().unwrap();
-        let rag = Doredore::new(temp_file.path(), Some("bge-small-en-v1.5"), None).unwrap();
-
+        let rag = mock_doredore(temp_file.path());
         rag.create_collection("test", None).unwrap();
-        rag.add_document("永代供養とは、お墓の管理を寺院に委託する供養形態です。", "test", None)
+
+        let jsonl_file = NamedTempFile::new().unwrap();
+        std::fs::write(
+            jsonl_file.path(),
+            "{\"text\": \"Boil the pasta\", \"meta\": {\"year\": 2023, \"tags\": [\"food\"], \
+             \"author\": {\"name\": \"Ann\", \"verified\": true}}}\n\
+             \n\
+             {\"text\": \"Add salt\", \"meta\": null}\n\
+             {\"text\": \"Drain the water\"}\n",
+        )
+        .unwrap();
+        let path = jsonl_file.path().to_str().unwrap();
+
+        let count = rag
+            .import_jsonl(path, "test", "text", Some("meta"))
             .unwrap();
+        assert_eq!(count, 3);
 
-        let result = rag
-            .enrich("永代供養について", Some("test"), None, 3, 0.0, SearchMode::Semantic, None)
+        let docs = rag.list_documents(Some("test"), 10, 0).unwrap();
+        assert_eq!(docs.len(), 3);
+        let pasta = docs.iter().find(|d| d.content == "Boil the pasta").unwrap();
+        let metadata = pasta.metadata.as_ref().unwrap();
+        assert_eq!(metadata["year"], 2023);
+        assert_eq!(metadata["tags"], serde_json::json!(["food"]));
+        assert_eq!(metadata["author"]["verified"], true);
+        assert_eq!(metadata["source_file"], path);
+        assert_eq!(metadata["source_row"], 1);
+        // 空行も行番号に数える
+        let salt = docs.iter().find(|d| d.content == "Add salt").unwrap();
+        assert_eq!(salt.metadata.as_ref().unwrap()["source_row"], 3);
+
+        // 不正な行があれば何も追加しない
+        std::fs::write(jsonl_file.path(), "{\"text\": \"Valid\"}\n{\"text\": 42}\n").unwrap();
+        let err = rag
+            .import_jsonl(path, "test", "text", Some("meta"))
+            .unwrap_err();
+        assert!(err.to_string().contains("Line 2"), "{}", err);
+        std::fs::write(jsonl_file.path(), "{\"text\": \"Valid\"}\nnot json\n").unwrap();
+        assert!(rag.import_jsonl(path, "test", "text", None).is_err());
+        assert_eq!(rag.count_documents(Some("test")).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_keyword_search_matches_indexed_metadata_fields() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let rag = mock_doredore(temp_file.path());
+        rag.create_collection("docs", None).unwrap();
+
+        let id = rag
+            .add_document(
+                "Boil the pasta in salted water for ten minutes.",
+                "docs",
+                Some(&serde_json::json!({"title": "Carbonara recipe", "tags": ["italian", "dinner"]})),
+            )
             .unwrap();
+        let search = |query: &str| {
+            rag.search(query, Some("docs"), None, 5, 0.0, SearchMode::Keyword, None)
+                .unwrap()
+                .iter()
+                .map(|r| r.document_id)
+                .collect::<Vec<_>>()
+        };
 
-        assert_eq!(result.question, "永代供養について");
-        assert!(!result.context.is_empty());
-        assert!(!result.sources.is_empty());
+        // デフォルトでは本文のみが対象
+        assert!(search("carbonara").is_empty());
+        assert!(rag.indexed_metadata_fields("docs").unwrap().is_empty());
+
+        // 既存ドキュメントも再インデックスされ、タイトル・タグのみに含まれる単語でヒットする
+        let fields = vec!["title".to_string(), "tags".to_string()];
+        rag.set_indexed_metadata_fields("docs", &fields).unwrap();
+        assert_eq!(rag.indexed_metadata_fields("docs").unwrap(), fields);
+        assert_eq!(search("carbonara"), vec![id]);
+        assert_eq!(search("italian"), vec![id]);
+
+        // 本文は元のまま返される
+        let results = rag
+            .search("carbonara", Some("docs"), None, 5, 0.0, SearchMode::Keyword, None)
+            .unwrap();
+        assert_eq!(results[0].content, "Boil the pasta in salted water for ten minutes.");
+
+        // 設定後に追加・更新したドキュメントにも反映される
+        let new_id = rag
+            .add_document(
+                "Simmer the tomatoes slowly.",
+                "docs",
+                Some(&serde_json::json!({"title": "Marinara sauce"})),
+            )
+            .unwrap();
+        assert_eq!(search("marinara"), vec![new_id]);
+        rag.update_document(new_id, None, Some(&serde_json::json!({"title": "Arrabbiata sauce"})))
+            .unwrap();
+        assert!(search("marinara").is_empty());
+        assert_eq!(search("arrabbiata"), vec![new_id]);
+
+        // バルクロード後の再構築でも設定は保たれる
+        let bulk_ids = rag
+            .add_documents_with_options(
+                vec!["Grill the fish.".to_string()],
+                "docs",
+                Some(vec![serde_json::json!({"title": "Salmon teriyaki"})]),
+                &AddOptions {
+                    bulk: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(search("teriyaki"), bulk_ids);
+        assert_eq!(search("carbonara"), vec![id]);
+
+        // 空に戻すと本文のみが対象になる
+        rag.set_indexed_metadata_fields("docs", &[]).unwrap();
+        assert!(search("carbonara").is_empty());
+        assert_eq!(search("pasta"), vec![id]);
+
+        assert!(rag.set_indexed_metadata_fields("missing", &fields).is_err());
     }
 }
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// 閾値ごとの検索精度（[`crate::Doredore::evaluate`] の結果）
+///
+/// 全クエリの件数を合算して計算する（マイクロ平均）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalPoint {
+    /// 類似度スコアの閾値
+    pub threshold: f32,
+
+    /// 適合率: 取得したドキュメントのうち正解だった割合（取得0件の場合は0.0）
+    pub precision: f32,
+
+    /// 再現率: 正解ドキュメントのうち取得できた割合（正解0件の場合は0.0）
+    pub recall: f32,
+
+    /// 適合率と再現率の調和平均
+    pub f1: f32,
+
+    /// 取得したドキュメント数（全クエリの合計）
+    pub retrieved: usize,
+
+    /// 取得したドキュメントのうち正解だった数（全クエリの合計）
+    pub relevant_retrieved: usize,
+}
+
+/// 1クエリ分の評価データ `(検索結果の (document_id, score), 正解ドキュメントID)`
+pub(crate) type EvalRun = (Vec<(i64, f32)>, HashSet<i64>);
+
+/// クエリごとの検索結果と正解IDから、閾値ごとの精度を計算
+///
+/// # 引数
+/// * `runs` - クエリごとの評価データ
+/// * `thresholds` - 評価する閾値（この順序で結果を返す）
+pub(crate) fn sweep_thresholds(runs: &[EvalRun], thresholds: &[f32]) -> Vec<EvalPoint> {
+    let relevant_total: usize = runs.iter().map(|(_, relevant)| relevant.len()).sum();
+
+    thresholds
+        .iter()
+        .map(|&threshold| {
+            let mut retrieved = 0;
+            let mut relevant_retrieved = 0;
+
+            for (results, relevant) in runs {
+                for (id, _) in results.iter().filter(|(_, score)| *score >= threshold) {
+                    retrieved += 1;
+                    if relevant.contains(id) {
+                        relevant_retrieved += 1;
+                    }
+                }
+            }
+
+            let ratio = |num: usize, den: usize| {
+                if den == 0 {
+                    0.0
+                } else {
+                    num as f32 / den as f32
+                }
+            };
+            let precision = ratio(relevant_retrieved, retrieved);
+            let recall = ratio(relevant_retrieved, relevant_total);
+            let f1 = if precision + recall == 0.0 {
+                0.0
+            } else {
+                2.0 * precision * recall / (precision + recall)
+            };
+
+            EvalPoint {
+                threshold,
+                precision,
+                recall,
+                f1,
+                retrieved,
+                relevant_retrieved,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sweep_thresholds() {
+        let runs = vec![
+            (vec![(1, 0.9), (2, 0.6), (3, 0.3)], HashSet::from([1, 3])),
+            (vec![(4, 0.8), (5, 0.4)], HashSet::from([5, 6])),
+        ];
+
+        let points = sweep_thresholds(&runs, &[0.0, 0.5, 0.95]);
+
+        // 閾値0.0: 5件取得、正解3件 / 正解は全4件
+        assert_eq!(points[0].retrieved, 5);
+        assert_eq!(points[0].relevant_retrieved, 3);
+        assert!((points[0].precision - 0.6).abs() < 1e-6);
+        assert!((points[0].recall - 0.75).abs() < 1e-6);
+        assert!((points[0].f1 - 2.0 / 3.0).abs() < 1e-6);
+
+        // 閾値0.5: 1, 2, 4 を取得、正解は1のみ
+        assert_eq!(points[1].retrieved, 3);
+        assert_eq!(points[1].relevant_retrieved, 1);
+        assert!((points[1].recall - 0.25).abs() < 1e-6);
+
+        // 何も取得しない閾値ではすべて0
+        assert_eq!(points[2].retrieved, 0);
+        assert_eq!(
+            (points[2].precision, points[2].recall, points[2].f1),
+            (0.0, 0.0, 0.0)
+        );
+    }
+}
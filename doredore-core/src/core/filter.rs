@@ -0,0 +1,322 @@
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// メタデータ条件の比較演算子
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterOp {
+    /// 等しい（`key:value`）
+    Eq,
+    /// より大きい（`key:>value`）
+    Gt,
+    /// 以上（`key:>=value`）
+    Gte,
+    /// より小さい（`key:<value`）
+    Lt,
+    /// 以下（`key:<=value`）
+    Lte,
+}
+
+impl FilterOp {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            FilterOp::Eq => "=",
+            FilterOp::Gt => ">",
+            FilterOp::Gte => ">=",
+            FilterOp::Lt => "<",
+            FilterOp::Lte => "<=",
+        }
+    }
+}
+
+/// メタデータに対する単一の条件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilterCondition {
+    /// メタデータのキー（`.` 区切りでネストしたキーを指定可能）
+    pub key: String,
+
+    /// 比較演算子
+    pub op: FilterOp,
+
+    /// 比較する値
+    /// - 数値: メタデータ側の値を数値に変換して比較（`"2021"` のような文字列も対象）
+    /// - 文字列: 文字列として比較（大小比較は辞書順）
+    /// - 真偽値: メタデータ側の真偽値と比較
+    pub value: serde_json::Value,
+}
+
+/// メタデータによる絞り込み条件（すべての条件をANDで結合）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetadataFilter {
+    #[serde(default)]
+    pub conditions: Vec<FilterCondition>,
+}
+
+impl MetadataFilter {
+    /// `{"key": value, ...}` 形式の等価条件からフィルターを作成
+    ///
+    /// `{"year": 2023, "lang": "ja"}` は `year = 2023 AND lang = "ja"` になる。
+    /// 値は文字列・数値・真偽値のみ指定できる
+    ///
+    /// # エラー
+    /// オブジェクト以外、または値がnull・配列・オブジェクトの場合は `Error::InvalidInput`
+    pub fn from_json(value: &serde_json::Value) -> Result<Self> {
+        let map = value.as_object().ok_or_else(|| {
+            Error::InvalidInput(format!("Metadata filter must be an object: {}", value))
+        })?;
+
+        let conditions = map
+            .iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(_)
+                | serde_json::Value::Number(_)
+                | serde_json::Value::Bool(_) => Ok(FilterCondition {
+                    key: key.clone(),
+                    op: FilterOp::Eq,
+                    value: value.clone(),
+                }),
+                _ => Err(Error::InvalidInput(format!(
+                    "Metadata filter value for '{}' must be a string, number or bool: {}",
+                    key, value
+                ))),
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { conditions })
+    }
+
+    /// 条件が1つもないかどうか
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    /// SQLのWHERE句に追加する条件を生成
+    ///
+    /// `documents` テーブルのエイリアスは `d` を前提とする
+    ///
+    /// # 戻り値
+    /// `(" AND ..." 形式のSQL断片, バインドするパラメータ)`
+    pub(crate) fn to_sql(&self) -> (String, Vec<Box<dyn rusqlite::ToSql>>) {
+        let mut sql = String::new();
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+        for cond in &self.conditions {
+            let path = format!("$.{}", cond.key);
+            match &cond.value {
+                serde_json::Value::Number(n) => {
+                    sql.push_str(&format!(
+                        " AND CAST(json_extract(d.metadata, ?) AS REAL) {} ?",
+                        cond.op.as_sql()
+                    ));
+                    params.push(Box::new(path));
+                    params.push(Box::new(n.as_f64().unwrap_or_default()));
+                }
+                serde_json::Value::Bool(b) => {
+                    // json_extract は真偽値を 1 / 0 で返すため、数値の 1 / 0 と区別する
+                    sql.push_str(" AND json_type(d.metadata, ?) IN ('true', 'false')");
+                    sql.push_str(&format!(
+                        " AND json_extract(d.metadata, ?) {} ?",
+                        cond.op.as_sql()
+                    ));
+                    params.push(Box::new(path.clone()));
+                    params.push(Box::new(path));
+                    params.push(Box::new(*b as i64));
+                }
+                value => {
+                    let text = match value {
+                        serde_json::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    sql.push_str(&format!(
+                        " AND json_extract(d.metadata, ?) {} ?",
+                        cond.op.as_sql()
+                    ));
+                    params.push(Box::new(path));
+                    params.push(Box::new(text));
+                }
+            }
+        }
+
+        (sql, params)
+    }
+}
+
+/// 検索ボックスの入力をメタデータ条件と検索テキストに分割
+///
+/// `key:value` 形式のトークンを条件として取り出し、残りを検索テキストとする
+///
+/// ```text
+/// category:legal year:>2020 permanent memorial
+/// → テキスト: "permanent memorial"
+///   条件: category = "legal", year > 2020
+/// ```
+///
+/// # 構文
+/// - 演算子: `key:value`（等しい）、`key:>value`、`key:>=value`、`key:<value`、`key:<=value`
+/// - 値に空白を含める場合はダブルクォートで囲む: `category:"estate planning"`
+/// - クォートされていない数値は数値として比較し、クォートした値は文字列として比較する
+/// - キーは英字で始まり、英数字・`_`・`.` のみを含む（それ以外のトークンはテキスト扱い）
+/// - ダブルクォートで囲まれたテキストはフレーズとしてそのまま検索テキストに残る
+pub fn parse_search_query(input: &str) -> (String, MetadataFilter) {
+    let mut text_tokens = Vec::new();
+    let mut filter = MetadataFilter::default();
+
+    for token in tokenize(input) {
+        match parse_filter_token(&token) {
+            Some(cond) => filter.conditions.push(cond),
+            None => text_tokens.push(token),
+        }
+    }
+
+    (text_tokens.join(" "), filter)
+}
+
+/// 空白で分割（ダブルクォート内の空白では分割しない）
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in input.chars() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+            current.push(c);
+        } else if c.is_whitespace() && !in_quotes {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(c);
+        }
+    }
+
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+/// `key:value` 形式のトークンを条件に変換（条件でなければNone）
+fn parse_filter_token(token: &str) -> Option<FilterCondition> {
+    let (key, rest) = token.split_once(':')?;
+
+    let mut key_chars = key.chars();
+    if !key_chars.next()?.is_alphabetic()
+        || !key_chars.all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+    {
+        return None;
+    }
+
+    // 2文字の演算子を先に判定する
+    let (op, raw) = if let Some(v) = rest.strip_prefix(">=") {
+        (FilterOp::Gte, v)
+    } else if let Some(v) = rest.strip_prefix("<=") {
+        (FilterOp::Lte, v)
+    } else if let Some(v) = rest.strip_prefix('>') {
+        (FilterOp::Gt, v)
+    } else if let Some(v) = rest.strip_prefix('<') {
+        (FilterOp::Lt, v)
+    } else {
+        (FilterOp::Eq, rest)
+    };
+
+    let value = if raw.len() >= 2 && raw.starts_with('"') && raw.ends_with('"') {
+        serde_json::Value::String(raw[1..raw.len() - 1].to_string())
+    } else if raw.is_empty() || raw.contains('"') {
+        return None;
+    } else if let Ok(n) = raw.parse::<i64>() {
+        serde_json::Value::from(n)
+    } else if let Some(n) = raw.parse::<f64>().ok().and_then(serde_json::Number::from_f64) {
+        serde_json::Value::Number(n)
+    } else {
+        serde_json::Value::String(raw.to_string())
+    };
+
+    Some(FilterCondition {
+        key: key.to_string(),
+        op,
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_filters_and_text() {
+        let (text, filter) = parse_search_query("category:legal year:>2020 permanent memorial");
+        assert_eq!(text, "permanent memorial");
+        assert_eq!(
+            filter.conditions,
+            vec![
+                FilterCondition {
+                    key: "category".to_string(),
+                    op: FilterOp::Eq,
+                    value: json!("legal"),
+                },
+                FilterCondition {
+                    key: "year".to_string(),
+                    op: FilterOp::Gt,
+                    value: json!(2020),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_operators() {
+        let (text, filter) = parse_search_query("a:>=1 b:<=2.5 c:<3 d:>4");
+        assert_eq!(text, "");
+        let ops: Vec<FilterOp> = filter.conditions.iter().map(|c| c.op).collect();
+        assert_eq!(ops, vec![FilterOp::Gte, FilterOp::Lte, FilterOp::Lt, FilterOp::Gt]);
+        assert_eq!(filter.conditions[1].value, json!(2.5));
+    }
+
+    #[test]
+    fn test_parse_quoted_values() {
+        let (text, filter) =
+            parse_search_query(r#"category:"estate planning" code:"2020" "permanent memorial""#);
+
+        // クォートされたフレーズは検索テキストにそのまま残る
+        assert_eq!(text, r#""permanent memorial""#);
+        assert_eq!(filter.conditions[0].value, json!("estate planning"));
+        // クォートした数値は文字列として扱う
+        assert_eq!(filter.conditions[1].value, json!("2020"));
+    }
+
+    #[test]
+    fn test_from_json() {
+        let filter =
+            MetadataFilter::from_json(&json!({"lang": "ja", "year": 2023, "draft": false}))
+                .unwrap();
+        assert_eq!(filter.conditions.len(), 3);
+        assert!(filter.conditions.iter().all(|c| c.op == FilterOp::Eq));
+        assert!(filter.conditions.contains(&FilterCondition {
+            key: "draft".to_string(),
+            op: FilterOp::Eq,
+            value: json!(false),
+        }));
+
+        assert!(MetadataFilter::from_json(&json!({})).unwrap().is_empty());
+        for invalid in [
+            json!("lang=ja"),
+            json!({"lang": null}),
+            json!({"tags": ["a"]}),
+        ] {
+            assert!(matches!(
+                MetadataFilter::from_json(&invalid),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_non_filter_tokens_stay_in_text() {
+        let (text, filter) = parse_search_query("meeting at 10:30 note: empty:");
+        assert_eq!(text, "meeting at 10:30 note: empty:");
+        assert!(filter.is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+//! 綴りの誤りを許容するキーワード照合（あいまい検索）
+//!
+//! クエリの各単語と本文の単語の編集距離を比べ、単語の長さに応じた許容範囲内であれば
+//! 一致とみなす（例: "machien" → "machine"）。単語は英数字以外の文字で区切るため、
+//! 空白で単語を区切らない日本語などには効果がない
+
+use std::collections::HashSet;
+
+/// 単語の長さ（文字数）に応じて許容する編集距離
+///
+/// 1〜2文字: 0（完全一致のみ）、3〜5文字: 1、6文字以上: 2
+pub fn max_edits(term_chars: usize) -> usize {
+    match term_chars {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    }
+}
+
+/// 2つの単語の編集距離（隣接文字の入れ替えも1回と数える制限付きダメラウ・レーベンシュタイン距離）
+///
+/// 距離が `max` を超える場合はNone
+pub fn edit_distance(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    // 長さの差だけで許容範囲を超える場合は計算しない
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+
+    // rows[i][j]: a[..i] と b[..j] の距離（直前2行分を保持して入れ替えを判定する）
+    let mut before_prev: Vec<usize> = Vec::new();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (prev[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before_prev[j - 2] + 1);
+            }
+        }
+        before_prev = std::mem::replace(&mut prev, current);
+    }
+
+    let distance = prev[b.len()];
+    (distance <= max).then_some(distance)
+}
+
+/// 英数字以外の文字で区切り、小文字化した単語の一覧
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// クエリと本文のあいまい一致の度合い（0〜1、一致する単語がなければNone）
+///
+/// クエリの各単語について、本文中で最も編集距離の小さい単語を探し、
+/// `1 - 距離 ÷ 単語の文字数` をその単語のスコアとする（許容距離を超える場合は0）。
+/// 全単語のスコアの平均を返すため、完全一致が多いほど、また誤りが少ないほど高くなる
+pub fn fuzzy_similarity(query: &str, content: &str) -> Option<f32> {
+    let mut seen = HashSet::new();
+    let terms: Vec<Vec<char>> = tokenize(query)
+        .into_iter()
+        .filter(|term| seen.insert(term.clone()))
+        .map(|term| term.chars().collect())
+        .collect();
+    if terms.is_empty() {
+        return None;
+    }
+
+    let words: Vec<Vec<char>> = tokenize(content)
+        .into_iter()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .map(|word| word.chars().collect())
+        .collect();
+
+    let mut total = 0.0;
+    let mut matched = false;
+    for term in &terms {
+        let best = words
+            .iter()
+            .filter_map(|word| edit_distance(term, word, max_edits(term.len())))
+            .min();
+        if let Some(distance) = best {
+            total += 1.0 - distance as f32 / term.len() as f32;
+            matched = true;
+        }
+    }
+
+    matched.then(|| total / terms.len() as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance(&chars("machine"), &chars("machine"), 2), Some(0));
+        // 隣接文字の入れ替えは1回
+        assert_eq!(edit_distance(&chars("machien"), &chars("machine"), 2), Some(1));
+        assert_eq!(edit_distance(&chars("lerning"), &chars("learning"), 2), Some(1));
+        assert_eq!(edit_distance(&chars("kitten"), &chars("sitting"), 3), Some(3));
+        // 許容範囲を超える場合はNone
+        assert_eq!(edit_distance(&chars("kitten"), &chars("sitting"), 2), None);
+        assert_eq!(edit_distance(&chars("cat"), &chars("category"), 2), None);
+        assert_eq!(edit_distance(&chars(""), &chars("ab"), 2), Some(2));
+    }
+
+    #[test]
+    fn test_fuzzy_similarity() {
+        let content = "Machine learning is a subset of artificial intelligence.";
+
+        assert_eq!(fuzzy_similarity("machine learning", content), Some(1.0));
+
+        let typo = fuzzy_similarity("machien lerning", content).unwrap();
+        assert!(typo > 0.8 && typo < 1.0);
+
+        // 一部の単語だけ一致
+        let partial = fuzzy_similarity("machine cooking", content).unwrap();
+        assert!(partial < typo);
+
+        // 短い単語は完全一致のみ、誤りが多すぎる単語は一致しない
+        assert_eq!(fuzzy_similarity("ai", content), None);
+        assert_eq!(fuzzy_similarity("mushroom", content), None);
+        assert_eq!(fuzzy_similarity("", content), None);
+    }
+}
@@ -0,0 +1,81 @@
+/// キーワード検索でFTS5ではなくLIKE検索を使う言語（ISO 639-3）
+///
+/// 単語を空白で区切らない言語は、FTS5のunicode61トークナイザーでは
+/// 文全体が1トークンになり単語単位でマッチしないため、部分一致で検索する
+pub const LIKE_SEARCH_LANGS: &[&str] = &["jpn", "cmn", "tha", "khm", "mya"];
+
+/// 言語コードがLIKE検索の対象かどうか
+pub fn prefers_like_search(lang: &str) -> bool {
+    LIKE_SEARCH_LANGS.contains(&lang)
+}
+
+/// メタデータの `lang` がLIKE検索対象の言語であるかを判定するSQL条件
+///
+/// `metadata` はメタデータの列（`d.metadata`、トリガー内では `NEW.metadata` など）
+pub(crate) fn like_lang_sql(metadata: &str) -> String {
+    let langs = LIKE_SEARCH_LANGS
+        .iter()
+        .map(|lang| format!("'{}'", lang))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!(
+        "COALESCE(json_extract({}, '$.lang'), '') IN ({})",
+        metadata, langs
+    )
+}
+
+/// テキストの言語を判定（ISO 639-3の言語コード）
+///
+/// 短いテキストなど判定の信頼度が低い場合はNone
+#[cfg(feature = "lang-detect")]
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let info = whatlang::detect(text)?;
+    info.is_reliable().then(|| info.lang().code())
+}
+
+/// 取り込み時にメタデータの `lang` として記録する言語コード
+///
+/// メタデータに `lang` が指定済みの場合や、`lang-detect` フィーチャーが無効な場合はNone
+#[cfg(feature = "lang-detect")]
+pub(crate) fn ingest_language(
+    content: &str,
+    metadata: Option<&serde_json::Value>,
+) -> Option<&'static str> {
+    if metadata.and_then(|m| m.get("lang")).is_some() {
+        return None;
+    }
+    detect_language(content)
+}
+
+#[cfg(not(feature = "lang-detect"))]
+pub(crate) fn ingest_language(
+    _content: &str,
+    _metadata: Option<&serde_json::Value>,
+) -> Option<&'static str> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefers_like_search() {
+        assert!(prefers_like_search("jpn"));
+        assert!(!prefers_like_search("eng"));
+    }
+
+    #[cfg(feature = "lang-detect")]
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(
+            detect_language("永代供養とは、お寺が故人の遺骨を管理し供養を続けることです。"),
+            Some("jpn")
+        );
+        assert_eq!(
+            detect_language("Permanent memorial services are offered by the temple every year."),
+            Some("eng")
+        );
+    }
+}
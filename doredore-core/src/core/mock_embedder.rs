@@ -0,0 +1,125 @@
+use crate::core::embedding::Embedder;
+use crate::error::Result;
+
+/// テスト用の決定的なEmbedder（`mock-embedder` フィーチャー）
+///
+/// モデルのダウンロード・ロードを行わず、入力テキストのハッシュから擬似乱数ベクトルを生成する。
+/// 単語（英数字以外で区切り、英字は小文字化）ごとに固定の擬似乱数ベクトルを割り当てて合計し、
+/// 正規化する。そのため同じテキストは常に同じベクトルになり、共通の単語が多いテキストほど
+/// コサイン類似度が高くなる。言い換えや類義語などの意味的な類似性は反映されない
+///
+/// ```
+/// use doredore_core::{Doredore, MockEmbedder, SearchMode};
+///
+/// let dir = tempfile::tempdir()?;
+/// let rag = Doredore::new_with_embedder(dir.path().join("test.db"), MockEmbedder::new(384))?;
+/// rag.create_collection("faq", None)?;
+/// let id = rag.add_document("Rust is a systems programming language", "faq", None)?;
+///
+/// let results = rag.search("rust language", None, None, 1, 0.0, SearchMode::Semantic, None)?;
+/// assert_eq!(results[0].document_id, id);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockEmbedder {
+    dimension: usize,
+}
+
+impl MockEmbedder {
+    /// 指定した次元数のベクトルを生成するEmbedderを作成
+    pub fn new(dimension: usize) -> Self {
+        Self { dimension }
+    }
+
+    /// 1件のテキストをベクトル化
+    fn embed_text(&self, text: &str) -> Vec<f32> {
+        let text = text.to_lowercase();
+        let mut tokens = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|token| !token.is_empty())
+            .peekable();
+
+        let mut vector = vec![0.0; self.dimension];
+        if tokens.peek().is_none() {
+            // 単語がない（空文字列・記号のみ）場合はテキスト全体から生成
+            add_token_vector(&mut vector, &text);
+        }
+        for token in tokens {
+            add_token_vector(&mut vector, token);
+        }
+
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        vector
+    }
+}
+
+impl Embedder for MockEmbedder {
+    fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|text| self.embed_text(text)).collect())
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_name(&self) -> &str {
+        "mock"
+    }
+}
+
+/// 単語のハッシュをシードにした擬似乱数ベクトル（各要素は -1.0〜1.0）を加算
+///
+/// 標準ライブラリの `DefaultHasher` はRustのバージョンで結果が変わりうるため、
+/// FNV-1a と SplitMix64 を使って環境によらず同じ値にする
+fn add_token_vector(vector: &mut [f32], token: &str) {
+    let mut state = token
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+
+    for value in vector.iter_mut() {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        // 上位53ビットを [0, 1) の値にして [-1, 1) に変換
+        *value += ((z >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0) as f32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::search::cosine_similarity;
+
+    #[test]
+    fn test_mock_embedder_is_deterministic() {
+        let embedder = MockEmbedder::new(384);
+        let texts = vec![
+            "Rust is a systems programming language".to_string(),
+            "rust, a SYSTEMS programming language!".to_string(),
+            "Boil the pasta in salted water".to_string(),
+            String::new(),
+        ];
+
+        let first = embedder.embed_batch(texts.clone()).unwrap();
+        assert_eq!(first, embedder.embed_batch(texts).unwrap());
+        assert!(first.iter().all(|v| v.len() == 384));
+
+        // 単語の重なりが多いほど類似度が高い
+        let query = embedder.embed_text("systems programming");
+        assert!(cosine_similarity(&query, &first[0]) > cosine_similarity(&query, &first[2]));
+
+        // 大文字・小文字や記号の違いは無視され、"is" の有無だけが差になる
+        assert!(cosine_similarity(&first[0], &first[1]) > 0.8);
+
+        // 空文字列でもゼロベクトルにならない
+        assert!((first[3].iter().map(|x| x * x).sum::<f32>() - 1.0).abs() < 1e-5);
+    }
+}
@@ -1,11 +1,29 @@
+pub mod ann;
+pub mod chunking;
 pub mod collection;
 pub mod database;
 pub mod embedding;
 pub mod enricher;
+pub mod evaluation;
+pub mod filter;
+pub mod fuzzy;
+pub mod language;
+#[cfg(any(test, feature = "mock-embedder"))]
+pub mod mock_embedder;
+pub mod preprocess;
 pub mod search;
+pub mod store;
 
 pub use collection::Collection;
-pub use database::Database;
-pub use embedding::EmbeddingModel;
+pub use database::{Database, EmbeddingFormat};
+pub use embedding::{Embedder, EmbeddingModel};
 pub use enricher::Doredore;
-pub use search::{SearchResult, EnrichResult};
+pub use evaluation::EvalPoint;
+pub use filter::{parse_search_query, FilterCondition, FilterOp, MetadataFilter};
+#[cfg(any(test, feature = "mock-embedder"))]
+pub use mock_embedder::MockEmbedder;
+pub use preprocess::{normalize_content, ContentType};
+pub use search::{
+    ChatMessage, SearchResult, EnrichResult, FusionMethod, KeywordNormalization, SearchOptions,
+};
+pub use store::Store;
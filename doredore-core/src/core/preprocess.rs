@@ -0,0 +1,312 @@
+use serde::{Deserialize, Serialize};
+
+/// 取り込むコンテンツの形式
+///
+/// HTMLやMarkdownのタグ・記法（`<div>`、`##` など）がEmbeddingやキーワード検索の
+/// ノイズにならないよう、保存前にプレーンテキストへ変換する
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentType {
+    /// そのまま保存（変換なし）
+    #[default]
+    Plain,
+
+    /// HTML: タグ・コメント・`<script>`/`<style>` の中身を除去し、文字参照をデコード
+    Html,
+
+    /// Markdown: 見出し・リスト・強調・リンクなどの記法を除去（インラインHTMLも除去）
+    Markdown,
+}
+
+impl ContentType {
+    /// コンテンツをプレーンテキストに変換
+    pub fn to_plain_text(&self, content: &str) -> String {
+        match self {
+            ContentType::Plain => content.to_string(),
+            // HTMLはブロック要素ごとに1行（空行は詰める）
+            ContentType::Html => normalize_whitespace(&strip_html(content), false),
+            // Markdownは段落区切りの空行を残す
+            ContentType::Markdown => {
+                normalize_whitespace(&strip_html(&markdown_to_text(content)), true)
+            }
+        }
+    }
+}
+
+/// 本文の空白を整理する
+///
+/// スクレイピングしたテキストなどの不揃いな空白を整える。
+/// 行内の連続する空白・タブを1つのスペースにまとめ、行頭・行末の空白を除去し、
+/// 連続する空行は1つにまとめる（段落区切りは残る）。先頭・末尾の空行も除去する
+pub fn normalize_content(text: &str) -> String {
+    normalize_whitespace(text, true)
+}
+
+/// 改行として扱うブロック要素
+const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "br", "dd", "div", "dl", "dt", "figcaption",
+    "figure", "footer", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li", "main", "nav",
+    "ol", "p", "pre", "section", "table", "td", "th", "tr", "ul",
+];
+
+/// 中身ごと除去する要素
+const SKIP_CONTENT_TAGS: &[&str] = &["script", "style", "noscript", "template"];
+
+/// HTMLからタグを除去してテキストのみを取り出す（ブロック要素は改行に置き換え）
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(pos) = rest.find('<') {
+        text.push_str(&decode_entities(&rest[..pos]));
+        rest = &rest[pos..];
+
+        // コメント
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+            continue;
+        }
+
+        // `<` の直後が英字・`/`・`!` 以外ならタグではない（"a < b" など）
+        let is_tag = rest[1..]
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        let Some(end) = rest.find('>').filter(|_| is_tag) else {
+            text.push('<');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        if SKIP_CONTENT_TAGS.contains(&name.as_str()) && !tag.starts_with('/') {
+            let close = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&close) {
+                Some(start) => rest[start..].find('>').map_or("", |end| &rest[start + end + 1..]),
+                None => "",
+            };
+        } else if BLOCK_TAGS.contains(&name.as_str()) {
+            text.push('\n');
+        }
+    }
+    text.push_str(&decode_entities(rest));
+
+    text
+}
+
+/// よく使われる文字参照をデコード
+fn decode_entities(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_string();
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(pos) = rest.find('&') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Markdownの記法を除去
+fn markdown_to_text(markdown: &str) -> String {
+    let mut lines = Vec::new();
+    let mut in_code_block = false;
+
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+
+        // コードブロックはフェンスを除いて中身をそのまま残す
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        // 水平線
+        let compact: String = trimmed.chars().filter(|c| !c.is_whitespace()).collect();
+        if compact.len() >= 3
+            && (compact.chars().all(|c| c == '-')
+                || compact.chars().all(|c| c == '*')
+                || compact.chars().all(|c| c == '_'))
+        {
+            lines.push(String::new());
+            continue;
+        }
+
+        // 行頭の記法（引用・見出し・リスト）
+        let mut body = trimmed;
+        while let Some(after) = body.strip_prefix('>') {
+            body = after.trim_start();
+        }
+        body = body.trim_start_matches('#').trim_start();
+        for marker in ["- ", "* ", "+ "] {
+            if let Some(after) = body.strip_prefix(marker) {
+                body = after;
+                break;
+            }
+        }
+        if let Some((num, after)) = body.split_once(". ") {
+            if !num.is_empty() && num.chars().all(|c| c.is_ascii_digit()) {
+                body = after;
+            }
+        }
+
+        lines.push(strip_inline_markdown(body));
+    }
+
+    lines.join("\n")
+}
+
+/// インラインの記法（リンク・画像・強調・コード）を除去
+fn strip_inline_markdown(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::with_capacity(line.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            // ![alt](url) / [text](url) → alt / text
+            '!' if chars.get(i + 1) == Some(&'[') => i += 1,
+            '[' => {
+                let close = chars[i..].iter().position(|&c| c == ']').map(|p| i + p);
+                match close {
+                    Some(close) if chars.get(close + 1) == Some(&'(') => {
+                        let url_end = chars[close..].iter().position(|&c| c == ')');
+                        out.extend(&chars[i + 1..close]);
+                        i = url_end.map_or(close + 1, |p| close + p + 1);
+                    }
+                    _ => {
+                        out.push('[');
+                        i += 1;
+                    }
+                }
+            }
+            // 強調・取り消し線・インラインコード
+            '*' | '`' => i += 1,
+            '_' | '~' if chars.get(i + 1) == Some(&chars[i]) => i += 2,
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    out
+}
+
+/// 行ごとに空白を整理する
+///
+/// `keep_blank_lines` がtrueの場合は連続する空行を1つにまとめ、falseの場合は空行を除去する
+fn normalize_whitespace(text: &str, keep_blank_lines: bool) -> String {
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() && (!keep_blank_lines || lines.last().is_none_or(|l| l.is_empty())) {
+            continue;
+        }
+        lines.push(line);
+    }
+
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text() {
+        let html = r#"<html><head><style>p { color: red; }</style></head>
+<body><div class="note"><h1>永代供養</h1><p>Tom &amp; Jerry &lt;3</p>
+<!-- comment --><script>alert("x")</script><p>1 < 2</p></div></body></html>"#;
+
+        assert_eq!(
+            ContentType::Html.to_plain_text(html),
+            "永代供養\nTom & Jerry <3\n1 < 2"
+        );
+    }
+
+    #[test]
+    fn test_markdown_to_text() {
+        let markdown = "## Permanent memorial\n\n\
+            - **Fee**: see [pricing](https://example.com/pricing)\n\
+            1. Call the `temple_office`\n\
+            > Quoted text\n\n\
+            ---\n\
+            ```\nlet x = 1;\n```";
+
+        assert_eq!(
+            ContentType::Markdown.to_plain_text(markdown),
+            "Permanent memorial\n\nFee: see pricing\nCall the temple_office\nQuoted text\n\nlet x = 1;"
+        );
+    }
+
+    #[test]
+    fn test_normalize_content() {
+        let text = "\n  Permanent\tmemorial  services \t\n\n\n\tFees  vary \r\n\n";
+
+        assert_eq!(
+            normalize_content(text),
+            "Permanent memorial services\n\nFees vary"
+        );
+        assert_eq!(normalize_content("already clean"), "already clean");
+    }
+
+    #[test]
+    fn test_plain_is_unchanged() {
+        let text = "<div> ## not converted";
+        assert_eq!(ContentType::Plain.to_plain_text(text), text);
+    }
+}
@@ -1,8 +1,11 @@
+use crate::core::filter::MetadataFilter;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// 検索モード
 /// RAGシステムで使用可能な3種類の検索アルゴリズムを定義
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
 pub enum SearchMode {
     /// セマンティック検索（意味ベース）
     /// - BGE/E5などの埋め込みモデルでベクトル化
@@ -10,6 +13,12 @@ pub enum SearchMode {
     /// - 言い換えや類義語にも対応可能
     Semantic,
 
+    /// 近似最近傍探索によるセマンティック検索
+    /// - スコアは `Semantic` と同じコサイン類似度
+    /// - HNSWインデックス（`Doredore::build_ann_index`）をたどるため、ドキュメント数が多くても高速
+    /// - 真の上位K件を取りこぼすことがある。インデックスのないコレクションは全件スキャン
+    SemanticAnn,
+
     /// キーワード検索（完全一致ベース）
     /// - 英語: SQLite FTS5 + BM25アルゴリズム
     /// - 日本語: LIKE検索（FTS5で結果がない場合の自動フォールバック）
@@ -17,12 +26,58 @@ pub enum SearchMode {
     Keyword,
 
     /// ハイブリッド検索（セマンティック + キーワード）
-    /// - 両方の検索結果を加重平均で統合
+    /// - 両方の検索結果を加重平均で統合（統合方法は [`SearchOptions::fusion`] で変更可能）
     /// - デフォルト重み: セマンティック 0.7、キーワード 0.3
     /// - 意味理解と正確性のバランスを取る
     Hybrid,
 }
 
+/// ハイブリッド検索でセマンティック検索とキーワード検索の結果を統合する方法
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FusionMethod {
+    /// スコアの加重平均（デフォルト）
+    /// - `w_s × semantic_score + w_k × keyword_score`
+    /// - コサイン類似度と正規化したBM25は分布が異なるため、どちらかが効きすぎることがある
+    #[default]
+    Weighted,
+
+    /// Reciprocal Rank Fusion（順位による統合）
+    /// - `w_s × rrf(semantic_rank) + w_k × rrf(keyword_rank)`（[`reciprocal_rank_score`]）
+    /// - スコアの分布に左右されず、両方の検索で上位のドキュメントが上位になる
+    Rrf,
+}
+
+/// キーワード検索のスコア（BM25と同じ向きの負の値）を0〜1に正規化する方法
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeywordNormalization {
+    /// Sigmoid（[`normalize_keyword_score`]、以前のバージョンのデフォルト）
+    /// - スコアは結果の件数によらず同じBM25なら同じ値になる
+    /// - 典型的なBM25の範囲では0.5付近に集まり、近いスコアの差がほとんど残らない
+    Sigmoid,
+
+    /// 返す結果の中での最小値・最大値によるスケーリング（デフォルト、[`min_max_keyword_scores`]）
+    /// - 最も関連の高い結果が1.0、最も低い結果が0.0になり、BM25の差の比率が保たれる
+    /// - 結果が1件のみ・全件同じスコアの場合はすべて1.0
+    /// - スコアは同じ検索で返した結果の間でのみ比較できる（閾値には向かない）
+    #[default]
+    MinMax,
+}
+
+/// キーワード検索で空白区切りの複数の語をどう組み合わせるか
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeywordMatch {
+    /// すべての語を含むドキュメントにマッチ（デフォルト、AND）
+    #[default]
+    All,
+
+    /// いずれかの語を含むドキュメントにマッチ（OR）
+    /// - 含む語が多いほど（出現回数が多いほど）スコアが高くなる
+    Any,
+}
+
 impl Default for SearchMode {
     /// デフォルトはセマンティック検索
     /// 多くのRAGユースケースで最も汎用性が高い
@@ -31,6 +86,370 @@ impl Default for SearchMode {
     }
 }
 
+/// 検索の追加オプション
+///
+/// `search` / `enrich` の検索モード・閾値・ハイブリッド検索の重みと、絞り込み条件などを
+/// まとめたもの（[`crate::Doredore::search_with_options`] を参照）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchOptions {
+    /// 検索モード（デフォルト: Semantic）
+    #[serde(default)]
+    pub mode: SearchMode,
+
+    /// セマンティック検索の最小スコア閾値（デフォルト: 0.0）
+    /// - Hybridモードでは統合後のスコアに適用される。Keywordモードでは使用しない
+    #[serde(default)]
+    pub threshold: f32,
+
+    /// ハイブリッド検索の重み `(semantic_weight, keyword_weight)`（デフォルト: None = (0.7, 0.3)）
+    /// - Hybridモードでのみ使用
+    #[serde(default)]
+    pub hybrid_weights: Option<(f32, f32)>,
+
+    /// 検索候補から除外するドキュメントID
+    /// - 会話型RAGで既に提示したドキュメントを除外し、新しいコンテキストを得る用途など
+    /// - セマンティック検索: スコア計算の対象外
+    /// - キーワード検索: SQLの `NOT IN` で除外
+    #[serde(default)]
+    pub exclude_ids: Vec<i64>,
+
+    /// メタデータによる絞り込み条件（空の場合は絞り込まない）
+    /// - 条件に一致しないドキュメントは検索候補から除外される
+    /// - 検索ボックスの入力から生成する場合は [`crate::parse_search_query`] を使用
+    #[serde(default)]
+    pub metadata_filter: MetadataFilter,
+
+    /// 存在しないコレクション名を無視するかどうか（デフォルト: false = エラー）
+    /// - 動的なコレクション一覧で検索する場合に、削除済みのコレクションがあっても
+    ///   残りのコレクションで検索を続ける用途
+    /// - スキップされた名前は [`crate::Doredore::find_missing_collections`] で取得できる
+    #[serde(default)]
+    pub skip_missing: bool,
+
+    /// 検索結果にドキュメントのEmbeddingベクトルを含めるかどうか（デフォルト: false）
+    /// - クライアント側でベクトルをキャッシュ・可視化する用途
+    /// - 1件あたり次元数分（384〜1024個）の数値が増えるため、必要な場合のみ指定する
+    #[serde(default)]
+    pub include_embedding: bool,
+
+    /// ハイブリッド検索で各検索から取得する候補数の倍率（デフォルト: 2）
+    /// - セマンティック検索・キーワード検索からそれぞれ `top_k × candidate_multiplier` 件を
+    ///   取得し、加重平均で再ランキングして上位 `top_k` 件を返す
+    /// - 両方の検索でそこそこ上位（どちらでも候補数の外）のドキュメントは、統合後なら
+    ///   上位に入る場合でも候補に含まれず結果から漏れる。倍率を上げると再現率は上がるが、
+    ///   統合・ソートする件数が増える（セマンティック検索の全件スキャンのコストは変わらない）
+    /// - 0は1として扱う。Semantic / Keywordモードでは使用しない
+    ///   （Semanticモードでも `mmr_lambda` を指定した場合は再ランキングの候補数に使う）
+    #[serde(default = "default_candidate_multiplier")]
+    pub candidate_multiplier: usize,
+
+    /// 検索結果の本文の空白を整理するかどうか（デフォルト: false = 保存された内容そのまま）
+    /// - 連続する空白・タブをまとめ、前後の空白と余分な空行を除去する
+    ///   （[`crate::normalize_content`] を参照）
+    /// - `enrich` のコンテキストにも反映されるため、LLMに渡すトークン数を減らせる
+    /// - 保存されている内容は変更しない。取り込み時に整理する場合は、追加前に
+    ///   `normalize_content` を適用する
+    #[serde(default)]
+    pub normalize_output: bool,
+
+    /// `enrich` のコンテキストの先頭に追加する文字列（指示文など）
+    /// - 例: "Answer using only the sources below. Cite [Source N]."
+    /// - ソースとは空行で区切られる。`sources` には影響しない
+    #[serde(default)]
+    pub context_prefix: Option<String>,
+
+    /// `enrich` のコンテキストの末尾に追加する文字列（ソースとは空行で区切られる）
+    #[serde(default)]
+    pub context_suffix: Option<String>,
+
+    /// `enrich` のコンテキストの各ソースの書式（デフォルト: None = `[Source N] (Score: ..., Collection: ...)`
+    /// の見出しと本文）
+    /// - `{index}` `{id}` `{score}` `{collection}` `{description}` `{metadata}` `{content}` を
+    ///   埋め込める（[`render_context_template`] を参照）
+    /// - 例: Markdownの脚注形式 `"{content} [^{index}]"`
+    #[serde(default)]
+    pub context_template: Option<String>,
+
+    /// `enrich` のコンテキストのソース間の区切り（デフォルト: None = 空行 `"\n\n"`）
+    #[serde(default)]
+    pub context_separator: Option<String>,
+
+    /// `enrich` のコンテキストの最大文字数（デフォルト: None = 無制限）
+    /// - スコアの高いソースから順に、収まる範囲でコンテキストに含める。収まらないソースは
+    ///   単語の区切りで切り詰め（末尾に `…`）、それ以降のソースは `context` と `sources` の
+    ///   両方から除外する
+    /// - `context_prefix` / `context_suffix` の文字数も含めて数える（前後の文字列自体は切り詰めない）
+    /// - 文字数は `char` 単位で数える。トークン数の目安は英語で約4文字、日本語で約1〜2文字
+    #[serde(default)]
+    pub max_context_chars: Option<usize>,
+
+    /// `enrich` の各ソースの見出しにコレクションの説明を含めるかどうか（デフォルト: false）
+    /// - 複数コレクションをまたいで検索する場合に、各ソースの出典の性質をLLMに伝える用途
+    /// - 説明が未設定のコレクションは見出しが変わらない
+    #[serde(default)]
+    pub include_collection_description: bool,
+
+    /// キーワード検索で綴りの誤りを許容するかどうか（デフォルト: false）
+    /// - "machien lerning" のようなクエリでも "machine learning" を含むドキュメントが見つかる
+    /// - 単語の長さに応じて1〜2文字の誤り（挿入・削除・置換・隣接文字の入れ替え）まで許容し、
+    ///   誤りが少ないほど高いスコアになる（[`crate::core::fuzzy`] を参照）
+    /// - 通常のキーワード検索の結果の後に、綴りの近い単語を含むドキュメントを追加する
+    ///   （綴りの近いヒットが通常のヒットより上位になることはない）
+    /// - 全ドキュメントの本文を走査するため、ドキュメント数が多いと遅くなる
+    /// - 単語を空白などで区切る言語向け。日本語などでは通常の結果と変わらない
+    /// - Keyword / Hybridモードで使用（Semanticモードでは使用しない）
+    #[serde(default)]
+    pub fuzzy: bool,
+
+    /// 本文が空（空白のみを含む）のドキュメントを検索候補から除外するかどうか（デフォルト: false）
+    /// - CSVの空のセルなどから作られたドキュメントは、空文字列のEmbeddingが短いクエリと
+    ///   予想外に高い類似度になり、上位に混ざることがある
+    /// - 除外ではなく削除する場合は [`crate::Doredore::find_empty_documents`] で探す
+    #[serde(default)]
+    pub exclude_empty: bool,
+
+    /// 閾値で絞り込んだ結果がこの件数に満たない場合に補う最低件数（デフォルト: 0 = 補わない）
+    /// - 閾値を無視して検索し直し、まだ含まれていない候補のうちスコアの高いものから
+    ///   `min(min_results, top_k)` 件になるまで追加する（候補が足りなければそれ以下）
+    /// - 追加した結果は [`SearchResult::backfilled`] がtrueになる
+    /// - 除外ID・メタデータ条件・`exclude_empty` などの絞り込みは補う結果にも適用される
+    /// - Semantic / Hybridモードで使用（閾値を使わないKeywordモードでは使用しない）
+    #[serde(default)]
+    pub min_results: usize,
+
+    /// キーワード検索のスコアを0〜1に正規化するSigmoidのスケーリング係数（デフォルト: 10.0）
+    /// - `keyword_normalization` が [`KeywordNormalization::Sigmoid`] の場合のみ使用
+    /// - `score = 1 / (1 + e^(bm25 / scale))`（BM25は負の値で、小さいほど高関連）
+    /// - 係数が大きいほどスコアが0.5付近に集まり、小さいほど1.0側に広がる。
+    ///   典型的なBM25（-1〜-10程度）ではデフォルトだと0.52〜0.73に収まるため、
+    ///   ハイブリッド検索ではキーワード検索の順位がほとんど効かない。
+    ///   2〜5程度にするとキーワードの一致度の差がハイブリッドスコアに反映されやすくなる
+    ///   （`hybrid_weights` のキーワード側の重みを上げるのと併せて調整する）
+    /// - 0以下は無視してデフォルト値を使う。Keyword / Hybridモードで使用
+    #[serde(default = "default_keyword_score_scale")]
+    pub keyword_score_scale: f32,
+
+    /// キーワード検索のスコアの正規化方法（デフォルト: MinMax）
+    /// - [`KeywordNormalization::MinMax`] では結果の中でBM25を0〜1にスケーリングし、
+    ///   近いスコアの差も順位どおりに広がる（`keyword_score_scale` は使用しない）
+    /// - 以前のバージョンと同じスコアが必要な場合は [`KeywordNormalization::Sigmoid`] を指定する
+    /// - Keyword / Hybridモードで使用（Hybridモードではキーワード検索の候補の中でスケーリングする）
+    #[serde(default)]
+    pub keyword_normalization: KeywordNormalization,
+
+    /// キーワード検索で複数の語をAND / ORのどちらで組み合わせるか（デフォルト: AND）
+    /// - クエリは空白で語に分割する。`"永代供養 費用"` のようにダブルクォートで囲んだ部分は
+    ///   分割せず、そのままの並びで照合する（フレーズ検索）
+    /// - Keyword / Hybridモードで使用
+    #[serde(default)]
+    pub keyword_match: KeywordMatch,
+
+    /// ハイブリッド検索の結果の統合方法（デフォルト: 加重平均）
+    /// - [`FusionMethod::Rrf`] ではスコアではなく各検索での順位から統合する
+    /// - Hybridモードでのみ使用
+    #[serde(default)]
+    pub fusion: FusionMethod,
+
+    /// セマンティック検索の結果をMMR（Maximal Marginal Relevance）で再ランキングする際の
+    /// 関連度と多様性のバランス λ（デフォルト: None = 再ランキングしない）
+    /// - ほぼ同じ内容のドキュメントが上位を占めて `enrich` のコンテキストが重複する場合に使う
+    /// - `top_k × candidate_multiplier` 件の候補から、`λ × クエリとの類似度 −
+    ///   (1 − λ) × 選択済みの結果との最大類似度` が最大のものを順に選ぶ
+    /// - 1.0ではスコア順と同じ、0.0に近いほど多様性を優先する（0.0〜1.0の範囲に丸める）
+    /// - 候補のEmbeddingは検索時に取得したものを使うため、追加のクエリは発生しない
+    /// - 結果のスコアはクエリとの類似度のままで、選ばれた順に並ぶ（スコア順とは限らない）
+    /// - Semanticモードで使用（Hybridモードではセマンティック検索側の候補に適用される）
+    #[serde(default)]
+    pub mmr_lambda: Option<f32>,
+
+    /// チャンク分割したドキュメントの結果を親ドキュメントごとに1件にまとめるかどうか（デフォルト: false）
+    /// - [`crate::Doredore::add_document_chunked`] で追加したドキュメントは、親ドキュメントが
+    ///   同じチャンクのうち最もスコアの高い1件だけを返す
+    ///   （チャンクでないドキュメントはそれぞれ1件として扱う）
+    /// - 同じ親のチャンクで上位が埋まる場合は多めに検索し直し、できるだけ `top_k` 件を返す
+    #[serde(default)]
+    pub collapse_chunks: bool,
+
+    /// コレクションごとのスコアの重み（デフォルト: 空 = すべて1.0）
+    /// - 複数コレクションをまたいで検索する場合に、信頼できるコレクションの結果を
+    ///   ノイズの多いコレクションより上位にする用途（例: `{"manual": 2.0, "forum": 0.5}`）
+    /// - 各結果のスコアにコレクションの重みを掛けてから並べ替え、上位 `top_k` 件を選ぶ
+    ///   （[`SearchOptions::collection_weight`] を参照）。指定のないコレクションは1.0、負の値は0.0として扱う
+    /// - 無限大・NaNの重みは `Error::InvalidInput`
+    /// - セマンティック検索の閾値は重みを掛ける前の類似度で判定する
+    /// - キーワード検索では全候補のスコアを正規化してから重みを掛ける
+    ///   （MinMaxのスケーリングも `top_k` 件ではなく全候補の範囲になる）
+    /// - ハイブリッド検索では両方の検索のスコアに掛かる（RRFでは各検索の順位に反映される）
+    #[serde(default)]
+    pub collection_weights: HashMap<String, f32>,
+}
+
+impl SearchOptions {
+    /// コレクションのスコアの重み（[`SearchOptions::collection_weights`]、指定がなければ1.0）
+    pub fn collection_weight(&self, collection: &str) -> f32 {
+        if self.collection_weights.is_empty() {
+            return 1.0;
+        }
+        self.collection_weights
+            .get(collection)
+            .map_or(1.0, |&weight| weight.max(0.0))
+    }
+}
+
+fn default_candidate_multiplier() -> usize {
+    2
+}
+
+fn default_keyword_score_scale() -> f32 {
+    10.0
+}
+
+/// キーワード検索のスコア（BM25と同じ向きの負の値）をSigmoidで0〜1に正規化
+///
+/// 式: `σ(-x / scale) = 1 / (1 + e^(x / scale))`。高関連（xが小さい）ほど1.0に近づき、
+/// 負のスコアは0.5〜1.0になる。`scale` が0以下の場合はデフォルトの10.0を使う
+pub fn normalize_keyword_score(score: f32, scale: f32) -> f32 {
+    let scale = if scale > 0.0 {
+        scale
+    } else {
+        default_keyword_score_scale()
+    };
+    1.0 / (1.0 + (score / scale).exp())
+}
+
+/// キーワード検索のスコア（BM25と同じ向きの負の値）を、与えた結果の中で0〜1にスケーリング
+///
+/// 式: `(max - x) / (max - min)`。最小（最も高関連）のスコアが1.0、最大が0.0になる。
+/// 最小値と最大値が同じ場合（1件のみなど）はすべて1.0
+pub fn min_max_keyword_scores(scores: &[f32]) -> Vec<f32> {
+    let min = scores.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let range = max - min;
+    scores
+        .iter()
+        .map(|&score| if range > 0.0 { (max - score) / range } else { 1.0 })
+        .collect()
+}
+
+/// コンテキストのテンプレートに1件のソースを埋め込む
+///
+/// # プレースホルダー
+/// - `{index}`: 1始まりの番号
+/// - `{id}`: ドキュメントID
+/// - `{score}`: スコア（小数点以下3桁）
+/// - `{collection}`: コレクション名
+/// - `{description}`: コレクションの説明（`include_collection_description` 指定時のみ、ない場合は空）
+/// - `{metadata}`: メタデータのJSON（ない場合は空）
+/// - `{content}`: 本文
+///
+/// 置き換えは1回のみ行うため、本文などに `{score}` のような文字列が含まれていても
+/// 置き換えられない。未知の `{...}` はそのまま残す
+pub fn render_context_template(
+    template: &str,
+    index: usize,
+    source: &SearchResult,
+    description: Option<&String>,
+    content: &str,
+) -> String {
+    let mut rendered = String::with_capacity(template.len() + content.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let Some(end) = after.find('}') else {
+            // 閉じていない `{` 以降はそのまま出力する
+            rest = after;
+            break;
+        };
+        let value = match &after[1..end] {
+            "index" => index.to_string(),
+            "id" => source.document_id.to_string(),
+            "score" => format!("{:.3}", source.score),
+            "collection" => source.collection_name.clone(),
+            "description" => description.cloned().unwrap_or_default(),
+            "metadata" => source
+                .metadata
+                .as_ref()
+                .map(|m| m.to_string())
+                .unwrap_or_default(),
+            "content" => content.to_string(),
+            _ => {
+                // 未知のプレースホルダーは `{` だけ出力し、続きから探し直す
+                rendered.push('{');
+                rest = &after[1..];
+                continue;
+            }
+        };
+        rendered.push_str(&value);
+        rest = &after[end + 1..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// 本文を `max_chars` 文字以下に切り詰める（末尾の `…` を含む）
+///
+/// 空白で区切られた単語の途中で切らないよう、最後の空白までで切る（空白がない場合は文字単位）。
+/// 切り詰めると何も残らない場合はNone
+fn truncate_at_word_boundary(content: &str, max_chars: usize) -> Option<String> {
+    // `…` の1文字分を除いた文字数
+    let limit = max_chars.checked_sub(1)?;
+    let head: String = content.chars().take(limit).collect();
+    let next_is_space = content.chars().nth(limit).is_some_and(char::is_whitespace);
+    let cut = if next_is_space {
+        head.as_str()
+    } else {
+        head.rfind(char::is_whitespace)
+            .map_or(head.as_str(), |end| &head[..end])
+    };
+    let cut = cut.trim_end();
+    (!cut.is_empty()).then(|| format!("{}…", cut))
+}
+
+/// Reciprocal Rank Fusion の定数 k（一般的な値の60）
+pub const RRF_K: f32 = 60.0;
+
+/// 順位（0始まり）に対する Reciprocal Rank Fusion のスコア
+///
+/// 式: `(k + 1) / (k + rank + 1)`（k = [`RRF_K`]）。一般的な `1 / (k + rank)` を
+/// 1位が1.0になるように定数倍したもので、順位の比較結果は変わらない
+pub fn reciprocal_rank_score(rank: usize) -> f32 {
+    (RRF_K + 1.0) / (RRF_K + rank as f32 + 1.0)
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            mode: SearchMode::default(),
+            threshold: 0.0,
+            hybrid_weights: None,
+            exclude_ids: Vec::new(),
+            metadata_filter: MetadataFilter::default(),
+            skip_missing: false,
+            include_embedding: false,
+            candidate_multiplier: default_candidate_multiplier(),
+            normalize_output: false,
+            context_prefix: None,
+            context_suffix: None,
+            context_template: None,
+            context_separator: None,
+            max_context_chars: None,
+            include_collection_description: false,
+            fuzzy: false,
+            exclude_empty: false,
+            min_results: 0,
+            keyword_score_scale: default_keyword_score_scale(),
+            keyword_normalization: KeywordNormalization::default(),
+            keyword_match: KeywordMatch::default(),
+            fusion: FusionMethod::default(),
+            mmr_lambda: None,
+            collapse_chunks: false,
+            collection_weights: HashMap::new(),
+        }
+    }
+}
+
 /// 検索結果の単一アイテム
 /// 各ドキュメントの検索スコアとメタデータを含む
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,6 +471,60 @@ pub struct SearchResult {
 
     /// このドキュメントが属するコレクション名
     pub collection_name: String,
+
+    /// 検索結果内の順位（1始まり、結果の並び順と一致）
+    /// - 検索メソッドが返す結果にのみ設定され、[`SearchResult::new`] で作成した場合は0
+    #[serde(default)]
+    pub rank: usize,
+
+    /// 閾値に届かなかったが [`SearchOptions::min_results`] を満たすために追加された結果かどうか
+    #[serde(default)]
+    pub backfilled: bool,
+
+    /// ドキュメントのEmbeddingベクトル
+    /// - [`SearchOptions::include_embedding`] を指定した場合のみ設定される
+    /// - 未設定の場合はシリアライズ時にフィールドごと省略される
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+}
+
+/// キーワード検索で実際に使われた照合方法（[`SearchDiagnostics::keyword_backend`]）
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeywordBackend {
+    /// FTS5（BM25）のみ
+    Fts5,
+    /// LIKE検索のみ（FTS5で見つからなかった場合のフォールバック、またはFTS5が使えない環境）
+    Like,
+    /// FTS5の結果と、LIKE検索向けの言語（日本語など）のドキュメントのLIKE検索の結果を統合
+    Fts5AndLike,
+}
+
+/// 検索の各段階の所要時間と候補数（[`crate::Doredore::search_with_diagnostics`]）
+///
+/// 閾値や重みの調整のためのもの。件数は重複除去・統合（ハイブリッド検索）の前の値。
+/// `min_results` による補完やチャンクの集約で検索を繰り返した場合、所要時間は合計、
+/// 候補数とキーワード検索の照合方法は最後に実行した検索の値
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SearchDiagnostics {
+    /// 検索モード
+    pub mode: SearchMode,
+    /// クエリのEmbedding生成にかかった時間（ミリ秒）
+    pub embedding_ms: f64,
+    /// セマンティック検索のスコア計算にかかった時間（ミリ秒、Embedding生成を除く）
+    pub semantic_ms: f64,
+    /// キーワード検索にかかった時間（ミリ秒）
+    pub keyword_ms: f64,
+    /// 検索全体にかかった時間（ミリ秒）
+    pub total_ms: f64,
+    /// セマンティック検索の候補数（実行しなかった場合はNone）
+    pub semantic_candidates: Option<usize>,
+    /// キーワード検索の候補数（実行しなかった場合はNone）
+    pub keyword_candidates: Option<usize>,
+    /// キーワード検索の照合方法（実行しなかった場合や、ストアが報告しない場合はNone）
+    pub keyword_backend: Option<KeywordBackend>,
+    /// ハイブリッド検索の統合方法（ハイブリッド検索以外はNone）
+    pub fusion: Option<FusionMethod>,
 }
 
 /// RAGエンリッチメント結果
@@ -69,6 +542,44 @@ pub struct EnrichResult {
     pub sources: Vec<SearchResult>,
 }
 
+/// チャット形式のAPIに渡すメッセージ（`{"role": "...", "content": "..."}`）
+///
+/// OpenAIなどのChat Completions APIの `messages` 配列の要素と同じ形にシリアライズされる
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// `"system"` または `"user"`
+    pub role: String,
+
+    /// メッセージの本文
+    pub content: String,
+}
+
+impl ChatMessage {
+    /// `system` ロールのメッセージを作成
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: "system".to_string(),
+            content: content.into(),
+        }
+    }
+
+    /// `user` ロールのメッセージを作成
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+/// `to_chat_messages` でシステムプロンプトを省略した場合の前置き
+const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful assistant.";
+
+/// システムメッセージに含める、ソースの使い方と引用の指示
+const CITATION_INSTRUCTIONS: &str = "Answer the user's question using only the sources below. \
+     Cite the sources you use as [Source N]. \
+     If the sources do not contain the answer, say that you don't know.";
+
 impl SearchResult {
     /// 新しい検索結果を作成
     ///
@@ -91,6 +602,9 @@ impl SearchResult {
             score,
             metadata,
             collection_name,
+            rank: 0,
+            backfilled: false,
+            embedding: None,
         }
     }
 }
@@ -113,28 +627,167 @@ impl EnrichResult {
     /// ドキュメントの内容...
     /// ```
     pub fn new(question: String, sources: Vec<SearchResult>) -> Self {
-        // 各ソースをLLM向けに整形
-        let context = sources
-            .iter()
-            .enumerate()
-            .map(|(i, result)| {
-                format!(
-                    "[Source {}] (Score: {:.3}, Collection: {})\n{}",
+        Self::with_collection_descriptions(question, sources, &HashMap::new())
+    }
+
+    /// 各ソースの見出しにコレクションの説明を含めてエンリッチメント結果を作成
+    ///
+    /// `descriptions` はコレクション名から説明への対応。含まれないコレクションのソースは
+    /// [`EnrichResult::new`] と同じ見出しになる
+    ///
+    /// ```text
+    /// [Source 1] (Score: 0.876, Collection: docs, Description: 製品マニュアル)
+    /// ドキュメントの内容...
+    /// ```
+    pub fn with_collection_descriptions(
+        question: String,
+        sources: Vec<SearchResult>,
+        descriptions: &HashMap<String, String>,
+    ) -> Self {
+        Self::with_context_limit(question, sources, descriptions, None)
+    }
+
+    /// コンテキストの文字数を `max_chars` 以下に抑えてエンリッチメント結果を作成
+    ///
+    /// ソースを順に（スコアの高い順に）コンテキストに追加し、収まらないソースは本文を
+    /// 単語の区切りで切り詰めて末尾に `…` を付ける（見出しと本文の一部も入らない場合は含めない）。
+    /// それ以降のソースは `context` にも `sources` にも含めない。`sources` に残したソースの
+    /// 本文は切り詰めない。`max_chars` がNoneの場合は [`EnrichResult::with_collection_descriptions`] と同じ
+    pub fn with_context_limit(
+        question: String,
+        sources: Vec<SearchResult>,
+        descriptions: &HashMap<String, String>,
+        max_chars: Option<usize>,
+    ) -> Self {
+        Self::with_template(question, sources, descriptions, max_chars, None, None)
+    }
+
+    /// 各ソースの書式とソース間の区切りを指定してエンリッチメント結果を作成
+    ///
+    /// `template` のプレースホルダーは [`render_context_template`] を参照。Noneの場合は
+    /// `[Source N] (Score: ..., Collection: ...)` の見出しと本文（デフォルトの書式）。
+    /// `separator` のデフォルトは空行（`"\n\n"`）。`max_chars` は [`EnrichResult::with_context_limit`]
+    /// と同じで、テンプレートで追加される文字と区切りも含めて数える
+    ///
+    /// ```
+    /// use doredore_core::{EnrichResult, SearchResult};
+    /// use std::collections::HashMap;
+    ///
+    /// let source =
+    ///     SearchResult::new(7, "Boil the pasta".to_string(), 0.8, None, "faq".to_string());
+    /// let result = EnrichResult::with_template(
+    ///     "How to cook pasta?".to_string(),
+    ///     vec![source],
+    ///     &HashMap::new(),
+    ///     None,
+    ///     Some("> {content} [^{index}]"),
+    ///     None,
+    /// );
+    /// assert_eq!(result.context, "> Boil the pasta [^1]");
+    /// ```
+    pub fn with_template(
+        question: String,
+        sources: Vec<SearchResult>,
+        descriptions: &HashMap<String, String>,
+        max_chars: Option<usize>,
+        template: Option<&str>,
+        separator: Option<&str>,
+    ) -> Self {
+        let separator = separator.unwrap_or("\n\n");
+        let mut blocks: Vec<String> = Vec::new();
+        let mut kept = Vec::new();
+        let mut used = 0;
+
+        for (i, result) in sources.into_iter().enumerate() {
+            let description = descriptions.get(&result.collection_name);
+            let render = |content: &str| match template {
+                Some(template) => {
+                    render_context_template(template, i + 1, &result, description, content)
+                }
+                None => format!(
+                    "[Source {}] (Score: {:.3}, Collection: {}{})\n{}",
                     i + 1,
                     result.score,
                     result.collection_name,
-                    result.content
-                )
-            })
-            .collect::<Vec<_>>()
-            .join("\n\n"); // ソース間を空行で区切る
+                    description
+                        .map(|d| format!(", Description: {}", d))
+                        .unwrap_or_default(),
+                    content
+                ),
+            };
+            let separator_chars = if blocks.is_empty() {
+                0
+            } else {
+                separator.chars().count()
+            };
+
+            let block = render(&result.content);
+            let block_chars = block.chars().count();
+            match max_chars {
+                Some(max) if used + separator_chars + block_chars > max => {
+                    // 本文以外（見出しなど）の文字数を除いた残りに本文を切り詰める
+                    let overhead = separator_chars + render("").chars().count();
+                    let available = max.saturating_sub(used + overhead);
+                    let block = truncate_at_word_boundary(&result.content, available)
+                        .map(|content| render(&content))
+                        // 本文を複数回埋め込むテンプレートでは切り詰めても収まらないことがある
+                        .filter(|block| used + separator_chars + block.chars().count() <= max);
+                    if let Some(block) = block {
+                        blocks.push(block);
+                        kept.push(result);
+                    }
+                    break;
+                }
+                _ => {
+                    used += separator_chars + block_chars;
+                    blocks.push(block);
+                    kept.push(result);
+                }
+            }
+        }
 
         Self {
             question,
-            context,
-            sources,
+            context: blocks.join(separator),
+            sources: kept,
         }
     }
+
+    /// コンテキストの前後に文字列（LLMへの指示文など）を追加
+    ///
+    /// ソースのブロックとは空行で区切る。ソースが0件の場合は前後の文字列のみになる
+    pub fn wrap_context(&mut self, prefix: Option<&str>, suffix: Option<&str>) {
+        let parts: Vec<&str> = [prefix, Some(self.context.as_str()), suffix]
+            .into_iter()
+            .flatten()
+            .filter(|part| !part.is_empty())
+            .collect();
+
+        self.context = parts.join("\n\n");
+    }
+
+    /// チャット形式のAPIにそのまま渡せるメッセージ列に変換
+    ///
+    /// 1. `system`: システムプロンプト、引用の指示（`[Source N]`）、コンテキスト
+    /// 2. `user`: 元の質問文
+    ///
+    /// `system_prompt` を省略した場合は汎用の前置きを使う。
+    /// ソースが0件の場合は、コンテキストの代わりにソースがないことを伝える
+    pub fn to_chat_messages(&self, system_prompt: Option<&str>) -> Vec<ChatMessage> {
+        let context = if self.context.is_empty() {
+            "(No sources were found.)"
+        } else {
+            self.context.as_str()
+        };
+        let system = format!(
+            "{}\n\n{}\n\n{}",
+            system_prompt.unwrap_or(DEFAULT_SYSTEM_PROMPT),
+            CITATION_INSTRUCTIONS,
+            context
+        );
+
+        vec![ChatMessage::system(system), ChatMessage::user(self.question.clone())]
+    }
 }
 
 /// コサイン類似度の計算
@@ -171,8 +824,8 @@ pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
 
     // 各ベクトルのノルム（長さ）を計算
     // ||A|| = sqrt(a1² + a2² + ... + an²)
-    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_a = vector_norm(a);
+    let norm_b = vector_norm(b);
 
     // ゼロベクトルの場合は類似度を計算できない
     if norm_a == 0.0 || norm_b == 0.0 {
@@ -183,6 +836,152 @@ pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     dot_product / (norm_a * norm_b)
 }
 
+/// ベクトルのノルム（長さ）`||A|| = sqrt(a1² + a2² + ... + an²)`
+///
+/// [`cosine_similarity`] と同じ順序で計算するため、保存したノルムを使っても結果は変わらない
+pub fn vector_norm(v: &[f32]) -> f32 {
+    v.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+/// ノルムを1にしたベクトル（ゼロベクトルはそのまま）
+///
+/// 正規化したベクトル同士では内積（[`dot`]）がそのままコサイン類似度になる
+pub fn normalized(vector: &[f32]) -> Vec<f32> {
+    let norm = vector_norm(vector);
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+    vector.iter().map(|x| x / norm).collect()
+}
+
+/// 内積（8つの部分和に分けて計算し、コンパイラがSIMD命令を使えるようにする）
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let (chunks_a, chunks_b) = (a.chunks_exact(8), b.chunks_exact(8));
+    let tail: f32 = chunks_a
+        .remainder()
+        .iter()
+        .zip(chunks_b.remainder())
+        .map(|(x, y)| x * y)
+        .sum();
+
+    let mut sums = [0.0f32; 8];
+    for (x, y) in chunks_a.zip(chunks_b) {
+        for ((sum, x), y) in sums.iter_mut().zip(x).zip(y) {
+            *sum += x * y;
+        }
+    }
+    sums.iter().sum::<f32>() + tail
+}
+
+/// 複数のベクトルの全ペアのコサイン類似度（対称行列）
+///
+/// `norms[i]` は `vectors[i]` のノルム（[`vector_norm`]）。ノルムを使い回し、対称性から
+/// 上三角のみ内積を計算する。次元数が異なるペアやゼロベクトルとの類似度は
+/// [`cosine_similarity`] と同じく0.0
+///
+/// # 計算量
+/// O(n² × d)（nはベクトル数、dは次元数）。結果の行列はn²要素
+pub fn cosine_similarity_matrix(vectors: &[&[f32]], norms: &[f32]) -> Vec<Vec<f32>> {
+    let n = vectors.len();
+    let mut matrix = vec![vec![0.0; n]; n];
+
+    for (i, (a, &norm_a)) in vectors.iter().zip(norms).enumerate() {
+        for (j, (b, &norm_b)) in vectors.iter().zip(norms).enumerate().skip(i) {
+            if a.len() != b.len() || norm_a == 0.0 || norm_b == 0.0 {
+                continue;
+            }
+            let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+            let score = dot_product / (norm_a * norm_b);
+            matrix[i][j] = score;
+            matrix[j][i] = score;
+        }
+    }
+
+    matrix
+}
+
+/// 枝刈りの判定で丸め誤差を見込んで上限に足す余裕
+const BOUND_MARGIN: f64 = 1e-3;
+
+/// 閾値付きのコサイン類似度（上限による枝刈り付き）
+///
+/// 次元を4分割して先頭から内積を計算し、区切りごとに残りの次元の寄与を
+/// コーシー・シュワルツの不等式 `A_rest · B_rest ≤ ||A_rest|| × ||B_rest||` で上から見積もる。
+/// 上限が閾値に届かなくなった時点で残りの計算を省略する。ドキュメント側の `||B_rest||` は
+/// 保存済みのノルムから `sqrt(||B||² - ||B_head||²)` で求めるため、残りの次元を読む必要がない
+///
+/// クエリは作成時に一度だけ正規化し、閾値に届きうるドキュメントのスコアは
+/// 正規化したクエリとの内積を保存済みのノルムで割って求める（ドキュメント側のノルムを
+/// 計算し直さない）。追加・更新時に正規化して保存したベクトルはノルムが1なので、
+/// 実質的には内積のみになる。正規化せずに保存した古いデータベースのベクトルも、
+/// 保存済みのノルムで割るため同じスコアになる。スコアは [`cosine_similarity`] と
+/// 丸め誤差（1e-5未満）の範囲で一致する
+pub struct BoundedCosine {
+    /// 正規化したクエリ
+    query: Vec<f32>,
+    /// 正規化前のクエリのノルム（ゼロベクトルの判定用）
+    query_norm: f32,
+    /// 枝刈りを判定する区切りの位置と、そこから後ろのクエリのノルム
+    checkpoints: Vec<(usize, f64)>,
+    threshold: f32,
+}
+
+impl BoundedCosine {
+    /// クエリベクトルと最小スコア閾値から作成（クエリの正規化はここで1度だけ行う）
+    pub fn new(query: &[f32], threshold: f32) -> Self {
+        let query_norm = vector_norm(query);
+        let query = normalized(query);
+        let checkpoints = (1..4)
+            .map(|i| query.len() * i / 4)
+            .map(|split| (split, vector_norm(&query[split..]) as f64))
+            .collect();
+
+        Self {
+            query,
+            query_norm,
+            checkpoints,
+            threshold,
+        }
+    }
+
+    /// 閾値以上ならコサイン類似度を、閾値未満なら `None` を返す
+    ///
+    /// `norm` は `embedding` のノルム（[`vector_norm`]）
+    pub fn score(&self, embedding: &[f32], norm: f32) -> Option<f32> {
+        // 次元数が一致しない・ゼロベクトルの場合は [`cosine_similarity`] と同じく0.0
+        let valid = embedding.len() == self.query.len() && self.query_norm > 0.0 && norm > 0.0;
+
+        // 閾値が0以下ではほとんど枝刈りできないため、上限の計算を省く
+        if self.threshold > 0.0 && valid {
+            // 保存済みノルムの丸め誤差で残りのノルムを小さく見積もらないよう、少し大きめにとる
+            let norm = norm as f64;
+            let norm_sq = norm * norm * (1.0 + BOUND_MARGIN);
+
+            let (mut head_dot, mut head_sq, mut start) = (0.0f64, 0.0f64, 0);
+            for &(split, query_tail_norm) in &self.checkpoints {
+                for (&q, &d) in self.query[start..split].iter().zip(&embedding[start..split]) {
+                    head_dot += q as f64 * d as f64;
+                    head_sq += d as f64 * d as f64;
+                }
+                start = split;
+
+                let tail_norm = (norm_sq - head_sq).max(0.0).sqrt();
+                let upper_bound = (head_dot + query_tail_norm * tail_norm) / norm;
+                if upper_bound + BOUND_MARGIN < self.threshold as f64 {
+                    return None;
+                }
+            }
+        }
+
+        let score = if valid {
+            dot(&self.query, embedding) / norm
+        } else {
+            0.0
+        };
+        (score >= self.threshold).then_some(score)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -209,4 +1008,243 @@ mod tests {
         let similarity = cosine_similarity(&a, &b);
         assert!((similarity + 1.0).abs() < 1e-6);
     }
+
+    #[test]
+    fn test_bounded_cosine_matches_brute_force() {
+        // 再現性のある擬似乱数（線形合同法）で -1.0〜1.0 の値を生成
+        let mut state = 42u64;
+        let mut next = move || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f32 / (1u64 << 53) as f32 * 2.0 - 1.0
+        };
+
+        let query: Vec<f32> = (0..384).map(|_| next()).collect();
+        // クエリとの類似度がばらつくよう、クエリに混ぜるノイズの割合を変える
+        let documents: Vec<Vec<f32>> = (0..200)
+            .map(|i| {
+                let mix = i as f32 / 200.0;
+                query.iter().map(|q| q * (1.0 - mix) + next() * mix * 2.0).collect()
+            })
+            .collect();
+
+        for threshold in [-1.0, 0.0, 0.1, 0.3, 0.5, 0.7, 0.9, 0.99] {
+            let scorer = BoundedCosine::new(&query, threshold);
+            for document in &documents {
+                // 閾値の境界に丸め誤差以内で近いドキュメントはないため、枝刈りの判定も一致する
+                let brute_force = cosine_similarity(&query, document);
+                let score = scorer.score(document, vector_norm(document));
+                assert_eq!(score.is_some(), brute_force >= threshold);
+                if let Some(score) = score {
+                    assert!(
+                        (score - brute_force).abs() < 1e-5,
+                        "{} vs {}",
+                        score,
+                        brute_force
+                    );
+                }
+            }
+        }
+
+        // 次元数が異なる・ゼロベクトルの場合も全件計算と同じ（類似度0.0）
+        let scorer = BoundedCosine::new(&query, 0.0);
+        assert_eq!(scorer.score(&[1.0, 2.0], vector_norm(&[1.0, 2.0])), Some(0.0));
+        assert_eq!(scorer.score(&[0.0; 384], 0.0), Some(0.0));
+    }
+
+    #[test]
+    fn test_enrich_context_with_collection_descriptions() {
+        let sources = vec![
+            SearchResult::new(1, "First".to_string(), 0.9, None, "faq".to_string()),
+            SearchResult::new(2, "Second".to_string(), 0.8, None, "blog".to_string()),
+        ];
+        let descriptions = HashMap::from([("faq".to_string(), "Official answers".to_string())]);
+
+        let result =
+            EnrichResult::with_collection_descriptions("question".to_string(), sources, &descriptions);
+
+        assert_eq!(
+            result.context,
+            "[Source 1] (Score: 0.900, Collection: faq, Description: Official answers)\nFirst\n\n\
+             [Source 2] (Score: 0.800, Collection: blog)\nSecond"
+        );
+    }
+
+    #[test]
+    fn test_enrich_context_limit_truncates_last_source() {
+        let sources = vec![
+            SearchResult::new(
+                1,
+                "alpha beta gamma".to_string(),
+                0.9,
+                None,
+                "faq".to_string(),
+            ),
+            SearchResult::new(
+                2,
+                "delta epsilon zeta eta".to_string(),
+                0.8,
+                None,
+                "faq".to_string(),
+            ),
+            SearchResult::new(3, "theta".to_string(), 0.7, None, "faq".to_string()),
+        ];
+        let header = "[Source 1] (Score: 0.900, Collection: faq)\n";
+        let first = format!("{}alpha beta gamma", header);
+        // 2件目は見出しと "delta epsilon" + "…" まで入る
+        let limit = first.chars().count()
+            + 2
+            + header.chars().count()
+            + "delta epsilon…".chars().count()
+            + 3;
+
+        let result = EnrichResult::with_context_limit(
+            "question".to_string(),
+            sources.clone(),
+            &HashMap::new(),
+            Some(limit),
+        );
+        assert_eq!(
+            result.context,
+            format!(
+                "{}\n\n[Source 2] (Score: 0.800, Collection: faq)\ndelta epsilon…",
+                first
+            )
+        );
+        assert!(result.context.chars().count() <= limit);
+        let ids: Vec<i64> = result.sources.iter().map(|s| s.document_id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(result.sources[1].content, "delta epsilon zeta eta");
+
+        // 本文が1文字も入らないソースは含めない
+        let result = EnrichResult::with_context_limit(
+            "question".to_string(),
+            sources,
+            &HashMap::new(),
+            Some(first.chars().count() + 5),
+        );
+        assert_eq!(result.context, first);
+        assert_eq!(result.sources.len(), 1);
+
+        assert_eq!(
+            truncate_at_word_boundary("永代供養の費用", 4),
+            Some("永代供…".to_string())
+        );
+        assert_eq!(
+            truncate_at_word_boundary("hello world", 7),
+            Some("hello…".to_string())
+        );
+        assert_eq!(
+            truncate_at_word_boundary("hello world", 3),
+            Some("he…".to_string())
+        );
+        assert_eq!(truncate_at_word_boundary("hello", 1), None);
+    }
+
+    #[test]
+    fn test_enrich_context_with_custom_template() {
+        let sources = vec![
+            SearchResult::new(
+                3,
+                "Boil the pasta {score}".to_string(),
+                0.91234,
+                Some(serde_json::json!({"lang": "en"})),
+                "faq".to_string(),
+            ),
+            SearchResult::new(8, "Add salt".to_string(), 0.5, None, "blog".to_string()),
+        ];
+        let descriptions = HashMap::from([("faq".to_string(), "Official answers".to_string())]);
+
+        let result = EnrichResult::with_template(
+            "question".to_string(),
+            sources,
+            &descriptions,
+            None,
+            Some(concat!(
+                "- {content} [^{index}] ",
+                "({id}, {score}, {collection}: {description}) {metadata} {unknown}"
+            )),
+            Some("\n"),
+        );
+
+        assert_eq!(
+            result.context,
+            "- Boil the pasta {score} [^1] (3, 0.912, faq: Official answers) \
+             {\"lang\":\"en\"} {unknown}\n\
+             - Add salt [^2] (8, 0.500, blog: )  {unknown}"
+        );
+    }
+
+    #[test]
+    fn test_render_context_template_with_unclosed_brace() {
+        let source = SearchResult::new(1, "Boil".to_string(), 0.5, None, "faq".to_string());
+        let render = |template: &str| render_context_template(template, 1, &source, None, "Boil");
+
+        assert_eq!(render("abc {"), "abc {");
+        assert_eq!(render("abc {def"), "abc {def");
+        assert_eq!(render("[{index}] {content"), "[1] {content");
+    }
+
+    #[test]
+    fn test_to_chat_messages() {
+        let sources = vec![
+            SearchResult::new(1, "First".to_string(), 0.9, None, "faq".to_string()),
+            SearchResult::new(2, "Second".to_string(), 0.8, None, "faq".to_string()),
+        ];
+        let result = EnrichResult::new("What is first?".to_string(), sources);
+
+        let messages = result.to_chat_messages(Some("You answer questions about our product."));
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].role, "system");
+        assert!(messages[0]
+            .content
+            .starts_with("You answer questions about our product.\n\n"));
+        assert!(messages[0].content.contains("[Source N]"));
+        assert!(messages[0].content.ends_with(&result.context));
+        assert_eq!(messages[1], ChatMessage::user("What is first?"));
+
+        // Chat Completions APIの messages と同じ形にシリアライズされる
+        assert_eq!(
+            serde_json::to_value(&messages[1]).unwrap(),
+            serde_json::json!({"role": "user", "content": "What is first?"})
+        );
+
+        // システムプロンプト省略時は汎用の前置き、ソースなしの場合はその旨を伝える
+        let empty = EnrichResult::new("question".to_string(), Vec::new());
+        let messages = empty.to_chat_messages(None);
+        assert!(messages[0].content.starts_with(DEFAULT_SYSTEM_PROMPT));
+        assert!(messages[0].content.ends_with("(No sources were found.)"));
+    }
+
+    #[test]
+    fn test_wrap_context() {
+        let sources = vec![
+            SearchResult::new(1, "First".to_string(), 0.9, None, "faq".to_string()),
+            SearchResult::new(2, "Second".to_string(), 0.8, None, "faq".to_string()),
+        ];
+        let mut result = EnrichResult::new("question".to_string(), sources);
+        let blocks = result.context.clone();
+
+        result.wrap_context(
+            Some("Answer using only the sources below. Cite [Source N]."),
+            Some("If the sources do not answer the question, say so."),
+        );
+
+        assert_eq!(
+            result.context,
+            format!(
+                "Answer using only the sources below. Cite [Source N].\n\n{}\n\n\
+                 If the sources do not answer the question, say so.",
+                blocks
+            )
+        );
+        assert!(blocks.starts_with("[Source 1]"));
+        // sources は変更されない
+        assert_eq!(result.sources.len(), 2);
+        assert_eq!(result.sources[0].content, "First");
+
+        // ソースが0件の場合は前後の文字列のみ
+        let mut empty = EnrichResult::new("question".to_string(), Vec::new());
+        empty.wrap_context(Some("Prefix"), None);
+        assert_eq!(empty.context, "Prefix");
+    }
 }
@@ -0,0 +1,1087 @@
+//! ドキュメントの保存先（ストレージバックエンド）の抽象化
+//!
+//! [`crate::Doredore`] はコレクション・ドキュメントの保存と検索候補の取得を [`Store`] を通して
+//! 行う。デフォルトはSQLite（[`Database`]）で、Postgres/pgvectorなどの別のデータベースや
+//! テスト用のインメモリ実装に差し替える場合は [`Store`] を実装して
+//! [`crate::Doredore::with_store`] に渡す
+
+use crate::core::collection::{Collection, Document};
+use crate::core::database::{Database, EmbeddedDocumentRow, EmbeddingFormat, ScoredDocumentRow};
+use crate::core::filter::MetadataFilter;
+use crate::core::search::{vector_norm, KeywordBackend, KeywordMatch};
+use crate::error::{Error, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// コレクション・ドキュメントの保存と検索候補の取得を行うストレージバックエンド
+///
+/// Embeddingの生成・スコアの正規化・ランキングは [`crate::Doredore`] 側で行うため、
+/// 実装するのはデータの保存・取得と候補の絞り込みのみ。各メソッドの意味は
+/// SQLiteによる実装（[`Database`] の同名のメソッド）に合わせる
+///
+/// 読み取り専用の判定・バルクロード・トランザクション・最適化・Embeddingキャッシュには
+/// デフォルト実装があり、それぞれ何もしない（トランザクションはロールバックせずにそのまま実行する）
+pub trait Store: Send {
+    /// 保存先のパス（ファイルを持たないストアは識別用の任意のパス）
+    fn path(&self) -> &Path;
+
+    /// 読み取り専用かどうか（trueの場合、変更操作は `Error::ReadOnly` を返すこと）
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    // コレクション
+
+    /// コレクションを作成してIDを返す（同名のコレクションがある場合はエラー）
+    fn create_collection(&self, name: &str, description: Option<&str>) -> Result<i64>;
+
+    /// Embeddingモデルを指定してコレクションを作成（Noneの場合はインスタンスのモデルを使う）
+    ///
+    /// デフォルト実装はモデルを指定しない場合のみ `create_collection` で作成し、
+    /// 指定した場合は `Error::Other` を返す（モデルを保存できないストア向け）
+    fn create_collection_with_model(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        model: Option<&str>,
+    ) -> Result<i64> {
+        match model {
+            None => self.create_collection(name, description),
+            Some(_) => Err(Error::Other(
+                "This store does not support per-collection embedding models".to_string(),
+            )),
+        }
+    }
+
+    /// 名前でコレクションを取得（存在しない場合はエラー）
+    fn get_collection(&self, name: &str) -> Result<Collection>;
+
+    fn list_collections(&self) -> Result<Vec<Collection>>;
+
+    /// Embeddingモデルを指定したコレクションのIDとモデル名（検索のたびに呼ばれるため軽量に）
+    ///
+    /// デフォルト実装は空（すべてのコレクションがインスタンスのモデルを使う）
+    fn get_collection_models(&self) -> Result<HashMap<i64, String>> {
+        Ok(HashMap::new())
+    }
+
+    /// コレクションとそのドキュメントを削除（削除した場合はtrue）
+    fn delete_collection(&self, name: &str) -> Result<bool>;
+
+    /// キーワード検索の対象にするメタデータのフィールド名（未設定の場合は空）
+    fn get_indexed_metadata_fields(&self, collection_id: i64) -> Result<Vec<String>>;
+
+    /// キーワード検索の対象にするメタデータのフィールド名を設定
+    fn set_indexed_metadata_fields(&self, collection_id: i64, fields: &[String]) -> Result<()>;
+
+    // ドキュメント
+
+    /// ドキュメントを追加してIDを返す
+    fn add_document(
+        &self,
+        collection_id: i64,
+        content: &str,
+        embedding: &[f32],
+        metadata: Option<&serde_json::Value>,
+    ) -> Result<i64>;
+
+    /// 外部ID付きでドキュメントを追加（外部IDは全コレクションで一意）
+    fn add_document_with_external_id(
+        &self,
+        collection_id: i64,
+        content: &str,
+        embedding: &[f32],
+        metadata: Option<&serde_json::Value>,
+        external_id: &str,
+    ) -> Result<i64>;
+
+    /// 同じコレクション内で内容が同一（前後・連続する空白の違いは無視）の最も古いドキュメントのID
+    fn find_duplicate(&self, collection_id: i64, content: &str) -> Result<Option<i64>>;
+
+    /// IDでドキュメントを取得（存在しない場合は `Error::DocumentNotFound`）
+    fn get_document(&self, document_id: i64) -> Result<Document>;
+
+    /// 指定したドキュメントを一括取得（存在しないIDは含まない）
+    fn get_documents(&self, document_ids: &[i64]) -> Result<HashMap<i64, Document>>;
+
+    /// 外部IDでドキュメントを取得（存在しない場合はNone）
+    fn get_document_by_external_id(&self, external_id: &str) -> Result<Option<Document>>;
+
+    /// ドキュメントの一覧（新しい順）
+    fn list_documents(
+        &self,
+        collection_id: Option<i64>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Document>>;
+
+    /// ドキュメント数（`collection_id` がNoneの場合は全コレクション）
+    fn count_documents(&self, collection_id: Option<i64>) -> Result<i64>;
+
+    /// 本文が空（スペース・タブ・改行のみを含む）のドキュメント（ID順）
+    fn find_empty_documents(&self, collection_id: Option<i64>) -> Result<Vec<Document>>;
+
+    /// 親ドキュメントに紐づくチャンク（親ドキュメント自身を含む、元テキストの順）
+    fn get_chunks(&self, parent_id: i64) -> Result<Vec<Document>>;
+
+    /// ドキュメントをチャンクとして親ドキュメントに紐づける
+    fn set_chunk_parent(&self, document_id: i64, parent_id: i64) -> Result<()>;
+
+    /// 指定したドキュメントのうちチャンクであるものの親ドキュメントID
+    fn get_chunk_parents(&self, document_ids: &[i64]) -> Result<HashMap<i64, i64>>;
+
+    /// 指定した項目のみ更新（更新した場合はtrue）
+    fn update_document(
+        &self,
+        document_id: i64,
+        content: Option<&str>,
+        embedding: Option<&[f32]>,
+        metadata: Option<&serde_json::Value>,
+    ) -> Result<bool>;
+
+    /// コレクション内の全ドキュメントのメタデータを一括更新し、更新件数を返す
+    ///
+    /// `merge = true` の場合はJSON Merge Patch（RFC 7396）としてマージ、falseの場合は置き換え
+    fn update_collection_metadata(
+        &self,
+        collection_id: i64,
+        patch: &serde_json::Value,
+        merge: bool,
+    ) -> Result<usize>;
+
+    /// ドキュメントを削除（削除した場合はtrue）
+    fn delete_document(&self, document_id: i64) -> Result<bool>;
+
+    /// 外部IDでドキュメントを削除（削除した場合はtrue）
+    fn delete_document_by_external_id(&self, external_id: &str) -> Result<bool>;
+
+    // 検索候補
+
+    /// 全ドキュメントのEmbeddingとそのノルム（セマンティック検索の候補）
+    fn get_all_documents_with_norms(
+        &self,
+        collection_ids: Option<&[i64]>,
+    ) -> Result<Vec<(EmbeddedDocumentRow, f32)>>;
+
+    /// 指定したドキュメントのEmbedding（存在しないIDは含まない）
+    fn get_embeddings(&self, document_ids: &[i64]) -> Result<HashMap<i64, Vec<f32>>>;
+
+    /// 指定したドキュメントのEmbeddingとそのノルム（存在しないIDは含まない）
+    ///
+    /// デフォルト実装は `get_embeddings` の結果からノルムを計算する
+    fn get_embeddings_with_norms(
+        &self,
+        document_ids: &[i64],
+    ) -> Result<HashMap<i64, (Vec<f32>, f32)>> {
+        Ok(self
+            .get_embeddings(document_ids)?
+            .into_iter()
+            .map(|(id, embedding)| {
+                let norm = vector_norm(&embedding);
+                (id, (embedding, norm))
+            })
+            .collect())
+    }
+
+    /// メタデータ条件に一致するドキュメントのID
+    fn filter_document_ids(
+        &self,
+        collection_ids: Option<&[i64]>,
+        filter: &MetadataFilter,
+    ) -> Result<HashSet<i64>>;
+
+    /// キーワード検索
+    ///
+    /// スコアはBM25と同じ向き（負の値、小さいほど関連性が高い）で、昇順に並べて返す
+    fn keyword_search(
+        &self,
+        query: &str,
+        collection_ids: Option<&[i64]>,
+        exclude_ids: &[i64],
+    ) -> Result<Vec<ScoredDocumentRow>>;
+
+    /// 複数の語の組み合わせ方（AND / OR）を指定したキーワード検索
+    ///
+    /// デフォルト実装は `keyword_match` を無視して `keyword_search` と同じ結果を返す
+    fn keyword_search_matching(
+        &self,
+        query: &str,
+        collection_ids: Option<&[i64]>,
+        exclude_ids: &[i64],
+        _keyword_match: KeywordMatch,
+    ) -> Result<Vec<ScoredDocumentRow>> {
+        self.keyword_search(query, collection_ids, exclude_ids)
+    }
+
+    /// 綴りの誤りを許容するキーワード検索（スコアの向きは `keyword_search` と同じ）
+    fn fuzzy_keyword_search(
+        &self,
+        query: &str,
+        collection_ids: Option<&[i64]>,
+        exclude_ids: &[i64],
+    ) -> Result<Vec<ScoredDocumentRow>>;
+
+    /// キーワード検索にマッチするドキュメント数
+    fn keyword_count(&self, query: &str, collection_ids: Option<&[i64]>) -> Result<usize>;
+
+    /// キーワード検索にマッチするドキュメントのID（順序は不定）
+    ///
+    /// デフォルト実装は `keyword_search` の結果からIDを取り出す
+    fn keyword_match_ids(&self, query: &str, collection_ids: Option<&[i64]>) -> Result<Vec<i64>> {
+        let results = self.keyword_search(query, collection_ids, &[])?;
+        Ok(results.into_iter().map(|(id, ..)| id).collect())
+    }
+
+    /// 最後の `keyword_search` で使った照合方法（検索の診断情報に使う）
+    ///
+    /// デフォルト実装は報告しない（常にNone）
+    fn last_keyword_backend(&self) -> Option<KeywordBackend> {
+        None
+    }
+
+    // 設定
+
+    /// 保存済みの設定値（未設定の場合はNone）
+    ///
+    /// デフォルト実装は保存しない（常にNone）。その場合、Embeddingモデルの次元数の
+    /// 検証（[`crate::Doredore::with_shared_model`] を参照）は行われない
+    fn get_setting(&self, _key: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// 設定値を保存（既にあれば置き換える）
+    fn set_setting(&self, _key: &str, _value: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// 以降に追加・更新するEmbeddingの保存形式を設定
+    ///
+    /// デフォルト実装はf32のみ受け付け、それ以外は `Error::InvalidInput` を返す
+    fn set_embedding_format(&self, format: EmbeddingFormat) -> Result<()> {
+        match format {
+            EmbeddingFormat::F32 => Ok(()),
+            other => Err(Error::InvalidInput(format!(
+                "Embedding format '{}' is not supported by this store",
+                other.as_str()
+            ))),
+        }
+    }
+
+    // 近似最近傍探索（ANN）のインデックス
+
+    /// 保存済みのANNインデックス（[`crate::core::ann::HnswIndex::to_bytes`] の形式）と、
+    /// 保存時のコレクションの変更世代（[`Store::collection_generation`]、ない場合はNone）
+    ///
+    /// デフォルト実装は保存しない（常にNone）。その場合、インデックスはインスタンスごとに
+    /// [`crate::Doredore::build_ann_index`] で構築し直す必要がある
+    fn load_ann_index(&self, _collection_id: i64) -> Result<Option<(Vec<u8>, Option<i64>)>> {
+        Ok(None)
+    }
+
+    /// ANNインデックスを、反映済みのコレクションの変更世代とともに保存（既にあれば置き換える）
+    fn save_ann_index(
+        &self,
+        _collection_id: i64,
+        _data: &[u8],
+        _generation: Option<i64>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// ANNインデックスを削除（削除した場合はtrue）
+    fn delete_ann_index(&self, _collection_id: i64) -> Result<bool> {
+        Ok(false)
+    }
+
+    // Embeddingキャッシュ
+
+    /// キャッシュ済みのEmbedding（入力のハッシュ → ベクトル、キャッシュにないものは含めない）
+    ///
+    /// デフォルト実装はキャッシュしない（常に空）
+    fn get_cached_embeddings(
+        &self,
+        _model: &str,
+        _dimension: usize,
+        _input_hashes: &[String],
+    ) -> Result<HashMap<String, Vec<f32>>> {
+        Ok(HashMap::new())
+    }
+
+    /// Embeddingをキャッシュに保存（同じキーがあれば置き換える）
+    fn put_cached_embeddings(
+        &self,
+        _model: &str,
+        _dimension: usize,
+        _entries: &[(String, Vec<f32>)],
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Embeddingキャッシュを空にして、削除した件数を返す
+    fn clear_embedding_cache(&self) -> Result<usize> {
+        Ok(0)
+    }
+
+    /// コレクションのドキュメントID（ANNインデックスとの差分の検出用）
+    ///
+    /// デフォルト実装は `get_all_documents_with_norms` から求める
+    fn collection_document_ids(&self, collection_id: i64) -> Result<Vec<i64>> {
+        Ok(self
+            .get_all_documents_with_norms(Some(&[collection_id]))?
+            .into_iter()
+            .map(|((id, ..), _)| id)
+            .collect())
+    }
+
+    /// コレクションの変更世代（ドキュメントの追加・Embeddingの更新・削除のたびに増える値。
+    /// ANNインデックスが古くなっていないかの確認用）
+    ///
+    /// デフォルト実装は変更を追跡しない（常にNone）。その場合、ANNインデックスは検索のたびに
+    /// ドキュメントIDの差分で追加・削除を反映し、他の接続による内容の更新は反映しない
+    fn collection_generation(&self, _collection_id: i64) -> Result<Option<i64>> {
+        Ok(None)
+    }
+
+    /// 変更世代 `since` より後に追加・Embeddingを更新したドキュメントのID
+    ///
+    /// デフォルト実装は変更を追跡しない（常に空）
+    fn changed_document_ids(&self, _collection_id: i64, _since: i64) -> Result<Vec<i64>> {
+        Ok(Vec::new())
+    }
+
+    // バルクロード・トランザクション・最適化
+
+    /// バルクロードを開始（キーワード検索のインデックス更新を `end_bulk_load` まで保留できる）
+    fn begin_bulk_load(&self) {}
+
+    /// バルクロードを終了し、保留したインデックスを構築
+    fn end_bulk_load(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// バルクロード中かどうか
+    fn is_bulk_loading(&self) -> bool {
+        false
+    }
+
+    /// `f` 内の変更を1つのトランザクションで実行（`f` がエラーを返した場合はロールバック）
+    ///
+    /// 入れ子で呼ばれた場合、内側のロールバックは内側の変更のみを取り消すこと
+    fn transaction(&self, f: &mut dyn FnMut() -> Result<()>) -> Result<()> {
+        f()
+    }
+
+    /// 領域の回収とインデックスの整理
+    fn optimize(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// インデックスの整理のみ（`optimize` より軽い処理）
+    fn optimize_indexes(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// キーワード検索のインデックスを保存済みのドキュメントから作り直す
+    fn rebuild_keyword_index(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Store for Database {
+    fn path(&self) -> &Path {
+        Database::path(self)
+    }
+
+    fn is_read_only(&self) -> bool {
+        Database::is_read_only(self)
+    }
+
+    fn create_collection(&self, name: &str, description: Option<&str>) -> Result<i64> {
+        Database::create_collection(self, name, description)
+    }
+
+    fn create_collection_with_model(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        model: Option<&str>,
+    ) -> Result<i64> {
+        Database::create_collection_with_model(self, name, description, model)
+    }
+
+    fn get_collection(&self, name: &str) -> Result<Collection> {
+        Database::get_collection(self, name)
+    }
+
+    fn list_collections(&self) -> Result<Vec<Collection>> {
+        Database::list_collections(self)
+    }
+
+    fn get_collection_models(&self) -> Result<HashMap<i64, String>> {
+        Database::get_collection_models(self)
+    }
+
+    fn delete_collection(&self, name: &str) -> Result<bool> {
+        Database::delete_collection(self, name)
+    }
+
+    fn get_indexed_metadata_fields(&self, collection_id: i64) -> Result<Vec<String>> {
+        Database::get_indexed_metadata_fields(self, collection_id)
+    }
+
+    fn set_indexed_metadata_fields(&self, collection_id: i64, fields: &[String]) -> Result<()> {
+        Database::set_indexed_metadata_fields(self, collection_id, fields)
+    }
+
+    fn add_document(
+        &self,
+        collection_id: i64,
+        content: &str,
+        embedding: &[f32],
+        metadata: Option<&serde_json::Value>,
+    ) -> Result<i64> {
+        Database::add_document(self, collection_id, content, embedding, metadata)
+    }
+
+    fn add_document_with_external_id(
+        &self,
+        collection_id: i64,
+        content: &str,
+        embedding: &[f32],
+        metadata: Option<&serde_json::Value>,
+        external_id: &str,
+    ) -> Result<i64> {
+        Database::add_document_with_external_id(
+            self,
+            collection_id,
+            content,
+            embedding,
+            metadata,
+            external_id,
+        )
+    }
+
+    fn find_duplicate(&self, collection_id: i64, content: &str) -> Result<Option<i64>> {
+        Database::find_duplicate(self, collection_id, content)
+    }
+
+    fn get_document(&self, document_id: i64) -> Result<Document> {
+        Database::get_document(self, document_id)
+    }
+
+    fn get_documents(&self, document_ids: &[i64]) -> Result<HashMap<i64, Document>> {
+        Database::get_documents(self, document_ids)
+    }
+
+    fn get_document_by_external_id(&self, external_id: &str) -> Result<Option<Document>> {
+        Database::get_document_by_external_id(self, external_id)
+    }
+
+    fn list_documents(
+        &self,
+        collection_id: Option<i64>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Document>> {
+        Database::list_documents(self, collection_id, limit, offset)
+    }
+
+    fn count_documents(&self, collection_id: Option<i64>) -> Result<i64> {
+        Database::count_documents(self, collection_id)
+    }
+
+    fn find_empty_documents(&self, collection_id: Option<i64>) -> Result<Vec<Document>> {
+        Database::find_empty_documents(self, collection_id)
+    }
+
+    fn get_chunks(&self, parent_id: i64) -> Result<Vec<Document>> {
+        Database::get_chunks(self, parent_id)
+    }
+
+    fn set_chunk_parent(&self, document_id: i64, parent_id: i64) -> Result<()> {
+        Database::set_chunk_parent(self, document_id, parent_id)
+    }
+
+    fn get_chunk_parents(&self, document_ids: &[i64]) -> Result<HashMap<i64, i64>> {
+        Database::get_chunk_parents(self, document_ids)
+    }
+
+    fn update_document(
+        &self,
+        document_id: i64,
+        content: Option<&str>,
+        embedding: Option<&[f32]>,
+        metadata: Option<&serde_json::Value>,
+    ) -> Result<bool> {
+        Database::update_document(self, document_id, content, embedding, metadata)
+    }
+
+    fn update_collection_metadata(
+        &self,
+        collection_id: i64,
+        patch: &serde_json::Value,
+        merge: bool,
+    ) -> Result<usize> {
+        Database::update_collection_metadata(self, collection_id, patch, merge)
+    }
+
+    fn delete_document(&self, document_id: i64) -> Result<bool> {
+        Database::delete_document(self, document_id)
+    }
+
+    fn delete_document_by_external_id(&self, external_id: &str) -> Result<bool> {
+        Database::delete_document_by_external_id(self, external_id)
+    }
+
+    fn get_all_documents_with_norms(
+        &self,
+        collection_ids: Option<&[i64]>,
+    ) -> Result<Vec<(EmbeddedDocumentRow, f32)>> {
+        Database::get_all_documents_with_norms(self, collection_ids)
+    }
+
+    fn get_embeddings(&self, document_ids: &[i64]) -> Result<HashMap<i64, Vec<f32>>> {
+        Database::get_embeddings(self, document_ids)
+    }
+
+    fn get_embeddings_with_norms(
+        &self,
+        document_ids: &[i64],
+    ) -> Result<HashMap<i64, (Vec<f32>, f32)>> {
+        Database::get_embeddings_with_norms(self, document_ids)
+    }
+
+    fn filter_document_ids(
+        &self,
+        collection_ids: Option<&[i64]>,
+        filter: &MetadataFilter,
+    ) -> Result<HashSet<i64>> {
+        Database::filter_document_ids(self, collection_ids, filter)
+    }
+
+    fn keyword_search(
+        &self,
+        query: &str,
+        collection_ids: Option<&[i64]>,
+        exclude_ids: &[i64],
+    ) -> Result<Vec<ScoredDocumentRow>> {
+        Database::keyword_search(self, query, collection_ids, exclude_ids)
+    }
+
+    fn keyword_search_matching(
+        &self,
+        query: &str,
+        collection_ids: Option<&[i64]>,
+        exclude_ids: &[i64],
+        keyword_match: KeywordMatch,
+    ) -> Result<Vec<ScoredDocumentRow>> {
+        Database::keyword_search_matching(self, query, collection_ids, exclude_ids, keyword_match)
+    }
+
+    fn fuzzy_keyword_search(
+        &self,
+        query: &str,
+        collection_ids: Option<&[i64]>,
+        exclude_ids: &[i64],
+    ) -> Result<Vec<ScoredDocumentRow>> {
+        Database::fuzzy_keyword_search(self, query, collection_ids, exclude_ids)
+    }
+
+    fn keyword_count(&self, query: &str, collection_ids: Option<&[i64]>) -> Result<usize> {
+        Database::keyword_count(self, query, collection_ids)
+    }
+
+    fn keyword_match_ids(&self, query: &str, collection_ids: Option<&[i64]>) -> Result<Vec<i64>> {
+        Database::keyword_match_ids(self, query, collection_ids)
+    }
+
+    fn last_keyword_backend(&self) -> Option<KeywordBackend> {
+        Database::last_keyword_backend(self)
+    }
+
+    fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        Database::get_setting(self, key)
+    }
+
+    fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        Database::set_setting(self, key, value)
+    }
+
+    fn set_embedding_format(&self, format: EmbeddingFormat) -> Result<()> {
+        Database::set_embedding_format(self, format);
+        Ok(())
+    }
+
+    fn load_ann_index(&self, collection_id: i64) -> Result<Option<(Vec<u8>, Option<i64>)>> {
+        Database::load_ann_index(self, collection_id)
+    }
+
+    fn save_ann_index(
+        &self,
+        collection_id: i64,
+        data: &[u8],
+        generation: Option<i64>,
+    ) -> Result<()> {
+        Database::save_ann_index(self, collection_id, data, generation)
+    }
+
+    fn delete_ann_index(&self, collection_id: i64) -> Result<bool> {
+        Database::delete_ann_index(self, collection_id)
+    }
+
+    fn get_cached_embeddings(
+        &self,
+        model: &str,
+        dimension: usize,
+        input_hashes: &[String],
+    ) -> Result<HashMap<String, Vec<f32>>> {
+        Database::get_cached_embeddings(self, model, dimension, input_hashes)
+    }
+
+    fn put_cached_embeddings(
+        &self,
+        model: &str,
+        dimension: usize,
+        entries: &[(String, Vec<f32>)],
+    ) -> Result<()> {
+        Database::put_cached_embeddings(self, model, dimension, entries)
+    }
+
+    fn clear_embedding_cache(&self) -> Result<usize> {
+        Database::clear_embedding_cache(self)
+    }
+
+    fn collection_document_ids(&self, collection_id: i64) -> Result<Vec<i64>> {
+        Database::collection_document_ids(self, collection_id)
+    }
+
+    fn collection_generation(&self, collection_id: i64) -> Result<Option<i64>> {
+        Database::collection_generation(self, collection_id)
+    }
+
+    fn changed_document_ids(&self, collection_id: i64, since: i64) -> Result<Vec<i64>> {
+        Database::changed_document_ids(self, collection_id, since)
+    }
+
+    fn begin_bulk_load(&self) {
+        Database::begin_bulk_load(self)
+    }
+
+    fn end_bulk_load(&self) -> Result<()> {
+        Database::end_bulk_load(self)
+    }
+
+    fn is_bulk_loading(&self) -> bool {
+        Database::is_bulk_loading(self)
+    }
+
+    fn transaction(&self, f: &mut dyn FnMut() -> Result<()>) -> Result<()> {
+        Database::transaction(self, f)
+    }
+
+    fn optimize(&self) -> Result<()> {
+        Database::optimize(self)
+    }
+
+    fn optimize_indexes(&self) -> Result<()> {
+        Database::optimize_indexes(self)
+    }
+
+    fn rebuild_keyword_index(&self) -> Result<()> {
+        Database::rebuild_fts_index(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::collection::AddOptions;
+    use crate::core::embedding::EmbeddingModel;
+    use crate::core::mock_embedder::MockEmbedder;
+    use crate::core::search::SearchMode;
+    use crate::core::Doredore;
+    use std::cell::RefCell;
+
+    /// テスト用の最小限のインメモリストア（全件をVecに保持し、線形探索する）
+    #[derive(Default)]
+    struct MemoryStore {
+        collections: RefCell<Vec<Collection>>,
+        documents: RefCell<Vec<(Document, Vec<f32>)>>,
+        chunk_parents: RefCell<HashMap<i64, i64>>,
+    }
+
+    impl MemoryStore {
+        fn documents_where(&self, pred: impl Fn(&Document) -> bool) -> Vec<Document> {
+            self.documents
+                .borrow()
+                .iter()
+                .filter(|(doc, _)| pred(doc))
+                .map(|(doc, _)| doc.clone())
+                .collect()
+        }
+
+        fn in_collections(doc: &Document, collection_ids: Option<&[i64]>) -> bool {
+            collection_ids.is_none_or(|ids| ids.contains(&doc.collection_id))
+        }
+    }
+
+    impl Store for MemoryStore {
+        fn path(&self) -> &Path {
+            Path::new(":memory:")
+        }
+
+        fn create_collection(&self, name: &str, description: Option<&str>) -> Result<i64> {
+            let mut collections = self.collections.borrow_mut();
+            if collections.iter().any(|c| c.name == name) {
+                return Err(Error::InvalidInput(format!("Collection '{}' exists", name)));
+            }
+            let id = collections.len() as i64 + 1;
+            collections.push(Collection::new(
+                id,
+                name.to_string(),
+                description.map(String::from),
+                0,
+                String::new(),
+                String::new(),
+            ));
+            Ok(id)
+        }
+
+        fn get_collection(&self, name: &str) -> Result<Collection> {
+            self.collections
+                .borrow()
+                .iter()
+                .find(|c| c.name == name)
+                .cloned()
+                .ok_or_else(|| {
+                    Error::CollectionNotFound(format!("Collection '{}' not found", name))
+                })
+        }
+
+        fn list_collections(&self) -> Result<Vec<Collection>> {
+            Ok(self.collections.borrow().clone())
+        }
+
+        fn delete_collection(&self, name: &str) -> Result<bool> {
+            let Ok(collection) = self.get_collection(name) else {
+                return Ok(false);
+            };
+            self.collections.borrow_mut().retain(|c| c.id != collection.id);
+            self.documents
+                .borrow_mut()
+                .retain(|(doc, _)| doc.collection_id != collection.id);
+            Ok(true)
+        }
+
+        fn get_indexed_metadata_fields(&self, _collection_id: i64) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        fn set_indexed_metadata_fields(&self, _collection_id: i64, _fields: &[String]) -> Result<()> {
+            Err(Error::Other("Not supported".to_string()))
+        }
+
+        fn add_document(
+            &self,
+            collection_id: i64,
+            content: &str,
+            embedding: &[f32],
+            metadata: Option<&serde_json::Value>,
+        ) -> Result<i64> {
+            let collection_name = self
+                .collections
+                .borrow()
+                .iter()
+                .find(|c| c.id == collection_id)
+                .map(|c| c.name.clone())
+                .ok_or_else(|| Error::CollectionNotFound(collection_id.to_string()))?;
+            let mut documents = self.documents.borrow_mut();
+            let id = documents.last().map_or(1, |(doc, _)| doc.id + 1);
+            documents.push((
+                Document {
+                    id,
+                    collection_id,
+                    collection_name,
+                    content: content.to_string(),
+                    metadata: metadata.cloned(),
+                    created_at: String::new(),
+                    updated_at: String::new(),
+                    external_id: None,
+                },
+                embedding.to_vec(),
+            ));
+            Ok(id)
+        }
+
+        fn add_document_with_external_id(
+            &self,
+            collection_id: i64,
+            content: &str,
+            embedding: &[f32],
+            metadata: Option<&serde_json::Value>,
+            external_id: &str,
+        ) -> Result<i64> {
+            let id = self.add_document(collection_id, content, embedding, metadata)?;
+            if let Some((doc, _)) = self.documents.borrow_mut().last_mut() {
+                doc.external_id = Some(external_id.to_string());
+            }
+            Ok(id)
+        }
+
+        fn find_duplicate(&self, collection_id: i64, content: &str) -> Result<Option<i64>> {
+            Ok(self
+                .documents_where(|doc| doc.collection_id == collection_id && doc.content == content)
+                .first()
+                .map(|doc| doc.id))
+        }
+
+        fn get_document(&self, document_id: i64) -> Result<Document> {
+            self.documents_where(|doc| doc.id == document_id)
+                .pop()
+                .ok_or(Error::DocumentNotFound(document_id))
+        }
+
+        fn get_documents(&self, document_ids: &[i64]) -> Result<HashMap<i64, Document>> {
+            Ok(self
+                .documents_where(|doc| document_ids.contains(&doc.id))
+                .into_iter()
+                .map(|doc| (doc.id, doc))
+                .collect())
+        }
+
+        fn get_document_by_external_id(&self, external_id: &str) -> Result<Option<Document>> {
+            Ok(self
+                .documents_where(|doc| doc.external_id.as_deref() == Some(external_id))
+                .pop())
+        }
+
+        fn list_documents(
+            &self,
+            collection_id: Option<i64>,
+            limit: i64,
+            offset: i64,
+        ) -> Result<Vec<Document>> {
+            let mut documents =
+                self.documents_where(|doc| collection_id.is_none_or(|id| doc.collection_id == id));
+            documents.reverse();
+            Ok(documents
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect())
+        }
+
+        fn count_documents(&self, collection_id: Option<i64>) -> Result<i64> {
+            Ok(self
+                .documents_where(|doc| collection_id.is_none_or(|id| doc.collection_id == id))
+                .len() as i64)
+        }
+
+        fn find_empty_documents(&self, collection_id: Option<i64>) -> Result<Vec<Document>> {
+            Ok(self.documents_where(|doc| {
+                collection_id.is_none_or(|id| doc.collection_id == id) && doc.content.trim().is_empty()
+            }))
+        }
+
+        fn get_chunks(&self, parent_id: i64) -> Result<Vec<Document>> {
+            let parents = self.chunk_parents.borrow();
+            Ok(self.documents_where(|doc| parents.get(&doc.id) == Some(&parent_id)))
+        }
+
+        fn set_chunk_parent(&self, document_id: i64, parent_id: i64) -> Result<()> {
+            self.chunk_parents
+                .borrow_mut()
+                .insert(document_id, parent_id);
+            Ok(())
+        }
+
+        fn get_chunk_parents(&self, document_ids: &[i64]) -> Result<HashMap<i64, i64>> {
+            let parents = self.chunk_parents.borrow();
+            Ok(document_ids
+                .iter()
+                .filter_map(|id| parents.get(id).map(|parent| (*id, *parent)))
+                .collect())
+        }
+
+        fn update_document(
+            &self,
+            document_id: i64,
+            content: Option<&str>,
+            embedding: Option<&[f32]>,
+            metadata: Option<&serde_json::Value>,
+        ) -> Result<bool> {
+            let mut documents = self.documents.borrow_mut();
+            let Some((doc, stored)) = documents.iter_mut().find(|(doc, _)| doc.id == document_id)
+            else {
+                return Ok(false);
+            };
+            if let Some(content) = content {
+                doc.content = content.to_string();
+            }
+            if let Some(embedding) = embedding {
+                *stored = embedding.to_vec();
+            }
+            if let Some(metadata) = metadata {
+                doc.metadata = Some(metadata.clone());
+            }
+            Ok(true)
+        }
+
+        fn update_collection_metadata(
+            &self,
+            _collection_id: i64,
+            _patch: &serde_json::Value,
+            _merge: bool,
+        ) -> Result<usize> {
+            Err(Error::Other("Not supported".to_string()))
+        }
+
+        fn delete_document(&self, document_id: i64) -> Result<bool> {
+            let mut documents = self.documents.borrow_mut();
+            let before = documents.len();
+            documents.retain(|(doc, _)| doc.id != document_id);
+            Ok(documents.len() < before)
+        }
+
+        fn delete_document_by_external_id(&self, external_id: &str) -> Result<bool> {
+            match self.get_document_by_external_id(external_id)? {
+                Some(doc) => self.delete_document(doc.id),
+                None => Ok(false),
+            }
+        }
+
+        fn get_all_documents_with_norms(
+            &self,
+            collection_ids: Option<&[i64]>,
+        ) -> Result<Vec<(EmbeddedDocumentRow, f32)>> {
+            Ok(self
+                .documents
+                .borrow()
+                .iter()
+                .filter(|(doc, _)| Self::in_collections(doc, collection_ids))
+                .map(|(doc, embedding)| {
+                    let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+                    let row = (
+                        doc.id,
+                        doc.content.clone(),
+                        embedding.clone(),
+                        doc.collection_name.clone(),
+                        doc.metadata.clone(),
+                    );
+                    (row, norm)
+                })
+                .collect())
+        }
+
+        fn get_embeddings(&self, document_ids: &[i64]) -> Result<HashMap<i64, Vec<f32>>> {
+            Ok(self
+                .documents
+                .borrow()
+                .iter()
+                .filter(|(doc, _)| document_ids.contains(&doc.id))
+                .map(|(doc, embedding)| (doc.id, embedding.clone()))
+                .collect())
+        }
+
+        fn filter_document_ids(
+            &self,
+            _collection_ids: Option<&[i64]>,
+            _filter: &MetadataFilter,
+        ) -> Result<HashSet<i64>> {
+            Err(Error::Other("Not supported".to_string()))
+        }
+
+        fn keyword_search(
+            &self,
+            query: &str,
+            collection_ids: Option<&[i64]>,
+            exclude_ids: &[i64],
+        ) -> Result<Vec<ScoredDocumentRow>> {
+            // 大文字・小文字を区別しない部分一致（スコアは一律）
+            let query = query.to_lowercase();
+            Ok(self
+                .documents_where(|doc| {
+                    Self::in_collections(doc, collection_ids)
+                        && !exclude_ids.contains(&doc.id)
+                        && doc.content.to_lowercase().contains(&query)
+                })
+                .into_iter()
+                .map(|doc| (doc.id, doc.content, -1.0, doc.collection_name, doc.metadata))
+                .collect())
+        }
+
+        fn fuzzy_keyword_search(
+            &self,
+            query: &str,
+            collection_ids: Option<&[i64]>,
+            exclude_ids: &[i64],
+        ) -> Result<Vec<ScoredDocumentRow>> {
+            self.keyword_search(query, collection_ids, exclude_ids)
+        }
+
+        fn keyword_count(&self, query: &str, collection_ids: Option<&[i64]>) -> Result<usize> {
+            Ok(self.keyword_search(query, collection_ids, &[])?.len())
+        }
+    }
+
+    #[test]
+    fn test_doredore_with_memory_store() {
+        let rag = Doredore::with_store(
+            MemoryStore::default(),
+            EmbeddingModel::from_embedder(MockEmbedder::new(64)),
+        );
+        assert_eq!(rag.db_path(), Path::new(":memory:"));
+
+        rag.create_collection("faq", None).unwrap();
+        let rust_id = rag
+            .add_document("Rust is a systems programming language", "faq", None)
+            .unwrap();
+        let pasta_id = rag
+            .add_document("Boil the pasta in salted water", "faq", None)
+            .unwrap();
+        assert!(rag.add_document("orphan", "missing", None).is_err());
+
+        // セマンティック検索・キーワード検索ともにストアの候補から結果を組み立てる
+        let results = rag
+            .search("rust language", Some("faq"), None, 1, 0.0, SearchMode::Semantic, None)
+            .unwrap();
+        assert_eq!(results[0].document_id, rust_id);
+        assert_eq!(results[0].rank, 1);
+
+        let results = rag
+            .search("PASTA", None, None, 5, 0.0, SearchMode::Keyword, None)
+            .unwrap();
+        let ids: Vec<i64> = results.iter().map(|r| r.document_id).collect();
+        assert_eq!(ids, vec![pasta_id]);
+
+        // 一括追加・重複スキップもストア経由で動く
+        let ids = rag
+            .add_documents_with_options(
+                vec![
+                    "Rust is a systems programming language".to_string(),
+                    "New".to_string(),
+                ],
+                "faq",
+                None,
+                &AddOptions {
+                    bulk: true,
+                    skip_duplicates: true,
+                },
+            )
+            .unwrap();
+        assert_eq!(ids[0], rust_id);
+        assert_eq!(rag.list_documents(Some("faq"), 10, 0).unwrap().len(), 3);
+
+        assert!(rag.delete_document(pasta_id).unwrap());
+        assert!(rag
+            .search("pasta", None, None, 5, 0.0, SearchMode::Keyword, None)
+            .unwrap()
+            .is_empty());
+
+        // デフォルト実装のトランザクションは値をそのまま返す
+        let count = rag
+            .transaction(|rag| Ok(rag.list_documents(None, 10, 0)?.len()))
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+}
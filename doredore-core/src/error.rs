@@ -31,6 +31,9 @@ pub enum Error {
     #[error("Invalid input: {0}")]
     InvalidInput(String),
 
+    #[error("Database is opened in read-only mode")]
+    ReadOnly,
+
     #[error("{0}")]
     Other(String),
 }
@@ -2,10 +2,19 @@ pub mod core;
 pub mod error;
 
 pub use crate::core::{
-    collection::Collection,
-    database::Database,
-    embedding::EmbeddingModel,
+    collection::{AddOptions, Collection, CsvImportOptions},
+    database::{Database, EmbeddingFormat},
+    embedding::{Embedder, EmbeddingModel, ModelLoadOptions},
     enricher::Doredore,
-    search::{SearchResult, EnrichResult, SearchMode},
+    evaluation::EvalPoint,
+    filter::{parse_search_query, FilterCondition, FilterOp, MetadataFilter},
+    preprocess::{normalize_content, ContentType},
+    search::{
+        ChatMessage, SearchResult, EnrichResult, FusionMethod, KeywordBackend, KeywordMatch,
+        KeywordNormalization, SearchDiagnostics, SearchMode, SearchOptions,
+    },
+    store::Store,
 };
+#[cfg(any(test, feature = "mock-embedder"))]
+pub use crate::core::mock_embedder::MockEmbedder;
 pub use crate::error::{Error, Result};
@@ -13,6 +13,8 @@ export interface Collection {
   description?: string
   /** Creation timestamp */
   createdAt: string
+  /** Embedding model used by this collection (unset = instance model) */
+  embeddingModel?: string
 }
 
 /**
@@ -29,6 +31,8 @@ export interface Document {
   metadata?: string
   /** Creation timestamp */
   createdAt: string
+  /** Caller-managed ID (UUID, URL, etc.), if set */
+  externalId?: string
 }
 
 /**
@@ -41,10 +45,14 @@ export interface SearchResult {
   content: string
   /** Similarity score (0.0 - 1.0) */
   score: number
+  /** 1-based position in the results (matches the array order) */
+  rank: number
   /** Collection name */
   collection: string
   /** Optional metadata (JSON string) */
   metadata?: string
+  /** True when added below the threshold to satisfy `minResults` */
+  backfilled: boolean
 }
 
 /**
@@ -59,6 +67,46 @@ export interface EnrichResult {
   sources: Array<SearchResult>
 }
 
+/**
+ * Options for `addDocuments`
+ */
+export interface AddOptions {
+  /** Build the keyword index once after the insert instead of per document (default: false) */
+  bulk?: boolean
+  /** Return the existing ID instead of adding a document with identical content (default: false) */
+  skipDuplicates?: boolean
+}
+
+/**
+ * Chat API message (`{ role, content }`)
+ */
+export interface ChatMessage {
+  /** "system" or "user" */
+  role: string
+  /** Message text */
+  content: string
+}
+
+/**
+ * Convert an enrich result into chat API messages
+ *
+ * Returns a `system` message (system prompt, citation instructions and the
+ * context) followed by a `user` message with the original query.
+ *
+ * @param result - Result of `Doredore.enrich()`
+ * @param systemPrompt - Leading system prompt (optional, a generic one is used by default)
+ *
+ * @example
+ * ```typescript
+ * const result = rag.enrich("永代供養について教えて", "faq");
+ * const response = await openai.chat.completions.create({
+ *   model: "gpt-4",
+ *   messages: toChatMessages(result, "You are a support agent."),
+ * });
+ * ```
+ */
+export function toChatMessages(result: EnrichResult, systemPrompt?: string): Array<ChatMessage>
+
 /**
  * doredore - Main class for RAG operations
  */
@@ -74,10 +122,33 @@ export class Doredore {
    *                - "bge-large-en-v1.5" (1024 dim, accurate)
    *                - "multilingual-e5-small" (384 dim, multilingual)
    *                - "multilingual-e5-base" (768 dim, multilingual)
+   *                - GTE, Jina, Nomic and other fastembed models by short name
+   *                  (e.g. "gte-base-en-v1.5"), fastembed variant name or Hugging Face model name
    * @param cacheDir - Model cache directory (optional)
    */
   constructor(dbPath: string, model?: string, cacheDir?: string)
 
+  /**
+   * Close the database connection and release the file lock
+   *
+   * After closing, every other method throws. Calling `close()` again is a no-op.
+   */
+  close(): void
+
+  /** Whether `close()` has been called */
+  readonly closed: boolean
+
+  /** Name of the embedding model in use (e.g. `"bge-small-en-v1.5"`) */
+  readonly modelName: string
+
+  /**
+   * Embedding vector dimension of the model in use (e.g. 384)
+   *
+   * Use it to check that precomputed vectors are compatible before bulk ingest.
+   * @throws If the instance has been closed
+   */
+  readonly dimension: number
+
   // ==========================================================================
   // Collection Management
   // ==========================================================================
@@ -87,9 +158,10 @@ export class Doredore {
    *
    * @param name - Collection name (must be unique)
    * @param description - Optional description
+   * @param model - Optional embedding model for this collection (defaults to the instance model)
    * @returns Collection ID
    */
-  createCollection(name: string, description?: string): number
+  createCollection(name: string, description?: string, model?: string): number
 
   /**
    * Get a collection by name
@@ -123,6 +195,10 @@ export class Doredore {
    * @param content - Document content
    * @param collection - Collection name (optional, default: "default")
    * @param metadata - Optional metadata object
+   * @param externalId - Optional caller-managed ID; an existing document with the same ID is updated
+   * @param skipDuplicates - If true and the collection already has a document with the same
+   *   content (ignoring whitespace differences), return its ID instead of adding a copy
+   *   (default: false; ignored when externalId is given)
    * @returns Document ID
    *
    * @example
@@ -137,9 +213,40 @@ export class Doredore {
   addDocument(
     content: string,
     collection?: string,
-    metadata?: Record<string, any>
+    metadata?: Record<string, any>,
+    externalId?: string,
+    skipDuplicates?: boolean
   ): number
 
+  /**
+   * Add multiple documents to a collection in one call
+   *
+   * Embeddings are generated in batches, which is much faster than calling
+   * addDocument once per document.
+   *
+   * @param documents - Document contents
+   * @param collection - Collection name (optional, default: "default")
+   * @param metadata - One JSON string per document (must have the same length as documents)
+   * @param options - Bulk loading and duplicate handling (optional)
+   * @returns Document IDs in the same order as documents
+   *
+   * @example
+   * ```typescript
+   * const ids = rag.addDocuments(
+   *   ["First answer", "Second answer"],
+   *   "faq",
+   *   [JSON.stringify({ category: "a" }), JSON.stringify({ category: "b" })],
+   *   { skipDuplicates: true }
+   * );
+   * ```
+   */
+  addDocuments(
+    documents: Array<string>,
+    collection?: string,
+    metadata?: Array<string>,
+    options?: AddOptions
+  ): Array<number>
+
   /**
    * Get a document by ID
    *
@@ -148,6 +255,14 @@ export class Doredore {
    */
   getDocument(id: number): Document | null
 
+  /**
+   * Get a document by its external ID
+   *
+   * @param externalId - External ID given to addDocument
+   * @returns Document information or null if not found
+   */
+  getDocumentByExternalId(externalId: string): Document | null
+
   /**
    * List documents in a collection
    *
@@ -162,6 +277,14 @@ export class Doredore {
     offset?: number
   ): Array<Document>
 
+  /**
+   * Count documents, e.g. the total when paging with listDocuments
+   *
+   * @param collection - Collection name (optional, null for all)
+   * @returns Number of documents
+   */
+  countDocuments(collection?: string): number
+
   /**
    * Delete a document by ID
    *
@@ -169,6 +292,14 @@ export class Doredore {
    */
   deleteDocument(id: number): void
 
+  /**
+   * Delete a document by its external ID
+   *
+   * @param externalId - External ID given to addDocument
+   * @returns true if a document was deleted
+   */
+  deleteDocumentByExternalId(externalId: string): boolean
+
   // ==========================================================================
   // Search & Enrich (Main RAG Functions)
   // ==========================================================================
@@ -181,6 +312,10 @@ export class Doredore {
    * @param collections - Multiple collections to search (optional)
    * @param topK - Number of results to return (default: 5)
    * @param threshold - Minimum similarity score (0.0 - 1.0, default: 0.0)
+   * @param mode - "semantic" (default), "keyword" or "hybrid"
+   * @param hybridWeights - [semantic, keyword] weights for hybrid mode
+   * @param metadataFilter - JSON object string of equality conditions; all keys must match
+   * @param minResults - Backfill below-threshold results up to this count (default: 0)
    * @returns Array of search results sorted by similarity
    *
    * @example
@@ -189,6 +324,10 @@ export class Doredore {
    * for (const result of results) {
    *   console.log(`Score: ${result.score}, Content: ${result.content}`);
    * }
+   *
+   * // Only Japanese documents from 2023
+   * const filtered = rag.search("永代供養", "faq", null, 3, 0.0, "semantic", null,
+   *   JSON.stringify({ lang: "ja", year: 2023 }));
    * ```
    */
   search(
@@ -196,6 +335,39 @@ export class Doredore {
     collection?: string,
     collections?: Array<string>,
     topK?: number,
+    threshold?: number,
+    mode?: string,
+    hybridWeights?: Array<number>,
+    metadataFilter?: string,
+    minResults?: number
+  ): Array<SearchResult>
+
+  /**
+   * Embed a text with the instance model (for debugging retrieval quality)
+   *
+   * The E5 "query: " prefix is not added, so the vector is the raw model output
+   *
+   * @param text - Text to embed
+   * @returns Embedding vector (length equals the model dimension)
+   */
+  embed(text: string): Array<number>
+
+  /**
+   * Search with a precomputed embedding vector (semantic only)
+   *
+   * @param vector - Query embedding (length must match the model dimension)
+   * @param collection - Single collection to search (optional)
+   * @param collections - Multiple collections to search (optional)
+   * @param topK - Number of results to return (default: 5)
+   * @param threshold - Minimum similarity score (0.0 - 1.0, default: 0.0)
+   * @returns Array of search results sorted by similarity
+   * @throws If the vector length does not match the model dimension
+   */
+  searchByVector(
+    vector: Array<number>,
+    collection?: string,
+    collections?: Array<string>,
+    topK?: number,
     threshold?: number
   ): Array<SearchResult>
 
@@ -210,6 +382,13 @@ export class Doredore {
    * @param collections - Multiple collections to search (optional)
    * @param topK - Number of source documents (default: 5)
    * @param threshold - Minimum similarity score (default: 0.0)
+   * @param mode - "semantic" (default), "keyword" or "hybrid"
+   * @param hybridWeights - [semantic, keyword] weights for hybrid mode
+   * @param metadataFilter - JSON object string of equality conditions (same as search)
+   * @param contextTemplate - Format of each source in the context; placeholders:
+   *   `{index}`, `{id}`, `{score}`, `{collection}`, `{description}`, `{metadata}`, `{content}`
+   *   (default: `[Source N] (Score: ..., Collection: ...)` followed by the content)
+   * @param contextSeparator - Separator between sources (default: a blank line)
    * @returns Enriched result with context for LLM
    *
    * @example
@@ -231,7 +410,12 @@ export class Doredore {
     collection?: string,
     collections?: Array<string>,
     topK?: number,
-    threshold?: number
+    threshold?: number,
+    mode?: string,
+    hybridWeights?: Array<number>,
+    metadataFilter?: string,
+    contextTemplate?: string,
+    contextSeparator?: string
   ): EnrichResult
 
   // ==========================================================================
@@ -243,8 +427,10 @@ export class Doredore {
    *
    * @param filePath - Path to CSV file
    * @param collection - Collection name (default: "default")
-   * @param contentColumn - Column name for document content (default: "content")
+   * @param contentColumn - Column name for document content (default: auto-detect)
    * @param metadataColumns - Column names to include as metadata (default: [])
+   * @param bulk - Build the keyword index once after the import instead of per row (default: false)
+   * @param inferTypes - Store numeric and boolean metadata cells as numbers/booleans instead of strings (default: true)
    * @returns Number of imported documents
    *
    * @example
@@ -262,7 +448,28 @@ export class Doredore {
     filePath: string,
     collection?: string,
     contentColumn?: string,
-    metadataColumns?: Array<string>
+    metadataColumns?: Array<string>,
+    bulk?: boolean,
+    inferTypes?: boolean
+  ): number
+
+  /**
+   * Import documents from a JSONL file (one JSON object per line)
+   *
+   * The metadata object is stored as-is, so numbers, booleans and nested
+   * values keep their types. Blank lines are skipped.
+   *
+   * @param filePath - Path to JSONL file
+   * @param collection - Collection name (default: "default")
+   * @param contentField - Field holding the document content (default: "content")
+   * @param metadataField - Field holding the metadata object (default: "metadata")
+   * @returns Number of imported documents
+   */
+  importJsonl(
+    filePath: string,
+    collection?: string,
+    contentField?: string,
+    metadataField?: string
   ): number
 
   /**
@@ -1,11 +1,16 @@
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
 use doredore_core::{
+    AddOptions,
+    ChatMessage,
     Collection,
+    CsvImportOptions,
     Doredore as CoreDoredore,
     SearchResult,
     EnrichResult,
+    MetadataFilter,
     SearchMode,
+    SearchOptions,
 };
 use doredore_core::core::collection::Document;
 
@@ -22,6 +27,7 @@ pub struct JsCollection {
     pub name: String,
     pub description: Option<String>,
     pub created_at: String,
+    pub embedding_model: Option<String>,
 }
 
 impl From<Collection> for JsCollection {
@@ -31,6 +37,7 @@ impl From<Collection> for JsCollection {
             name: c.name,
             description: c.description,
             created_at: c.created_at,
+            embedding_model: c.embedding_model,
         }
     }
 }
@@ -46,6 +53,7 @@ pub struct JsDocument {
     pub content: String,
     pub metadata: Option<String>,
     pub created_at: String,
+    pub external_id: Option<String>,
 }
 
 impl From<Document> for JsDocument {
@@ -56,6 +64,7 @@ impl From<Document> for JsDocument {
             content: d.content,
             metadata: d.metadata.map(|m| m.to_string()),
             created_at: d.created_at,
+            external_id: d.external_id,
         }
     }
 }
@@ -69,8 +78,12 @@ pub struct JsSearchResult {
     pub document_id: i64,
     pub content: String,
     pub score: f64,
+    /// 検索結果内の順位（1始まり）
+    pub rank: u32,
     pub collection: String,
     pub metadata: Option<String>,
+    /// `min_results` を満たすために閾値未満から補った結果か
+    pub backfilled: bool,
 }
 
 impl From<SearchResult> for JsSearchResult {
@@ -79,8 +92,10 @@ impl From<SearchResult> for JsSearchResult {
             document_id: r.document_id,
             content: r.content,
             score: r.score as f64,
+            rank: r.rank as u32,
             collection: r.collection_name,
             metadata: r.metadata.map(|m| m.to_string()),
+            backfilled: r.backfilled,
         }
     }
 }
@@ -106,13 +121,95 @@ impl From<EnrichResult> for JsEnrichResult {
     }
 }
 
+// ============================================================================
+// AddOptions
+// ============================================================================
+
+/// Options for `addDocuments`
+#[napi(object)]
+pub struct JsAddOptions {
+    /// Build the keyword index once after the insert instead of per document
+    pub bulk: Option<bool>,
+    /// Return the existing ID instead of adding a document with identical content
+    pub skip_duplicates: Option<bool>,
+}
+
+impl From<JsAddOptions> for AddOptions {
+    fn from(o: JsAddOptions) -> Self {
+        Self {
+            bulk: o.bulk.unwrap_or(false),
+            skip_duplicates: o.skip_duplicates.unwrap_or(false),
+        }
+    }
+}
+
+// ============================================================================
+// ChatMessage
+// ============================================================================
+
+#[napi(object)]
+pub struct JsChatMessage {
+    /// "system" or "user"
+    pub role: String,
+    pub content: String,
+}
+
+impl From<ChatMessage> for JsChatMessage {
+    fn from(m: ChatMessage) -> Self {
+        Self {
+            role: m.role,
+            content: m.content,
+        }
+    }
+}
+
+/// Convert an enrich result into chat API messages (system context + user question)
+#[napi]
+pub fn to_chat_messages(result: JsEnrichResult, system_prompt: Option<String>) -> Vec<JsChatMessage> {
+    let sources = result
+        .sources
+        .into_iter()
+        .map(|s| {
+            let mut source = SearchResult::new(
+                s.document_id,
+                s.content,
+                s.score as f32,
+                s.metadata.and_then(|m| serde_json::from_str(&m).ok()),
+                s.collection,
+            );
+            source.rank = s.rank as usize;
+            source
+        })
+        .collect();
+    let enriched = EnrichResult {
+        question: result.query,
+        context: result.context,
+        sources,
+    };
+
+    enriched
+        .to_chat_messages(system_prompt.as_deref())
+        .into_iter()
+        .map(Into::into)
+        .collect()
+}
+
 // ============================================================================
 // Doredore (Main Class)
 // ============================================================================
 
 #[napi]
 pub struct Doredore {
-    inner: CoreDoredore,
+    /// `close()` 後は `None`（データベース接続を解放済み）
+    inner: Option<CoreDoredore>,
+}
+
+impl Doredore {
+    fn inner(&self) -> Result<&CoreDoredore> {
+        self.inner
+            .as_ref()
+            .ok_or_else(|| Error::from_reason("Doredore instance is closed"))
+    }
 }
 
 #[napi]
@@ -136,29 +233,56 @@ impl Doredore {
         )
         .map_err(|e| Error::from_reason(e.to_string()))?;
 
-        Ok(Self { inner })
+        Ok(Self { inner: Some(inner) })
+    }
+
+    /// Close the database connection and release the file lock
+    ///
+    /// Any later method call throws an error. Calling `close()` again is a no-op.
+    #[napi]
+    pub fn close(&mut self) {
+        self.inner = None;
+    }
+
+    /// Whether `close()` has been called
+    #[napi(getter)]
+    pub fn closed(&self) -> bool {
+        self.inner.is_none()
+    }
+
+    /// Name of the embedding model in use
+    #[napi(getter)]
+    pub fn model_name(&self) -> Result<String> {
+        Ok(self.inner()?.model_name().to_string())
+    }
+
+    /// Embedding vector dimension of the model in use
+    #[napi(getter)]
+    pub fn dimension(&self) -> Result<u32> {
+        Ok(self.inner()?.dimension() as u32)
     }
 
     // ========================================================================
     // Collection Management
     // ========================================================================
 
-    /// Create a new collection
+    /// Create a new collection, optionally with its own embedding model
     #[napi]
     pub fn create_collection(
         &self,
         name: String,
         description: Option<String>,
+        model: Option<String>,
     ) -> Result<i64> {
-        self.inner
-            .create_collection(&name, description.as_deref())
+        self.inner()?
+            .create_collection_with_model(&name, description.as_deref(), model.as_deref())
             .map_err(|e| Error::from_reason(e.to_string()))
     }
 
     /// Get a collection by name
     #[napi]
     pub fn get_collection(&self, name: String) -> Result<JsCollection> {
-        self.inner
+        self.inner()?
             .get_collection(&name)
             .map(Into::into)
             .map_err(|e| Error::from_reason(e.to_string()))
@@ -167,7 +291,7 @@ impl Doredore {
     /// List all collections
     #[napi]
     pub fn list_collections(&self) -> Result<Vec<JsCollection>> {
-        self.inner
+        self.inner()?
             .list_collections()
             .map(|collections| collections.into_iter().map(Into::into).collect())
             .map_err(|e| Error::from_reason(e.to_string()))
@@ -176,7 +300,7 @@ impl Doredore {
     /// Delete a collection
     #[napi]
     pub fn delete_collection(&self, name: String) -> Result<bool> {
-        self.inner
+        self.inner()?
             .delete_collection(&name)
             .map_err(|e| Error::from_reason(e.to_string()))
     }
@@ -186,12 +310,18 @@ impl Doredore {
     // ========================================================================
 
     /// Add a document to a collection
+    ///
+    /// If `external_id` is given and a document with that ID exists, it is updated instead.
+    /// With `skip_duplicates`, content that already exists in the collection returns the
+    /// existing document's ID instead of adding a copy
     #[napi]
     pub fn add_document(
         &self,
         content: String,
         collection: Option<String>,
         metadata: Option<String>,
+        external_id: Option<String>,
+        skip_duplicates: Option<bool>,
     ) -> Result<i64> {
         let collection_name = collection.unwrap_or_else(|| "default".to_string());
 
@@ -203,20 +333,86 @@ impl Doredore {
             })
             .transpose()?;
 
-        self.inner
-            .add_document(&content, &collection_name, metadata_value.as_ref())
+        let rag = self.inner()?;
+        let result = match external_id {
+            Some(external_id) => rag.add_document_with_external_id(
+                &content,
+                &collection_name,
+                metadata_value.as_ref(),
+                &external_id,
+            ),
+            None if skip_duplicates.unwrap_or(false) => {
+                rag.add_document_unique(&content, &collection_name, metadata_value.as_ref())
+            }
+            None => rag.add_document(&content, &collection_name, metadata_value.as_ref()),
+        };
+        result.map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Add multiple documents to a collection, generating their embeddings in batches
+    ///
+    /// `metadata` holds one JSON string per document and must have the same length as `documents`
+    #[napi]
+    pub fn add_documents(
+        &self,
+        documents: Vec<String>,
+        collection: Option<String>,
+        metadata: Option<Vec<String>>,
+        options: Option<JsAddOptions>,
+    ) -> Result<Vec<i64>> {
+        let collection_name = collection.unwrap_or_else(|| "default".to_string());
+
+        let metadata_values = metadata
+            .map(|list| {
+                if list.len() != documents.len() {
+                    return Err(Error::from_reason(format!(
+                        "Metadata length ({}) does not match documents length ({})",
+                        list.len(),
+                        documents.len()
+                    )));
+                }
+                list.iter()
+                    .enumerate()
+                    .map(|(i, json_str)| {
+                        serde_json::from_str(json_str).map_err(|e| {
+                            Error::from_reason(format!(
+                                "Metadata parsing failed at index {}: {}",
+                                i, e
+                            ))
+                        })
+                    })
+                    .collect::<Result<Vec<serde_json::Value>>>()
+            })
+            .transpose()?;
+
+        self.inner()?
+            .add_documents_with_options(
+                documents,
+                &collection_name,
+                metadata_values,
+                &options.map(AddOptions::from).unwrap_or_default(),
+            )
             .map_err(|e| Error::from_reason(e.to_string()))
     }
 
     /// Get a document by ID
     #[napi]
     pub fn get_document(&self, id: i64) -> Result<JsDocument> {
-        self.inner
+        self.inner()?
             .get_document(id)
             .map(Into::into)
             .map_err(|e| Error::from_reason(e.to_string()))
     }
 
+    /// Get a document by its external ID (null if not found)
+    #[napi]
+    pub fn get_document_by_external_id(&self, external_id: String) -> Result<Option<JsDocument>> {
+        self.inner()?
+            .get_document_by_external_id(&external_id)
+            .map(|doc| doc.map(Into::into))
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
     /// List documents in a collection
     #[napi]
     pub fn list_documents(
@@ -229,25 +425,45 @@ impl Doredore {
         let limit_val = limit.unwrap_or(100);
         let offset_val = offset.unwrap_or(0);
 
-        self.inner
+        self.inner()?
             .list_documents(collection_name, limit_val, offset_val)
             .map(|docs| docs.into_iter().map(Into::into).collect())
             .map_err(|e| Error::from_reason(e.to_string()))
     }
 
+    /// Count documents (all collections if omitted), e.g. the total for paging listDocuments
+    #[napi]
+    pub fn count_documents(&self, collection: Option<String>) -> Result<i64> {
+        self.inner()?
+            .count_documents(collection.as_deref())
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
     /// Delete a document by ID
     #[napi]
     pub fn delete_document(&self, id: i64) -> Result<bool> {
-        self.inner
+        self.inner()?
             .delete_document(id)
             .map_err(|e| Error::from_reason(e.to_string()))
     }
 
+    /// Delete a document by its external ID
+    #[napi]
+    pub fn delete_document_by_external_id(&self, external_id: String) -> Result<bool> {
+        self.inner()?
+            .delete_document_by_external_id(&external_id)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
     // ========================================================================
     // Search & Enrich
     // ========================================================================
 
     /// Search for similar documents
+    ///
+    /// `metadata_filter` is a JSON object string of equality conditions
+    /// (e.g. `{"lang": "ja", "year": 2023}`); all keys must match.
+    /// `min_results` backfills below-threshold results up to that count
     #[napi]
     pub fn search(
         &self,
@@ -258,6 +474,8 @@ impl Doredore {
         threshold: Option<f64>,
         mode: Option<String>,
         hybrid_weights: Option<Vec<f64>>,
+        metadata_filter: Option<String>,
+        min_results: Option<u32>,
     ) -> Result<Vec<JsSearchResult>> {
         let top_k_val = top_k.unwrap_or(5) as usize;
         let threshold_val = threshold.unwrap_or(0.0) as f32;
@@ -282,21 +500,76 @@ impl Doredore {
             }
         });
 
-        self.inner
-            .search(
+        let options = SearchOptions {
+            mode: search_mode,
+            threshold: threshold_val,
+            hybrid_weights: weights,
+            metadata_filter: parse_metadata_filter(metadata_filter)?,
+            min_results: min_results.unwrap_or(0) as usize,
+            ..Default::default()
+        };
+
+        self.inner()?
+            .search_with_options(
                 &query,
                 collection.as_deref(),
                 collections.as_deref(),
                 top_k_val,
-                threshold_val,
-                search_mode,
-                weights,
+                &options,
+            )
+            .map(|results| results.into_iter().map(Into::into).collect())
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Embed a text with the instance model (for debugging and external similarity checks)
+    #[napi]
+    pub fn embed(&self, text: String) -> Result<Vec<f64>> {
+        self.inner()?
+            .embed_text(&text)
+            .map(|vector| vector.into_iter().map(f64::from).collect())
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Search with a precomputed embedding vector (semantic only)
+    #[napi]
+    pub fn search_by_vector(
+        &self,
+        vector: Vec<f64>,
+        collection: Option<String>,
+        collections: Option<Vec<String>>,
+        top_k: Option<u32>,
+        threshold: Option<f64>,
+    ) -> Result<Vec<JsSearchResult>> {
+        // 次元数の不一致はRust側に渡す前に検出
+        let expected = self.inner()?.dimension();
+        if vector.len() != expected {
+            return Err(Error::from_reason(format!(
+                "Vector dimension mismatch: expected {}, got {}",
+                expected,
+                vector.len()
+            )));
+        }
+
+        let vector: Vec<f32> = vector.into_iter().map(|v| v as f32).collect();
+
+        self.inner()?
+            .search_by_vector(
+                &vector,
+                collection.as_deref(),
+                collections.as_deref(),
+                top_k.unwrap_or(5) as usize,
+                threshold.unwrap_or(0.0) as f32,
             )
             .map(|results| results.into_iter().map(Into::into).collect())
             .map_err(|e| Error::from_reason(e.to_string()))
     }
 
     /// Enrich a query with context (main RAG function)
+    ///
+    /// `metadata_filter` works the same as in `search`.
+    /// `context_template` formats each source (placeholders: `{index}`, `{id}`, `{score}`,
+    /// `{collection}`, `{description}`, `{metadata}`, `{content}`) and `context_separator`
+    /// joins them (default: a blank line)
     #[napi]
     pub fn enrich(
         &self,
@@ -307,6 +580,9 @@ impl Doredore {
         threshold: Option<f64>,
         mode: Option<String>,
         hybrid_weights: Option<Vec<f64>>,
+        metadata_filter: Option<String>,
+        context_template: Option<String>,
+        context_separator: Option<String>,
     ) -> Result<JsEnrichResult> {
         let top_k_val = top_k.unwrap_or(5) as usize;
         let threshold_val = threshold.unwrap_or(0.0) as f32;
@@ -331,15 +607,23 @@ impl Doredore {
             }
         });
 
-        self.inner
-            .enrich(
+        let options = SearchOptions {
+            mode: search_mode,
+            threshold: threshold_val,
+            hybrid_weights: weights,
+            metadata_filter: parse_metadata_filter(metadata_filter)?,
+            context_template,
+            context_separator,
+            ..Default::default()
+        };
+
+        self.inner()?
+            .enrich_with_options(
                 &query,
                 collection.as_deref(),
                 collections.as_deref(),
                 top_k_val,
-                threshold_val,
-                search_mode,
-                weights,
+                &options,
             )
             .map(Into::into)
             .map_err(|e| Error::from_reason(e.to_string()))
@@ -357,17 +641,47 @@ impl Doredore {
         collection: Option<String>,
         content_column: Option<String>,
         metadata_columns: Option<Vec<String>>,
+        bulk: Option<bool>,
+        infer_types: Option<bool>,
     ) -> Result<i32> {
         let collection_name = collection.unwrap_or_else(|| "default".to_string());
-        let content_col = content_column.unwrap_or_else(|| "content".to_string());
-        let metadata_cols = metadata_columns.unwrap_or_else(Vec::new);
+        // content_column未指定の場合は本文カラムを自動検出
+        let options = CsvImportOptions {
+            content_column: content_column.unwrap_or_default(),
+            metadata_columns: Some(metadata_columns.unwrap_or_else(Vec::new)),
+            infer_types: infer_types.unwrap_or(true),
+            add_options: AddOptions {
+                bulk: bulk.unwrap_or(false),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
 
-        self.inner
-            .import_csv(
+        self.inner()?
+            .import_csv_detailed(&file_path, &collection_name, &options)
+            .map(|(count, _)| count as i32)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Import documents from a JSONL file (one JSON object per line)
+    #[napi]
+    pub fn import_jsonl(
+        &self,
+        file_path: String,
+        collection: Option<String>,
+        content_field: Option<String>,
+        metadata_field: Option<String>,
+    ) -> Result<i32> {
+        let collection_name = collection.unwrap_or_else(|| "default".to_string());
+        let content_field = content_field.unwrap_or_else(|| "content".to_string());
+        let metadata_field = metadata_field.unwrap_or_else(|| "metadata".to_string());
+
+        self.inner()?
+            .import_jsonl(
                 &file_path,
                 &collection_name,
-                &content_col,
-                Some(metadata_cols),
+                &content_field,
+                Some(&metadata_field),
             )
             .map(|count| count as i32)
             .map_err(|e| Error::from_reason(e.to_string()))
@@ -380,9 +694,19 @@ impl Doredore {
         file_path: String,
         collection: Option<String>,
     ) -> Result<i32> {
-        self.inner
+        self.inner()?
             .export_csv(&file_path, collection.as_deref())
             .map(|count| count as i32)
             .map_err(|e| Error::from_reason(e.to_string()))
     }
 }
+
+/// JSON文字列のメタデータ条件（`{"lang": "ja"}` など）を `MetadataFilter` に変換
+fn parse_metadata_filter(filter: Option<String>) -> Result<MetadataFilter> {
+    let Some(json_str) = filter else {
+        return Ok(MetadataFilter::default());
+    };
+    let value: serde_json::Value = serde_json::from_str(&json_str)
+        .map_err(|e| Error::from_reason(format!("Metadata filter parsing failed: {}", e)))?;
+    MetadataFilter::from_json(&value).map_err(|e| Error::from_reason(e.to_string()))
+}
@@ -1,44 +1,121 @@
 use pyo3::prelude::*;
 use pyo3::types::PyDict;
-use doredore_core::{Collection, EnrichResult, Doredore as CoreDoredore, SearchResult, SearchMode};
+use doredore_core::{AddOptions, Collection, CsvImportOptions, EmbeddingFormat, EnrichResult, EvalPoint, KeywordMatch, KeywordNormalization, Doredore as CoreDoredore, MetadataFilter, SearchResult, SearchMode, SearchOptions};
 use doredore_core::core::collection::Document;
+use std::collections::HashMap;
 
 #[pyclass]
 struct PyDoredore {
-    inner: CoreDoredore,
+    /// `close()` 後は `None`（データベース接続を解放済み）
+    inner: Option<CoreDoredore>,
+}
+
+impl PyDoredore {
+    fn inner(&self) -> PyResult<&CoreDoredore> {
+        self.inner.as_ref().ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "Doredore instance is closed".to_string(),
+            )
+        })
+    }
 }
 
 #[pymethods]
 impl PyDoredore {
+    /// read_only: 既存のデータベースを読み取り専用で開く（変更操作は RuntimeError）
+    /// embedding_format: 追加するEmbeddingの保存形式（"f32"（デフォルト）または "int8"）
+    /// embedding_cache: 同じテキストのEmbeddingを再利用する（データベースにキャッシュする）
     #[new]
-    #[pyo3(signature = (db_path, model=None, cache_dir=None))]
+    #[pyo3(signature = (db_path, model=None, cache_dir=None, read_only=false, embedding_format=None, embedding_cache=false))]
     fn new(
         db_path: String,
         model: Option<String>,
         cache_dir: Option<String>,
+        read_only: bool,
+        embedding_format: Option<String>,
+        embedding_cache: bool,
     ) -> PyResult<Self> {
-        let inner = CoreDoredore::new(
-            db_path,
-            model.as_deref(),
-            cache_dir.as_deref(),
-        )
+        let format = embedding_format
+            .map(|name| {
+                EmbeddingFormat::parse(&name.to_lowercase()).ok_or_else(|| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Invalid embedding format: '{}'. Use 'f32' or 'int8'",
+                        name
+                    ))
+                })
+            })
+            .transpose()?;
+
+        let mut inner = if read_only {
+            CoreDoredore::new_read_only(db_path, model.as_deref(), cache_dir.as_deref())
+        } else {
+            CoreDoredore::new(db_path, model.as_deref(), cache_dir.as_deref())
+        }
         .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
 
-        Ok(Self { inner })
+        if let Some(format) = format {
+            inner
+                .set_embedding_format(format)
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
+        }
+        inner.set_embedding_cache(embedding_cache);
+
+        Ok(Self { inner: Some(inner) })
+    }
+
+    /// データベース接続を閉じてファイルロックを解放する
+    ///
+    /// 以降のメソッド呼び出しは RuntimeError になる。複数回呼んでも問題ない
+    fn close(&mut self) {
+        self.inner = None;
+    }
+
+    /// Embeddingキャッシュを空にして、削除した件数を返す
+    fn clear_embedding_cache(&self) -> PyResult<usize> {
+        self.inner()?
+            .clear_embedding_cache()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+    }
+
+    #[getter]
+    fn read_only(&self) -> PyResult<bool> {
+        Ok(self.inner()?.is_read_only())
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.inner.is_none()
+    }
+
+    /// 使用中のEmbeddingモデル名
+    #[getter]
+    fn model_name(&self) -> PyResult<String> {
+        Ok(self.inner()?.model_name().to_string())
+    }
+
+    /// Embeddingベクトルの次元数（取り込み前に外部のベクトルとの互換性を確認する用途）
+    #[getter]
+    fn dimension(&self) -> PyResult<usize> {
+        Ok(self.inner()?.dimension())
     }
 
     // Collection methods
 
-    #[pyo3(signature = (name, description=None))]
-    fn create_collection(&self, name: String, description: Option<String>) -> PyResult<i64> {
-        self.inner
-            .create_collection(&name, description.as_deref())
+    #[pyo3(signature = (name, description=None, model=None))]
+    fn create_collection(
+        &self,
+        name: String,
+        description: Option<String>,
+        model: Option<String>,
+    ) -> PyResult<i64> {
+        self.inner()?
+            .create_collection_with_model(&name, description.as_deref(), model.as_deref())
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
     }
 
     fn get_collection(&self, name: String) -> PyResult<PyCollection> {
         let coll = self
-            .inner
+            .inner()?
             .get_collection(&name)
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
 
@@ -47,7 +124,7 @@ impl PyDoredore {
 
     fn list_collections(&self) -> PyResult<Vec<PyCollection>> {
         let colls = self
-            .inner
+            .inner()?
             .list_collections()
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
 
@@ -55,36 +132,47 @@ impl PyDoredore {
     }
 
     fn delete_collection(&self, name: String) -> PyResult<bool> {
-        self.inner
+        self.inner()?
             .delete_collection(&name)
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
     }
 
     // Document methods
 
-    #[pyo3(signature = (content, collection="default".to_string(), metadata=None))]
+    /// external_id: 呼び出し側が管理するID。同じ外部IDのドキュメントがあれば更新する（upsert）
+    #[pyo3(signature = (content, collection="default".to_string(), metadata=None, skip_duplicates=false, external_id=None))]
     fn add_document(
         &self,
         content: String,
         collection: String,
         metadata: Option<&Bound<'_, PyDict>>,
+        skip_duplicates: bool,
+        external_id: Option<String>,
     ) -> PyResult<i64> {
         let meta = metadata
             .map(|d| pythonize::depythonize(d.as_any()))
             .transpose()
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
 
-        self.inner
-            .add_document(&content, &collection, meta.as_ref())
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+        let rag = self.inner()?;
+        let result = if let Some(external_id) = external_id {
+            rag.add_document_with_external_id(&content, &collection, meta.as_ref(), &external_id)
+        } else if skip_duplicates {
+            rag.add_document_unique(&content, &collection, meta.as_ref())
+        } else {
+            rag.add_document(&content, &collection, meta.as_ref())
+        };
+        result.map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
     }
 
-    #[pyo3(signature = (documents, collection="default".to_string(), metadata=None))]
+    #[pyo3(signature = (documents, collection="default".to_string(), metadata=None, bulk=false, skip_duplicates=false))]
     fn add_documents(
         &self,
         documents: Vec<String>,
         collection: String,
         metadata: Option<Vec<Bound<'_, PyDict>>>,
+        bulk: bool,
+        skip_duplicates: bool,
     ) -> PyResult<Vec<i64>> {
         let meta_list = if let Some(meta_vec) = metadata {
             Some(
@@ -98,20 +186,37 @@ impl PyDoredore {
             None
         };
 
-        self.inner
-            .add_documents(documents, &collection, meta_list)
+        self.inner()?
+            .add_documents_with_options(
+                documents,
+                &collection,
+                meta_list,
+                &AddOptions {
+                    bulk,
+                    skip_duplicates,
+                },
+            )
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
     }
 
     fn get_document(&self, document_id: i64) -> PyResult<PyDocument> {
         let doc = self
-            .inner
+            .inner()?
             .get_document(document_id)
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
 
         Ok(PyDocument::from(doc))
     }
 
+    fn get_document_by_external_id(&self, external_id: String) -> PyResult<Option<PyDocument>> {
+        let doc = self
+            .inner()?
+            .get_document_by_external_id(&external_id)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
+
+        Ok(doc.map(PyDocument::from))
+    }
+
     #[pyo3(signature = (collection=None, limit=100, offset=0))]
     fn list_documents(
         &self,
@@ -120,13 +225,32 @@ impl PyDoredore {
         offset: i64,
     ) -> PyResult<Vec<PyDocument>> {
         let docs = self
-            .inner
+            .inner()?
             .list_documents(collection.as_deref(), limit, offset)
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
 
         Ok(docs.into_iter().map(PyDocument::from).collect())
     }
 
+    /// ドキュメント数（list_documents でページングする際の総件数、collection を省略すると全件）
+    #[pyo3(signature = (collection=None))]
+    fn count_documents(&self, collection: Option<String>) -> PyResult<i64> {
+        self.inner()?
+            .count_documents(collection.as_deref())
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+    }
+
+    /// 本文が空（空白のみ）のドキュメントを取得（削除などの整理用）
+    #[pyo3(signature = (collection=None))]
+    fn find_empty_documents(&self, collection: Option<String>) -> PyResult<Vec<PyDocument>> {
+        let docs = self
+            .inner()?
+            .find_empty_documents(collection.as_deref())
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
+
+        Ok(docs.into_iter().map(PyDocument::from).collect())
+    }
+
     #[pyo3(signature = (document_id, content=None, metadata=None))]
     fn update_document(
         &self,
@@ -139,20 +263,37 @@ impl PyDoredore {
             .transpose()
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
 
-        self.inner
+        self.inner()?
             .update_document(document_id, content.as_deref(), meta.as_ref())
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
     }
 
     fn delete_document(&self, document_id: i64) -> PyResult<bool> {
-        self.inner
+        self.inner()?
             .delete_document(document_id)
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
     }
 
+    fn delete_document_by_external_id(&self, external_id: String) -> PyResult<bool> {
+        self.inner()?
+            .delete_document_by_external_id(&external_id)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+    }
+
     // Search methods
 
-    #[pyo3(signature = (query, collection=None, collections=None, top_k=5, threshold=0.0, mode="semantic".to_string(), hybrid_weights=None))]
+    /// candidate_multiplier: ハイブリッド検索で各検索から取得する候補数の倍率（top_k × 倍率）
+    /// normalize_output: 結果の本文の空白を整理する（保存された内容は変更しない）
+    /// fuzzy: キーワード検索で綴りの誤りを許容する（全件スキャンのため遅い、keyword/hybridのみ）
+    /// exclude_empty: 本文が空のドキュメントを検索候補から除外する
+    /// keyword_score_scale: キーワードスコアを正規化するSigmoidの係数（小さいほどスコアの差が広がる、keyword_normalization="sigmoid" のみ）
+    /// keyword_normalization: キーワードスコアの正規化方法（"min_max"（デフォルト）または "sigmoid"、keyword/hybridのみ）
+    /// metadata_filter: メタデータの等価条件（例: {"lang": "ja", "year": 2023}、複数のキーはAND）
+    /// mmr_lambda: MMRで再ランキングする際の関連度と多様性のバランス（0.0〜1.0、小さいほど重複を避ける）
+    /// collapse_chunks: チャンク分割したドキュメントを親ドキュメントごとに1件にまとめる
+    /// collection_weights: コレクションごとのスコアの重み（例: {"manual": 2.0, "forum": 0.5}、指定のないコレクションは1.0）
+    /// keyword_match: 空白区切りの複数の語の組み合わせ方（"all"（デフォルト、AND）または "any"（OR）、keyword/hybridのみ）
+    #[pyo3(signature = (query, collection=None, collections=None, top_k=5, threshold=0.0, mode="semantic".to_string(), hybrid_weights=None, candidate_multiplier=2, normalize_output=false, fuzzy=false, exclude_empty=false, min_results=0, keyword_score_scale=10.0, metadata_filter=None, mmr_lambda=None, collapse_chunks=false, keyword_normalization="min_max".to_string(), collection_weights=None, keyword_match="all".to_string()))]
     fn search(
         &self,
         query: String,
@@ -162,6 +303,18 @@ impl PyDoredore {
         threshold: f32,
         mode: String,
         hybrid_weights: Option<(f32, f32)>,
+        candidate_multiplier: usize,
+        normalize_output: bool,
+        fuzzy: bool,
+        exclude_empty: bool,
+        min_results: usize,
+        keyword_score_scale: f32,
+        metadata_filter: Option<&Bound<'_, PyDict>>,
+        mmr_lambda: Option<f32>,
+        collapse_chunks: bool,
+        keyword_normalization: String,
+        collection_weights: Option<HashMap<String, f32>>,
+        keyword_match: String,
     ) -> PyResult<Vec<PySearchResult>> {
         // モード文字列をSearchModeに変換
         let search_mode = match mode.to_lowercase().as_str() {
@@ -173,23 +326,207 @@ impl PyDoredore {
             )),
         };
 
+        let keyword_normalization = match keyword_normalization.to_lowercase().as_str() {
+            "sigmoid" => KeywordNormalization::Sigmoid,
+            "min_max" => KeywordNormalization::MinMax,
+            _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                format!("Invalid keyword normalization: '{}'. Use 'sigmoid' or 'min_max'", keyword_normalization)
+            )),
+        };
+
+        let keyword_match = match keyword_match.to_lowercase().as_str() {
+            "all" => KeywordMatch::All,
+            "any" => KeywordMatch::Any,
+            _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                format!("Invalid keyword match: '{}'. Use 'all' or 'any'", keyword_match)
+            )),
+        };
+
+        let options = SearchOptions {
+            mode: search_mode,
+            threshold,
+            hybrid_weights,
+            candidate_multiplier,
+            normalize_output,
+            fuzzy,
+            exclude_empty,
+            min_results,
+            keyword_score_scale,
+            keyword_normalization,
+            keyword_match,
+            metadata_filter: metadata_filter_from_py(metadata_filter)?,
+            mmr_lambda,
+            collapse_chunks,
+            collection_weights: collection_weights.unwrap_or_default(),
+            ..Default::default()
+        };
+
         let results = self
-            .inner
-            .search(
+            .inner()?
+            .search_with_options(
+                &query,
+                collection.as_deref(),
+                collections.as_deref(),
+                top_k,
+                &options,
+            )
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
+
+        Ok(results.into_iter().map(PySearchResult::from).collect())
+    }
+
+    /// ドキュメントIDとスコアのみを返す検索（本文・メタデータを取得しないため高速）
+    #[pyo3(signature = (query, collection=None, collections=None, top_k=5, threshold=0.0, mode="semantic".to_string(), hybrid_weights=None))]
+    fn search_ids(
+        &self,
+        query: String,
+        collection: Option<String>,
+        collections: Option<Vec<String>>,
+        top_k: usize,
+        threshold: f32,
+        mode: String,
+        hybrid_weights: Option<(f32, f32)>,
+    ) -> PyResult<Vec<(i64, f32)>> {
+        // モード文字列をSearchModeに変換
+        let search_mode = match mode.to_lowercase().as_str() {
+            "semantic" => SearchMode::Semantic,
+            "keyword" => SearchMode::Keyword,
+            "hybrid" => SearchMode::Hybrid,
+            _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                format!("Invalid search mode: '{}'. Use 'semantic', 'keyword', or 'hybrid'", mode)
+            )),
+        };
+
+        let options = SearchOptions {
+            mode: search_mode,
+            threshold,
+            hybrid_weights,
+            ..Default::default()
+        };
+
+        self.inner()?
+            .search_ids(
                 &query,
                 collection.as_deref(),
                 collections.as_deref(),
                 top_k,
+                &options,
+            )
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+    }
+
+    /// 閾値ごとの適合率・再現率・F1を計算（閾値チューニング用）
+    ///
+    /// queries: [(クエリ, [正解ドキュメントID, ...]), ...]
+    #[pyo3(signature = (queries, thresholds, mode="semantic".to_string(), collection=None, top_k=5))]
+    fn evaluate(
+        &self,
+        queries: Vec<(String, Vec<i64>)>,
+        thresholds: Vec<f32>,
+        mode: String,
+        collection: Option<String>,
+        top_k: usize,
+    ) -> PyResult<Vec<PyEvalPoint>> {
+        // モード文字列をSearchModeに変換
+        let search_mode = match mode.to_lowercase().as_str() {
+            "semantic" => SearchMode::Semantic,
+            "keyword" => SearchMode::Keyword,
+            "hybrid" => SearchMode::Hybrid,
+            _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                format!("Invalid search mode: '{}'. Use 'semantic', 'keyword', or 'hybrid'", mode)
+            )),
+        };
+
+        let points = self.inner()?
+            .evaluate(&queries, &thresholds, search_mode, collection.as_deref(), top_k)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
+
+        Ok(points.into_iter().map(PyEvalPoint::from).collect())
+    }
+
+    /// テキストをインスタンスのモデルでベクトル化（デバッグ・外部での類似度計算用）
+    fn embed(&self, text: String) -> PyResult<Vec<f32>> {
+        self.inner()?
+            .embed_text(&text)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+    }
+
+    #[pyo3(signature = (vector, collection=None, collections=None, top_k=5, threshold=0.0))]
+    fn search_by_vector(
+        &self,
+        vector: Vec<f32>,
+        collection: Option<String>,
+        collections: Option<Vec<String>>,
+        top_k: usize,
+        threshold: f32,
+    ) -> PyResult<Vec<PySearchResult>> {
+        // 次元数の不一致はRust側に渡す前にValueErrorとして検出
+        let expected = self.inner()?.dimension();
+        if vector.len() != expected {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Vector dimension mismatch: expected {}, got {}",
+                expected,
+                vector.len()
+            )));
+        }
+
+        let results = self
+            .inner()?
+            .search_by_vector(
+                &vector,
+                collection.as_deref(),
+                collections.as_deref(),
+                top_k,
+                threshold,
+            )
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
+
+        Ok(results.into_iter().map(PySearchResult::from).collect())
+    }
+
+    /// ドキュメント同士のコサイン類似度行列（ネストしたリスト。`numpy.array()` にそのまま渡せる）
+    ///
+    /// 計算量はO(n²·d)（nはID数、dは次元数）のため、大量のドキュメントでは対象を絞って呼ぶ
+    fn similarity_matrix(&self, ids: Vec<i64>) -> PyResult<Vec<Vec<f32>>> {
+        self.inner()?
+            .similarity_matrix(&ids)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+    }
+
+    #[pyo3(signature = (queries, weights=None, collection=None, collections=None, top_k=5, threshold=0.0))]
+    fn search_multi_query(
+        &self,
+        queries: Vec<String>,
+        weights: Option<Vec<f32>>,
+        collection: Option<String>,
+        collections: Option<Vec<String>>,
+        top_k: usize,
+        threshold: f32,
+    ) -> PyResult<Vec<PySearchResult>> {
+        let results = self
+            .inner()?
+            .search_multi_query(
+                &queries,
+                weights.as_deref(),
+                collection.as_deref(),
+                collections.as_deref(),
+                top_k,
                 threshold,
-                search_mode,
-                hybrid_weights,
             )
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
 
         Ok(results.into_iter().map(PySearchResult::from).collect())
     }
 
-    #[pyo3(signature = (query, collection=None, collections=None, top_k=3, threshold=0.0, mode="semantic".to_string(), hybrid_weights=None))]
+    /// context_prefix / context_suffix: コンテキストの前後に追加する文字列（指示文など）
+    /// include_collection_description: 各ソースの見出しにコレクションの説明を含める
+    /// metadata_filter: メタデータの等価条件（`search` と同じ）
+    /// mmr_lambda: MMRでの再ランキング（`search` と同じ）
+    /// max_context_chars: コンテキストの最大文字数（収まらないソースは切り詰め・除外する）
+    /// context_template: 各ソースの書式（{index} {id} {score} {collection} {description} {metadata} {content}）
+    /// context_separator: ソース間の区切り（デフォルト: 空行）
+    /// collection_weights: コレクションごとのスコアの重み（`search` と同じ）
+    #[pyo3(signature = (query, collection=None, collections=None, top_k=3, threshold=0.0, mode="semantic".to_string(), hybrid_weights=None, context_prefix=None, context_suffix=None, include_collection_description=false, metadata_filter=None, mmr_lambda=None, max_context_chars=None, context_template=None, context_separator=None, collection_weights=None))]
     fn enrich(
         &self,
         query: String,
@@ -199,6 +536,15 @@ impl PyDoredore {
         threshold: f32,
         mode: String,
         hybrid_weights: Option<(f32, f32)>,
+        context_prefix: Option<String>,
+        context_suffix: Option<String>,
+        include_collection_description: bool,
+        metadata_filter: Option<&Bound<'_, PyDict>>,
+        mmr_lambda: Option<f32>,
+        max_context_chars: Option<usize>,
+        context_template: Option<String>,
+        context_separator: Option<String>,
+        collection_weights: Option<HashMap<String, f32>>,
     ) -> PyResult<PyEnrichResult> {
         // モード文字列をSearchModeに変換
         let search_mode = match mode.to_lowercase().as_str() {
@@ -210,16 +556,30 @@ impl PyDoredore {
             )),
         };
 
+        let options = SearchOptions {
+            mode: search_mode,
+            threshold,
+            hybrid_weights,
+            context_prefix,
+            context_suffix,
+            include_collection_description,
+            max_context_chars,
+            context_template,
+            context_separator,
+            metadata_filter: metadata_filter_from_py(metadata_filter)?,
+            mmr_lambda,
+            collection_weights: collection_weights.unwrap_or_default(),
+            ..Default::default()
+        };
+
         let result = self
-            .inner
-            .enrich(
+            .inner()?
+            .enrich_with_options(
                 &query,
                 collection.as_deref(),
                 collections.as_deref(),
                 top_k,
-                threshold,
-                search_mode,
-                hybrid_weights,
+                &options,
             )
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
 
@@ -228,22 +588,52 @@ impl PyDoredore {
 
     // Import/Export methods
 
-    #[pyo3(signature = (file_path, collection, content_column="content".to_string(), metadata_columns=None))]
+    /// infer_types: メタデータカラムの数値・真偽値をその型で保存する（False ですべて文字列）
+    #[pyo3(signature = (file_path, collection, content_column=None, metadata_columns=None, bulk=false, infer_types=true))]
     fn import_csv(
         &self,
         file_path: String,
         collection: String,
-        content_column: String,
+        content_column: Option<String>,
         metadata_columns: Option<Vec<String>>,
+        bulk: bool,
+        infer_types: bool,
     ) -> PyResult<usize> {
-        self.inner
-            .import_csv(&file_path, &collection, &content_column, metadata_columns)
+        // content_column未指定の場合は本文カラムを自動検出
+        let options = CsvImportOptions {
+            content_column: content_column.unwrap_or_default(),
+            metadata_columns,
+            infer_types,
+            add_options: AddOptions {
+                bulk,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        self.inner()?
+            .import_csv_detailed(&file_path, &collection, &options)
+            .map(|(count, _)| count)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+    }
+
+    /// JSONLファイルからインポート（metadata_field のオブジェクトは型を保ったまま保存する）
+    #[pyo3(signature = (file_path, collection, content_field="content".to_string(), metadata_field="metadata".to_string()))]
+    fn import_jsonl(
+        &self,
+        file_path: String,
+        collection: String,
+        content_field: String,
+        metadata_field: String,
+    ) -> PyResult<usize> {
+        self.inner()?
+            .import_jsonl(&file_path, &collection, &content_field, Some(&metadata_field))
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
     }
 
     #[pyo3(signature = (file_path, collection=None))]
     fn export_csv(&self, file_path: String, collection: Option<String>) -> PyResult<usize> {
-        self.inner
+        self.inner()?
             .export_csv(&file_path, collection.as_deref())
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
     }
@@ -266,6 +656,8 @@ struct PyCollection {
     created_at: String,
     #[pyo3(get)]
     updated_at: String,
+    #[pyo3(get)]
+    embedding_model: Option<String>,
 }
 
 impl From<Collection> for PyCollection {
@@ -277,6 +669,7 @@ impl From<Collection> for PyCollection {
             document_count: c.document_count,
             created_at: c.created_at,
             updated_at: c.updated_at,
+            embedding_model: c.embedding_model,
         }
     }
 }
@@ -296,13 +689,37 @@ struct PyDocument {
     created_at: String,
     #[pyo3(get)]
     updated_at: String,
+    #[pyo3(get)]
+    external_id: Option<String>,
+    metadata: Option<serde_json::Value>,
 }
 
 #[pymethods]
 impl PyDocument {
     #[getter]
     fn metadata(&self, py: Python) -> PyResult<PyObject> {
-        Ok(py.None())
+        metadata_to_py(py, self.metadata.as_ref())
+    }
+}
+
+/// dictのメタデータ条件（`{"lang": "ja"}` など）を `MetadataFilter` に変換
+fn metadata_filter_from_py(filter: Option<&Bound<'_, PyDict>>) -> PyResult<MetadataFilter> {
+    let Some(filter) = filter else {
+        return Ok(MetadataFilter::default());
+    };
+    let value: serde_json::Value = pythonize::depythonize(filter.as_any())
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+    MetadataFilter::from_json(&value)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+}
+
+/// メタデータ（JSON）をPythonのオブジェクトに変換（メタデータなしは `None`）
+fn metadata_to_py(py: Python, metadata: Option<&serde_json::Value>) -> PyResult<PyObject> {
+    match metadata {
+        Some(value) => pythonize::pythonize(py, value)
+            .map(|obj| obj.unbind())
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string())),
+        None => Ok(py.None()),
     }
 }
 
@@ -315,6 +732,8 @@ impl From<Document> for PyDocument {
             content: d.content,
             created_at: d.created_at,
             updated_at: d.updated_at,
+            external_id: d.external_id,
+            metadata: d.metadata,
         }
     }
 }
@@ -328,15 +747,22 @@ struct PySearchResult {
     content: String,
     #[pyo3(get)]
     score: f32,
+    /// 検索結果内の順位（1始まり）
+    #[pyo3(get)]
+    rank: usize,
+    /// 閾値未満だが `min_results` を満たすために追加された結果かどうか
+    #[pyo3(get)]
+    backfilled: bool,
     #[pyo3(get)]
     collection_name: String,
+    metadata: Option<serde_json::Value>,
 }
 
 #[pymethods]
 impl PySearchResult {
     #[getter]
     fn metadata(&self, py: Python) -> PyResult<PyObject> {
-        Ok(py.None())
+        metadata_to_py(py, self.metadata.as_ref())
     }
 }
 
@@ -346,7 +772,10 @@ impl From<SearchResult> for PySearchResult {
             document_id: r.document_id,
             content: r.content,
             score: r.score,
+            rank: r.rank,
+            backfilled: r.backfilled,
             collection_name: r.collection_name,
+            metadata: r.metadata,
         }
     }
 }
@@ -354,20 +783,82 @@ impl From<SearchResult> for PySearchResult {
 #[pyclass]
 #[derive(Clone)]
 struct PyEnrichResult {
-    #[pyo3(get)]
-    question: String,
-    #[pyo3(get)]
-    context: String,
     #[pyo3(get)]
     sources: Vec<PySearchResult>,
+    /// 元の結果（質問文・コンテキストを保持する。ソースは `sources` に移して空にしてある）
+    inner: EnrichResult,
+}
+
+#[pymethods]
+impl PyEnrichResult {
+    #[getter]
+    fn question(&self) -> &str {
+        &self.inner.question
+    }
+
+    #[getter]
+    fn context(&self) -> &str {
+        &self.inner.context
+    }
+
+    /// チャット形式のAPIに渡せるメッセージのリスト（`[{"role": ..., "content": ...}]`）
+    ///
+    /// system_prompt: システムメッセージの前置き（省略時は汎用の文）
+    #[pyo3(signature = (system_prompt=None))]
+    fn to_chat_messages<'py>(
+        &self,
+        py: Python<'py>,
+        system_prompt: Option<String>,
+    ) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        self.inner
+            .to_chat_messages(system_prompt.as_deref())
+            .into_iter()
+            .map(|message| {
+                let dict = PyDict::new_bound(py);
+                dict.set_item("role", message.role)?;
+                dict.set_item("content", message.content)?;
+                Ok(dict)
+            })
+            .collect()
+    }
 }
 
 impl From<EnrichResult> for PyEnrichResult {
-    fn from(r: EnrichResult) -> Self {
+    fn from(mut r: EnrichResult) -> Self {
+        let sources = std::mem::take(&mut r.sources);
+        Self {
+            sources: sources.into_iter().map(PySearchResult::from).collect(),
+            inner: r,
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct PyEvalPoint {
+    #[pyo3(get)]
+    threshold: f32,
+    #[pyo3(get)]
+    precision: f32,
+    #[pyo3(get)]
+    recall: f32,
+    #[pyo3(get)]
+    f1: f32,
+    #[pyo3(get)]
+    retrieved: usize,
+    #[pyo3(get)]
+    relevant_retrieved: usize,
+}
+
+impl From<EvalPoint> for PyEvalPoint {
+    fn from(p: EvalPoint) -> Self {
         Self {
-            question: r.question,
-            context: r.context,
-            sources: r.sources.into_iter().map(PySearchResult::from).collect(),
+            threshold: p.threshold,
+            precision: p.precision,
+            recall: p.recall,
+            f1: p.f1,
+            retrieved: p.retrieved,
+            relevant_retrieved: p.relevant_retrieved,
         }
     }
 }
@@ -379,5 +870,6 @@ fn doredore(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PyDocument>()?;
     m.add_class::<PySearchResult>()?;
     m.add_class::<PyEnrichResult>()?;
+    m.add_class::<PyEvalPoint>()?;
     Ok(())
 }
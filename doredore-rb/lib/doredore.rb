@@ -18,7 +18,9 @@ module Doredore
              :content, :pointer,
              :score, :double,
              :collection, :pointer,
-             :metadata, :pointer
+             :metadata, :pointer,
+             :rank, :int,
+             :backfilled, :int
     end
 
     # Search results array
@@ -27,21 +29,47 @@ module Doredore
              :count, :int
     end
 
+    # Enrich result (context + sources)
+    class CEnrichResult < FFI::Struct
+      layout :question, :pointer,
+             :context, :pointer,
+             :sources, :pointer,
+             :source_count, :int
+    end
+
     # Core functions
     attach_function :doredore_new, [:pointer, :pointer, :pointer], :pointer
     attach_function :doredore_free, [:pointer], :void
+    attach_function :doredore_info, [:pointer], :pointer
+
+    # Error reporting (the string is owned by the library, so :string copies it)
+    attach_function :doredore_last_error, [], :string
+    attach_function :doredore_last_error_code, [], :int
 
     # Collection management
     attach_function :doredore_create_collection, [:pointer, :pointer, :pointer], :long_long
+    attach_function :doredore_create_collection_with_model, [:pointer, :pointer, :pointer, :pointer], :long_long
     attach_function :doredore_delete_collection, [:pointer, :pointer], :int
 
     # Document management
     attach_function :doredore_add_document, [:pointer, :pointer, :pointer, :pointer], :long_long
+    attach_function :doredore_add_document_unique, [:pointer, :pointer, :pointer, :pointer], :long_long
+    attach_function :doredore_add_documents, [:pointer, :pointer, :int, :pointer, :pointer], :pointer
     attach_function :doredore_delete_document, [:pointer, :long_long], :int
+    attach_function :doredore_add_document_with_external_id,
+                    [:pointer, :pointer, :pointer, :pointer, :pointer], :long_long
+    attach_function :doredore_get_document_by_external_id, [:pointer, :pointer], :pointer
+    attach_function :doredore_delete_document_by_external_id, [:pointer, :pointer], :int
 
     # Search & Enrich
-    attach_function :doredore_search, [:pointer, :pointer, :pointer, :int, :double], :pointer
-    attach_function :doredore_enrich, [:pointer, :pointer, :pointer, :int, :double], :pointer
+    attach_function :doredore_search,
+                    [:pointer, :pointer, :pointer, :int, :double, :pointer, :double, :double, :int], :pointer
+    attach_function :doredore_enrich,
+                    [:pointer, :pointer, :pointer, :int, :double, :pointer, :double, :double], :pointer
+    attach_function :doredore_enrich_full,
+                    [:pointer, :pointer, :pointer, :int, :double, :pointer, :double, :double], :pointer
+    attach_function :doredore_enrich_chat_messages,
+                    [:pointer, :pointer, :pointer, :int, :double, :pointer, :pointer], :pointer
 
     # CSV operations
     attach_function :doredore_import_csv, [:pointer, :pointer, :pointer, :pointer], :int
@@ -50,18 +78,53 @@ module Doredore
     # Memory management
     attach_function :doredore_free_string, [:pointer], :void
     attach_function :doredore_free_search_results, [:pointer], :void
+    attach_function :doredore_free_enrich_result, [:pointer], :void
+    attach_function :doredore_free_ids, [:pointer, :int], :void
+  end
+
+  # Error codes returned by Error#code (DOREDORE_ERROR_* in the native library)
+  ERROR_DATABASE = 1
+  ERROR_EMBEDDING = 2
+  ERROR_SEARCH = 3
+  ERROR_COLLECTION_NOT_FOUND = 4
+  ERROR_DOCUMENT_NOT_FOUND = 5
+  ERROR_IO = 6
+  ERROR_CSV = 7
+  ERROR_JSON = 8
+  ERROR_INVALID_INPUT = 9
+  ERROR_READ_ONLY = 10
+  ERROR_OTHER = 11
+  ERROR_NULL_POINTER = 12
+
+  # Raised when a native call fails; code is one of the ERROR_* constants
+  class Error < StandardError
+    attr_reader :code
+
+    def initialize(message, code = nil)
+      super(message)
+      @code = code
+    end
+
+    # Build an error from the last failure recorded by the native library on this thread
+    def self.last(message)
+      detail = Native.doredore_last_error
+      code = Native.doredore_last_error_code
+      new(detail ? "#{message}: #{detail}" : message, code.zero? ? nil : code)
+    end
   end
 
   # Search result Ruby class
   class SearchResult
-    attr_reader :document_id, :content, :score, :collection, :metadata
+    attr_reader :document_id, :content, :score, :rank, :collection, :metadata, :backfilled
 
-    def initialize(document_id:, content:, score:, collection:, metadata: nil)
+    def initialize(document_id:, content:, score:, collection:, metadata: nil, rank: nil, backfilled: false)
       @document_id = document_id
       @content = content
       @score = score
+      @rank = rank
       @collection = collection
       @metadata = metadata
+      @backfilled = backfilled
     end
 
     def to_h
@@ -69,8 +132,10 @@ module Doredore
         document_id: @document_id,
         content: @content,
         score: @score,
+        rank: @rank,
         collection: @collection,
-        metadata: @metadata
+        metadata: @metadata,
+        backfilled: @backfilled
       }
     end
   end
@@ -84,7 +149,7 @@ module Doredore
 
       @handle = Native.doredore_new(db_ptr, model_ptr, cache_ptr)
 
-      raise 'Failed to initialize Doredore' if @handle.null?
+      raise Error.last('Failed to initialize Doredore') if @handle.null?
 
       ObjectSpace.define_finalizer(self, self.class.finalize(@handle))
     end
@@ -93,16 +158,27 @@ module Doredore
       proc { Native.doredore_free(handle) }
     end
 
+    # Instance information (model, dimension, db_path)
+    def info
+      info_ptr = Native.doredore_info(@handle)
+      raise Error.last('Failed to get instance info') if info_ptr.null?
+
+      info = JSON.parse(info_ptr.read_string)
+      Native.doredore_free_string(info_ptr)
+      info
+    end
+
     # ==================================================================
     # Collection Management
     # ==================================================================
 
-    def create_collection(name, description: nil)
+    def create_collection(name, description: nil, model: nil)
       name_ptr = FFI::MemoryPointer.from_string(name)
       desc_ptr = description ? FFI::MemoryPointer.from_string(description) : nil
+      model_ptr = model ? FFI::MemoryPointer.from_string(model) : nil
 
-      id = Native.doredore_create_collection(@handle, name_ptr, desc_ptr)
-      raise "Failed to create collection: #{name}" if id == -1
+      id = Native.doredore_create_collection_with_model(@handle, name_ptr, desc_ptr, model_ptr)
+      raise Error.last("Failed to create collection: #{name}") if id == -1
 
       id
     end
@@ -110,7 +186,7 @@ module Doredore
     def delete_collection(name)
       name_ptr = FFI::MemoryPointer.from_string(name)
       result = Native.doredore_delete_collection(@handle, name_ptr)
-      raise "Failed to delete collection: #{name}" if result == -1
+      raise Error.last("Failed to delete collection: #{name}") if result == -1
 
       true
     end
@@ -119,68 +195,101 @@ module Doredore
     # Document Management
     # ==================================================================
 
-    def add_document(content, collection: 'default', metadata: nil)
+    # external_id: caller-managed ID; a document with the same ID is updated instead
+    # skip_duplicates: return the ID of an existing document with the same content instead of adding
+    def add_document(content, collection: 'default', metadata: nil, external_id: nil, skip_duplicates: false)
       content_ptr = FFI::MemoryPointer.from_string(content)
       collection_ptr = FFI::MemoryPointer.from_string(collection)
       metadata_ptr = metadata ? FFI::MemoryPointer.from_string(metadata.to_json) : nil
 
-      id = Native.doredore_add_document(@handle, content_ptr, collection_ptr, metadata_ptr)
-      raise 'Failed to add document' if id == -1
+      id = if external_id
+             external_id_ptr = FFI::MemoryPointer.from_string(external_id)
+             Native.doredore_add_document_with_external_id(
+               @handle, content_ptr, collection_ptr, metadata_ptr, external_id_ptr
+             )
+           elsif skip_duplicates
+             Native.doredore_add_document_unique(@handle, content_ptr, collection_ptr, metadata_ptr)
+           else
+             Native.doredore_add_document(@handle, content_ptr, collection_ptr, metadata_ptr)
+           end
+      raise Error.last('Failed to add document') if id == -1
 
       id
     end
 
+    # Add many documents with batched embedding generation (all or nothing).
+    # Returns the document IDs in the same order as contents
+    def add_documents(contents, collection: 'default')
+      content_ptrs = contents.map { |content| FFI::MemoryPointer.from_string(content) }
+      contents_ptr = FFI::MemoryPointer.new(:pointer, [content_ptrs.size, 1].max)
+      contents_ptr.write_array_of_pointer(content_ptrs)
+      collection_ptr = FFI::MemoryPointer.from_string(collection)
+      count_ptr = FFI::MemoryPointer.new(:int)
+
+      ids_ptr = Native.doredore_add_documents(@handle, contents_ptr, content_ptrs.size, collection_ptr, count_ptr)
+      raise Error.last('Failed to add documents') if ids_ptr.null?
+
+      count = count_ptr.read_int
+      ids = ids_ptr.read_array_of_long_long(count)
+      Native.doredore_free_ids(ids_ptr, count)
+      ids
+    end
+
     def delete_document(id)
       result = Native.doredore_delete_document(@handle, id)
-      raise "Failed to delete document: #{id}" if result == -1
+      raise Error.last("Failed to delete document: #{id}") if result == -1
 
       true
     end
 
+    # Returns the document as a Hash, or nil if no document has the ID
+    def get_document_by_external_id(external_id)
+      external_id_ptr = FFI::MemoryPointer.from_string(external_id)
+      document_ptr = Native.doredore_get_document_by_external_id(@handle, external_id_ptr)
+      raise Error.last("Failed to get document: #{external_id}") if document_ptr.null?
+
+      document = JSON.parse(document_ptr.read_string)
+      Native.doredore_free_string(document_ptr)
+      document
+    end
+
+    # Returns true if a document was deleted, false if no document has the ID
+    def delete_document_by_external_id(external_id)
+      external_id_ptr = FFI::MemoryPointer.from_string(external_id)
+      result = Native.doredore_delete_document_by_external_id(@handle, external_id_ptr)
+      raise Error.last("Failed to delete document: #{external_id}") if result == -1
+
+      result == 1
+    end
+
     # ==================================================================
     # Search & Enrich
     # ==================================================================
 
-    def search(query, collection: nil, top_k: 5, threshold: 0.0)
+    def search(query, collection: nil, top_k: 5, threshold: 0.0, mode: 'semantic', min_results: 0)
       query_ptr = FFI::MemoryPointer.from_string(query)
       collection_ptr = collection ? FFI::MemoryPointer.from_string(collection) : nil
+      mode_ptr = FFI::MemoryPointer.from_string(mode)
 
-      results_ptr = Native.doredore_search(@handle, query_ptr, collection_ptr, top_k, threshold)
+      results_ptr = Native.doredore_search(@handle, query_ptr, collection_ptr, top_k, threshold,
+                                           mode_ptr, 0.0, 0.0, min_results)
       return [] if results_ptr.null?
 
       results_struct = Native::CSearchResults.new(results_ptr)
-      results_array_ptr = results_struct[:results]
-      count = results_struct[:count]
-
-      results = []
-      (0...count).each do |i|
-        result_ptr = results_array_ptr + (i * Native::CSearchResult.size)
-        result_struct = Native::CSearchResult.new(result_ptr)
-
-        content = result_struct[:content].read_string
-        collection_name = result_struct[:collection].read_string
-        metadata_ptr = result_struct[:metadata]
-        metadata = metadata_ptr.null? ? nil : JSON.parse(metadata_ptr.read_string)
-
-        results << SearchResult.new(
-          document_id: result_struct[:document_id],
-          content: content,
-          score: result_struct[:score],
-          collection: collection_name,
-          metadata: metadata
-        )
-      end
+      results = read_search_results(results_struct[:results], results_struct[:count])
 
       Native.doredore_free_search_results(results_ptr)
       results
     end
 
-    def enrich(query, collection: nil, top_k: 5, threshold: 0.0)
+    def enrich(query, collection: nil, top_k: 5, threshold: 0.0, mode: 'semantic')
       query_ptr = FFI::MemoryPointer.from_string(query)
       collection_ptr = collection ? FFI::MemoryPointer.from_string(collection) : nil
+      mode_ptr = FFI::MemoryPointer.from_string(mode)
 
-      context_ptr = Native.doredore_enrich(@handle, query_ptr, collection_ptr, top_k, threshold)
-      raise 'Failed to enrich query' if context_ptr.null?
+      context_ptr = Native.doredore_enrich(@handle, query_ptr, collection_ptr, top_k, threshold,
+                                           mode_ptr, 0.0, 0.0)
+      raise Error.last('Failed to enrich query') if context_ptr.null?
 
       context = context_ptr.read_string
       Native.doredore_free_string(context_ptr)
@@ -188,21 +297,61 @@ module Doredore
       {
         query: query,
         context: context,
-        sources: search(query, collection: collection, top_k: top_k, threshold: threshold)
+        sources: search(query, collection: collection, top_k: top_k, threshold: threshold, mode: mode)
       }
     end
 
+    # Enrich with context and structured source citations in a single call
+    def enrich_full(query, collection: nil, top_k: 5, threshold: 0.0, mode: 'semantic')
+      query_ptr = FFI::MemoryPointer.from_string(query)
+      collection_ptr = collection ? FFI::MemoryPointer.from_string(collection) : nil
+      mode_ptr = FFI::MemoryPointer.from_string(mode)
+
+      result_ptr = Native.doredore_enrich_full(@handle, query_ptr, collection_ptr, top_k, threshold,
+                                               mode_ptr, 0.0, 0.0)
+      raise Error.last('Failed to enrich query') if result_ptr.null?
+
+      result_struct = Native::CEnrichResult.new(result_ptr)
+      result = {
+        query: result_struct[:question].read_string,
+        context: result_struct[:context].read_string,
+        sources: read_search_results(result_struct[:sources], result_struct[:source_count])
+      }
+
+      Native.doredore_free_enrich_result(result_ptr)
+      result
+    end
+
+    # Enrich and return chat API messages ([{ 'role' => ..., 'content' => ... }]):
+    # a system message with the context and citation instructions, then the user query
+    def enrich_chat_messages(query, collection: nil, top_k: 5, threshold: 0.0, mode: 'semantic',
+                             system_prompt: nil)
+      query_ptr = FFI::MemoryPointer.from_string(query)
+      collection_ptr = collection ? FFI::MemoryPointer.from_string(collection) : nil
+      mode_ptr = FFI::MemoryPointer.from_string(mode)
+      prompt_ptr = system_prompt ? FFI::MemoryPointer.from_string(system_prompt) : nil
+
+      json_ptr = Native.doredore_enrich_chat_messages(@handle, query_ptr, collection_ptr, top_k, threshold,
+                                                      mode_ptr, prompt_ptr)
+      raise Error.last('Failed to enrich query') if json_ptr.null?
+
+      messages = JSON.parse(json_ptr.read_string)
+      Native.doredore_free_string(json_ptr)
+      messages
+    end
+
     # ==================================================================
     # CSV Operations
     # ==================================================================
 
-    def import_csv(file_path, collection: 'default', content_column: 'content')
+    # content_column: nil auto-detects the column with the longest average cell length
+    def import_csv(file_path, collection: 'default', content_column: nil)
       file_ptr = FFI::MemoryPointer.from_string(file_path)
       collection_ptr = FFI::MemoryPointer.from_string(collection)
-      content_ptr = FFI::MemoryPointer.from_string(content_column)
+      content_ptr = content_column ? FFI::MemoryPointer.from_string(content_column) : nil
 
       count = Native.doredore_import_csv(@handle, file_ptr, collection_ptr, content_ptr)
-      raise "Failed to import CSV: #{file_path}" if count == -1
+      raise Error.last("Failed to import CSV: #{file_path}") if count == -1
 
       count
     end
@@ -212,9 +361,31 @@ module Doredore
       collection_ptr = collection ? FFI::MemoryPointer.from_string(collection) : nil
 
       count = Native.doredore_export_csv(@handle, file_ptr, collection_ptr)
-      raise "Failed to export CSV: #{file_path}" if count == -1
+      raise Error.last("Failed to export CSV: #{file_path}") if count == -1
 
       count
     end
+
+    private
+
+    def read_search_results(results_array_ptr, count)
+      (0...count).map do |i|
+        result_ptr = results_array_ptr + (i * Native::CSearchResult.size)
+        result_struct = Native::CSearchResult.new(result_ptr)
+
+        metadata_ptr = result_struct[:metadata]
+        metadata = metadata_ptr.null? ? nil : JSON.parse(metadata_ptr.read_string)
+
+        SearchResult.new(
+          document_id: result_struct[:document_id],
+          content: result_struct[:content].read_string,
+          score: result_struct[:score],
+          collection: result_struct[:collection].read_string,
+          metadata: metadata,
+          rank: result_struct[:rank],
+          backfilled: result_struct[:backfilled] != 0
+        )
+      end
+    end
   end
 end
@@ -1,4 +1,6 @@
 use doredore_core::core::enricher::Doredore as CoreDoredore;
+use doredore_core::{Error, SearchMode, SearchOptions, SearchResult};
+use std::cell::RefCell;
 use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_double, c_int, c_longlong};
 use std::ptr;
@@ -20,6 +22,10 @@ pub struct CSearchResult {
     pub score: c_double,
     pub collection: *mut c_char,
     pub metadata: *mut c_char,
+    /// 1-based position in the results
+    pub rank: c_int,
+    /// 1 if added below the threshold to satisfy min_results, otherwise 0
+    pub backfilled: c_int,
 }
 
 /// Array of search results
@@ -29,6 +35,15 @@ pub struct CSearchResults {
     pub count: c_int,
 }
 
+/// Enrich result structure for C FFI (context + source citations)
+#[repr(C)]
+pub struct CEnrichResult {
+    pub question: *mut c_char,
+    pub context: *mut c_char,
+    pub sources: *mut CSearchResult,
+    pub source_count: c_int,
+}
+
 // ============================================================================
 // Helper Functions
 // ============================================================================
@@ -46,6 +61,178 @@ unsafe fn from_c_string(s: *const c_char) -> String {
     CStr::from_ptr(s).to_string_lossy().into_owned()
 }
 
+/// Convert SearchResult to C format (caller must free the strings)
+unsafe fn to_c_search_result(r: SearchResult) -> CSearchResult {
+    CSearchResult {
+        document_id: r.document_id,
+        content: to_c_string(r.content),
+        score: r.score as c_double,
+        collection: to_c_string(r.collection_name),
+        metadata: if let Some(m) = r.metadata {
+            to_c_string(m.to_string())
+        } else {
+            ptr::null_mut()
+        },
+        rank: r.rank as c_int,
+        backfilled: r.backfilled as c_int,
+    }
+}
+
+/// Convert SearchResults to a C array (caller must free with free_c_search_result_array)
+unsafe fn to_c_search_result_array(results: Vec<SearchResult>) -> (*mut CSearchResult, c_int) {
+    // Box<[T]>に変換して len == capacity を保証する（解放時に from_raw_parts で復元するため）
+    let c_results: Box<[CSearchResult]> = results
+        .into_iter()
+        .map(|r| to_c_search_result(r))
+        .collect();
+
+    let count = c_results.len() as c_int;
+    let results_ptr = Box::into_raw(c_results) as *mut CSearchResult;
+
+    (results_ptr, count)
+}
+
+/// Free a C array created by to_c_search_result_array
+unsafe fn free_c_search_result_array(results: *mut CSearchResult, count: c_int) {
+    let results_vec = Vec::from_raw_parts(results, count as usize, count as usize);
+
+    for result in results_vec {
+        doredore_free_string(result.content);
+        doredore_free_string(result.collection);
+        if !result.metadata.is_null() {
+            doredore_free_string(result.metadata);
+        }
+    }
+}
+
+// ============================================================================
+// Error Reporting
+// ============================================================================
+
+/// No error
+pub const DOREDORE_OK: c_int = 0;
+/// SQLite error
+pub const DOREDORE_ERROR_DATABASE: c_int = 1;
+/// The embedding model failed to load or to embed a text
+pub const DOREDORE_ERROR_EMBEDDING: c_int = 2;
+/// Search failed
+pub const DOREDORE_ERROR_SEARCH: c_int = 3;
+/// No collection has the given name
+pub const DOREDORE_ERROR_COLLECTION_NOT_FOUND: c_int = 4;
+/// No document has the given ID
+pub const DOREDORE_ERROR_DOCUMENT_NOT_FOUND: c_int = 5;
+/// File I/O error
+pub const DOREDORE_ERROR_IO: c_int = 6;
+/// Malformed CSV
+pub const DOREDORE_ERROR_CSV: c_int = 7;
+/// Malformed JSON (e.g. the metadata argument)
+pub const DOREDORE_ERROR_JSON: c_int = 8;
+/// Invalid argument value
+pub const DOREDORE_ERROR_INVALID_INPUT: c_int = 9;
+/// The database was opened in read-only mode
+pub const DOREDORE_ERROR_READ_ONLY: c_int = 10;
+/// Any other error
+pub const DOREDORE_ERROR_OTHER: c_int = 11;
+/// A required pointer argument was NULL
+pub const DOREDORE_ERROR_NULL_POINTER: c_int = 12;
+
+thread_local! {
+    /// このスレッドで最後に失敗した呼び出しのエラー（コードとメッセージ）
+    static LAST_ERROR: RefCell<Option<(c_int, CString)>> = const { RefCell::new(None) };
+}
+
+/// `Error` の種類に対応するエラーコード
+fn error_code(e: &Error) -> c_int {
+    match e {
+        Error::Database(_) => DOREDORE_ERROR_DATABASE,
+        Error::Embedding(_) => DOREDORE_ERROR_EMBEDDING,
+        Error::Search(_) => DOREDORE_ERROR_SEARCH,
+        Error::CollectionNotFound(_) => DOREDORE_ERROR_COLLECTION_NOT_FOUND,
+        Error::DocumentNotFound(_) => DOREDORE_ERROR_DOCUMENT_NOT_FOUND,
+        Error::Io(_) => DOREDORE_ERROR_IO,
+        Error::Csv(_) => DOREDORE_ERROR_CSV,
+        Error::Json(_) => DOREDORE_ERROR_JSON,
+        Error::InvalidInput(_) => DOREDORE_ERROR_INVALID_INPUT,
+        Error::ReadOnly => DOREDORE_ERROR_READ_ONLY,
+        Error::Other(_) => DOREDORE_ERROR_OTHER,
+    }
+}
+
+/// 失敗を記録（doredore_last_error() / doredore_last_error_code() で取得できる）
+fn set_last_error(code: c_int, message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some((code, message)));
+}
+
+/// 記録したエラーを消去（各関数の開始時に呼ぶ）
+fn clear_last_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+/// エラーなら記録してNoneを返す
+fn check<T>(result: doredore_core::Result<T>) -> Option<T> {
+    result
+        .map_err(|e| set_last_error(error_code(&e), e.to_string()))
+        .ok()
+}
+
+/// NULLだったポインタ引数を記録
+fn null_argument(name: &str) {
+    set_last_error(
+        DOREDORE_ERROR_NULL_POINTER,
+        format!("Argument '{}' must not be NULL", name),
+    );
+}
+
+/// モード文字列をSearchModeに変換（NULLはsemantic、不明なモードは記録してNone）
+unsafe fn parse_search_mode(mode: *const c_char) -> Option<SearchMode> {
+    if mode.is_null() {
+        return Some(SearchMode::Semantic);
+    }
+
+    let mode_str = from_c_string(mode);
+    match mode_str.to_lowercase().as_str() {
+        "semantic" => Some(SearchMode::Semantic),
+        "keyword" => Some(SearchMode::Keyword),
+        "hybrid" => Some(SearchMode::Hybrid),
+        _ => {
+            set_last_error(
+                DOREDORE_ERROR_INVALID_INPUT,
+                format!(
+                    "Invalid search mode '{}' (expected semantic, keyword, or hybrid)",
+                    mode_str
+                ),
+            );
+            None
+        }
+    }
+}
+
+/// Message of the error from the last call on the calling thread, or NULL if
+/// that call succeeded
+///
+/// Every function except the doredore_free_* functions clears the error on
+/// entry and records it when it fails, so read it right after a call returns
+/// -1 or NULL.
+///
+/// The returned string is owned by the library and stays valid until the next
+/// call into the library on the same thread. Do not free it
+#[no_mangle]
+pub extern "C" fn doredore_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |(_, message)| message.as_ptr())
+    })
+}
+
+/// Code of the error from the last call on the calling thread (one of the
+/// DOREDORE_ERROR_* constants), or DOREDORE_OK if that call succeeded
+#[no_mangle]
+pub extern "C" fn doredore_last_error_code() -> c_int {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(DOREDORE_OK, |(code, _)| *code))
+}
+
 // ============================================================================
 // Core Functions
 // ============================================================================
@@ -60,6 +247,12 @@ pub unsafe extern "C" fn doredore_new(
     model: *const c_char,
     cache_dir: *const c_char,
 ) -> *mut Doredore {
+    clear_last_error();
+    if db_path.is_null() {
+        null_argument("db_path");
+        return ptr::null_mut();
+    }
+
     let db_path_str = from_c_string(db_path);
     let model_str = if model.is_null() {
         None
@@ -72,13 +265,13 @@ pub unsafe extern "C" fn doredore_new(
         Some(from_c_string(cache_dir))
     };
 
-    match CoreDoredore::new(
+    match check(CoreDoredore::new(
         &db_path_str,
         model_str.as_deref(),
         cache_str.as_deref(),
-    ) {
-        Ok(enricher) => Box::into_raw(Box::new(Doredore { inner: enricher })),
-        Err(_) => ptr::null_mut(),
+    )) {
+        Some(enricher) => Box::into_raw(Box::new(Doredore { inner: enricher })),
+        None => ptr::null_mut(),
     }
 }
 
@@ -93,6 +286,36 @@ pub unsafe extern "C" fn doredore_free(rag: *mut Doredore) {
     }
 }
 
+/// Get instance information as a JSON string
+///
+/// # Returns
+/// A JSON object describing the instance, or null on error:
+/// ```json
+/// { "model": "bge-small-en-v1.5", "dimension": 384, "db_path": "./knowledge.db" }
+/// ```
+///
+/// # Safety
+/// The returned string is owned by the caller and must be released with
+/// doredore_free_string()
+#[no_mangle]
+pub unsafe extern "C" fn doredore_info(rag: *mut Doredore) -> *mut c_char {
+    clear_last_error();
+    if rag.is_null() {
+        null_argument("rag");
+        return ptr::null_mut();
+    }
+
+    let enricher = &(*rag).inner;
+
+    let info = serde_json::json!({
+        "model": enricher.model_name(),
+        "dimension": enricher.dimension(),
+        "db_path": enricher.db_path().to_string_lossy(),
+    });
+
+    to_c_string(info.to_string())
+}
+
 // ============================================================================
 // Collection Management
 // ============================================================================
@@ -104,7 +327,9 @@ pub unsafe extern "C" fn doredore_create_collection(
     name: *const c_char,
     description: *const c_char,
 ) -> c_longlong {
+    clear_last_error();
     if rag.is_null() {
+        null_argument("rag");
         return -1;
     }
 
@@ -116,10 +341,46 @@ pub unsafe extern "C" fn doredore_create_collection(
         Some(from_c_string(description))
     };
 
-    match enricher.create_collection(&name_str, desc_str.as_deref()) {
-        Ok(id) => id,
-        Err(_) => -1,
+    check(enricher.create_collection(&name_str, desc_str.as_deref())).unwrap_or(-1)
+}
+
+/// Create a new collection with its own embedding model
+///
+/// # Safety
+/// `name` must be a valid C string; `description` and `model` may be NULL
+/// (NULL model = use the instance model)
+#[no_mangle]
+pub unsafe extern "C" fn doredore_create_collection_with_model(
+    rag: *mut Doredore,
+    name: *const c_char,
+    description: *const c_char,
+    model: *const c_char,
+) -> c_longlong {
+    clear_last_error();
+    if rag.is_null() {
+        null_argument("rag");
+        return -1;
     }
+
+    let enricher = &(*rag).inner;
+    let name_str = from_c_string(name);
+    let desc_str = if description.is_null() {
+        None
+    } else {
+        Some(from_c_string(description))
+    };
+    let model_str = if model.is_null() {
+        None
+    } else {
+        Some(from_c_string(model))
+    };
+
+    check(enricher.create_collection_with_model(
+        &name_str,
+        desc_str.as_deref(),
+        model_str.as_deref(),
+    ))
+    .unwrap_or(-1)
 }
 
 /// Delete a collection
@@ -128,17 +389,16 @@ pub unsafe extern "C" fn doredore_delete_collection(
     rag: *mut Doredore,
     name: *const c_char,
 ) -> c_int {
+    clear_last_error();
     if rag.is_null() {
+        null_argument("rag");
         return -1;
     }
 
     let enricher = &(*rag).inner;
     let name_str = from_c_string(name);
 
-    match enricher.delete_collection(&name_str) {
-        Ok(_) => 0,
-        Err(_) => -1,
-    }
+    check(enricher.delete_collection(&name_str)).map_or(-1, |_| 0)
 }
 
 // ============================================================================
@@ -153,7 +413,51 @@ pub unsafe extern "C" fn doredore_add_document(
     collection: *const c_char,
     metadata: *const c_char,
 ) -> c_longlong {
+    clear_last_error();
+    if rag.is_null() {
+        null_argument("rag");
+        return -1;
+    }
+
+    let enricher = &(*rag).inner;
+    let content_str = from_c_string(content);
+    let collection_str = if collection.is_null() {
+        "default".to_string()
+    } else {
+        from_c_string(collection)
+    };
+    let metadata_json = if metadata.is_null() {
+        None
+    } else {
+        let metadata_str = from_c_string(metadata);
+        match check(serde_json::from_str(&metadata_str).map_err(Error::from)) {
+            Some(json) => Some(json),
+            None => return -1,
+        }
+    };
+
+    check(enricher.add_document(&content_str, &collection_str, metadata_json.as_ref()))
+        .unwrap_or(-1)
+}
+
+/// Add a document unless the collection already has one with the same content
+///
+/// Content is compared ignoring leading/trailing and repeated whitespace. If a
+/// duplicate exists, its ID is returned and nothing is added.
+///
+/// # Safety
+/// All string arguments must be valid NUL-terminated strings; `collection`
+/// and `metadata` may be null
+#[no_mangle]
+pub unsafe extern "C" fn doredore_add_document_unique(
+    rag: *mut Doredore,
+    content: *const c_char,
+    collection: *const c_char,
+    metadata: *const c_char,
+) -> c_longlong {
+    clear_last_error();
     if rag.is_null() {
+        null_argument("rag");
         return -1;
     }
 
@@ -168,16 +472,83 @@ pub unsafe extern "C" fn doredore_add_document(
         None
     } else {
         let metadata_str = from_c_string(metadata);
-        match serde_json::from_str(&metadata_str) {
-            Ok(json) => Some(json),
-            Err(_) => return -1,
+        match check(serde_json::from_str(&metadata_str).map_err(Error::from)) {
+            Some(json) => Some(json),
+            None => return -1,
         }
     };
 
-    match enricher.add_document(&content_str, &collection_str, metadata_json.as_ref()) {
-        Ok(id) => id,
-        Err(_) => -1,
+    check(enricher.add_document_unique(&content_str, &collection_str, metadata_json.as_ref()))
+        .unwrap_or(-1)
+}
+
+/// Add multiple documents to a collection in one call
+///
+/// Embeddings are generated in batches, so this is much faster than calling
+/// doredore_add_document() once per document. Either all documents are added
+/// or none are.
+///
+/// # Parameters
+/// * contents - Array of `count` document contents
+/// * count - Number of documents
+/// * collection - Collection name (NULL = "default")
+/// * out_count - Receives the number of IDs in the returned array
+///
+/// # Returns
+/// An array of document IDs in the same order as `contents`, or null on error
+///
+/// # Safety
+/// `contents` must point to `count` valid NUL-terminated strings. The returned
+/// array is owned by the caller and must be released with
+/// doredore_free_ids() together with the count written to `out_count`
+#[no_mangle]
+pub unsafe extern "C" fn doredore_add_documents(
+    rag: *mut Doredore,
+    contents: *const *const c_char,
+    count: c_int,
+    collection: *const c_char,
+    out_count: *mut c_int,
+) -> *mut c_longlong {
+    clear_last_error();
+    if rag.is_null() {
+        null_argument("rag");
+        return ptr::null_mut();
+    }
+    if out_count.is_null() {
+        null_argument("out_count");
+        return ptr::null_mut();
+    }
+    if count < 0 {
+        set_last_error(
+            DOREDORE_ERROR_INVALID_INPUT,
+            format!("count must not be negative (got {})", count),
+        );
+        return ptr::null_mut();
     }
+    if contents.is_null() && count > 0 {
+        null_argument("contents");
+        return ptr::null_mut();
+    }
+
+    let enricher = &(*rag).inner;
+    let documents: Vec<String> = (0..count as usize)
+        .map(|i| from_c_string(*contents.add(i)))
+        .collect();
+    let collection_str = if collection.is_null() {
+        "default".to_string()
+    } else {
+        from_c_string(collection)
+    };
+
+    let ids = match check(enricher.add_documents(documents, &collection_str, None)) {
+        Some(ids) => ids,
+        None => return ptr::null_mut(),
+    };
+
+    // Box<[T]>に変換して len == capacity を保証する（doredore_free_ids で復元するため）
+    let ids: Box<[c_longlong]> = ids.into_boxed_slice();
+    *out_count = ids.len() as c_int;
+    Box::into_raw(ids) as *mut c_longlong
 }
 
 /// Delete a document by ID
@@ -186,16 +557,131 @@ pub unsafe extern "C" fn doredore_delete_document(
     rag: *mut Doredore,
     id: c_longlong,
 ) -> c_int {
+    clear_last_error();
+    if rag.is_null() {
+        null_argument("rag");
+        return -1;
+    }
+
+    let enricher = &(*rag).inner;
+
+    check(enricher.delete_document(id)).map_or(-1, |_| 0)
+}
+
+/// Add a document with an external (caller-managed) ID, or update the
+/// document that already has that ID
+///
+/// # Safety
+/// All string arguments must be valid NUL-terminated strings; `collection`
+/// and `metadata` may be null
+#[no_mangle]
+pub unsafe extern "C" fn doredore_add_document_with_external_id(
+    rag: *mut Doredore,
+    content: *const c_char,
+    collection: *const c_char,
+    metadata: *const c_char,
+    external_id: *const c_char,
+) -> c_longlong {
+    clear_last_error();
     if rag.is_null() {
+        null_argument("rag");
+        return -1;
+    }
+    if external_id.is_null() {
+        null_argument("external_id");
         return -1;
     }
 
     let enricher = &(*rag).inner;
+    let content_str = from_c_string(content);
+    let collection_str = if collection.is_null() {
+        "default".to_string()
+    } else {
+        from_c_string(collection)
+    };
+    let metadata_json = if metadata.is_null() {
+        None
+    } else {
+        let metadata_str = from_c_string(metadata);
+        match check(serde_json::from_str(&metadata_str).map_err(Error::from)) {
+            Some(json) => Some(json),
+            None => return -1,
+        }
+    };
+    let external_id_str = from_c_string(external_id);
+
+    check(enricher.add_document_with_external_id(
+        &content_str,
+        &collection_str,
+        metadata_json.as_ref(),
+        &external_id_str,
+    ))
+    .unwrap_or(-1)
+}
+
+/// Get a document by external ID
+///
+/// # Returns
+/// The document as a JSON object, the JSON literal `null` if no document has
+/// the ID, or a null pointer on error
+///
+/// # Safety
+/// The returned string is owned by the caller and must be released with
+/// doredore_free_string()
+#[no_mangle]
+pub unsafe extern "C" fn doredore_get_document_by_external_id(
+    rag: *mut Doredore,
+    external_id: *const c_char,
+) -> *mut c_char {
+    clear_last_error();
+    if rag.is_null() {
+        null_argument("rag");
+        return ptr::null_mut();
+    }
+    if external_id.is_null() {
+        null_argument("external_id");
+        return ptr::null_mut();
+    }
+
+    let enricher = &(*rag).inner;
+    let external_id_str = from_c_string(external_id);
+
+    let json = enricher
+        .get_document_by_external_id(&external_id_str)
+        .and_then(|document| serde_json::to_string(&document).map_err(Error::from));
+    match check(json) {
+        Some(json) => to_c_string(json),
+        None => ptr::null_mut(),
+    }
+}
 
-    match enricher.delete_document(id) {
-        Ok(_) => 0,
-        Err(_) => -1,
+/// Delete a document by external ID
+///
+/// # Returns
+/// 1 if a document was deleted, 0 if no document has the ID, -1 on error
+///
+/// # Safety
+/// `external_id` must be a valid NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn doredore_delete_document_by_external_id(
+    rag: *mut Doredore,
+    external_id: *const c_char,
+) -> c_int {
+    clear_last_error();
+    if rag.is_null() {
+        null_argument("rag");
+        return -1;
     }
+    if external_id.is_null() {
+        null_argument("external_id");
+        return -1;
+    }
+
+    let enricher = &(*rag).inner;
+    let external_id_str = from_c_string(external_id);
+
+    check(enricher.delete_document_by_external_id(&external_id_str))
+        .map_or(-1, |deleted| deleted as c_int)
 }
 
 // ============================================================================
@@ -205,9 +691,11 @@ pub unsafe extern "C" fn doredore_delete_document(
 /// Search for similar documents
 ///
 /// # Parameters
-/// * mode - Search mode: "semantic", "keyword", or "hybrid" (default: "semantic")
+/// * mode - Search mode: "semantic", "keyword", or "hybrid" (default: "semantic");
+///   any other value fails with DOREDORE_ERROR_INVALID_INPUT
 /// * semantic_weight - Weight for semantic score in hybrid mode (default: 0.7)
 /// * keyword_weight - Weight for keyword score in hybrid mode (default: 0.3)
+/// * min_results - Backfill below-threshold results up to this count (0 disables)
 ///
 /// # Safety
 /// Caller must call doredore_free_search_results() to deallocate
@@ -221,8 +709,11 @@ pub unsafe extern "C" fn doredore_search(
     mode: *const c_char,
     semantic_weight: c_double,
     keyword_weight: c_double,
+    min_results: c_int,
 ) -> *mut CSearchResults {
+    clear_last_error();
     if rag.is_null() {
+        null_argument("rag");
         return ptr::null_mut();
     }
 
@@ -234,19 +725,9 @@ pub unsafe extern "C" fn doredore_search(
         Some(from_c_string(collection))
     };
 
-    // モード文字列をSearchModeに変換
-    use doredore_core::SearchMode;
-    let mode_str = if mode.is_null() {
-        "semantic".to_string()
-    } else {
-        from_c_string(mode)
-    };
-
-    let search_mode = match mode_str.to_lowercase().as_str() {
-        "semantic" => SearchMode::Semantic,
-        "keyword" => SearchMode::Keyword,
-        "hybrid" => SearchMode::Hybrid,
-        _ => SearchMode::Semantic, // デフォルトにフォールバック
+    let search_mode = match parse_search_mode(mode) {
+        Some(mode) => mode,
+        None => return ptr::null_mut(),
     };
 
     // hybrid_weightsを設定（デフォルト: 0.7, 0.3）
@@ -256,38 +737,27 @@ pub unsafe extern "C" fn doredore_search(
         None
     };
 
-    let results = match enricher.search(
+    let options = SearchOptions {
+        mode: search_mode,
+        threshold: threshold as f32,
+        hybrid_weights: weights,
+        min_results: min_results.max(0) as usize,
+        ..Default::default()
+    };
+
+    let results = match check(enricher.search_with_options(
         &query_str,
         collection_str.as_deref(),
         None,
         top_k as usize,
-        threshold as f32,
-        search_mode,
-        weights,
-    ) {
-        Ok(r) => r,
-        Err(_) => return ptr::null_mut(),
+        &options,
+    )) {
+        Some(r) => r,
+        None => return ptr::null_mut(),
     };
 
     // Convert results to C format
-    let mut c_results: Vec<CSearchResult> = results
-        .into_iter()
-        .map(|r| CSearchResult {
-            document_id: r.document_id,
-            content: to_c_string(r.content),
-            score: r.score as c_double,
-            collection: to_c_string(r.collection_name),
-            metadata: if let Some(m) = r.metadata {
-                to_c_string(m.to_string())
-            } else {
-                ptr::null_mut()
-            },
-        })
-        .collect();
-
-    let count = c_results.len() as c_int;
-    let results_ptr = c_results.as_mut_ptr();
-    std::mem::forget(c_results);
+    let (results_ptr, count) = to_c_search_result_array(results);
 
     Box::into_raw(Box::new(CSearchResults {
         results: results_ptr,
@@ -298,7 +768,8 @@ pub unsafe extern "C" fn doredore_search(
 /// Get enriched context for a query (main RAG function)
 ///
 /// # Parameters
-/// * mode - Search mode: "semantic", "keyword", or "hybrid" (default: "semantic")
+/// * mode - Search mode: "semantic", "keyword", or "hybrid" (default: "semantic");
+///   any other value fails with DOREDORE_ERROR_INVALID_INPUT
 /// * semantic_weight - Weight for semantic score in hybrid mode (default: 0.7)
 /// * keyword_weight - Weight for keyword score in hybrid mode (default: 0.3)
 ///
@@ -315,7 +786,9 @@ pub unsafe extern "C" fn doredore_enrich(
     semantic_weight: c_double,
     keyword_weight: c_double,
 ) -> *mut c_char {
+    clear_last_error();
     if rag.is_null() {
+        null_argument("rag");
         return ptr::null_mut();
     }
 
@@ -327,19 +800,68 @@ pub unsafe extern "C" fn doredore_enrich(
         Some(from_c_string(collection))
     };
 
-    // モード文字列をSearchModeに変換
-    use doredore_core::SearchMode;
-    let mode_str = if mode.is_null() {
-        "semantic".to_string()
+    let search_mode = match parse_search_mode(mode) {
+        Some(mode) => mode,
+        None => return ptr::null_mut(),
+    };
+
+    // hybrid_weightsを設定（デフォルト: 0.7, 0.3）
+    let weights = if semantic_weight > 0.0 && keyword_weight > 0.0 {
+        Some((semantic_weight as f32, keyword_weight as f32))
     } else {
-        from_c_string(mode)
+        None
     };
 
-    let search_mode = match mode_str.to_lowercase().as_str() {
-        "semantic" => SearchMode::Semantic,
-        "keyword" => SearchMode::Keyword,
-        "hybrid" => SearchMode::Hybrid,
-        _ => SearchMode::Semantic, // デフォルトにフォールバック
+    match check(enricher.enrich(
+        &query_str,
+        collection_str.as_deref(),
+        None,
+        top_k as usize,
+        threshold as f32,
+        search_mode,
+        weights,
+    )) {
+        Some(result) => to_c_string(result.context),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Get enriched context together with its source citations
+///
+/// Same parameters as doredore_enrich(), but returns a CEnrichResult holding
+/// both the formatted context and the array of sources (document_id, score,
+/// collection, content, metadata), so callers can build citation UIs.
+///
+/// # Safety
+/// Caller must call doredore_free_enrich_result() to deallocate
+#[no_mangle]
+pub unsafe extern "C" fn doredore_enrich_full(
+    rag: *mut Doredore,
+    query: *const c_char,
+    collection: *const c_char,
+    top_k: c_int,
+    threshold: c_double,
+    mode: *const c_char,
+    semantic_weight: c_double,
+    keyword_weight: c_double,
+) -> *mut CEnrichResult {
+    clear_last_error();
+    if rag.is_null() {
+        null_argument("rag");
+        return ptr::null_mut();
+    }
+
+    let enricher = &(*rag).inner;
+    let query_str = from_c_string(query);
+    let collection_str = if collection.is_null() {
+        None
+    } else {
+        Some(from_c_string(collection))
+    };
+
+    let search_mode = match parse_search_mode(mode) {
+        Some(mode) => mode,
+        None => return ptr::null_mut(),
     };
 
     // hybrid_weightsを設定（デフォルト: 0.7, 0.3）
@@ -349,7 +871,7 @@ pub unsafe extern "C" fn doredore_enrich(
         None
     };
 
-    match enricher.enrich(
+    let result = match check(enricher.enrich(
         &query_str,
         collection_str.as_deref(),
         None,
@@ -357,9 +879,82 @@ pub unsafe extern "C" fn doredore_enrich(
         threshold as f32,
         search_mode,
         weights,
-    ) {
-        Ok(result) => to_c_string(result.context),
-        Err(_) => ptr::null_mut(),
+    )) {
+        Some(r) => r,
+        None => return ptr::null_mut(),
+    };
+
+    let (sources_ptr, source_count) = to_c_search_result_array(result.sources);
+
+    Box::into_raw(Box::new(CEnrichResult {
+        question: to_c_string(result.question),
+        context: to_c_string(result.context),
+        sources: sources_ptr,
+        source_count,
+    }))
+}
+
+/// Get enriched context as chat API messages (JSON)
+///
+/// Same parameters as doredore_enrich() without the hybrid weights (the
+/// defaults are used), plus an optional system prompt (NULL = generic prompt).
+/// Returns a JSON array of `{"role", "content"}` objects: a system message
+/// with the context and citation instructions, followed by a user message
+/// with the query.
+///
+/// # Safety
+/// Caller must call doredore_free_string() on the returned string
+#[no_mangle]
+pub unsafe extern "C" fn doredore_enrich_chat_messages(
+    rag: *mut Doredore,
+    query: *const c_char,
+    collection: *const c_char,
+    top_k: c_int,
+    threshold: c_double,
+    mode: *const c_char,
+    system_prompt: *const c_char,
+) -> *mut c_char {
+    clear_last_error();
+    if rag.is_null() {
+        null_argument("rag");
+        return ptr::null_mut();
+    }
+
+    let enricher = &(*rag).inner;
+    let query_str = from_c_string(query);
+    let collection_str = if collection.is_null() {
+        None
+    } else {
+        Some(from_c_string(collection))
+    };
+    let system_prompt_str = if system_prompt.is_null() {
+        None
+    } else {
+        Some(from_c_string(system_prompt))
+    };
+
+    let search_mode = match parse_search_mode(mode) {
+        Some(mode) => mode,
+        None => return ptr::null_mut(),
+    };
+
+    let json = enricher
+        .enrich(
+            &query_str,
+            collection_str.as_deref(),
+            None,
+            top_k as usize,
+            threshold as f32,
+            search_mode,
+            None,
+        )
+        .and_then(|result| {
+            let messages = result.to_chat_messages(system_prompt_str.as_deref());
+            serde_json::to_string(&messages).map_err(Error::from)
+        });
+    match check(json) {
+        Some(json) => to_c_string(json),
+        None => ptr::null_mut(),
     }
 }
 
@@ -368,6 +963,9 @@ pub unsafe extern "C" fn doredore_enrich(
 // ============================================================================
 
 /// Import documents from CSV file
+///
+/// If content_column is NULL or empty, the content column is auto-detected
+/// (the column with the longest average cell length).
 #[no_mangle]
 pub unsafe extern "C" fn doredore_import_csv(
     rag: *mut Doredore,
@@ -375,7 +973,9 @@ pub unsafe extern "C" fn doredore_import_csv(
     collection: *const c_char,
     content_column: *const c_char,
 ) -> c_int {
+    clear_last_error();
     if rag.is_null() {
+        null_argument("rag");
         return -1;
     }
 
@@ -386,16 +986,15 @@ pub unsafe extern "C" fn doredore_import_csv(
     } else {
         from_c_string(collection)
     };
+    // NULLの場合は本文カラムを自動検出
     let content_col = if content_column.is_null() {
-        "content".to_string()
+        String::new()
     } else {
         from_c_string(content_column)
     };
 
-    match enricher.import_csv(&file_str, &collection_str, &content_col, None) {
-        Ok(count) => count as c_int,
-        Err(_) => -1,
-    }
+    check(enricher.import_csv(&file_str, &collection_str, &content_col, None))
+        .map_or(-1, |count| count as c_int)
 }
 
 /// Export documents to CSV file
@@ -405,7 +1004,9 @@ pub unsafe extern "C" fn doredore_export_csv(
     file_path: *const c_char,
     collection: *const c_char,
 ) -> c_int {
+    clear_last_error();
     if rag.is_null() {
+        null_argument("rag");
         return -1;
     }
 
@@ -417,10 +1018,8 @@ pub unsafe extern "C" fn doredore_export_csv(
         Some(from_c_string(collection))
     };
 
-    match enricher.export_csv(&file_str, collection_str.as_deref()) {
-        Ok(count) => count as c_int,
-        Err(_) => -1,
-    }
+    check(enricher.export_csv(&file_str, collection_str.as_deref()))
+        .map_or(-1, |count| count as c_int)
 }
 
 // ============================================================================
@@ -443,14 +1042,188 @@ pub unsafe extern "C" fn doredore_free_search_results(results: *mut CSearchResul
     }
 
     let results_box = Box::from_raw(results);
-    let results_vec =
-        Vec::from_raw_parts(results_box.results, results_box.count as usize, results_box.count as usize);
+    free_c_search_result_array(results_box.results, results_box.count);
+}
 
-    for result in results_vec {
-        doredore_free_string(result.content);
-        doredore_free_string(result.collection);
-        if !result.metadata.is_null() {
-            doredore_free_string(result.metadata);
+/// Free an ID array returned by doredore_add_documents()
+///
+/// # Safety
+/// `count` must be the value written to `out_count` by doredore_add_documents()
+#[no_mangle]
+pub unsafe extern "C" fn doredore_free_ids(ids: *mut c_longlong, count: c_int) {
+    if ids.is_null() {
+        return;
+    }
+
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ids, count as usize)));
+}
+
+/// Free an enrich result returned by doredore_enrich_full()
+#[no_mangle]
+pub unsafe extern "C" fn doredore_free_enrich_result(result: *mut CEnrichResult) {
+    if result.is_null() {
+        return;
+    }
+
+    let result_box = Box::from_raw(result);
+    doredore_free_string(result_box.question);
+    doredore_free_string(result_box.context);
+    free_c_search_result_array(result_box.sources, result_box.source_count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use doredore_core::MockEmbedder;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_add_document_unique_returns_existing_id() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let inner =
+            CoreDoredore::new_with_embedder(temp_db.path(), MockEmbedder::new(384)).unwrap();
+        let rag = Box::into_raw(Box::new(Doredore { inner }));
+
+        let collection = CString::new("docs").unwrap();
+        let content = CString::new("same text").unwrap();
+        let other = CString::new("other text").unwrap();
+
+        unsafe {
+            assert!(doredore_create_collection(rag, collection.as_ptr(), ptr::null()) > 0);
+
+            let first = doredore_add_document_unique(
+                rag,
+                content.as_ptr(),
+                collection.as_ptr(),
+                ptr::null(),
+            );
+            let second = doredore_add_document_unique(
+                rag,
+                content.as_ptr(),
+                collection.as_ptr(),
+                ptr::null(),
+            );
+            assert!(first > 0);
+            assert_eq!(second, first);
+            assert_eq!((*rag).inner.count_documents(Some("docs")).unwrap(), 1);
+
+            let third =
+                doredore_add_document_unique(rag, other.as_ptr(), collection.as_ptr(), ptr::null());
+            assert_ne!(third, first);
+            assert_eq!((*rag).inner.count_documents(Some("docs")).unwrap(), 2);
+
+            doredore_free(rag);
+        }
+    }
+
+    #[test]
+    fn test_add_documents_round_trip() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let inner =
+            CoreDoredore::new_with_embedder(temp_db.path(), MockEmbedder::new(384)).unwrap();
+        let rag = Box::into_raw(Box::new(Doredore { inner }));
+
+        let collection = CString::new("docs").unwrap();
+        let texts: Vec<CString> = ["first", "second", "third"]
+            .iter()
+            .map(|t| CString::new(*t).unwrap())
+            .collect();
+        let pointers: Vec<*const c_char> = texts.iter().map(|t| t.as_ptr()).collect();
+
+        unsafe {
+            assert!(doredore_create_collection(rag, collection.as_ptr(), ptr::null()) > 0);
+
+            let mut count: c_int = -1;
+            let ids = doredore_add_documents(
+                rag,
+                pointers.as_ptr(),
+                pointers.len() as c_int,
+                collection.as_ptr(),
+                &mut count,
+            );
+            assert!(!ids.is_null());
+            assert_eq!(count, 3);
+
+            // 入力と同じ順序でIDが返る
+            let id_slice = std::slice::from_raw_parts(ids, count as usize);
+            for (id, text) in id_slice.iter().zip(["first", "second", "third"]) {
+                assert_eq!((*rag).inner.get_document(*id).unwrap().content, text);
+            }
+            doredore_free_ids(ids, count);
+
+            // 存在しないコレクションはnull
+            let missing = CString::new("missing").unwrap();
+            let ids = doredore_add_documents(
+                rag,
+                pointers.as_ptr(),
+                pointers.len() as c_int,
+                missing.as_ptr(),
+                &mut count,
+            );
+            assert!(ids.is_null());
+
+            doredore_free(rag);
+        }
+    }
+
+    #[test]
+    fn test_last_error_reports_failure_kind() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let inner =
+            CoreDoredore::new_with_embedder(temp_db.path(), MockEmbedder::new(384)).unwrap();
+        let rag = Box::into_raw(Box::new(Doredore { inner }));
+
+        let content = CString::new("hello").unwrap();
+        let missing = CString::new("missing").unwrap();
+        let docs = CString::new("docs").unwrap();
+        let last_error = || unsafe { CStr::from_ptr(doredore_last_error()).to_str().unwrap() };
+
+        unsafe {
+            // 存在しないコレクション
+            let id = doredore_add_document(rag, content.as_ptr(), missing.as_ptr(), ptr::null());
+            assert_eq!(id, -1);
+            assert_eq!(
+                doredore_last_error_code(),
+                DOREDORE_ERROR_COLLECTION_NOT_FOUND
+            );
+            assert!(last_error().contains("missing"), "{}", last_error());
+
+            // 成功した呼び出しでエラーは消去される
+            assert!(doredore_create_collection(rag, docs.as_ptr(), ptr::null()) > 0);
+            assert_eq!(doredore_last_error_code(), DOREDORE_OK);
+            assert!(doredore_last_error().is_null());
+
+            // 不正なメタデータJSON
+            let metadata = CString::new("{not json").unwrap();
+            let id = doredore_add_document(rag, content.as_ptr(), docs.as_ptr(), metadata.as_ptr());
+            assert_eq!(id, -1);
+            assert_eq!(doredore_last_error_code(), DOREDORE_ERROR_JSON);
+            assert!(last_error().starts_with("JSON error"), "{}", last_error());
+
+            // NULLのハンドル
+            assert_eq!(
+                doredore_delete_collection(ptr::null_mut(), docs.as_ptr()),
+                -1
+            );
+            assert_eq!(doredore_last_error_code(), DOREDORE_ERROR_NULL_POINTER);
+            assert!(last_error().contains("rag"), "{}", last_error());
+
+            // 不明な検索モード
+            let mode = CString::new("fuzzy").unwrap();
+            let messages = doredore_enrich_chat_messages(
+                rag,
+                content.as_ptr(),
+                docs.as_ptr(),
+                5,
+                0.0,
+                mode.as_ptr(),
+                ptr::null(),
+            );
+            assert!(messages.is_null());
+            assert_eq!(doredore_last_error_code(), DOREDORE_ERROR_INVALID_INPUT);
+            assert!(last_error().contains("fuzzy"), "{}", last_error());
+
+            doredore_free(rag);
         }
     }
 }
@@ -1,21 +1,38 @@
+mod metrics;
+mod pool;
+
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::{Html, IntoResponse, Json},
-    routing::{delete, get, post},
+    http::{header, StatusCode},
+    middleware,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Json, Response,
+    },
+    routing::{get, patch, post},
     Router,
 };
 use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use tower_http::{
+    compression::CompressionLayer,
     cors::{Any, CorsLayer},
     services::ServeDir,
     trace::TraceLayer,
 };
 use tracing::{info, warn};
+use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+use metrics::{Gauges, Metrics, MetricsLayer};
+use pool::ReaderPool;
 
 use doredore_core::core::enricher::Doredore;
-use doredore_core::SearchMode;
+use doredore_core::{
+    parse_search_query, AddOptions, ContentType, CsvImportOptions, EmbeddingModel, Error,
+    ModelLoadOptions, SearchMode, SearchOptions, SearchResult,
+};
 
 // ============================================================================
 // Application State
@@ -23,7 +40,21 @@ use doredore_core::SearchMode;
 
 #[derive(Clone)]
 struct AppState {
-    rag: Arc<Mutex<Doredore>>,
+    /// 検索・取得系のエンドポイントが使う読み取り専用のインスタンスのプール
+    ///
+    /// インスタンスごとに別の接続を持つため、プールのサイズまで並列に処理できる
+    rag: Arc<ReaderPool>,
+    /// 変更系のエンドポイントが使う書き込み用のインスタンス（READ_ONLY時はNone）
+    ///
+    /// `rag` とは別の接続で同じファイルを開くため、書き込み中も検索は待たされない。
+    /// 変更系のリクエストはこのインスタンスのロックで1件ずつ処理する
+    writer: Option<Arc<Mutex<Doredore>>>,
+    /// レスポンスのスコアを丸める小数点以下の桁数（None = 丸めない）
+    score_precision: Option<u8>,
+    /// 1レスポンスに含める本文の合計文字数の上限（None = 無制限）
+    max_response_chars: Option<usize>,
+    /// `/metrics` で公開するメトリクス（METRICS_ENABLED が無効ならNone）
+    metrics: Option<Arc<Metrics>>,
 }
 
 // ============================================================================
@@ -34,6 +65,21 @@ struct AppState {
 struct CreateCollectionRequest {
     name: String,
     description: Option<String>,
+    /// 省略時はサーバーのモデルを使用
+    model: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IndexedFieldsRequest {
+    /// キーワード検索の対象に加えるメタデータのフィールド名（空 = 本文のみ）
+    fields: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateCollectionMetadataRequest {
+    patch: serde_json::Value,
+    /// true: 既存メタデータにマージ（デフォルト） / false: 置き換え
+    merge: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -41,21 +87,268 @@ struct AddDocumentRequest {
     content: String,
     collection: Option<String>,
     metadata: Option<serde_json::Value>,
+    /// "plain"（デフォルト）/ "html" / "markdown"
+    #[serde(default)]
+    content_type: ContentType,
+    /// 変換前のコンテンツをメタデータの `original_content` に保存する
+    #[serde(default)]
+    keep_original: bool,
+    /// 呼び出し側が管理するID。同じ外部IDのドキュメントがあれば更新する（upsert）
+    external_id: Option<String>,
+    /// 同じコレクションに内容が同一のドキュメントがあれば、追加せずにそのIDを返す
+    #[serde(default)]
+    skip_duplicates: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateDocumentRequest {
+    /// 変更後の本文（変更するとEmbeddingを再生成する）
+    content: Option<String>,
+    /// 変更後のメタデータ（既存のメタデータを置き換える）
+    metadata: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize)]
 struct SearchQuery {
     q: String,
     collection: Option<String>,
+    /// 検索モード: "semantic"（デフォルト）、"semantic_ann"、"keyword"、"hybrid"
+    mode: Option<String>,
+    /// ハイブリッド検索のセマンティックスコアの重み（デフォルト: 0.7）
+    semantic_weight: Option<f32>,
+    /// ハイブリッド検索のキーワードスコアの重み（デフォルト: 0.3）
+    keyword_weight: Option<f32>,
+    /// 複数コレクション（カンマ区切り: "faq,docs"）。`collection` 指定時は無視
+    collections: Option<String>,
+    /// 存在しないコレクションをエラーにせずスキップする
+    skip_missing: Option<bool>,
     top_k: Option<usize>,
     threshold: Option<f32>,
+    /// 除外するドキュメントID（カンマ区切り: "1,2,3"）
+    exclude_ids: Option<String>,
+    /// スコアを丸める小数点以下の桁数（サーバー設定より優先）
+    round_scores: Option<u8>,
+    /// `q` から `key:value` 形式のメタデータ条件を取り出す
+    /// （例: "category:legal year:>2020 permanent memorial"）
+    parse_query: Option<bool>,
+    /// 各結果にEmbeddingベクトル（`embedding`）を含める
+    include_embedding: Option<bool>,
+    /// 各結果の本文の空白を整理する（連続する空白・余分な空行・前後の空白を除去）
+    normalize_output: Option<bool>,
+    /// 本文が空のドキュメントを検索候補から除外する
+    exclude_empty: Option<bool>,
+    /// 閾値で絞り込んだ結果がこの件数に満たなければ、閾値未満の上位から補う
+    min_results: Option<usize>,
+    /// 各段階の所要時間・候補数などの診断情報（`diagnostics`）を含める
+    explain: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
 struct EnrichQuery {
     q: String,
     collection: Option<String>,
+    /// 検索モード: "semantic"（デフォルト）、"semantic_ann"、"keyword"、"hybrid"
+    mode: Option<String>,
+    /// ハイブリッド検索のセマンティックスコアの重み（デフォルト: 0.7）
+    semantic_weight: Option<f32>,
+    /// ハイブリッド検索のキーワードスコアの重み（デフォルト: 0.3）
+    keyword_weight: Option<f32>,
+    /// 複数コレクション（カンマ区切り: "faq,docs"）。`collection` 指定時は無視
+    collections: Option<String>,
+    /// 存在しないコレクションをエラーにせずスキップする
+    skip_missing: Option<bool>,
     top_k: Option<usize>,
+    /// 除外するドキュメントID（カンマ区切り: "1,2,3"）
+    exclude_ids: Option<String>,
+    /// スコアを丸める小数点以下の桁数（サーバー設定より優先）
+    round_scores: Option<u8>,
+    /// 各ソースにEmbeddingベクトル（`embedding`）を含める
+    include_embedding: Option<bool>,
+    /// 各ソースとコンテキストの本文の空白を整理する
+    normalize_output: Option<bool>,
+    /// コンテキストの先頭に追加する文字列（指示文など）
+    context_prefix: Option<String>,
+    /// コンテキストの末尾に追加する文字列
+    context_suffix: Option<String>,
+    /// コンテキストの最大文字数（収まらないソースは切り詰め・除外する）
+    max_context_chars: Option<usize>,
+    /// 各ソースの書式（`{index}` `{score}` `{collection}` `{content}` などを埋め込む）
+    context_template: Option<String>,
+    /// ソース間の区切り（デフォルト: 空行）
+    context_separator: Option<String>,
+    /// 各ソースの見出しにコレクションの説明を含める
+    include_collection_description: Option<bool>,
+    /// 本文が空のドキュメントを検索候補から除外する
+    exclude_empty: Option<bool>,
+    /// 閾値で絞り込んだ結果がこの件数に満たなければ、閾値未満の上位から補う
+    min_results: Option<usize>,
+    /// レスポンスの形式: "context"（デフォルト）または "chat"（`context` の代わりに `messages`）
+    format: Option<String>,
+    /// `format=chat` のシステムメッセージの前置き（省略時は汎用の文）
+    system_prompt: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CountQuery {
+    q: String,
+    collection: Option<String>,
+    mode: Option<String>,
+    threshold: Option<f32>,
+}
+
+/// スコアをJSON値に変換（precisionが指定されていれば小数点以下N桁に丸める）
+///
+/// 無限大・NaNはJSONの数値として表せないため、丸めずに `null` にする
+fn score_value(score: f32, precision: Option<u8>) -> serde_json::Value {
+    if !score.is_finite() {
+        return serde_json::Value::Null;
+    }
+    match precision {
+        Some(digits) => {
+            let factor = 10f64.powi(digits as i32);
+            serde_json::json!((score as f64 * factor).round() / factor)
+        }
+        None => serde_json::json!(score),
+    }
+}
+
+/// 検索結果をレスポンス用のJSONに変換
+///
+/// `embedding` は結果に含まれている場合（`include_embedding` 指定時）のみ出力する
+fn search_result_json(result: SearchResult, precision: Option<u8>) -> serde_json::Value {
+    let mut json = serde_json::json!({
+        "document_id": result.document_id,
+        "content": result.content,
+        "score": score_value(result.score, precision),
+        "rank": result.rank,
+        "backfilled": result.backfilled,
+        "collection": result.collection_name,
+        "metadata": result.metadata
+    });
+    if let Some(embedding) = result.embedding {
+        json["embedding"] = serde_json::json!(embedding);
+    }
+    json
+}
+
+/// 検索モード文字列をSearchModeに変換
+fn parse_search_mode(mode: Option<&str>) -> Result<SearchMode, String> {
+    match mode.unwrap_or("semantic").to_lowercase().as_str() {
+        "semantic" => Ok(SearchMode::Semantic),
+        "semantic_ann" => Ok(SearchMode::SemanticAnn),
+        "keyword" => Ok(SearchMode::Keyword),
+        "hybrid" => Ok(SearchMode::Hybrid),
+        other => Err(format!(
+            "Invalid search mode: '{}'. Use 'semantic', 'semantic_ann', 'keyword', or 'hybrid'",
+            other
+        )),
+    }
+}
+
+/// ハイブリッド検索の重みを検証（片方のみ指定された場合、もう片方はデフォルト値）
+fn parse_hybrid_weights(
+    semantic_weight: Option<f32>,
+    keyword_weight: Option<f32>,
+) -> Result<Option<(f32, f32)>, String> {
+    if semantic_weight.is_none() && keyword_weight.is_none() {
+        return Ok(None);
+    }
+    let weights = (semantic_weight.unwrap_or(0.7), keyword_weight.unwrap_or(0.3));
+    if !weights.0.is_finite() || !weights.1.is_finite() || weights.0 < 0.0 || weights.1 < 0.0 {
+        return Err("semantic_weight and keyword_weight must be non-negative numbers".to_string());
+    }
+    if weights.0 + weights.1 <= 0.0 {
+        return Err("semantic_weight and keyword_weight must not both be 0".to_string());
+    }
+    Ok(Some(weights))
+}
+
+/// CSVインポートのリクエストをCsvImportOptionsに変換（本文カラムが未指定の場合は自動検出）
+fn csv_import_options(req: &ImportCsvRequest) -> CsvImportOptions {
+    CsvImportOptions {
+        content_column: req.content_column.clone().unwrap_or_default(),
+        metadata_columns: req.metadata_columns.clone(),
+        content_type: req.content_type,
+        infer_types: req.infer_types.unwrap_or(true),
+        add_options: AddOptions {
+            bulk: req.bulk.unwrap_or(false),
+            ..Default::default()
+        },
+    }
+}
+
+/// カンマ区切りのドキュメントIDリストをSearchOptionsに変換
+fn parse_search_options(exclude_ids: Option<&str>) -> Result<SearchOptions, String> {
+    let exclude_ids = exclude_ids
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<i64>()
+                .map_err(|_| format!("Invalid document id in exclude_ids: '{}'", s))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(SearchOptions {
+        exclude_ids,
+        ..Default::default()
+    })
+}
+
+/// カンマ区切りのコレクション名をパース（"faq,docs"）
+fn parse_collections(collections: Option<&str>) -> Option<Vec<String>> {
+    collections.map(|c| {
+        c.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect()
+    })
+}
+
+/// `skip_missing` で検索した際にスキップされたコレクション名
+fn skipped_collections(
+    rag: &Doredore,
+    collection: Option<&str>,
+    collections: Option<&[String]>,
+    options: &SearchOptions,
+) -> Vec<String> {
+    if !options.skip_missing {
+        return Vec::new();
+    }
+
+    let names = match (collection, collections) {
+        (Some(name), _) => vec![name.to_string()],
+        (None, Some(names)) => names.to_vec(),
+        (None, None) => return Vec::new(),
+    };
+
+    rag.find_missing_collections(&names).unwrap_or_default()
+}
+
+/// レスポンスに含める本文の合計文字数が上限以内か確認
+///
+/// 上限を超える場合は、件数を減らすよう促すエラーメッセージを返す
+/// （巨大なJSONのシリアライズでサーバーやクライアントのメモリを使い切らないため）
+fn check_response_size<'a>(
+    contents: impl IntoIterator<Item = &'a str>,
+    max_chars: Option<usize>,
+) -> Result<(), String> {
+    let Some(max_chars) = max_chars else {
+        return Ok(());
+    };
+
+    let total: usize = contents.into_iter().map(|c| c.chars().count()).sum();
+    if total > max_chars {
+        return Err(format!(
+            "Response too large: {} characters of content exceeds the limit of {} \
+             (MAX_RESPONSE_CHARS). Reduce top_k or limit, raise threshold, or narrow the collections",
+            total, max_chars
+        ));
+    }
+
+    Ok(())
 }
 
 #[derive(Debug, Deserialize)]
@@ -63,6 +356,25 @@ struct ImportCsvRequest {
     file_path: String,
     collection: Option<String>,
     content_column: Option<String>,
+    /// メタデータとして取り込むカラム（デフォルト: なし）
+    metadata_columns: Option<Vec<String>>,
+    /// メタデータの値を数値・真偽値として解釈する（デフォルト: true、falseならすべて文字列）
+    infer_types: Option<bool>,
+    /// FTSインデックスをインポート完了後にまとめて構築する（大量インポート向け）
+    bulk: Option<bool>,
+    /// 本文カラムの形式: "plain"（デフォルト）/ "html" / "markdown"
+    #[serde(default)]
+    content_type: ContentType,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportJsonlRequest {
+    file_path: String,
+    collection: Option<String>,
+    /// 本文のフィールド名（デフォルト: "content"）
+    content_field: Option<String>,
+    /// メタデータとして取り込むオブジェクトのフィールド名（デフォルト: "metadata"）
+    metadata_field: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -72,14 +384,37 @@ struct ApiResponse<T> {
     data: Option<T>,
     #[serde(skip_serializing_if = "Option::is_none")]
     error: Option<String>,
+    /// 一覧の総件数と取得範囲（`data` と同じ階層に出力する）
+    #[serde(flatten)]
+    page: Option<Page>,
+}
+
+/// 一覧のページ情報（`total` は `limit` / `offset` を適用する前の件数）
+#[derive(Debug, Serialize)]
+struct Page {
+    total: i64,
+    limit: i64,
+    offset: i64,
 }
 
+/// ハンドラーが途中で返すエラーレスポンス
+type ErrorResponse = (StatusCode, Json<ApiResponse<serde_json::Value>>);
+
 impl<T> ApiResponse<T> {
     fn success(data: T) -> Self {
         Self {
             success: true,
             data: Some(data),
             error: None,
+            page: None,
+        }
+    }
+
+    /// 一覧の1ページ分と、そのページ情報
+    fn page(data: T, page: Page) -> Self {
+        Self {
+            page: Some(page),
+            ..Self::success(data)
         }
     }
 
@@ -88,42 +423,131 @@ impl<T> ApiResponse<T> {
             success: false,
             data: None,
             error: Some(message),
+            page: None,
         }
     }
 }
 
+/// エラーのステータスコード（存在しないコレクション・ドキュメントは404、それ以外は `fallback`）
+fn error_status(e: &Error, fallback: StatusCode) -> StatusCode {
+    match e {
+        Error::CollectionNotFound(_) | Error::DocumentNotFound(_) => StatusCode::NOT_FOUND,
+        _ => fallback,
+    }
+}
+
+/// 変更系のエンドポイントで使う書き込み用のインスタンス
+///
+/// 読み取り専用モード（READ_ONLY）では書き込み用の接続を開かないため、405を返す
+fn writer(state: &AppState) -> Result<Arc<Mutex<Doredore>>, ErrorResponse> {
+    state.writer.clone().ok_or_else(|| {
+        (
+            StatusCode::METHOD_NOT_ALLOWED,
+            Json(ApiResponse::error(
+                "Server is running in read-only mode (READ_ONLY); write endpoints are disabled"
+                    .to_string(),
+            )),
+        )
+    })
+}
+
 // ============================================================================
 // API Handlers
 // ============================================================================
 
 /// Health check endpoint
-async fn health_check() -> impl IntoResponse {
-    Json(serde_json::json!({
-        "status": "ok",
-        "service": "doredore-server",
-        "version": env!("CARGO_PKG_VERSION")
-    }))
+///
+/// Probes the database with a collection listing so load balancers only route traffic to
+/// an instance that can serve requests. Returns 503 if the probe fails
+async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
+    let rag = state.rag.get().await;
+    let (status, db_ok, collections, error) = match rag.list_collections() {
+        Ok(collections) => (StatusCode::OK, true, collections, None),
+        Err(e) => {
+            warn!("Health check failed: {}", e);
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                false,
+                Vec::new(),
+                Some(e.to_string()),
+            )
+        }
+    };
+    let document_count: i64 = collections.iter().map(|c| c.document_count).sum();
+
+    (
+        status,
+        Json(serde_json::json!({
+            "status": if db_ok { "ok" } else { "unavailable" },
+            "service": "doredore-server",
+            "version": env!("CARGO_PKG_VERSION"),
+            "db_ok": db_ok,
+            "model": rag.model_name(),
+            "dimension": rag.dimension(),
+            "collection_count": collections.len(),
+            "document_count": document_count,
+            "error": error
+        })),
+    )
+}
+
+/// Prometheus metrics (text exposition format)
+async fn prometheus_metrics(State(state): State<AppState>) -> Response {
+    let Some(metrics) = &state.metrics else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let rag = state.rag.get().await;
+    let collections = match rag.list_collections() {
+        Ok(collections) => collections,
+        Err(e) => {
+            warn!("Failed to collect metrics: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    };
+    let gauges = Gauges {
+        documents: collections.iter().map(|c| c.document_count as usize).sum(),
+        collections: collections.len(),
+        db_size_bytes: std::fs::metadata(rag.db_path()).map_or(0, |m| m.len()),
+    };
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(&gauges),
+    )
+        .into_response()
+}
+
+/// List supported embedding models and the one currently in use
+async fn list_models(State(state): State<AppState>) -> impl IntoResponse {
+    let rag = state.rag.get().await;
+    let current = rag.model_name();
+
+    let models: Vec<_> = EmbeddingModel::list_supported_models()
+        .into_iter()
+        .map(|(name, dimension)| {
+            serde_json::json!({
+                "name": name,
+                "dimension": dimension,
+                "current": name == current
+            })
+        })
+        .collect();
+
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(serde_json::json!({
+            "current": current,
+            "models": models
+        }))),
+    )
 }
 
 /// List all collections
 async fn list_collections(State(state): State<AppState>) -> impl IntoResponse {
-    let rag = state.rag.lock().unwrap();
+    let rag = state.rag.get().await;
     match rag.list_collections() {
-        Ok(collections) => {
-            let collections_data: Vec<_> = collections
-                .into_iter()
-                .map(|c| {
-                    serde_json::json!({
-                        "id": c.id,
-                        "name": c.name,
-                        "description": c.description,
-                        "created_at": c.created_at
-                    })
-                })
-                .collect();
-
-            (StatusCode::OK, Json(ApiResponse::success(collections_data)))
-        }
+        Ok(collections) => (StatusCode::OK, Json(ApiResponse::success(collections))),
         Err(e) => {
             warn!("Failed to list collections: {}", e);
             (
@@ -134,13 +558,39 @@ async fn list_collections(State(state): State<AppState>) -> impl IntoResponse {
     }
 }
 
+/// Get a collection with its document count and timestamps
+async fn get_collection(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    let rag = state.rag.get().await;
+    match rag.get_collection(&name) {
+        Ok(collection) => (StatusCode::OK, Json(ApiResponse::success(collection))),
+        Err(e) => {
+            warn!("Failed to get collection: {}", e);
+            (
+                error_status(&e, StatusCode::INTERNAL_SERVER_ERROR),
+                Json(ApiResponse::error(e.to_string())),
+            )
+        }
+    }
+}
+
 /// Create a new collection
 async fn create_collection(
     State(state): State<AppState>,
     Json(req): Json<CreateCollectionRequest>,
 ) -> impl IntoResponse {
-    let rag = state.rag.lock().unwrap();
-    match rag.create_collection(&req.name, req.description.as_deref()) {
+    let writer = match writer(&state) {
+        Ok(writer) => writer,
+        Err(response) => return response,
+    };
+    let rag = writer.lock().unwrap();
+    match rag.create_collection_with_model(
+        &req.name,
+        req.description.as_deref(),
+        req.model.as_deref(),
+    ) {
         Ok(id) => {
             info!("Created collection '{}' with id {}", req.name, id);
             (
@@ -166,9 +616,17 @@ async fn delete_collection(
     State(state): State<AppState>,
     Path(name): Path<String>,
 ) -> impl IntoResponse {
-    let rag = state.rag.lock().unwrap();
+    let writer = match writer(&state) {
+        Ok(writer) => writer,
+        Err(response) => return response,
+    };
+    let rag = writer.lock().unwrap();
     match rag.delete_collection(&name) {
-        Ok(_) => {
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Collection '{}' not found", name))),
+        ),
+        Ok(true) => {
             info!("Deleted collection '{}'", name);
             (
                 StatusCode::OK,
@@ -180,7 +638,94 @@ async fn delete_collection(
         Err(e) => {
             warn!("Failed to delete collection: {}", e);
             (
-                StatusCode::BAD_REQUEST,
+                error_status(&e, StatusCode::BAD_REQUEST),
+                Json(ApiResponse::error(e.to_string())),
+            )
+        }
+    }
+}
+
+/// Update metadata of every document in a collection
+async fn update_collection_metadata(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(req): Json<UpdateCollectionMetadataRequest>,
+) -> impl IntoResponse {
+    let merge = req.merge.unwrap_or(true);
+
+    let writer = match writer(&state) {
+        Ok(writer) => writer,
+        Err(response) => return response,
+    };
+    let rag = writer.lock().unwrap();
+    match rag.update_collection_metadata(&name, &req.patch, merge) {
+        Ok(count) => {
+            info!("Updated metadata of {} documents in '{}'", count, name);
+            (
+                StatusCode::OK,
+                Json(ApiResponse::success(serde_json::json!({
+                    "collection": name,
+                    "updated": count
+                }))),
+            )
+        }
+        Err(e) => {
+            warn!("Failed to update collection metadata: {}", e);
+            (
+                error_status(&e, StatusCode::BAD_REQUEST),
+                Json(ApiResponse::error(e.to_string())),
+            )
+        }
+    }
+}
+
+/// Get the metadata fields included in keyword search
+async fn get_indexed_fields(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    let rag = state.rag.get().await;
+    match rag.indexed_metadata_fields(&name) {
+        Ok(fields) => (
+            StatusCode::OK,
+            Json(ApiResponse::success(serde_json::json!({
+                "collection": name,
+                "fields": fields
+            }))),
+        ),
+        Err(e) => (
+            error_status(&e, StatusCode::INTERNAL_SERVER_ERROR),
+            Json(ApiResponse::error(e.to_string())),
+        ),
+    }
+}
+
+/// Set the metadata fields included in keyword search (reindexes the collection)
+async fn set_indexed_fields(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(req): Json<IndexedFieldsRequest>,
+) -> impl IntoResponse {
+    let writer = match writer(&state) {
+        Ok(writer) => writer,
+        Err(response) => return response,
+    };
+    let rag = writer.lock().unwrap();
+    match rag.set_indexed_metadata_fields(&name, &req.fields) {
+        Ok(()) => {
+            info!("Indexed metadata fields of '{}': {:?}", name, req.fields);
+            (
+                StatusCode::OK,
+                Json(ApiResponse::success(serde_json::json!({
+                    "collection": name,
+                    "fields": req.fields
+                }))),
+            )
+        }
+        Err(e) => {
+            warn!("Failed to set indexed metadata fields: {}", e);
+            (
+                error_status(&e, StatusCode::BAD_REQUEST),
                 Json(ApiResponse::error(e.to_string())),
             )
         }
@@ -194,20 +739,133 @@ async fn add_document(
 ) -> impl IntoResponse {
     let collection = req.collection.as_deref().unwrap_or("default");
 
-    let rag = state.rag.lock().unwrap();
-    match rag.add_document(&req.content, collection, req.metadata.as_ref()) {
+    let writer = match writer(&state) {
+        Ok(writer) => writer,
+        Err(response) => return response,
+    };
+    let rag = writer.lock().unwrap();
+
+    // 重複は変換後の本文で判定する。外部ID付きの場合はupsertを優先する
+    if req.skip_duplicates && req.external_id.is_none() {
+        let content = req.content_type.to_plain_text(&req.content);
+        match rag.find_duplicate(&content, collection) {
+            Ok(Some(id)) => {
+                return (
+                    StatusCode::OK,
+                    Json(ApiResponse::success(serde_json::json!({
+                        "id": id,
+                        "collection": collection,
+                        "duplicate": true
+                    }))),
+                );
+            }
+            Ok(None) => {}
+            Err(e) => {
+                return (
+                    error_status(&e, StatusCode::BAD_REQUEST),
+                    Json(ApiResponse::error(e.to_string())),
+                );
+            }
+        }
+    }
+
+    let result = match req.external_id.as_deref() {
+        // 更新時に元のコンテンツを保持する方法がないため、keep_original とは併用できない
+        Some(_) if req.keep_original => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(
+                    "keep_original cannot be combined with external_id".to_string(),
+                )),
+            );
+        }
+        Some(external_id) => rag.add_document_with_external_id(
+            &req.content_type.to_plain_text(&req.content),
+            collection,
+            req.metadata.as_ref(),
+            external_id,
+        ),
+        None => rag.add_document_as(
+            &req.content,
+            collection,
+            req.metadata.as_ref(),
+            req.content_type,
+            req.keep_original,
+        ),
+    };
+
+    match result {
         Ok(id) => {
             info!("Added document {} to collection '{}'", id, collection);
             (
                 StatusCode::CREATED,
                 Json(ApiResponse::success(serde_json::json!({
                     "id": id,
-                    "collection": collection
+                    "collection": collection,
+                    "duplicate": false
                 }))),
             )
         }
         Err(e) => {
             warn!("Failed to add document: {}", e);
+            (
+                error_status(&e, StatusCode::BAD_REQUEST),
+                Json(ApiResponse::error(e.to_string())),
+            )
+        }
+    }
+}
+
+/// Get a document by ID
+async fn get_document(State(state): State<AppState>, Path(id): Path<i64>) -> impl IntoResponse {
+    let rag = state.rag.get().await;
+    match rag.get_document(id) {
+        Ok(document) => (StatusCode::OK, Json(ApiResponse::success(document))),
+        Err(e @ Error::DocumentNotFound(_)) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(e.to_string())),
+        ),
+        Err(e) => {
+            warn!("Failed to get document: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        }
+    }
+}
+
+/// Update a document's content and/or metadata (content changes are re-embedded)
+async fn update_document(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<UpdateDocumentRequest>,
+) -> impl IntoResponse {
+    let writer = match writer(&state) {
+        Ok(writer) => writer,
+        Err(response) => return response,
+    };
+    let rag = writer.lock().unwrap();
+    // 存在しないIDでEmbeddingを生成しないよう、先に存在を確認する
+    let result = rag
+        .get_document(id)
+        .and_then(|_| rag.update_document(id, req.content.as_deref(), req.metadata.as_ref()))
+        .and_then(|_| rag.get_document(id));
+
+    match result {
+        Ok(document) => {
+            info!("Updated document {}", id);
+            (
+                StatusCode::OK,
+                Json(ApiResponse::success(serde_json::json!(document))),
+            )
+        }
+        Err(e @ Error::DocumentNotFound(_)) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(e.to_string())),
+        ),
+        Err(e) => {
+            warn!("Failed to update document: {}", e);
             (
                 StatusCode::BAD_REQUEST,
                 Json(ApiResponse::error(e.to_string())),
@@ -221,9 +879,17 @@ async fn delete_document(
     State(state): State<AppState>,
     Path(id): Path<i64>,
 ) -> impl IntoResponse {
-    let rag = state.rag.lock().unwrap();
+    let writer = match writer(&state) {
+        Ok(writer) => writer,
+        Err(response) => return response,
+    };
+    let rag = writer.lock().unwrap();
     match rag.delete_document(id) {
-        Ok(_) => {
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Document {} not found", id))),
+        ),
+        Ok(true) => {
             info!("Deleted document {}", id);
             (
                 StatusCode::OK,
@@ -232,6 +898,59 @@ async fn delete_document(
                 }))),
             )
         }
+        Err(e) => {
+            warn!("Failed to delete document: {}", e);
+            (
+                error_status(&e, StatusCode::BAD_REQUEST),
+                Json(ApiResponse::error(e.to_string())),
+            )
+        }
+    }
+}
+
+/// Get a document by external ID
+async fn get_document_by_external_id(
+    State(state): State<AppState>,
+    Path(external_id): Path<String>,
+) -> impl IntoResponse {
+    let rag = state.rag.get().await;
+    match rag.get_document_by_external_id(&external_id) {
+        Ok(Some(document)) => (StatusCode::OK, Json(ApiResponse::success(document))),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!(
+                "Document with external ID '{}' not found",
+                external_id
+            ))),
+        ),
+        Err(e) => {
+            warn!("Failed to get document: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        }
+    }
+}
+
+/// Delete a document by external ID
+async fn delete_document_by_external_id(
+    State(state): State<AppState>,
+    Path(external_id): Path<String>,
+) -> impl IntoResponse {
+    let writer = match writer(&state) {
+        Ok(writer) => writer,
+        Err(response) => return response,
+    };
+    let rag = writer.lock().unwrap();
+    match rag.delete_document_by_external_id(&external_id) {
+        Ok(deleted) => {
+            info!("Deleted document with external ID '{}'", external_id);
+            (
+                StatusCode::OK,
+                Json(ApiResponse::success(serde_json::json!({ "deleted": deleted }))),
+            )
+        }
         Err(e) => {
             warn!("Failed to delete document: {}", e);
             (
@@ -257,9 +976,20 @@ async fn list_documents(
         .and_then(|s| s.parse().ok())
         .unwrap_or(0);
 
-    let rag = state.rag.lock().unwrap();
-    match rag.list_documents(collection, limit, offset) {
-        Ok(documents) => {
+    let rag = state.rag.get().await;
+    let page = rag
+        .list_documents(collection, limit, offset)
+        .and_then(|documents| Ok((documents, rag.count_documents(collection)?)));
+    match page {
+        Ok((documents, total)) => {
+            if let Err(e) = check_response_size(
+                documents.iter().map(|d| d.content.as_str()),
+                state.max_response_chars,
+            ) {
+                warn!("List documents rejected: {}", e);
+                return (StatusCode::PAYLOAD_TOO_LARGE, Json(ApiResponse::error(e)));
+            }
+
             let docs_data: Vec<_> = documents
                 .into_iter()
                 .map(|d| {
@@ -268,17 +998,28 @@ async fn list_documents(
                         "collection_id": d.collection_id,
                         "content": d.content,
                         "metadata": d.metadata,
-                        "created_at": d.created_at
+                        "created_at": d.created_at,
+                        "external_id": d.external_id
                     })
                 })
                 .collect();
 
-            (StatusCode::OK, Json(ApiResponse::success(docs_data)))
+            (
+                StatusCode::OK,
+                Json(ApiResponse::page(
+                    serde_json::json!(docs_data),
+                    Page {
+                        total,
+                        limit,
+                        offset,
+                    },
+                )),
+            )
         }
         Err(e) => {
             warn!("Failed to list documents: {}", e);
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                error_status(&e, StatusCode::INTERNAL_SERVER_ERROR),
                 Json(ApiResponse::error(e.to_string())),
             )
         }
@@ -292,96 +1033,298 @@ async fn search(
 ) -> impl IntoResponse {
     let top_k = query.top_k.unwrap_or(5);
     let threshold = query.threshold.unwrap_or(0.0);
+    let precision = query.round_scores.or(state.score_precision);
+    let mode = match parse_search_mode(query.mode.as_deref()) {
+        Ok(mode) => mode,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiResponse::error(e))),
+    };
+    let weights = match parse_hybrid_weights(query.semantic_weight, query.keyword_weight) {
+        Ok(weights) => weights,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiResponse::error(e))),
+    };
+    let mut options = match parse_search_options(query.exclude_ids.as_deref()) {
+        Ok(options) => options,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiResponse::error(e))),
+    };
+    options.skip_missing = query.skip_missing.unwrap_or(false);
+    options.include_embedding = query.include_embedding.unwrap_or(false);
+    options.normalize_output = query.normalize_output.unwrap_or(false);
+    options.exclude_empty = query.exclude_empty.unwrap_or(false);
+    options.min_results = query.min_results.unwrap_or(0);
+    options.mode = mode;
+    options.threshold = threshold;
+    options.hybrid_weights = weights;
+    let collections = parse_collections(query.collections.as_deref());
+
+    // メタデータ条件を取り出し、残りを検索テキストとして使う
+    let text = if query.parse_query.unwrap_or(false) {
+        let (text, metadata_filter) = parse_search_query(&query.q);
+        if text.is_empty() {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(
+                    "Search text is empty after extracting filters".to_string(),
+                )),
+            );
+        }
+        options.metadata_filter = metadata_filter;
+        text
+    } else {
+        query.q.clone()
+    };
+
+    let rag = state.rag.get().await;
+    let searched = if query.explain.unwrap_or(false) {
+        rag.search_with_diagnostics(
+            &text,
+            query.collection.as_deref(),
+            collections.as_deref(),
+            top_k,
+            &options,
+        )
+        .map(|(results, diagnostics)| (results, Some(diagnostics)))
+    } else {
+        rag.search_with_options(
+            &text,
+            query.collection.as_deref(),
+            collections.as_deref(),
+            top_k,
+            &options,
+        )
+        .map(|results| (results, None))
+    };
+    match searched {
+        Ok((results, diagnostics)) => {
+            if let Err(e) = check_response_size(
+                results.iter().map(|r| r.content.as_str()),
+                state.max_response_chars,
+            ) {
+                warn!("Search rejected: {}", e);
+                return (StatusCode::PAYLOAD_TOO_LARGE, Json(ApiResponse::error(e)));
+            }
 
-    let rag = state.rag.lock().unwrap();
-    match rag.search(&query.q, query.collection.as_deref(), None, top_k, threshold, SearchMode::Semantic, None) {
-        Ok(results) => {
             let results_data: Vec<_> = results
                 .into_iter()
-                .map(|r| {
-                    serde_json::json!({
-                        "document_id": r.document_id,
-                        "content": r.content,
-                        "score": r.score,
-                        "collection": r.collection_name,
-                        "metadata": r.metadata
-                    })
-                })
+                .map(|r| search_result_json(r, precision))
                 .collect();
 
-            (
-                StatusCode::OK,
-                Json(ApiResponse::success(serde_json::json!({
-                    "query": query.q,
-                    "results": results_data,
-                    "count": results_data.len()
-                }))),
-            )
+            let mut data = serde_json::json!({
+                "query": query.q,
+                "results": results_data,
+                "count": results_data.len(),
+                "skipped_collections": skipped_collections(
+                    &rag,
+                    query.collection.as_deref(),
+                    collections.as_deref(),
+                    &options,
+                )
+            });
+            if let Some(diagnostics) = diagnostics {
+                data["diagnostics"] = serde_json::json!(diagnostics);
+            }
+
+            (StatusCode::OK, Json(ApiResponse::success(data)))
         }
         Err(e) => {
             warn!("Search failed: {}", e);
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                error_status(&e, StatusCode::INTERNAL_SERVER_ERROR),
                 Json(ApiResponse::error(e.to_string())),
             )
         }
     }
 }
 
-/// Enrich query with context (main RAG function)
-async fn enrich(
+/// Count matching documents without returning content
+///
+/// Note: semantic and hybrid counting still scans every document
+async fn search_count(
     State(state): State<AppState>,
-    Query(query): Query<EnrichQuery>,
+    Query(query): Query<CountQuery>,
 ) -> impl IntoResponse {
-    let top_k = query.top_k.unwrap_or(3);
+    let threshold = query.threshold.unwrap_or(0.0);
+    let mode = match parse_search_mode(query.mode.as_deref()) {
+        Ok(mode) => mode,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiResponse::error(e))),
+    };
 
-    let rag = state.rag.lock().unwrap();
-    match rag.enrich(&query.q, query.collection.as_deref(), None, top_k, 0.0, SearchMode::Semantic, None) {
-        Ok(result) => {
-            let sources: Vec<_> = result
+    let rag = state.rag.get().await;
+    match rag.count_matches(&query.q, query.collection.as_deref(), mode, threshold) {
+        Ok(count) => (
+            StatusCode::OK,
+            Json(ApiResponse::success(serde_json::json!({
+                "query": query.q,
+                "count": count
+            }))),
+        ),
+        Err(e) => {
+            warn!("Count failed: {}", e);
+            (
+                error_status(&e, StatusCode::INTERNAL_SERVER_ERROR),
+                Json(ApiResponse::error(e.to_string())),
+            )
+        }
+    }
+}
+
+/// Enrich query with context (main RAG function)
+async fn enrich(
+    State(state): State<AppState>,
+    Query(query): Query<EnrichQuery>,
+) -> impl IntoResponse {
+    let top_k = query.top_k.unwrap_or(3);
+    let precision = query.round_scores.or(state.score_precision);
+    let chat_format = match query.format.as_deref() {
+        None | Some("context") => false,
+        Some("chat") => true,
+        Some(other) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(format!(
+                    "Invalid format: '{}'. Use 'context' or 'chat'",
+                    other
+                ))),
+            )
+        }
+    };
+    let mode = match parse_search_mode(query.mode.as_deref()) {
+        Ok(mode) => mode,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiResponse::error(e))),
+    };
+    let weights = match parse_hybrid_weights(query.semantic_weight, query.keyword_weight) {
+        Ok(weights) => weights,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiResponse::error(e))),
+    };
+    let mut options = match parse_search_options(query.exclude_ids.as_deref()) {
+        Ok(options) => options,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiResponse::error(e))),
+    };
+    options.skip_missing = query.skip_missing.unwrap_or(false);
+    options.include_embedding = query.include_embedding.unwrap_or(false);
+    options.normalize_output = query.normalize_output.unwrap_or(false);
+    options.exclude_empty = query.exclude_empty.unwrap_or(false);
+    options.min_results = query.min_results.unwrap_or(0);
+    options.context_prefix = query.context_prefix.clone();
+    options.context_suffix = query.context_suffix.clone();
+    options.max_context_chars = query.max_context_chars;
+    options.context_template = query.context_template.clone();
+    options.context_separator = query.context_separator.clone();
+    options.include_collection_description = query.include_collection_description.unwrap_or(false);
+    options.mode = mode;
+    options.hybrid_weights = weights;
+    let collections = parse_collections(query.collections.as_deref());
+
+    let rag = state.rag.get().await;
+    match rag.enrich_with_options(
+        &query.q,
+        query.collection.as_deref(),
+        collections.as_deref(),
+        top_k,
+        &options,
+    ) {
+        Ok(result) => {
+            // コンテキストとソースの両方に本文が入るため、両方を数える
+            let contents = std::iter::once(result.context.as_str())
+                .chain(result.sources.iter().map(|s| s.content.as_str()));
+            if let Err(e) = check_response_size(contents, state.max_response_chars) {
+                warn!("Enrich rejected: {}", e);
+                return (StatusCode::PAYLOAD_TOO_LARGE, Json(ApiResponse::error(e)));
+            }
+
+            let messages = chat_format
+                .then(|| result.to_chat_messages(query.system_prompt.as_deref()));
+            let sources: Vec<_> = result
                 .sources
                 .into_iter()
-                .map(|s| {
-                    serde_json::json!({
-                        "document_id": s.document_id,
-                        "content": s.content,
-                        "score": s.score,
-                        "collection": s.collection_name,
-                        "metadata": s.metadata
-                    })
-                })
+                .map(|s| search_result_json(s, precision))
                 .collect();
 
+            let mut data = serde_json::json!({
+                "query": result.question,
+                "sources": sources,
+                "source_count": sources.len(),
+                "skipped_collections": skipped_collections(
+                    &rag,
+                    query.collection.as_deref(),
+                    collections.as_deref(),
+                    &options,
+                )
+            });
+            // format=chat ではチャットAPIの messages 配列をそのまま返す
+            match messages {
+                Some(messages) => data["messages"] = serde_json::json!(messages),
+                None => data["context"] = serde_json::json!(result.context),
+            }
+
+            (StatusCode::OK, Json(ApiResponse::success(data)))
+        }
+        Err(e) => {
+            warn!("Enrich failed: {}", e);
+            (
+                error_status(&e, StatusCode::INTERNAL_SERVER_ERROR),
+                Json(ApiResponse::error(e.to_string())),
+            )
+        }
+    }
+}
+
+/// Import CSV
+async fn import_csv(
+    State(state): State<AppState>,
+    Json(req): Json<ImportCsvRequest>,
+) -> impl IntoResponse {
+    let collection = req.collection.as_deref().unwrap_or("default");
+
+    let writer = match writer(&state) {
+        Ok(writer) => writer,
+        Err(response) => return response,
+    };
+    let rag = writer.lock().unwrap();
+    match rag.import_csv_detailed(&req.file_path, collection, &csv_import_options(&req)) {
+        Ok((count, content_column)) => {
+            info!(
+                "Imported {} documents from {} (content column: '{}')",
+                count, req.file_path, content_column
+            );
             (
                 StatusCode::OK,
                 Json(ApiResponse::success(serde_json::json!({
-                    "query": result.question,
-                    "context": result.context,
-                    "sources": sources,
-                    "source_count": sources.len()
+                    "count": count,
+                    "collection": collection,
+                    "content_column": content_column
                 }))),
             )
         }
         Err(e) => {
-            warn!("Enrich failed: {}", e);
+            warn!("CSV import failed: {}", e);
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                error_status(&e, StatusCode::BAD_REQUEST),
                 Json(ApiResponse::error(e.to_string())),
             )
         }
     }
 }
 
-/// Import CSV
-async fn import_csv(
+/// Import JSONL (one JSON object per line, nested metadata keeps its types)
+async fn import_jsonl(
     State(state): State<AppState>,
-    Json(req): Json<ImportCsvRequest>,
+    Json(req): Json<ImportJsonlRequest>,
 ) -> impl IntoResponse {
     let collection = req.collection.as_deref().unwrap_or("default");
-    let content_column = req.content_column.as_deref().unwrap_or("content");
+    let content_field = req.content_field.as_deref().unwrap_or("content");
+    let metadata_field = req.metadata_field.as_deref().unwrap_or("metadata");
 
-    let rag = state.rag.lock().unwrap();
-    match rag.import_csv(&req.file_path, collection, content_column, None) {
+    let writer = match writer(&state) {
+        Ok(writer) => writer,
+        Err(response) => return response,
+    };
+    let rag = writer.lock().unwrap();
+    match rag.import_jsonl(
+        &req.file_path,
+        collection,
+        content_field,
+        Some(metadata_field),
+    ) {
         Ok(count) => {
             info!("Imported {} documents from {}", count, req.file_path);
             (
@@ -393,9 +1336,129 @@ async fn import_csv(
             )
         }
         Err(e) => {
-            warn!("CSV import failed: {}", e);
+            warn!("JSONL import failed: {}", e);
             (
-                StatusCode::BAD_REQUEST,
+                error_status(&e, StatusCode::BAD_REQUEST),
+                Json(ApiResponse::error(e.to_string())),
+            )
+        }
+    }
+}
+
+/// Import CSV, streaming progress as server-sent events
+///
+/// Emits `progress` events (`{"processed", "total"}`) after each embedding batch,
+/// then a single `done` event with the same summary as `POST /api/import-csv`,
+/// or an `error` event if the import fails
+async fn import_csv_stream(
+    State(state): State<AppState>,
+    Json(req): Json<ImportCsvRequest>,
+) -> Response {
+    let writer = match writer(&state) {
+        Ok(writer) => writer,
+        Err(response) => return response.into_response(),
+    };
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Event>();
+
+    // インポートはEmbedding生成でブロックするため専用スレッドで実行し、イベントをチャネルで送る
+    tokio::task::spawn_blocking(move || {
+        let collection = req.collection.as_deref().unwrap_or("default");
+
+        let rag = writer.lock().unwrap();
+        // クライアントが切断しても送信エラーを無視してインポートは最後まで行う
+        let result = rag.import_csv_with_progress(
+            &req.file_path,
+            collection,
+            &csv_import_options(&req),
+            |processed, total| {
+                let data = serde_json::json!({ "processed": processed, "total": total });
+                let _ = tx.send(Event::default().event("progress").data(data.to_string()));
+            },
+        );
+
+        let event = match result {
+            Ok((count, content_column)) => {
+                info!(
+                    "Imported {} documents from {} (content column: '{}')",
+                    count, req.file_path, content_column
+                );
+                let data = serde_json::json!({
+                    "count": count,
+                    "collection": collection,
+                    "content_column": content_column
+                });
+                Event::default().event("done").data(data.to_string())
+            }
+            Err(e) => {
+                warn!("CSV import failed: {}", e);
+                let data = serde_json::json!({ "error": e.to_string() });
+                Event::default().event("error").data(data.to_string())
+            }
+        };
+        let _ = tx.send(event);
+    });
+
+    // 送信側（インポートのスレッド）が終了するとストリームも終わる
+    let stream = futures_util::stream::poll_fn(move |cx| {
+        rx.poll_recv(cx).map(|event| event.map(Ok::<_, Infallible>))
+    });
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Optimize the database (VACUUM + FTS optimize + ANALYZE)
+///
+/// VACUUM needs exclusive access and can be slow on large databases
+async fn optimize_database(State(state): State<AppState>) -> impl IntoResponse {
+    let writer = match writer(&state) {
+        Ok(writer) => writer,
+        Err(response) => return response,
+    };
+    let rag = writer.lock().unwrap();
+    match rag.optimize() {
+        Ok(_) => {
+            info!("Database optimized");
+            (
+                StatusCode::OK,
+                Json(ApiResponse::success(serde_json::json!({
+                    "message": "Database optimized"
+                }))),
+            )
+        }
+        Err(e) => {
+            warn!("Optimize failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        }
+    }
+}
+
+/// Rebuild the keyword search (FTS) index from the stored documents
+///
+/// Use after writing to the database with external tools, which leaves the index out of sync
+async fn rebuild_fts_index(State(state): State<AppState>) -> impl IntoResponse {
+    let writer = match writer(&state) {
+        Ok(writer) => writer,
+        Err(response) => return response,
+    };
+    let rag = writer.lock().unwrap();
+    match rag.rebuild_keyword_index() {
+        Ok(_) => {
+            info!("Keyword index rebuilt");
+            (
+                StatusCode::OK,
+                Json(ApiResponse::success(serde_json::json!({
+                    "message": "Keyword index rebuilt"
+                }))),
+            )
+        }
+        Err(e) => {
+            warn!("Rebuilding keyword index failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ApiResponse::error(e.to_string())),
             )
         }
@@ -411,17 +1474,111 @@ async fn admin_ui() -> impl IntoResponse {
 // Main Application
 // ============================================================================
 
+/// APIのルーティング（`/api` 以下）
+fn api_router(state: AppState) -> Router {
+    Router::new()
+        // Models
+        .route("/models", get(list_models))
+        // Collections
+        .route("/collections", get(list_collections).post(create_collection))
+        .route("/collections/:name", get(get_collection).delete(delete_collection))
+        .route("/collections/:name/metadata", patch(update_collection_metadata))
+        .route(
+            "/collections/:name/indexed-fields",
+            get(get_indexed_fields).put(set_indexed_fields),
+        )
+        // Documents
+        .route("/documents", get(list_documents).post(add_document))
+        .route(
+            "/documents/:id",
+            get(get_document)
+                .patch(update_document)
+                .delete(delete_document),
+        )
+        .route(
+            "/documents/external/:external_id",
+            get(get_document_by_external_id).delete(delete_document_by_external_id),
+        )
+        // Search & Enrich
+        .route("/search", get(search))
+        .route("/search/count", get(search_count))
+        .route("/enrich", get(enrich))
+        // CSV
+        .route("/import-csv", post(import_csv))
+        .route("/import-csv/stream", post(import_csv_stream))
+        .route("/import-jsonl", post(import_jsonl))
+        // Maintenance
+        .route("/maintenance/optimize", post(optimize_database))
+        .route("/maintenance/rebuild-fts", post(rebuild_fts_index))
+        .with_state(state)
+}
+
+/// メトリクスが有効な場合、`/metrics` を追加してリクエスト数を数える
+///
+/// リクエスト数はルーティング後に数えるため、ルートを定義し終えた `Router` に適用する
+fn with_metrics(app: Router, state: AppState) -> Router {
+    let Some(metrics) = state.metrics.clone() else {
+        return app;
+    };
+
+    app.route("/metrics", get(prometheus_metrics).with_state(state))
+        .route_layer(middleware::from_fn_with_state(metrics, metrics::track_requests))
+}
+
+/// FTSインデックスの整理と統計情報の更新を定期実行するバックグラウンドタスク
+///
+/// 処理中は書き込み用のインスタンスのロックを保持するため、その間の変更系のリクエストは
+/// 待たされる（検索・取得系は別の接続で処理する）。
+/// ブロッキング処理は `spawn_blocking` で実行し、非同期ランタイムのスレッドは塞がない
+async fn run_index_maintenance(rag: Arc<Mutex<Doredore>>, interval: std::time::Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // 処理が長引いて次の実行時刻を過ぎても、まとめて連続実行しない
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // 最初のtickは即座に完了するため、起動直後の実行を避けて読み飛ばす
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+
+        let rag = rag.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let started = std::time::Instant::now();
+            let rag = rag.lock().unwrap();
+            rag.optimize_indexes().map(|_| started.elapsed())
+        })
+        .await;
+
+        match result {
+            Ok(Ok(elapsed)) => info!("Index maintenance completed in {:?}", elapsed),
+            Ok(Err(e)) => warn!("Index maintenance failed: {}", e),
+            Err(e) => warn!("Index maintenance task panicked: {}", e),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .compact()
-        .init();
-
     // Load environment variables
     dotenvy::dotenv().ok();
 
+    // Prometheusメトリクス（METRICS_ENABLED=true で GET /metrics を有効化）
+    let metrics = std::env::var("METRICS_ENABLED")
+        .map(|v| matches!(v.to_lowercase().as_str(), "true" | "1" | "on"))
+        .unwrap_or(false)
+        .then(|| Arc::new(Metrics::default()));
+
+    // Initialize tracing
+    // ログはINFO以上。メトリクスのレイヤーはdoredore-coreのDEBUGレベルのスパンも受け取る
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .compact()
+                .with_filter(LevelFilter::INFO),
+        )
+        .with(metrics.clone().map(MetricsLayer::new))
+        .init();
+
     // Get configuration from environment
     let db_path = std::env::var("DATABASE_PATH").unwrap_or_else(|_| "./knowledge.db".to_string());
     let model = std::env::var("EMBEDDING_MODEL").unwrap_or_else(|_| "bge-small-en-v1.5".to_string());
@@ -430,15 +1587,99 @@ async fn main() -> anyhow::Result<()> {
         .ok()
         .and_then(|p| p.parse().ok())
         .unwrap_or(3000);
+    // レスポンスのスコアを丸める桁数（未設定なら丸めない）
+    let score_precision = std::env::var("SCORE_PRECISION")
+        .ok()
+        .and_then(|p| p.parse().ok());
+    // レスポンス圧縮（gzip/br/deflate、デフォルト有効。COMPRESSION=false で無効化）
+    let compression = std::env::var("COMPRESSION")
+        .map(|v| !matches!(v.to_lowercase().as_str(), "false" | "0" | "off"))
+        .unwrap_or(true);
+    // Embedding生成時の最大入力文字数（未設定なら切り詰めない）
+    let max_input_chars = std::env::var("MAX_INPUT_CHARS")
+        .ok()
+        .and_then(|n| n.parse().ok());
+    // 1レスポンスに含める本文の合計文字数の上限（未設定なら無制限）
+    let max_response_chars = std::env::var("MAX_RESPONSE_CHARS")
+        .ok()
+        .and_then(|n| n.parse().ok());
+    // FTSインデックス整理 + ANALYZE を定期実行する間隔（秒、未設定なら実行しない）
+    let maintenance_interval = std::env::var("MAINTENANCE_INTERVAL_SECS")
+        .ok()
+        .and_then(|n| n.parse::<u64>().ok())
+        .filter(|&n| n > 0)
+        .map(std::time::Duration::from_secs);
+    // 検索・取得系のリクエストを並列に処理する読み取り専用の接続数（未設定ならCPUコア数）
+    let read_pool_size = std::env::var("READ_POOL_SIZE")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get()));
+    // 検索専用ノード向けの読み取り専用モード（READ_ONLY=true で有効化）
+    let read_only = std::env::var("READ_ONLY")
+        .map(|v| matches!(v.to_lowercase().as_str(), "true" | "1" | "on"))
+        .unwrap_or(false);
+
+    // モデルのダウンロードの試行回数と、ダウンロード済みのモデルのディレクトリ（指定時はダウンロードしない）
+    let mut load_options = ModelLoadOptions::default();
+    if let Some(attempts) = std::env::var("MODEL_DOWNLOAD_ATTEMPTS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+    {
+        load_options.max_attempts = attempts;
+    }
+    load_options.model_dir = std::env::var("MODEL_DIR").ok().map(PathBuf::from);
 
     info!("Initializing Doredore...");
-    let rag = Doredore::new(&db_path, Some(&model), None)?;
+    let embedding_model = EmbeddingModel::with_load_options(Some(&model), None, &load_options)?;
+    // 書き込み用の接続を先に開く（ファイルがなければ作成し、スキーマを用意する）
+    let writer = if read_only {
+        None
+    } else {
+        let mut writer = Doredore::with_shared_model(&db_path, embedding_model.clone())?;
+        writer.set_max_input_chars(max_input_chars);
+        Some(writer)
+    };
+    // 検索・取得系は書き込みと別の読み取り専用の接続のプールで処理する
+    // （デフォルトのモデルとコレクションごとのモデルは書き込み用の接続と共有）
+    let rag = ReaderPool::open(
+        &db_path,
+        &embedding_model,
+        read_pool_size,
+        max_input_chars,
+        writer.as_ref(),
+    )?;
+    let writer = writer.map(|writer| Arc::new(Mutex::new(writer)));
     info!("Doredore initialized with model: {}", model);
+    info!("Serving reads with {} connections", rag.size());
+    if read_only {
+        info!("Read-only mode: write endpoints will return 405 Method Not Allowed");
+    }
+    if let Some(max) = max_input_chars {
+        info!("Embedding input limited to {} chars", max);
+    }
+    if let Some(max) = max_response_chars {
+        info!("Response content limited to {} chars", max);
+    }
 
     let state = AppState {
-        rag: Arc::new(Mutex::new(rag)),
+        rag: Arc::new(rag),
+        writer,
+        score_precision,
+        max_response_chars,
+        metrics,
     };
 
+    match (maintenance_interval, &state.writer) {
+        (Some(_), None) => {
+            info!("Background index maintenance disabled: database is read-only");
+        }
+        (Some(interval), Some(writer)) => {
+            info!("Background index maintenance every {:?}", interval);
+            tokio::spawn(run_index_maintenance(writer.clone(), interval));
+        }
+        (None, _) => {}
+    }
+
     // Configure CORS
     let cors = CorsLayer::new()
         .allow_origin(Any)
@@ -446,28 +1687,24 @@ async fn main() -> anyhow::Result<()> {
         .allow_headers(Any);
 
     // Build API routes
-    let api_routes = Router::new()
-        // Collections
-        .route("/collections", get(list_collections).post(create_collection))
-        .route("/collections/:name", delete(delete_collection))
-        // Documents
-        .route("/documents", get(list_documents).post(add_document))
-        .route("/documents/:id", delete(delete_document))
-        // Search & Enrich
-        .route("/search", get(search))
-        .route("/enrich", get(enrich))
-        // CSV
-        .route("/import-csv", post(import_csv))
-        .with_state(state.clone());
+    let metrics_enabled = state.metrics.is_some();
+    let api_routes = api_router(state.clone());
 
     // Build main app
     let app = Router::new()
         .route("/", get(admin_ui))
-        .route("/health", get(health_check))
+        .route("/health", get(health_check).with_state(state.clone()))
         .nest("/api", api_routes)
-        .nest_service("/static", ServeDir::new("static"))
-        .layer(cors)
-        .layer(TraceLayer::new_for_http());
+        .nest_service("/static", ServeDir::new("static"));
+    let app = with_metrics(app, state).layer(cors);
+
+    // Accept-Encodingに応じて圧縮（SSEや画像などはCompressionLayerのデフォルト条件で対象外）
+    let app = if compression {
+        app.layer(CompressionLayer::new())
+    } else {
+        app
+    };
+    let app = app.layer(TraceLayer::new_for_http());
 
     let addr = format!("{}:{}", host, port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
@@ -478,18 +1715,37 @@ async fn main() -> anyhow::Result<()> {
     info!("==========================================================");
     info!("");
     info!("🚀 Server running on http://{}", addr);
+    info!(
+        "Response compression: {}",
+        if compression { "enabled" } else { "disabled" }
+    );
     info!("");
     info!("API Endpoints:");
     info!("  GET    /health");
+    if metrics_enabled {
+        info!("  GET    /metrics");
+    }
+    info!("  GET    /api/models");
     info!("  GET    /api/collections");
     info!("  POST   /api/collections");
+    info!("  GET    /api/collections/:name");
     info!("  DELETE /api/collections/:name");
+    info!("  PATCH  /api/collections/:name/metadata");
+    info!("  GET    /api/collections/:name/indexed-fields");
+    info!("  PUT    /api/collections/:name/indexed-fields");
     info!("  GET    /api/documents");
     info!("  POST   /api/documents");
+    info!("  GET    /api/documents/:id");
+    info!("  PATCH  /api/documents/:id");
     info!("  DELETE /api/documents/:id");
     info!("  GET    /api/search?q=...");
+    info!("  GET    /api/search/count?q=...");
     info!("  GET    /api/enrich?q=...");
     info!("  POST   /api/import-csv");
+    info!("  POST   /api/import-csv/stream");
+    info!("  POST   /api/import-jsonl");
+    info!("  POST   /api/maintenance/optimize");
+    info!("  POST   /api/maintenance/rebuild-fts");
     info!("");
     info!("Admin UI:");
     info!("  http://{}/", addr);
@@ -500,3 +1756,766 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use doredore_core::MockEmbedder;
+    use tower::ServiceExt;
+
+    /// テスト用のサーバー状態（`writable` がfalseなら READ_ONLY=1 と同じ構成）
+    fn test_state(db_path: &std::path::Path, writable: bool) -> AppState {
+        let model = EmbeddingModel::from_embedder(MockEmbedder::new(64));
+        let writer = writable.then(|| {
+            let writer = Doredore::with_shared_model(db_path, model.clone()).unwrap();
+            Arc::new(Mutex::new(writer))
+        });
+        let rag = ReaderPool::open(db_path, &model, 1, None, None).unwrap();
+
+        AppState {
+            rag: Arc::new(rag),
+            writer,
+            score_precision: None,
+            max_response_chars: None,
+            metrics: None,
+        }
+    }
+
+    /// APIにリクエストを送り、ステータスとJSONボディ（JSONでなければNull）を返す
+    async fn call(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or_default())
+    }
+
+    #[test]
+    fn test_score_value() {
+        assert_eq!(score_value(0.87654, Some(2)), serde_json::json!(0.88));
+        assert_eq!(score_value(0.87654, Some(0)), serde_json::json!(1.0));
+        assert_eq!(score_value(0.5, None), serde_json::json!(0.5));
+        // 桁数が大きくても有限の値のまま
+        assert!(score_value(0.5, Some(u8::MAX)).is_f64());
+
+        // 無限大・NaNは数値にしない
+        for score in [f32::INFINITY, f32::NEG_INFINITY, f32::NAN] {
+            assert_eq!(score_value(score, None), serde_json::Value::Null);
+            assert_eq!(score_value(score, Some(3)), serde_json::Value::Null);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_only_server_rejects_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+
+        // 書き込み側のプロセスに相当するインスタンスでデータを用意する
+        let id = {
+            let writer = Doredore::new_with_embedder(&db_path, MockEmbedder::new(64)).unwrap();
+            writer.create_collection("faq", None).unwrap();
+            writer
+                .add_document("Rust is a systems programming language", "faq", None)
+                .unwrap()
+        };
+
+        let app = api_router(test_state(&db_path, false));
+
+        // 検索・取得系は読み取り専用の接続で処理される
+        let (status, body) = call(&app, "GET", "/collections", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"][0]["name"], "faq");
+
+        let uri = "/search?q=rust%20language&collection=faq";
+        let (status, body) = call(&app, "GET", uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["results"][0]["document_id"], id);
+
+        // 変更系はすべて405
+        let writes = [
+            (
+                "POST",
+                "/collections",
+                Some(serde_json::json!({ "name": "docs" })),
+            ),
+            ("DELETE", "/collections/faq", None),
+            (
+                "PUT",
+                "/collections/faq/indexed-fields",
+                Some(serde_json::json!({ "fields": ["title"] })),
+            ),
+            (
+                "POST",
+                "/documents",
+                Some(serde_json::json!({ "content": "New", "collection": "faq" })),
+            ),
+            (
+                "PATCH",
+                &format!("/documents/{}", id),
+                Some(serde_json::json!({ "content": "Updated" })),
+            ),
+            ("DELETE", &format!("/documents/{}", id), None),
+            (
+                "POST",
+                "/import-csv/stream",
+                Some(serde_json::json!({ "file_path": "missing.csv" })),
+            ),
+            (
+                "POST",
+                "/import-jsonl",
+                Some(serde_json::json!({ "file_path": "missing.jsonl" })),
+            ),
+            ("POST", "/maintenance/optimize", None),
+            ("POST", "/maintenance/rebuild-fts", None),
+        ];
+        for (method, uri, body) in writes {
+            let (status, response) = call(&app, method, uri, body).await;
+            assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED, "{} {}", method, uri);
+            assert_eq!(response["success"], false);
+        }
+
+        let (_, body) = call(&app, "GET", "/documents?collection=faq", None).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_writes_are_visible_to_read_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = api_router(test_state(&dir.path().join("test.db"), true));
+
+        let collection = serde_json::json!({ "name": "faq" });
+        let (status, _) = call(&app, "POST", "/collections", Some(collection)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let document = serde_json::json!({
+            "content": "Boil the pasta in salted water",
+            "collection": "faq"
+        });
+        let (status, body) = call(&app, "POST", "/documents", Some(document)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let id = body["data"]["id"].clone();
+
+        // 書き込み用とは別の接続で検索しても、コミット済みの変更が見える
+        let (status, body) = call(&app, "GET", "/search?q=pasta&collection=faq", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["results"][0]["document_id"], id);
+    }
+
+    #[tokio::test]
+    async fn test_add_document_skip_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = api_router(test_state(&dir.path().join("test.db"), true));
+
+        let collection = serde_json::json!({ "name": "faq" });
+        let (status, _) = call(&app, "POST", "/collections", Some(collection)).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let document = serde_json::json!({
+            "content": "Boil the pasta in salted water",
+            "collection": "faq",
+            "skip_duplicates": true
+        });
+        let (status, first) = call(&app, "POST", "/documents", Some(document.clone())).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(first["data"]["duplicate"], false);
+
+        let (status, second) = call(&app, "POST", "/documents", Some(document)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(second["data"]["id"], first["data"]["id"]);
+        assert_eq!(second["data"]["duplicate"], true);
+
+        let (_, body) = call(&app, "GET", "/documents?collection=faq", None).await;
+        assert_eq!(body["total"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_import_jsonl() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = api_router(test_state(&dir.path().join("test.db"), true));
+        call(
+            &app,
+            "POST",
+            "/collections",
+            Some(serde_json::json!({ "name": "faq" })),
+        )
+        .await;
+
+        let jsonl_path = dir.path().join("faq.jsonl");
+        std::fs::write(
+            &jsonl_path,
+            "{\"content\": \"Boil the pasta\", \
+             \"metadata\": {\"year\": 2023, \"tags\": [\"food\"]}}\n\
+             \n\
+             {\"content\": \"Add salt\"}\n",
+        )
+        .unwrap();
+        let request = serde_json::json!({
+            "file_path": jsonl_path.to_str().unwrap(),
+            "collection": "faq"
+        });
+        let (status, body) = call(&app, "POST", "/import-jsonl", Some(request)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["count"], 2);
+
+        let (_, body) = call(&app, "GET", "/search?q=pasta&mode=keyword", None).await;
+        let metadata = &body["data"]["results"][0]["metadata"];
+        assert_eq!(metadata["year"], 2023);
+        assert_eq!(metadata["tags"], serde_json::json!(["food"]));
+
+        let request = serde_json::json!({ "file_path": "missing.jsonl", "collection": "faq" });
+        let (status, _) = call(&app, "POST", "/import-jsonl", Some(request)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_import_csv_infer_types() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = api_router(test_state(&dir.path().join("test.db"), true));
+        for name in ["typed", "strings"] {
+            call(
+                &app,
+                "POST",
+                "/collections",
+                Some(serde_json::json!({ "name": name })),
+            )
+            .await;
+        }
+
+        let csv_path = dir.path().join("faq.csv");
+        std::fs::write(&csv_path, "body,year,version\nBoil the pasta,2023,1.10\n").unwrap();
+        for (collection, infer_types, year) in [
+            ("typed", true, serde_json::json!(2023)),
+            ("strings", false, serde_json::json!("2023")),
+        ] {
+            let request = serde_json::json!({
+                "file_path": csv_path.to_str().unwrap(),
+                "collection": collection,
+                "content_column": "body",
+                "metadata_columns": ["year", "version"],
+                "infer_types": infer_types
+            });
+            let (status, body) = call(&app, "POST", "/import-csv", Some(request)).await;
+            assert_eq!(status, StatusCode::OK, "{}", body);
+
+            let uri = format!("/search?q=pasta&mode=keyword&collection={}", collection);
+            let (_, body) = call(&app, "GET", &uri, None).await;
+            let metadata = &body["data"]["results"][0]["metadata"];
+            assert_eq!(metadata["year"], year);
+            assert_eq!(metadata["version"], "1.10");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_documents_includes_total() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = api_router(test_state(&dir.path().join("test.db"), true));
+
+        for (name, count) in [("faq", 3), ("blog", 2)] {
+            call(
+                &app,
+                "POST",
+                "/collections",
+                Some(serde_json::json!({ "name": name })),
+            )
+            .await;
+            for i in 0..count {
+                let document = serde_json::json!({
+                    "content": format!("{} document {}", name, i),
+                    "collection": name
+                });
+                call(&app, "POST", "/documents", Some(document)).await;
+            }
+        }
+
+        let (status, body) = call(&app, "GET", "/documents?collection=faq&limit=2", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"].as_array().unwrap().len(), 2);
+        assert_eq!(body["total"], 3);
+        assert_eq!(body["limit"], 2);
+        assert_eq!(body["offset"], 0);
+
+        let (_, body) = call(&app, "GET", "/documents?limit=1&offset=4", None).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["total"], 5);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_searches_use_reader_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let model = EmbeddingModel::from_embedder(MockEmbedder::new(64));
+        let writer = Doredore::with_shared_model(&db_path, model.clone()).unwrap();
+        let state = AppState {
+            rag: Arc::new(ReaderPool::open(&db_path, &model, 4, None, None).unwrap()),
+            writer: Some(Arc::new(Mutex::new(writer))),
+            score_precision: None,
+            max_response_chars: None,
+            metrics: None,
+        };
+        assert_eq!(state.rag.size(), 4);
+        let app = api_router(state);
+
+        call(&app, "POST", "/collections", Some(serde_json::json!({ "name": "faq" }))).await;
+        for i in 0..20 {
+            let document = serde_json::json!({
+                "content": format!("Pasta recipe number {}", i),
+                "collection": "faq"
+            });
+            call(&app, "POST", "/documents", Some(document)).await;
+        }
+
+        // 検索と書き込みを同時に実行しても、ロックの競合でエラーにならない
+        let searches = (0..32).map(|_| {
+            let app = app.clone();
+            tokio::spawn(async move { call(&app, "GET", "/search?q=pasta&top_k=5", None).await })
+        });
+        let writes = (0..4).map(|i| {
+            let app = app.clone();
+            let document = serde_json::json!({
+                "content": format!("Risotto recipe number {}", i),
+                "collection": "faq"
+            });
+            tokio::spawn(async move { call(&app, "POST", "/documents", Some(document)).await })
+        });
+        let tasks: Vec<_> = searches.chain(writes).collect();
+        for task in tasks {
+            let (status, body) = task.await.unwrap();
+            assert!(status.is_success(), "{}: {}", status, body);
+        }
+
+        let (_, body) = call(&app, "GET", "/search?q=risotto&mode=keyword", None).await;
+        assert_eq!(body["data"]["results"].as_array().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_saturated_reader_pool_waits_without_blocking_runtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let model = EmbeddingModel::from_embedder(MockEmbedder::new(64));
+        Doredore::with_shared_model(&db_path, model.clone()).unwrap();
+        let pool = Arc::new(ReaderPool::open(&db_path, &model, 1, None, None).unwrap());
+
+        // 単一スレッドのランタイムで、すべて使用中のプールを待つタスクがあっても他のタスクが進む
+        let reader = pool.get().await;
+        let waiting = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.get().await.model_name().to_string() }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        drop(reader);
+        assert_eq!(waiting.await.unwrap(), model.model_name());
+    }
+
+    #[tokio::test]
+    async fn test_get_and_update_document() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = api_router(test_state(&dir.path().join("test.db"), true));
+
+        call(
+            &app,
+            "POST",
+            "/collections",
+            Some(serde_json::json!({ "name": "faq" })),
+        )
+        .await;
+        let document = serde_json::json!({
+            "content": "Boil the pasta in salted water",
+            "collection": "faq",
+            "metadata": { "lang": "en" }
+        });
+        let (_, body) = call(&app, "POST", "/documents", Some(document)).await;
+        let id = body["data"]["id"].as_i64().unwrap();
+        let uri = format!("/documents/{}", id);
+
+        let (status, body) = call(&app, "GET", &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["content"], "Boil the pasta in salted water");
+        assert_eq!(body["data"]["metadata"]["lang"], "en");
+
+        // 本文を変更するとEmbeddingも再生成され、新しい内容で検索できる
+        let patch = serde_json::json!({ "content": "Rust is a systems programming language" });
+        let (status, body) = call(&app, "PATCH", &uri, Some(patch)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["data"]["content"],
+            "Rust is a systems programming language"
+        );
+        assert_eq!(body["data"]["metadata"]["lang"], "en");
+        let search_uri =
+            "/search?q=Rust%20is%20a%20systems%20programming%20language&threshold=0.99";
+        let (_, body) = call(&app, "GET", search_uri, None).await;
+        assert_eq!(body["data"]["results"][0]["document_id"], id);
+
+        let patch = serde_json::json!({ "metadata": { "lang": "en", "reviewed": true } });
+        let (status, body) = call(&app, "PATCH", &uri, Some(patch)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["metadata"]["reviewed"], true);
+        let (_, body) = call(&app, "GET", &uri, None).await;
+        assert_eq!(body["data"]["metadata"]["reviewed"], true);
+
+        // 存在しないIDは404
+        let missing = format!("/documents/{}", id + 100);
+        let (status, body) = call(&app, "GET", &missing, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["success"], false);
+        let patch = serde_json::json!({ "content": "New" });
+        let (status, _) = call(&app, "PATCH", &missing, Some(patch)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_health_check_reports_readiness() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir.path().join("test.db"), true);
+        let app = Router::new()
+            .route("/health", get(health_check).with_state(state.clone()))
+            .nest("/api", api_router(state));
+
+        let (status, body) = call(&app, "GET", "/health", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["db_ok"], true);
+        assert!(body["model"].is_string());
+        assert_eq!(body["dimension"], 64);
+        assert_eq!(body["collection_count"], 0);
+        assert_eq!(body["document_count"], 0);
+        assert!(body["error"].is_null());
+
+        let collection = serde_json::json!({ "name": "faq" });
+        call(&app, "POST", "/api/collections", Some(collection)).await;
+        let document = serde_json::json!({ "content": "Boil the pasta", "collection": "faq" });
+        call(&app, "POST", "/api/documents", Some(document)).await;
+
+        let (_, body) = call(&app, "GET", "/health", None).await;
+        assert_eq!(body["collection_count"], 1);
+        assert_eq!(body["document_count"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_collection_includes_document_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = api_router(test_state(&dir.path().join("test.db"), true));
+
+        let collection = serde_json::json!({ "name": "faq", "description": "FAQs" });
+        call(&app, "POST", "/collections", Some(collection)).await;
+        for content in ["Boil the pasta", "Salt the water"] {
+            let document = serde_json::json!({ "content": content, "collection": "faq" });
+            call(&app, "POST", "/documents", Some(document)).await;
+        }
+
+        let (status, body) = call(&app, "GET", "/collections/faq", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["name"], "faq");
+        assert_eq!(body["data"]["description"], "FAQs");
+        assert_eq!(body["data"]["document_count"], 2);
+        assert!(body["data"]["created_at"].is_string());
+        assert!(body["data"]["updated_at"].is_string());
+
+        let (status, body) = call(&app, "GET", "/collections", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"][0]["name"], "faq");
+        assert_eq!(body["data"][0]["document_count"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_missing_collection_returns_404() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = api_router(test_state(&dir.path().join("test.db"), true));
+
+        let document = serde_json::json!({ "content": "Boil the pasta", "collection": "missing" });
+        let requests = [
+            ("POST", "/documents", Some(document)),
+            ("GET", "/documents?collection=missing", None),
+            ("GET", "/search?q=pasta&collection=missing", None),
+            ("GET", "/search/count?q=pasta&collection=missing", None),
+            ("GET", "/enrich?q=pasta&collection=missing", None),
+            ("GET", "/collections/missing/indexed-fields", None),
+            ("GET", "/collections/missing", None),
+            ("DELETE", "/collections/missing", None),
+        ];
+        for (method, uri, body) in requests {
+            let (status, response) = call(&app, method, uri, body).await;
+            assert_eq!(
+                status,
+                StatusCode::NOT_FOUND,
+                "{} {}: {}",
+                method,
+                uri,
+                response
+            );
+            assert!(
+                response["error"].as_str().unwrap().contains("'missing'"),
+                "{}",
+                response
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_document_returns_404() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = api_router(test_state(&dir.path().join("test.db"), true));
+
+        let collection = serde_json::json!({ "name": "faq" });
+        call(&app, "POST", "/collections", Some(collection)).await;
+        let document = serde_json::json!({ "content": "Boil the pasta", "collection": "faq" });
+        let (_, body) = call(&app, "POST", "/documents", Some(document)).await;
+        let id = body["data"]["id"].as_i64().unwrap();
+
+        let uri = format!("/documents/{}", id);
+        let (status, _) = call(&app, "DELETE", &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // 削除済み・存在しないドキュメントは404
+        let (status, response) = call(&app, "DELETE", &uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", response);
+        assert!(
+            response["error"]
+                .as_str()
+                .unwrap()
+                .contains(&format!("Document {}", id)),
+            "{}",
+            response
+        );
+    }
+
+    #[tokio::test]
+    async fn test_enrich_chat_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = api_router(test_state(&dir.path().join("test.db"), true));
+
+        let collection = serde_json::json!({ "name": "faq" });
+        call(&app, "POST", "/collections", Some(collection)).await;
+        let document = serde_json::json!({
+            "content": "Boil the pasta in salted water",
+            "collection": "faq"
+        });
+        call(&app, "POST", "/documents", Some(document)).await;
+
+        let uri = "/enrich?q=pasta&collection=faq&format=chat&system_prompt=You%20are%20a%20chef.";
+        let (status, body) = call(&app, "GET", uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let messages = body["data"]["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["role"], "system");
+        let system = messages[0]["content"].as_str().unwrap();
+        assert!(system.starts_with("You are a chef."));
+        assert!(system.contains("Boil the pasta in salted water"));
+        assert_eq!(messages[1], serde_json::json!({ "role": "user", "content": "pasta" }));
+        assert!(body["data"].get("context").is_none());
+        assert_eq!(body["data"]["source_count"], 1);
+
+        let (status, body) = call(&app, "GET", "/enrich?q=pasta&collection=faq", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["data"]["context"].is_string());
+        assert!(body["data"].get("messages").is_none());
+
+        // 各ソースの書式を指定できる（`{` `}` はURLエンコードする）
+        let uri =
+            "/enrich?q=pasta&collection=faq&context_template=%7Bcontent%7D%20%5B%5E%7Bindex%7D%5D";
+        let (status, body) = call(&app, "GET", uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["data"]["context"],
+            "Boil the pasta in salted water [^1]"
+        );
+
+        let (status, _) = call(&app, "GET", "/enrich?q=pasta&format=xml", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_search_mode_and_weights() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = api_router(test_state(&dir.path().join("test.db"), true));
+
+        call(&app, "POST", "/collections", Some(serde_json::json!({ "name": "faq" }))).await;
+        for content in ["Boil the pasta in salted water", "Rust is a systems language"] {
+            let document = serde_json::json!({ "content": content, "collection": "faq" });
+            call(&app, "POST", "/documents", Some(document)).await;
+        }
+
+        // キーワード検索では語を含むドキュメントだけが返る
+        let (status, body) = call(&app, "GET", "/search?q=pasta&mode=keyword", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let results = body["data"]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["content"], "Boil the pasta in salted water");
+        assert!(body["data"].get("diagnostics").is_none());
+
+        let uri = "/search?q=pasta&mode=hybrid&semantic_weight=0.2&keyword_weight=0.8";
+        let (status, _) = call(&app, "GET", uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // explain=true で各段階の候補数と照合方法を返す
+        let uri = "/search?q=pasta&mode=hybrid&threshold=-1&explain=true";
+        let (status, body) = call(&app, "GET", uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let diagnostics = &body["data"]["diagnostics"];
+        assert_eq!(diagnostics["mode"], "hybrid");
+        assert_eq!(diagnostics["keyword_backend"], "fts5");
+        assert_eq!(diagnostics["keyword_candidates"], 1);
+        assert_eq!(diagnostics["semantic_candidates"], 2);
+        assert_eq!(diagnostics["fusion"], "weighted");
+        assert!(diagnostics["total_ms"].as_f64().unwrap() >= 0.0);
+
+        let (status, body) = call(&app, "GET", "/enrich?q=pasta&mode=KEYWORD", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["source_count"], 1);
+
+        let (status, _) = call(&app, "POST", "/maintenance/rebuild-fts", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = call(&app, "GET", "/search?q=pasta&mode=keyword", None).await;
+        assert_eq!(body["data"]["results"].as_array().unwrap().len(), 1);
+
+        let (status, body) = call(&app, "GET", "/search?q=pasta&mode=fuzzy", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("Invalid search mode: 'fuzzy'"));
+        let (status, _) = call(&app, "GET", "/enrich?q=pasta&mode=fuzzy", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let uri = "/search?q=pasta&mode=hybrid&semantic_weight=-1";
+        let (status, _) = call(&app, "GET", uri, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let uri = "/enrich?q=pasta&semantic_weight=0&keyword_weight=0";
+        let (status, _) = call(&app, "GET", uri, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir.path().join("test.db"), true);
+
+        // 無効の場合は /metrics を追加しない
+        let app = with_metrics(Router::new().nest("/api", api_router(state.clone())), state.clone());
+        let (status, _) = call(&app, "GET", "/metrics", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let state = AppState {
+            metrics: Some(Arc::new(Metrics::default())),
+            ..state
+        };
+        let app = with_metrics(Router::new().nest("/api", api_router(state.clone())), state);
+        let collection = serde_json::json!({ "name": "faq" });
+        call(&app, "POST", "/api/collections", Some(collection)).await;
+        let document = serde_json::json!({ "content": "Boil the pasta", "collection": "faq" });
+        call(&app, "POST", "/api/documents", Some(document)).await;
+        call(&app, "GET", "/api/search?q=pasta", None).await;
+        call(&app, "GET", "/api/search?q=pasta", None).await;
+
+        let request = Request::builder().uri("/metrics").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain"));
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+
+        // パスのラベルはルート定義、ゲージはスクレイプ時の値
+        assert!(text.contains(
+            "doredore_http_requests_total{method=\"GET\",path=\"/api/search\",status=\"200\"} 2\n"
+        ));
+        assert!(text.contains(
+            "doredore_http_requests_total{method=\"POST\",path=\"/api/documents\",status=\"201\"} 1\n"
+        ));
+        assert!(text.contains("doredore_documents 1\n"));
+        assert!(text.contains("doredore_collections 1\n"));
+        assert!(!text.contains("doredore_db_size_bytes 0\n"));
+    }
+
+    #[test]
+    fn test_check_response_size() {
+        let small = "永代供養とは".to_string();
+        let oversized = "a".repeat(10_000);
+
+        // 上限なしなら常に通す
+        assert!(check_response_size([oversized.as_str()], None).is_ok());
+
+        // 上限ちょうどまでは通す（バイト数ではなく文字数で数える）
+        assert!(check_response_size([small.as_str(), small.as_str()], Some(12)).is_ok());
+
+        let err = check_response_size([small.as_str(), oversized.as_str()], Some(5_000)).unwrap_err();
+        assert!(err.contains("10006"));
+        assert!(err.contains("MAX_RESPONSE_CHARS"));
+    }
+
+    #[tokio::test]
+    async fn test_max_response_chars_rejects_oversized_responses() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = api_router(AppState {
+            max_response_chars: Some(2_500),
+            ..test_state(&dir.path().join("test.db"), true)
+        });
+
+        call(
+            &app,
+            "POST",
+            "/collections",
+            Some(serde_json::json!({ "name": "faq" })),
+        )
+        .await;
+        // 1件あたり1,011文字
+        for i in 0..3 {
+            let document = serde_json::json!({
+                "content": format!("Rust tip {}: {}", i, "x".repeat(1_000)),
+                "collection": "faq"
+            });
+            let (status, _) = call(&app, "POST", "/documents", Some(document)).await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        // 本文の合計が上限を超えると413、件数を減らせば通る
+        let cases = [
+            (
+                "/search?q=rust&mode=keyword&collection=faq&top_k=3",
+                StatusCode::PAYLOAD_TOO_LARGE,
+            ),
+            (
+                "/search?q=rust&mode=keyword&collection=faq&top_k=2",
+                StatusCode::OK,
+            ),
+            ("/documents?collection=faq", StatusCode::PAYLOAD_TOO_LARGE),
+            ("/documents?collection=faq&limit=2", StatusCode::OK),
+            // コンテキストとソースの両方を数えるため、2件でも上限を超える
+            (
+                "/enrich?q=rust&mode=keyword&collection=faq&top_k=2",
+                StatusCode::PAYLOAD_TOO_LARGE,
+            ),
+            (
+                "/enrich?q=rust&mode=keyword&collection=faq&top_k=1",
+                StatusCode::OK,
+            ),
+        ];
+        for (uri, expected) in cases {
+            let (status, body) = call(&app, "GET", uri, None).await;
+            assert_eq!(status, expected, "{}: {}", uri, body);
+            if expected == StatusCode::PAYLOAD_TOO_LARGE {
+                assert_eq!(body["success"], false);
+                assert!(body["error"]
+                    .as_str()
+                    .unwrap()
+                    .contains("MAX_RESPONSE_CHARS"));
+            }
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! Prometheusのテキスト形式で公開するサーバーのメトリクス（`METRICS_ENABLED=true` で有効化）
+//!
+//! - リクエスト数: ルーティング後のミドルウェア（[`track_requests`]）で数える
+//! - Embedding生成・検索の所要時間: doredore-coreの `embed` / `search` スパンの開始から
+//!   終了までを [`MetricsLayer`] で計測する
+//! - ドキュメント数などのゲージ: スクレイプ時にデータベースから取得する
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::span::{Attributes, Id};
+use tracing::{Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// ヒストグラムのバケットの上限（秒）
+const BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// 所要時間のヒストグラム（バケットごとの件数は累積値で保持する）
+#[derive(Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        for (bucket, le) in self.buckets.iter().zip(BUCKETS) {
+            if seconds <= le {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (bucket, le) in self.buckets.iter().zip(BUCKETS) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{le=\"{}\"}} {}",
+                name,
+                le,
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+        let count = self.count();
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let sum = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+/// スクレイプ時にデータベースから取得する値
+pub struct Gauges {
+    pub documents: usize,
+    pub collections: usize,
+    pub db_size_bytes: u64,
+}
+
+/// サーバー全体で共有するメトリクス
+#[derive(Default)]
+pub struct Metrics {
+    /// `(method, path, status)` ごとのリクエスト数
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    pub embedding: Histogram,
+    pub search: Histogram,
+}
+
+impl Metrics {
+    pub fn record_request(&self, method: &str, path: &str, status: u16) {
+        let mut requests = self.requests.lock().unwrap();
+        *requests
+            .entry((method.to_string(), path.to_string(), status))
+            .or_default() += 1;
+    }
+
+    /// 計測対象のdoredore-coreのスパンに対応するヒストグラム
+    fn histogram_for(&self, metadata: &Metadata<'_>) -> Option<&Histogram> {
+        if !metadata.target().starts_with("doredore_core") {
+            return None;
+        }
+        match metadata.name() {
+            "embed" => Some(&self.embedding),
+            "search" => Some(&self.search),
+            _ => None,
+        }
+    }
+
+    /// Prometheusのテキスト形式（`text/plain; version=0.0.4`）で出力
+    pub fn render(&self, gauges: &Gauges) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP doredore_http_requests_total Total HTTP requests by method, route and status\n");
+        out.push_str("# TYPE doredore_http_requests_total counter\n");
+        for ((method, path, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "doredore_http_requests_total{{method=\"{}\",path=\"{}\",status=\"{}\"}} {}",
+                method, path, status, count
+            );
+        }
+
+        self.embedding.render(
+            &mut out,
+            "doredore_embedding_duration_seconds",
+            "Time spent generating embeddings per call (single text or batch)",
+        );
+        self.search.render(
+            &mut out,
+            "doredore_search_duration_seconds",
+            "Time spent per search, including query embedding",
+        );
+
+        let gauges = [
+            ("doredore_documents", "Number of documents", gauges.documents as u64),
+            ("doredore_collections", "Number of collections", gauges.collections as u64),
+            ("doredore_db_size_bytes", "Size of the SQLite database file", gauges.db_size_bytes),
+        ];
+        for (name, help, value) in gauges {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+
+        out
+    }
+}
+
+/// スパンの開始時刻（スパンの拡張領域に保存する）
+struct SpanStart(Instant);
+
+/// doredore-coreの `embed` / `search` スパンの所要時間をヒストグラムに記録するレイヤー
+pub struct MetricsLayer {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if self.metrics.histogram_for(attrs.metadata()).is_none() {
+            return;
+        }
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanStart(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(histogram) = self.metrics.histogram_for(span.metadata()) else {
+            return;
+        };
+        let extensions = span.extensions();
+        if let Some(start) = extensions.get::<SpanStart>() {
+            histogram.observe(start.0.elapsed());
+        }
+    }
+}
+
+/// リクエスト数を数えるミドルウェア（`Router::route_layer` で登録する）
+///
+/// パスのラベルにはルート定義（`/api/documents/:id` など）を使い、IDごとに系列が増えないようにする
+pub async fn track_requests(
+    State(metrics): State<Arc<Metrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| request.uri().path().to_string(), |p| p.as_str().to_string());
+
+    let response = next.run(request).await;
+    metrics.record_request(&method, &path, response.status().as_u16());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use doredore_core::{Doredore, MockEmbedder, SearchMode};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_layer_records_core_spans() {
+        let metrics = Arc::new(Metrics::default());
+        let subscriber = tracing_subscriber::registry().with(MetricsLayer::new(metrics.clone()));
+
+        let dir = tempfile::tempdir().unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            let rag =
+                Doredore::new_with_embedder(dir.path().join("test.db"), MockEmbedder::new(64))
+                    .unwrap();
+            rag.create_collection("faq", None).unwrap();
+            rag.add_document("Rust is a systems programming language", "faq", None)
+                .unwrap();
+            rag.search("rust", None, None, 5, 0.0, SearchMode::Semantic, None)
+                .unwrap();
+
+            // doredore-core以外の同名のスパンは数えない
+            tracing::info_span!("search").in_scope(|| {});
+        });
+
+        // ドキュメント追加とクエリで1回ずつ、検索1回（クエリのEmbeddingを含む）
+        assert_eq!(metrics.embedding.count(), 2);
+        assert_eq!(metrics.search.count(), 1);
+
+        metrics.record_request("GET", "/api/search", 200);
+        metrics.record_request("GET", "/api/search", 200);
+        let text = metrics.render(&Gauges {
+            documents: 1,
+            collections: 1,
+            db_size_bytes: 4096,
+        });
+        assert!(text.contains(
+            "doredore_http_requests_total{method=\"GET\",path=\"/api/search\",status=\"200\"} 2\n"
+        ));
+        assert!(text.contains("# TYPE doredore_search_duration_seconds histogram\n"));
+        assert!(text.contains("doredore_search_duration_seconds_bucket{le=\"+Inf\"} 1\n"));
+        assert!(text.contains("doredore_embedding_duration_seconds_count 2\n"));
+        assert!(text.contains("doredore_documents 1\n"));
+        assert!(text.contains("doredore_db_size_bytes 4096\n"));
+    }
+}
@@ -0,0 +1,108 @@
+//! 検索・取得系のエンドポイントが使う読み取り専用インスタンスのプール
+//!
+//! `Doredore` は1つのSQLite接続を持ち、同時に1つのリクエストしか処理できない。
+//! 同じファイルを読み取り専用で開いたインスタンスを複数用意しておき、リクエストごとに
+//! 空いているものを貸し出すことで、検索（クエリのEmbedding生成を含む）を並列に処理する。
+//! デフォルトのEmbeddingモデルとコレクションごとのモデルは全インスタンスで共有するため、
+//! モデルのメモリはインスタンス数に比例しない。ANNインデックスはインスタンスごとに読み込む
+//! （インデックスのメモリはインスタンス数に比例する）
+
+use doredore_core::core::enricher::Doredore;
+use doredore_core::EmbeddingModel;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Mutex;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// 読み取り専用インスタンスのプール
+pub struct ReaderPool {
+    idle: Mutex<Vec<Doredore>>,
+    /// 空いているインスタンスの数だけ許可を持つ（待っている間もtokioのワーカーを塞がない）
+    available: Semaphore,
+    size: usize,
+}
+
+impl ReaderPool {
+    /// `size` 個の読み取り専用インスタンスを開く（0は1として扱う）
+    ///
+    /// `models_from` を指定した場合、コレクションごとのモデルはそのインスタンス（書き込み用の
+    /// インスタンスなど）の登録先を共有する（[`Doredore::share_models_with`]）
+    pub fn open<P: AsRef<Path>>(
+        db_path: P,
+        model: &EmbeddingModel,
+        size: usize,
+        max_input_chars: Option<usize>,
+        models_from: Option<&Doredore>,
+    ) -> doredore_core::Result<Self> {
+        let size = size.max(1);
+        let mut readers: Vec<Doredore> = Vec::with_capacity(size);
+        for _ in 0..size {
+            let mut rag = Doredore::with_shared_model_read_only(&db_path, model.clone())?;
+            rag.set_max_input_chars(max_input_chars);
+            // コレクションごとのモデルも共有する（指定がなければ最初のインスタンスの登録先）
+            if let Some(shared) = models_from.or(readers.first()) {
+                rag.share_models_with(shared);
+            }
+            readers.push(rag);
+        }
+
+        Ok(Self {
+            idle: Mutex::new(readers),
+            available: Semaphore::new(size),
+            size,
+        })
+    }
+
+    /// プールのインスタンス数（同時に処理できる検索・取得系のリクエスト数）
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// 空いているインスタンスを借りる（すべて使用中の場合は返却されるまで非同期に待つ）
+    ///
+    /// 借りたインスタンスは [`PooledReader`] を破棄するとプールに戻る
+    pub async fn get(&self) -> PooledReader<'_> {
+        // セマフォは閉じないため、取得に失敗することはない
+        let permit = self
+            .available
+            .acquire()
+            .await
+            .expect("reader pool semaphore is never closed");
+        // 許可を得た時点で、空いているインスタンスが少なくとも1つある
+        let rag = self
+            .idle
+            .lock()
+            .unwrap()
+            .pop()
+            .expect("a permit guarantees an idle reader");
+        PooledReader {
+            pool: self,
+            rag: Some(rag),
+            _permit: permit,
+        }
+    }
+}
+
+/// プールから借りた読み取り専用インスタンス（破棄するとプールに戻る）
+pub struct PooledReader<'a> {
+    pool: &'a ReaderPool,
+    rag: Option<Doredore>,
+    /// インスタンスをプールに戻した後に解放される（`drop` の後にフィールドが破棄されるため）
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Deref for PooledReader<'_> {
+    type Target = Doredore;
+
+    fn deref(&self) -> &Doredore {
+        self.rag.as_ref().expect("reader is returned only on drop")
+    }
+}
+
+impl Drop for PooledReader<'_> {
+    fn drop(&mut self) {
+        if let Some(rag) = self.rag.take() {
+            self.pool.idle.lock().unwrap().push(rag);
+        }
+    }
+}